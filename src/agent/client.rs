@@ -0,0 +1,117 @@
+use crate::agent::protocol::{self, Request, Response};
+use crate::agent::{AgentError, vault_id};
+use crate::vault::crypto;
+use base64::Engine as _;
+use base64::engine::general_purpose::STANDARD;
+use secrecy::ExposeSecret;
+use std::io::BufReader;
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use zeroize::{Zeroize, Zeroizing};
+
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AgentStatus {
+    pub pid: u32,
+    pub vaults: Vec<String>,
+    pub idle_timeout: Duration,
+    /// Time until keys are dropped; `None` when nothing is cached.
+    pub expires_in: Option<Duration>,
+}
+
+/// Talks to a running agent; every call opens a fresh connection.
+pub struct AgentClient {
+    socket: PathBuf,
+}
+
+impl AgentClient {
+    pub fn new(socket: impl Into<PathBuf>) -> Self {
+        Self {
+            socket: socket.into(),
+        }
+    }
+
+    pub fn socket(&self) -> &Path {
+        &self.socket
+    }
+
+    pub fn get_key(&self, vault_path: &Path) -> Result<Option<crypto::SecretBytes>, AgentError> {
+        let request = Request::Get {
+            vault: vault_id(vault_path),
+        };
+        match self.request(&request)? {
+            Response::Key { key: None } => Ok(None),
+            Response::Key { key: Some(mut key) } => {
+                let decoded = STANDARD.decode(key.as_bytes()).map(Zeroizing::new);
+                key.zeroize();
+                let dek = decoded.map_err(|_| AgentError::UnexpectedResponse)?;
+                Ok(Some(crypto::SecretBytes::from(dek.to_vec())))
+            }
+            _ => Err(AgentError::UnexpectedResponse),
+        }
+    }
+
+    pub fn add_key(&self, vault_path: &Path, dek: &crypto::SecretBytes) -> Result<(), AgentError> {
+        let mut request = Request::Add {
+            vault: vault_id(vault_path),
+            key: STANDARD.encode(dek.expose_secret()),
+        };
+        let response = self.request(&request);
+        if let Request::Add { key, .. } = &mut request {
+            key.zeroize();
+        }
+        expect_ok(response?)
+    }
+
+    pub fn forget(&self, vault_path: &Path) -> Result<(), AgentError> {
+        expect_ok(self.request(&Request::Forget {
+            vault: vault_id(vault_path),
+        })?)
+    }
+
+    pub fn lock(&self) -> Result<(), AgentError> {
+        expect_ok(self.request(&Request::Lock)?)
+    }
+
+    pub fn stop(&self) -> Result<(), AgentError> {
+        expect_ok(self.request(&Request::Stop)?)
+    }
+
+    pub fn status(&self) -> Result<AgentStatus, AgentError> {
+        match self.request(&Request::Status)? {
+            Response::Status {
+                pid,
+                vaults,
+                idle_timeout_secs,
+                expires_in_secs,
+            } => Ok(AgentStatus {
+                pid,
+                vaults,
+                idle_timeout: Duration::from_secs(idle_timeout_secs),
+                expires_in: expires_in_secs.map(Duration::from_secs),
+            }),
+            _ => Err(AgentError::UnexpectedResponse),
+        }
+    }
+
+    fn request(&self, request: &Request) -> Result<Response, AgentError> {
+        let stream = UnixStream::connect(&self.socket)?;
+        stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+        stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+
+        protocol::write_message(&mut &stream, request)?;
+        match protocol::read_message(&mut BufReader::new(&stream))? {
+            Response::Error { message } => Err(AgentError::Remote(message)),
+            response => Ok(response),
+        }
+    }
+}
+
+fn expect_ok(response: Response) -> Result<(), AgentError> {
+    match response {
+        Response::Ok => Ok(()),
+        _ => Err(AgentError::UnexpectedResponse),
+    }
+}
@@ -0,0 +1,159 @@
+//! Background unlock agent (ssh-agent style).
+//!
+//! The agent holds unwrapped vault DEKs in locked memory behind a `0600` Unix
+//! socket so that repeated `vault`/`env`/`run`/`inject` invocations pay the
+//! Argon2id cost (and the password prompt) only once per idle period.
+//!
+//! Design notes:
+//!
+//! - Wire protocol: one JSON request line and one JSON response line per
+//!   connection (see `protocol`). Keys travel base64-encoded.
+//! - Keys are indexed by the canonicalized vault path.
+//! - Keys are dropped after `idle_timeout` without a `get`/`add`, on `lock`,
+//!   and when the agent exits.
+//! - The agent never sees the master password or keyfile, only the DEK.
+//!
+//! `ssh` holds the separate `passworder ssh-agent`, which speaks the OpenSSH
+//! agent protocol and serves `ssh-key` items; it shares the socket setup.
+
+mod client;
+pub mod protocol;
+mod server;
+mod ssh;
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use thiserror::Error;
+
+pub use client::AgentClient;
+pub use server::{AgentState, bind, serve};
+pub use ssh::{SshAgentState, serve_ssh};
+
+/// Environment variable pointing vault commands at a running agent.
+pub const AGENT_SOCK_ENV: &str = "PASSWORDER_AGENT_SOCK";
+/// Idle period after which the agent forgets every key.
+pub const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(15 * 60);
+
+const APP_DIR: &str = "passworder";
+const SOCKET_FILE: &str = "agent.sock";
+const SSH_SOCKET_FILE: &str = "ssh-agent.sock";
+
+#[derive(Debug, Error)]
+pub enum AgentError {
+    #[error("agent io error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("malformed agent message: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("agent message exceeds {0} bytes")]
+    MessageTooLarge(usize),
+
+    #[error("agent closed the connection without a response")]
+    NoResponse,
+
+    #[error("another agent is already listening on {0}")]
+    SocketInUse(String),
+
+    #[error("refusing to use {0}: it must be a directory owned by you with mode 0700")]
+    InsecureDir(String),
+
+    #[error("agent error: {0}")]
+    Remote(String),
+
+    #[error("unexpected agent response")]
+    UnexpectedResponse,
+}
+
+/// The agent socket from `PASSWORDER_AGENT_SOCK`, if set.
+pub fn socket_from_env() -> Option<PathBuf> {
+    std::env::var_os(AGENT_SOCK_ENV)
+        .filter(|p| !p.is_empty())
+        .map(PathBuf::from)
+}
+
+/// Default socket location: the per-user runtime dir, else a per-user temp dir.
+pub fn default_socket_path() -> Result<PathBuf, AgentError> {
+    Ok(runtime_dir()?.join(SOCKET_FILE))
+}
+
+/// Default `ssh-agent` socket, next to the unlock agent's.
+pub fn default_ssh_socket_path() -> Result<PathBuf, AgentError> {
+    Ok(runtime_dir()?.join(SSH_SOCKET_FILE))
+}
+
+/// The per-user socket directory, created `0700` if missing.
+///
+/// The temp-dir fallback lives in a world-writable directory, so anyone
+/// could have created it first; refuse it unless it is ours and private.
+fn runtime_dir() -> Result<PathBuf, AgentError> {
+    let dir = match dirs::runtime_dir() {
+        Some(dir) => dir.join(APP_DIR),
+        None => {
+            // SAFETY: getuid has no preconditions and cannot fail.
+            let uid = unsafe { libc::getuid() };
+            std::env::temp_dir().join(format!("{APP_DIR}-{uid}"))
+        }
+    };
+    ensure_private_dir(&dir)?;
+    Ok(dir)
+}
+
+fn ensure_private_dir(dir: &Path) -> Result<(), AgentError> {
+    use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+
+    match std::fs::DirBuilder::new().mode(0o700).create(dir) {
+        Ok(()) => {}
+        Err(error) if error.kind() == std::io::ErrorKind::AlreadyExists => {}
+        Err(error) => return Err(error.into()),
+    }
+
+    // `symlink_metadata` so a planted symlink is refused rather than followed.
+    let metadata = std::fs::symlink_metadata(dir)?;
+    // SAFETY: getuid has no preconditions and cannot fail.
+    let uid = unsafe { libc::getuid() };
+    if !metadata.is_dir() || metadata.uid() != uid || metadata.permissions().mode() & 0o077 != 0 {
+        return Err(AgentError::InsecureDir(dir.display().to_string()));
+    }
+    Ok(())
+}
+
+/// Key under which the agent stores a vault's DEK.
+pub fn vault_id(vault_path: &Path) -> String {
+    std::fs::canonicalize(vault_path)
+        .unwrap_or_else(|_| vault_path.to_path_buf())
+        .display()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn socket_dir_must_be_private() {
+        let temp = tempfile::tempdir().expect("temp dir");
+
+        let fresh = temp.path().join("fresh");
+        ensure_private_dir(&fresh).unwrap();
+        let mode = std::fs::metadata(&fresh).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o700);
+        ensure_private_dir(&fresh).unwrap();
+
+        let shared = temp.path().join("shared");
+        std::fs::create_dir(&shared).unwrap();
+        std::fs::set_permissions(&shared, std::fs::Permissions::from_mode(0o755)).unwrap();
+        assert!(matches!(
+            ensure_private_dir(&shared),
+            Err(AgentError::InsecureDir(_))
+        ));
+
+        let link = temp.path().join("link");
+        std::os::unix::fs::symlink(&fresh, &link).unwrap();
+        assert!(matches!(
+            ensure_private_dir(&link),
+            Err(AgentError::InsecureDir(_))
+        ));
+    }
+}
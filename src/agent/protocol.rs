@@ -0,0 +1,122 @@
+//! Agent wire protocol: newline-delimited JSON, one request per connection.
+//!
+//! ```text
+//! -> {"op":"get","vault":"/home/me/.config/passworder/vault.pwder"}
+//! <- {"status":"key","key":"<base64 DEK>"}
+//! ```
+
+use crate::agent::AgentError;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, Read, Write};
+
+/// Upper bound on a single message line, so a misbehaving peer cannot make
+/// the agent buffer unbounded input.
+pub const MAX_MESSAGE_LEN: usize = 64 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "kebab-case")]
+pub enum Request {
+    /// Report cached vaults and the idle timer.
+    Status,
+    /// Fetch the cached DEK for a vault.
+    Get { vault: String },
+    /// Cache a DEK for a vault (replacing any previous one).
+    Add { vault: String, key: String },
+    /// Drop the DEK for one vault.
+    Forget { vault: String },
+    /// Drop every cached DEK.
+    Lock,
+    /// Drop every cached DEK and exit.
+    Stop,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "kebab-case")]
+pub enum Response {
+    Ok,
+    Key {
+        key: Option<String>,
+    },
+    Status {
+        pid: u32,
+        vaults: Vec<String>,
+        idle_timeout_secs: u64,
+        /// Seconds until keys are dropped; `None` when nothing is cached.
+        expires_in_secs: Option<u64>,
+    },
+    Error {
+        message: String,
+    },
+}
+
+pub fn read_message<T: DeserializeOwned>(reader: &mut impl BufRead) -> Result<T, AgentError> {
+    let mut line = zeroize::Zeroizing::new(String::new());
+    let read = reader
+        .take(MAX_MESSAGE_LEN as u64 + 1)
+        .read_line(&mut line)?;
+    if read == 0 {
+        return Err(AgentError::NoResponse);
+    }
+    if read > MAX_MESSAGE_LEN {
+        return Err(AgentError::MessageTooLarge(MAX_MESSAGE_LEN));
+    }
+    Ok(serde_json::from_str(line.trim_end())?)
+}
+
+pub fn write_message<T: Serialize>(writer: &mut impl Write, message: &T) -> Result<(), AgentError> {
+    let mut line = zeroize::Zeroizing::new(serde_json::to_vec(message)?);
+    line.push(b'\n');
+    writer.write_all(&line)?;
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn request_wire_format_is_tagged_by_op() {
+        let mut out = Vec::new();
+        write_message(
+            &mut out,
+            &Request::Get {
+                vault: "/tmp/v.pwder".to_string(),
+            },
+        )
+        .unwrap();
+        assert_eq!(out, b"{\"op\":\"get\",\"vault\":\"/tmp/v.pwder\"}\n");
+
+        let parsed: Request = read_message(&mut Cursor::new(out)).unwrap();
+        assert_eq!(
+            parsed,
+            Request::Get {
+                vault: "/tmp/v.pwder".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn response_roundtrip() {
+        let mut out = Vec::new();
+        write_message(&mut out, &Response::Key { key: None }).unwrap();
+        assert_eq!(out, b"{\"status\":\"key\",\"key\":null}\n");
+        let parsed: Response = read_message(&mut Cursor::new(out)).unwrap();
+        assert_eq!(parsed, Response::Key { key: None });
+    }
+
+    #[test]
+    fn rejects_oversized_and_empty_messages() {
+        let big = vec![b'x'; MAX_MESSAGE_LEN + 10];
+        assert!(matches!(
+            read_message::<Request>(&mut Cursor::new(big)),
+            Err(AgentError::MessageTooLarge(_))
+        ));
+        assert!(matches!(
+            read_message::<Request>(&mut Cursor::new(Vec::new())),
+            Err(AgentError::NoResponse)
+        ));
+    }
+}
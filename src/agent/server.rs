@@ -0,0 +1,397 @@
+use crate::agent::AgentError;
+use crate::agent::protocol::{self, Request, Response};
+use crate::vault::crypto;
+use base64::Engine as _;
+use base64::engine::general_purpose::STANDARD;
+use std::collections::HashMap;
+use std::fs::DirBuilder;
+use std::io::BufReader;
+use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::time::{Duration, Instant};
+use zeroize::{Zeroize, Zeroizing};
+
+/// How long a client may take to send its request or read the response.
+const CONNECTION_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A DEK pinned in RAM (best-effort `mlock`) and wiped on drop.
+struct LockedKey {
+    bytes: Box<[u8]>,
+    locked: bool,
+}
+
+impl LockedKey {
+    fn new(source: &[u8]) -> Self {
+        let mut bytes = vec![0u8; source.len()].into_boxed_slice();
+        bytes.copy_from_slice(source);
+        // SAFETY: the pointer/length describe memory owned by `bytes`, which
+        // stays at the same address until `drop` unlocks it.
+        let locked = unsafe { libc::mlock(bytes.as_ptr().cast(), bytes.len()) } == 0;
+        Self { bytes, locked }
+    }
+
+    fn expose(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+impl Drop for LockedKey {
+    fn drop(&mut self) {
+        self.bytes.zeroize();
+        if self.locked {
+            // SAFETY: same region that was passed to `mlock` in `new`.
+            unsafe {
+                libc::munlock(self.bytes.as_ptr().cast(), self.bytes.len());
+            }
+        }
+    }
+}
+
+/// In-memory key cache with an idle timer; transport-agnostic so it can be
+/// unit tested without a socket.
+pub struct AgentState {
+    keys: HashMap<String, LockedKey>,
+    idle_timeout: Duration,
+    last_used: Instant,
+}
+
+impl AgentState {
+    pub fn new(idle_timeout: Duration) -> Self {
+        Self {
+            keys: HashMap::new(),
+            idle_timeout,
+            last_used: Instant::now(),
+        }
+    }
+
+    /// Handle one request; the flag is `true` when the agent should exit.
+    pub fn handle(&mut self, request: Request, now: Instant) -> (Response, bool) {
+        self.expire(now);
+
+        match request {
+            Request::Status => {
+                let mut vaults = self.keys.keys().cloned().collect::<Vec<_>>();
+                vaults.sort();
+                let response = Response::Status {
+                    pid: std::process::id(),
+                    vaults,
+                    idle_timeout_secs: self.idle_timeout.as_secs(),
+                    expires_in_secs: self.time_left(now).map(|d| d.as_secs()),
+                };
+                (response, false)
+            }
+            Request::Get { vault } => {
+                let key = self.keys.get(&vault).map(|k| STANDARD.encode(k.expose()));
+                if key.is_some() {
+                    self.last_used = now;
+                }
+                (Response::Key { key }, false)
+            }
+            Request::Add { vault, mut key } => {
+                let decoded = STANDARD.decode(key.as_bytes()).map(Zeroizing::new);
+                key.zeroize();
+                match decoded {
+                    Ok(dek) if dek.len() == crypto::DEK_LEN => {
+                        self.keys.insert(vault, LockedKey::new(&dek));
+                        self.last_used = now;
+                        (Response::Ok, false)
+                    }
+                    _ => (
+                        Response::Error {
+                            message: "invalid key".to_string(),
+                        },
+                        false,
+                    ),
+                }
+            }
+            Request::Forget { vault } => {
+                self.keys.remove(&vault);
+                (Response::Ok, false)
+            }
+            Request::Lock => {
+                self.keys.clear();
+                (Response::Ok, false)
+            }
+            Request::Stop => {
+                self.keys.clear();
+                (Response::Ok, true)
+            }
+        }
+    }
+
+    /// Drop every key once the idle timeout has passed.
+    pub fn expire(&mut self, now: Instant) {
+        if !self.keys.is_empty() && self.time_left(now) == Some(Duration::ZERO) {
+            self.keys.clear();
+        }
+    }
+
+    /// Time until keys are dropped; `None` when nothing is cached.
+    fn time_left(&self, now: Instant) -> Option<Duration> {
+        if self.keys.is_empty() {
+            return None;
+        }
+        let idle = now.saturating_duration_since(self.last_used);
+        Some(self.idle_timeout.saturating_sub(idle))
+    }
+}
+
+/// Bind the agent socket with `0600` permissions, replacing a stale socket file.
+///
+/// A missing parent directory is created with `0700`.
+pub fn bind(socket_path: &Path) -> Result<UnixListener, AgentError> {
+    if let Some(parent) = socket_path.parent()
+        && !parent.as_os_str().is_empty()
+        && !parent.exists()
+    {
+        DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(parent)?;
+    }
+
+    if socket_path.exists() {
+        if UnixStream::connect(socket_path).is_ok() {
+            return Err(AgentError::SocketInUse(socket_path.display().to_string()));
+        }
+        std::fs::remove_file(socket_path)?;
+    }
+
+    // Create the socket without group/other access from the start.
+    // SAFETY: umask only changes the process file mode creation mask.
+    let previous = unsafe { libc::umask(0o177) };
+    let listener = UnixListener::bind(socket_path);
+    // SAFETY: restores the mask saved above.
+    unsafe { libc::umask(previous) };
+    let listener = listener?;
+
+    std::fs::set_permissions(socket_path, std::fs::Permissions::from_mode(0o600))?;
+    Ok(listener)
+}
+
+/// Serve requests until a `stop` request arrives.
+///
+/// Connections are handled one at a time; the idle timer is enforced even
+/// while no client connects by bounding each `poll` with the time left.
+pub fn serve(listener: UnixListener, state: &mut AgentState) -> Result<(), AgentError> {
+    disable_core_dumps();
+    let fd = listener.as_raw_fd();
+
+    loop {
+        let now = Instant::now();
+        state.expire(now);
+        let timeout_ms = match state.time_left(now) {
+            Some(left) => i32::try_from(left.as_millis() + 1).unwrap_or(i32::MAX),
+            None => -1,
+        };
+
+        let mut pollfd = libc::pollfd {
+            fd,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        // SAFETY: `pollfd` is a valid, initialized array of length 1.
+        let ready = unsafe { libc::poll(&mut pollfd, 1, timeout_ms) };
+        if ready < 0 {
+            let error = std::io::Error::last_os_error();
+            if error.kind() == std::io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(error.into());
+        }
+        if ready == 0 {
+            continue;
+        }
+
+        let stream = match listener.accept() {
+            Ok((stream, _)) => stream,
+            Err(error) if error.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(error) => return Err(error.into()),
+        };
+        if !peer_is_same_user(&stream) {
+            continue;
+        }
+        if handle_connection(&stream, state) {
+            return Ok(());
+        }
+    }
+}
+
+/// Whether the connecting process runs as our uid.
+///
+/// The socket's `0600` mode already keeps other users out; this also covers
+/// a socket path whose permissions were loosened after `bind`.
+pub(super) fn peer_is_same_user(stream: &UnixStream) -> bool {
+    // SAFETY: getuid has no preconditions and cannot fail.
+    let uid = unsafe { libc::getuid() };
+    peer_uid(stream).is_ok_and(|peer| peer == uid)
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn peer_uid(stream: &UnixStream) -> std::io::Result<libc::uid_t> {
+    let mut cred = libc::ucred {
+        pid: 0,
+        uid: 0,
+        gid: 0,
+    };
+    let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    // SAFETY: `cred` and `len` describe a writable `ucred`, as SO_PEERCRED expects.
+    let rc = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            (&mut cred as *mut libc::ucred).cast(),
+            &mut len,
+        )
+    };
+    if rc != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(cred.uid)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn peer_uid(stream: &UnixStream) -> std::io::Result<libc::uid_t> {
+    let mut uid = 0;
+    let mut gid = 0;
+    // SAFETY: `uid` and `gid` are valid out-pointers.
+    if unsafe { libc::getpeereid(stream.as_raw_fd(), &mut uid, &mut gid) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(uid)
+}
+
+/// Returns `true` when the client asked the agent to stop.
+fn handle_connection(stream: &UnixStream, state: &mut AgentState) -> bool {
+    let _ = stream.set_read_timeout(Some(CONNECTION_TIMEOUT));
+    let _ = stream.set_write_timeout(Some(CONNECTION_TIMEOUT));
+
+    let (mut response, stop) = match protocol::read_message::<Request>(&mut BufReader::new(stream))
+    {
+        Ok(request) => state.handle(request, Instant::now()),
+        Err(error) => (
+            Response::Error {
+                message: error.to_string(),
+            },
+            false,
+        ),
+    };
+
+    // A client that went away is not the agent's problem.
+    let _ = protocol::write_message(&mut &*stream, &response);
+    if let Response::Key { key: Some(key) } = &mut response {
+        key.zeroize();
+    }
+    stop
+}
+
+pub(super) fn disable_core_dumps() {
+    let limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    // SAFETY: `limit` is a valid rlimit; failure only means dumps stay enabled.
+    unsafe {
+        libc::setrlimit(libc::RLIMIT_CORE, &limit);
+    }
+    #[cfg(target_os = "linux")]
+    // SAFETY: PR_SET_DUMPABLE takes a plain integer argument.
+    unsafe {
+        libc::prctl(libc::PR_SET_DUMPABLE, 0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::AgentClient;
+    use secrecy::ExposeSecret;
+
+    fn add(vault: &str) -> Request {
+        Request::Add {
+            vault: vault.to_string(),
+            key: STANDARD.encode([7u8; crypto::DEK_LEN]),
+        }
+    }
+
+    #[test]
+    fn keys_expire_after_idle_timeout() {
+        let mut state = AgentState::new(Duration::from_secs(60));
+        let start = Instant::now();
+        assert_eq!(state.handle(add("/v"), start).0, Response::Ok);
+
+        let get = Request::Get {
+            vault: "/v".to_string(),
+        };
+        let (response, _) = state.handle(get.clone(), start + Duration::from_secs(59));
+        assert!(matches!(response, Response::Key { key: Some(_) }));
+
+        // The successful get reset the timer.
+        let (response, _) = state.handle(get.clone(), start + Duration::from_secs(118));
+        assert!(matches!(response, Response::Key { key: Some(_) }));
+
+        let (response, _) = state.handle(get, start + Duration::from_secs(179));
+        assert_eq!(response, Response::Key { key: None });
+    }
+
+    #[test]
+    fn rejects_keys_of_the_wrong_length() {
+        let mut state = AgentState::new(Duration::from_secs(60));
+        let request = Request::Add {
+            vault: "/v".to_string(),
+            key: STANDARD.encode([1u8; 8]),
+        };
+        assert!(matches!(
+            state.handle(request, Instant::now()).0,
+            Response::Error { .. }
+        ));
+    }
+
+    #[test]
+    fn peer_credentials_report_our_uid() {
+        let (a, b) = UnixStream::pair().unwrap();
+        // SAFETY: getuid has no preconditions and cannot fail.
+        let uid = unsafe { libc::getuid() };
+        assert_eq!(peer_uid(&a).unwrap(), uid);
+        assert!(peer_is_same_user(&b));
+    }
+
+    #[test]
+    fn client_and_server_speak_over_a_temp_socket() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket = dir.path().join("agent.sock");
+        let listener = bind(&socket).unwrap();
+        let mode = std::fs::metadata(&socket).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        let server = std::thread::spawn(move || {
+            let mut state = AgentState::new(Duration::from_secs(60));
+            serve(listener, &mut state)
+        });
+
+        let vault = dir.path().join("vault.pwder");
+        let client = AgentClient::new(&socket);
+        assert!(client.get_key(&vault).unwrap().is_none());
+
+        let dek = crypto::generate_dek();
+        client.add_key(&vault, &dek).unwrap();
+        let cached = client.get_key(&vault).unwrap().expect("cached key");
+        assert_eq!(cached.expose_secret(), dek.expose_secret());
+
+        let status = client.status().unwrap();
+        assert_eq!(status.vaults.len(), 1);
+        assert!(status.expires_in.is_some());
+
+        client.lock().unwrap();
+        assert!(client.get_key(&vault).unwrap().is_none());
+        assert!(client.status().unwrap().expires_in.is_none());
+
+        assert!(matches!(bind(&socket), Err(AgentError::SocketInUse(_))));
+
+        client.stop().unwrap();
+        server.join().unwrap().unwrap();
+    }
+}
@@ -0,0 +1,178 @@
+//! OpenSSH agent protocol (draft-miller-ssh-agent) serving `ssh-key` items.
+//!
+//! Only the read-only half of the protocol is implemented: listing
+//! identities and signing. Adding, removing and locking keys through the
+//! agent is refused; keys are managed in the vault instead.
+
+use crate::agent::AgentError;
+use crate::vault::SshKey;
+use std::io::{Read, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::sync::Arc;
+
+/// Upper bound on one message, matching OpenSSH's own agent.
+pub const MAX_MESSAGE_LEN: usize = 256 * 1024;
+
+const SSH_AGENT_FAILURE: u8 = 5;
+const SSH_AGENTC_REQUEST_IDENTITIES: u8 = 11;
+const SSH_AGENT_IDENTITIES_ANSWER: u8 = 12;
+const SSH_AGENTC_SIGN_REQUEST: u8 = 13;
+const SSH_AGENT_SIGN_RESPONSE: u8 = 14;
+
+/// The keys an ssh-agent serves; transport-agnostic so it can be unit tested
+/// without a socket.
+pub struct SshAgentState {
+    keys: Vec<SshKey>,
+}
+
+impl SshAgentState {
+    pub fn new(keys: Vec<SshKey>) -> Self {
+        Self { keys }
+    }
+
+    /// Answer one message body (without its length prefix).
+    pub fn handle(&self, message: &[u8]) -> Vec<u8> {
+        match message.split_first() {
+            Some((&SSH_AGENTC_REQUEST_IDENTITIES, _)) => {
+                let mut out = vec![SSH_AGENT_IDENTITIES_ANSWER];
+                put_u32(&mut out, self.keys.len() as u32);
+                for key in &self.keys {
+                    put_string(&mut out, &key.public_key_blob());
+                    put_string(&mut out, key.comment.as_bytes());
+                }
+                out
+            }
+            Some((&SSH_AGENTC_SIGN_REQUEST, body)) => self.sign(body).unwrap_or_else(failure),
+            _ => failure(),
+        }
+    }
+
+    fn sign(&self, body: &[u8]) -> Option<Vec<u8>> {
+        let mut body = body;
+        let blob = take_string(&mut body)?;
+        let data = take_string(&mut body)?;
+        // Flags only select RSA signature hashes; Ed25519 has a single form.
+        let _flags = take_u32(&mut body)?;
+
+        let key = self.keys.iter().find(|k| k.public_key_blob() == blob)?;
+        let mut out = vec![SSH_AGENT_SIGN_RESPONSE];
+        put_string(&mut out, &key.sign(data));
+        Some(out)
+    }
+}
+
+/// Serve connections until the process is killed, one thread per client so
+/// that a long-lived `ssh` session does not block others.
+pub fn serve_ssh(listener: UnixListener, state: SshAgentState) -> Result<(), AgentError> {
+    super::server::disable_core_dumps();
+    let state = Arc::new(state);
+
+    loop {
+        let stream = match listener.accept() {
+            Ok((stream, _)) => stream,
+            Err(error) if error.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(error) => return Err(error.into()),
+        };
+        if !super::server::peer_is_same_user(&stream) {
+            continue;
+        }
+        let state = Arc::clone(&state);
+        std::thread::spawn(move || handle_connection(stream, &state));
+    }
+}
+
+fn handle_connection(mut stream: UnixStream, state: &SshAgentState) {
+    loop {
+        let mut len = [0u8; 4];
+        if stream.read_exact(&mut len).is_err() {
+            return;
+        }
+        let len = u32::from_be_bytes(len) as usize;
+        if len > MAX_MESSAGE_LEN {
+            return;
+        }
+        let mut message = vec![0u8; len];
+        if stream.read_exact(&mut message).is_err() {
+            return;
+        }
+
+        let response = state.handle(&message);
+        let mut framed = Vec::with_capacity(response.len() + 4);
+        put_string(&mut framed, &response);
+        if stream.write_all(&framed).is_err() {
+            return;
+        }
+    }
+}
+
+fn failure() -> Vec<u8> {
+    vec![SSH_AGENT_FAILURE]
+}
+
+fn put_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_be_bytes());
+}
+
+fn put_string(out: &mut Vec<u8>, value: &[u8]) {
+    put_u32(out, value.len() as u32);
+    out.extend_from_slice(value);
+}
+
+fn take_u32(input: &mut &[u8]) -> Option<u32> {
+    let (head, rest) = input.split_first_chunk::<4>()?;
+    *input = rest;
+    Some(u32::from_be_bytes(*head))
+}
+
+fn take_string<'a>(input: &mut &'a [u8]) -> Option<&'a [u8]> {
+    let len = take_u32(input)? as usize;
+    if input.len() < len {
+        return None;
+    }
+    let (head, rest) = input.split_at(len);
+    *input = rest;
+    Some(head)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sign_request(blob: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut message = vec![SSH_AGENTC_SIGN_REQUEST];
+        put_string(&mut message, blob);
+        put_string(&mut message, data);
+        put_u32(&mut message, 0);
+        message
+    }
+
+    #[test]
+    fn lists_identities_and_signs_with_known_keys() {
+        let key = SshKey::generate("deploy");
+        let blob = key.public_key_blob();
+        let expected = key.sign(b"challenge");
+        let state = SshAgentState::new(vec![key]);
+
+        let mut answer = &state.handle(&[SSH_AGENTC_REQUEST_IDENTITIES])[..];
+        assert_eq!(answer[0], SSH_AGENT_IDENTITIES_ANSWER);
+        answer = &answer[1..];
+        assert_eq!(take_u32(&mut answer), Some(1));
+        assert_eq!(take_string(&mut answer), Some(&blob[..]));
+        assert_eq!(take_string(&mut answer), Some(&b"deploy"[..]));
+
+        let mut response = &state.handle(&sign_request(&blob, b"challenge"))[..];
+        assert_eq!(response[0], SSH_AGENT_SIGN_RESPONSE);
+        response = &response[1..];
+        assert_eq!(take_string(&mut response), Some(&expected[..]));
+    }
+
+    #[test]
+    fn refuses_unknown_keys_and_unsupported_requests() {
+        let state = SshAgentState::new(vec![SshKey::generate("a")]);
+        let other = SshKey::generate("b").public_key_blob();
+        assert_eq!(state.handle(&sign_request(&other, b"x")), failure());
+        // SSH_AGENTC_REMOVE_ALL_IDENTITIES
+        assert_eq!(state.handle(&[19]), failure());
+        assert_eq!(state.handle(&[]), failure());
+    }
+}
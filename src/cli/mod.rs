@@ -1,6 +1,8 @@
-use crate::vault::VaultItemType;
+use crate::timespec::{parse_date, parse_duration};
+use crate::vault::{ConflictPolicy, ForeignFormat, VaultItemType};
 use clap::{Args, Parser, Subcommand, ValueEnum};
 use std::path::PathBuf;
+use std::time::Duration;
 use uuid::Uuid;
 #[derive(Debug, Parser)]
 #[command(
@@ -66,13 +68,92 @@ pub enum Commands {
     )]
     Profile(ProfileArgs),
     #[command(subcommand_required = true, about = "Manage a local encrypted vault.")]
-    Vault(VaultArgs),
+    Vault(Box<VaultArgs>),
     #[command(about = "Emit environment variables from the vault (guarded).")]
     Env(EnvArgs),
     #[command(about = "Run a command with environment injected from the vault (guarded).")]
     Run(RunArgs),
     #[command(about = "Inject secrets into a template file (guarded).")]
     Inject(InjectArgs),
+    #[cfg(unix)]
+    #[command(about = "Run or control the background unlock agent (caches the vault key).")]
+    Agent(AgentArgs),
+    #[cfg(unix)]
+    #[command(about = "Serve the vault's ssh-key items over the OpenSSH agent protocol.")]
+    SshAgent(SshAgentArgs),
+}
+
+#[cfg(unix)]
+#[derive(Debug, Args)]
+#[command(args_conflicts_with_subcommands = true)]
+pub struct AgentArgs {
+    #[command(subcommand)]
+    pub command: Option<AgentCommands>,
+
+    #[command(flatten)]
+    pub start: AgentStartArgs,
+}
+
+#[cfg(unix)]
+#[derive(Debug, Subcommand)]
+pub enum AgentCommands {
+    #[command(about = "Show whether the agent is running and which vaults it holds keys for.")]
+    Status(AgentSocketArgs),
+    #[command(about = "Drop every cached key (the agent keeps running).")]
+    Lock(AgentSocketArgs),
+    #[command(about = "Drop every cached key and stop the agent.")]
+    Stop(AgentSocketArgs),
+}
+
+#[cfg(unix)]
+#[derive(Debug, Args)]
+pub struct AgentStartArgs {
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Socket path (defaults to a per-user runtime directory)."
+    )]
+    pub socket: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "DURATION",
+        default_value = "15m",
+        value_parser = parse_duration,
+        help = "Forget cached keys after this long without use (e.g. 90s, 15m, 2h)."
+    )]
+    pub idle_timeout: Duration,
+
+    #[arg(long, help = "Stay in the foreground instead of detaching.")]
+    pub foreground: bool,
+}
+
+#[cfg(unix)]
+#[derive(Debug, Args)]
+pub struct AgentSocketArgs {
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Agent socket (defaults to $PASSWORDER_AGENT_SOCK)."
+    )]
+    pub socket: Option<PathBuf>,
+}
+
+#[cfg(unix)]
+#[derive(Debug, Args)]
+pub struct SshAgentArgs {
+    #[command(flatten)]
+    pub path: VaultPathOverrideArgs,
+
+    #[command(flatten)]
+    pub keyfile: VaultKeyfileArgs,
+
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Socket path (defaults to ssh-agent.sock in a per-user runtime directory)."
+    )]
+    pub socket: Option<PathBuf>,
 }
 
 #[derive(Debug, Args)]
@@ -91,45 +172,861 @@ pub enum VaultCommands {
     Path(VaultPathArgs),
     #[command(about = "Add a new item to the vault.")]
     Add(VaultAddArgs),
-    #[command(about = "Get an item by id (secrets are redacted unless explicitly revealed).")]
+    #[command(
+        about = "Get an item by id, name or path (secrets are redacted unless explicitly revealed)."
+    )]
     Get(VaultGetArgs),
-    #[command(about = "Edit an existing item by id.")]
+    #[command(
+        about = "Print the current one-time code of a totp item and the seconds it stays valid."
+    )]
+    Totp(VaultTotpArgs),
+    #[command(about = "Edit an existing item.")]
     Edit(VaultEditArgs),
-    #[command(about = "Remove an item by id.")]
+    #[command(
+        about = "Replace an item's secret with a freshly generated one (the old one is kept in history)."
+    )]
+    Rotate(VaultRotateArgs),
+    #[command(about = "Attach a file to an item (stored encrypted in the vault).")]
+    Attach(VaultAttachArgs),
+    #[command(about = "List an item's attachments with their sizes and SHA-256 hashes.")]
+    Attachments(VaultAttachmentsArgs),
+    #[command(
+        about = "Delete an attachment from an item; history keeps only its name and hash, so this cannot be undone."
+    )]
+    Detach(VaultDetachArgs),
+    #[command(about = "Move an item to the trash.")]
     Rm(VaultRmArgs),
+    #[command(
+        subcommand_required = true,
+        about = "List, restore, or permanently delete trashed items."
+    )]
+    Trash(VaultTrashArgs),
+    #[command(about = "Show prior revisions of an item (secrets are redacted).")]
+    History(VaultHistoryArgs),
+    #[command(
+        about = "Restore an item to a prior revision (the current state is kept in history)."
+    )]
+    Restore(VaultRestoreArgs),
     #[command(about = "List items in the vault (no secrets).")]
     List(VaultListArgs),
-    #[command(about = "Search items in the vault (no persistent index).")]
+    #[command(
+        about = "Search items in the vault (no persistent index).",
+        long_about = "Search items in the vault (no persistent index).\n\n\
+            A query is a list of terms that must all match. A bare word matches \
+            part of the name, path, tags, username, URLs or notes. FIELD:VALUE \
+            matches a field exactly, with * as a wildcard (path:a/b also matches \
+            items below a/b); FIELD~VALUE matches part of it. Fields: type, tag, \
+            path, name, username, url, notes, id. created and updated compare with \
+            < and > against an age (updated<30d) or a date (created<2024-01-01). \
+            Combine terms with AND, OR, NOT or a leading -, and group them with \
+            parentheses; quote values containing spaces.\n\n\
+            Example: type:api-token tag:prod path:services/* name~\"stripe\" -tag:deprecated updated<30d"
+    )]
     Search(VaultSearchArgs),
+    #[command(
+        about = "List items that are expired or due for rotation; exits 3 if any are overdue."
+    )]
+    Due(VaultDueArgs),
+    #[command(
+        about = "Change the password of the key slot used to unlock (re-wraps the vault key)."
+    )]
+    Passwd(VaultPasswdArgs),
+    #[command(about = "Rotate the vault data key and re-encrypt all items.")]
+    Rekey(VaultRekeyArgs),
+    #[command(
+        about = "Export items to a passphrase-encrypted bundle, or to plaintext with --unsafe."
+    )]
+    Export(VaultExportArgs),
+    #[command(
+        about = "Import items from a `vault export` bundle or another password manager's export."
+    )]
+    Import(VaultImportArgs),
+    #[command(about = "Check the vault file for corruption or tampering and report every problem.")]
+    Verify(VaultVerifyArgs),
+    #[command(
+        subcommand_required = true,
+        about = "List or restore automatic backups taken before each vault write."
+    )]
+    Backup(VaultBackupArgs),
+    #[command(
+        about = "Merge another copy of this vault into it, item by item, reporting conflicts."
+    )]
+    Merge(VaultMergeArgs),
+    #[command(
+        args_conflicts_with_subcommands = true,
+        about = "Commit, pull, merge item by item and push the vault through git (see `vault sync init`)."
+    )]
+    Sync(VaultSyncArgs),
+    #[command(about = "Rewrite a v1 vault in the v2 format, which encrypts each item separately.")]
+    Upgrade(VaultUpgradeArgs),
+    #[command(
+        subcommand_required = true,
+        about = "Manage key slots (additional passwords that unlock the vault)."
+    )]
+    Slot(VaultSlotArgs),
+    #[command(about = "Unlock with the recovery key and set a new master password.")]
+    Recover(VaultRecoverArgs),
+    #[command(
+        subcommand_required = true,
+        about = "Benchmark and tune the Argon2id key derivation cost."
+    )]
+    Kdf(VaultKdfArgs),
+}
+
+#[derive(Debug, Args, Clone, Default)]
+pub struct VaultPathOverrideArgs {
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Override the vault path (defaults to ~/Library/Application Support/passworder/vault.pwder)."
+    )]
+    pub path: Option<PathBuf>,
+}
+
+#[derive(Debug, Args, Clone, Default)]
+pub struct VaultKeyfileArgs {
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Keyfile required as a second unlock factor (defaults to $PASSWORDER_KEYFILE)."
+    )]
+    pub keyfile: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum EnvFormat {
+    Bash,
+    Json,
+}
+
+#[derive(Debug, Args)]
+pub struct EnvArgs {
+    #[arg(
+        long,
+        value_name = "NAME",
+        help = "Profile name (matches vault item `path`)."
+    )]
+    pub profile: String,
+
+    #[arg(long, value_name = "FORMAT", value_enum, help = "Output format.")]
+    pub format: EnvFormat,
+
+    #[arg(
+        long = "unsafe",
+        help = "Required to print secrets or write them to disk; acknowledge CI/logging risks."
+    )]
+    pub unsafe_mode: bool,
+
+    #[command(flatten)]
+    pub vault: VaultPathOverrideArgs,
+
+    #[command(flatten)]
+    pub keyfile: VaultKeyfileArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct RunArgs {
+    #[arg(
+        long,
+        value_name = "NAME",
+        help = "Profile name (matches vault item `path`)."
+    )]
+    pub profile: String,
+
+    #[arg(
+        long = "unsafe",
+        help = "Allow running in CI / non-interactive contexts where env may be logged."
+    )]
+    pub unsafe_mode: bool,
+
+    #[command(flatten)]
+    pub vault: VaultPathOverrideArgs,
+
+    #[command(flatten)]
+    pub keyfile: VaultKeyfileArgs,
+
+    #[arg(
+        value_name = "CMD",
+        required = true,
+        num_args = 1..,
+        last = true,
+        help = "Command to run (must be after `--`)."
+    )]
+    pub cmd: Vec<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct InjectArgs {
+    #[arg(
+        long,
+        value_name = "NAME",
+        help = "Profile name (matches vault item `path`)."
+    )]
+    pub profile: String,
+
+    #[arg(long = "in", value_name = "FILE", help = "Template input file.")]
+    pub input: PathBuf,
+
+    #[arg(long = "out", value_name = "FILE", help = "Output file path.")]
+    pub output: PathBuf,
+
+    #[arg(
+        long = "unsafe",
+        help = "Required to print secrets or write them to disk; acknowledge CI/logging risks."
+    )]
+    pub unsafe_mode: bool,
+
+    #[arg(long, help = "Overwrite output file if it already exists.")]
+    pub force: bool,
+
+    #[command(flatten)]
+    pub vault: VaultPathOverrideArgs,
+
+    #[command(flatten)]
+    pub keyfile: VaultKeyfileArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct VaultInitArgs {
+    #[command(flatten)]
+    pub path: VaultPathOverrideArgs,
+
+    #[command(flatten)]
+    pub keyfile: VaultKeyfileArgs,
+
+    #[arg(
+        long,
+        help = "Also generate a printable recovery key (shown once) in a second key slot."
+    )]
+    pub recovery_key: bool,
+
+    #[command(flatten)]
+    pub kdf: VaultNewKdfArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct VaultStatusArgs {
+    #[command(flatten)]
+    pub path: VaultPathOverrideArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct VaultPathArgs {
+    #[command(flatten)]
+    pub path: VaultPathOverrideArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct VaultAddArgs {
+    #[command(flatten)]
+    pub path: VaultPathOverrideArgs,
+
+    #[command(flatten)]
+    pub keyfile: VaultKeyfileArgs,
+
+    #[arg(long = "type", value_name = "TYPE", help = "Item type.")]
+    pub item_type: VaultItemType,
+
+    #[arg(long, value_name = "NAME", help = "Item display name.")]
+    pub name: String,
+
+    #[arg(long, value_name = "PATH", help = "Optional folder/path for grouping.")]
+    pub item_path: Option<String>,
+
+    #[arg(long = "tag", value_name = "TAG", action = clap::ArgAction::Append, help = "Tag (repeatable).")]
+    pub tags: Vec<String>,
+
+    #[arg(long, value_name = "USERNAME", help = "Optional username.")]
+    pub username: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "SECRET",
+        allow_hyphen_values = true,
+        help = "Secret value (avoid using this in shell history when possible). For `--type totp`, an otpauth:// URI or a base32 seed; for `--type ssh-key`, an unencrypted OpenSSH private key."
+    )]
+    pub secret: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "KIND",
+        num_args = 0..=1,
+        conflicts_with = "secret",
+        help = "Generate the secret instead of prompting for it [default: password for logins, token-b64 for api tokens, ssh-key for ssh keys]."
+    )]
+    pub generate: Option<Option<SecretGenerator>>,
+
+    #[arg(
+        long,
+        value_name = "NAME",
+        requires = "generate",
+        help = "Saved password profile to generate with (see `profile`)."
+    )]
+    pub profile: Option<String>,
+
+    #[arg(
+        long,
+        requires = "generate",
+        help = "Print the generated secret to STDOUT."
+    )]
+    pub reveal: bool,
+
+    #[arg(long = "url", value_name = "URL", action = clap::ArgAction::Append, help = "Associated URL (repeatable).")]
+    pub urls: Vec<String>,
+
+    #[arg(long, value_name = "NOTES", help = "Optional notes.")]
+    pub notes: Option<String>,
+
+    #[arg(
+        long = "field",
+        value_name = "KEY=VALUE",
+        value_parser = parse_field,
+        allow_hyphen_values = true,
+        action = clap::ArgAction::Append,
+        help = "Custom field (repeatable)."
+    )]
+    pub fields: Vec<(String, String)>,
+
+    #[arg(
+        long = "secret-field",
+        value_name = "KEY",
+        action = clap::ArgAction::Append,
+        help = "Mark a custom field concealed (repeatable); without a matching --field, its value is prompted for."
+    )]
+    pub secret_fields: Vec<String>,
+
+    #[arg(
+        long,
+        value_name = "YYYY-MM-DD",
+        value_parser = parse_date,
+        help = "Date (UTC) the item expires; listed by `vault due` from then on."
+    )]
+    pub expires_at: Option<u64>,
+
+    #[arg(
+        long,
+        value_name = "DURATION",
+        value_parser = parse_duration,
+        help = "Rotate the secret this often, e.g. 90d; listed by `vault due` once it is older."
+    )]
+    pub rotate_every: Option<Duration>,
+}
+
+#[derive(Debug, Args)]
+pub struct VaultGetArgs {
+    #[command(flatten)]
+    pub path: VaultPathOverrideArgs,
+
+    #[command(flatten)]
+    pub keyfile: VaultKeyfileArgs,
+
+    #[arg(value_name = "ITEM", help = "Item: id, id prefix, name or path/name.")]
+    pub item: String,
+
+    #[arg(
+        long,
+        help = "Reveal the secret to STDOUT (unsafe unless you know where output goes)."
+    )]
+    pub reveal: bool,
+
+    #[arg(
+        long,
+        value_name = "NAME",
+        requires = "out",
+        help = "Write this attachment to --out instead of showing the item."
+    )]
+    pub attachment: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "PATH",
+        requires = "attachment",
+        help = "File to write the attachment to (created with 0600 permissions)."
+    )]
+    pub out: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct VaultAttachArgs {
+    #[command(flatten)]
+    pub path: VaultPathOverrideArgs,
+
+    #[command(flatten)]
+    pub keyfile: VaultKeyfileArgs,
+
+    #[arg(value_name = "ITEM", help = "Item: id, id prefix, name or path/name.")]
+    pub item: String,
+
+    #[arg(value_name = "FILE", help = "File to attach.")]
+    pub file: PathBuf,
+
+    #[arg(
+        long,
+        value_name = "NAME",
+        help = "Attachment name (defaults to the file name); an existing attachment with this name is replaced."
+    )]
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct VaultDueArgs {
+    #[command(flatten)]
+    pub path: VaultPathOverrideArgs,
+
+    #[command(flatten)]
+    pub keyfile: VaultKeyfileArgs,
+
+    #[arg(
+        long,
+        value_name = "DURATION",
+        default_value = "14d",
+        value_parser = parse_duration,
+        help = "Also list items that fall due within this long."
+    )]
+    pub within: Duration,
+}
+
+#[derive(Debug, Args)]
+pub struct VaultAttachmentsArgs {
+    #[command(flatten)]
+    pub path: VaultPathOverrideArgs,
+
+    #[command(flatten)]
+    pub keyfile: VaultKeyfileArgs,
+
+    #[arg(value_name = "ITEM", help = "Item: id, id prefix, name or path/name.")]
+    pub item: String,
+}
+
+#[derive(Debug, Args)]
+pub struct VaultDetachArgs {
+    #[command(flatten)]
+    pub path: VaultPathOverrideArgs,
+
+    #[command(flatten)]
+    pub keyfile: VaultKeyfileArgs,
+
+    #[arg(value_name = "ITEM", help = "Item: id, id prefix, name or path/name.")]
+    pub item: String,
+
+    #[arg(value_name = "NAME", help = "Attachment name.")]
+    pub name: String,
+
+    #[arg(
+        long,
+        help = "Confirm deleting the attachment; its contents cannot be restored afterwards."
+    )]
+    pub force: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct VaultTotpArgs {
+    #[command(flatten)]
+    pub path: VaultPathOverrideArgs,
+
+    #[command(flatten)]
+    pub keyfile: VaultKeyfileArgs,
+
+    #[arg(
+        value_name = "ITEM",
+        help = "Item: id, id prefix, name or path/name of a totp item."
+    )]
+    pub item: String,
+}
+
+/// Generator for a new item secret.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum SecretGenerator {
+    /// `password`, with `--profile` settings if given.
+    Password,
+    /// `passphrase` with its default settings.
+    Passphrase,
+    /// 32 random bytes as hex.
+    TokenHex,
+    /// 32 random bytes as URL-safe base64.
+    TokenB64,
+    /// A new ed25519 keypair.
+    SshKey,
+}
+
+#[derive(Debug, Args)]
+pub struct VaultRotateArgs {
+    #[command(flatten)]
+    pub path: VaultPathOverrideArgs,
+
+    #[command(flatten)]
+    pub keyfile: VaultKeyfileArgs,
+
+    #[arg(value_name = "ITEM", help = "Item: id, id prefix, name or path/name.")]
+    pub item: String,
+
+    #[arg(
+        long,
+        value_name = "KIND",
+        help = "Generator to use [default: password for logins, token-b64 for api tokens, ssh-key for ssh keys]."
+    )]
+    pub generate: Option<SecretGenerator>,
+
+    #[arg(
+        long,
+        value_name = "NAME",
+        help = "Saved password profile to generate with (see `profile`)."
+    )]
+    pub profile: Option<String>,
+
+    #[arg(long, help = "Print the new secret to STDOUT.")]
+    pub reveal: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct VaultEditArgs {
+    #[command(flatten)]
+    pub path: VaultPathOverrideArgs,
+
+    #[command(flatten)]
+    pub keyfile: VaultKeyfileArgs,
+
+    #[arg(value_name = "ITEM", help = "Item: id, id prefix, name or path/name.")]
+    pub item: String,
+
+    #[arg(long = "type", value_name = "TYPE", help = "Update item type.")]
+    pub item_type: Option<VaultItemType>,
+
+    #[arg(long, value_name = "NAME", help = "Update item name.")]
+    pub name: Option<String>,
+
+    #[arg(long, value_name = "PATH", help = "Update item folder/path.")]
+    pub item_path: Option<String>,
+    #[arg(long, help = "Clear folder/path.")]
+    pub clear_path: bool,
+
+    #[arg(long = "tag", value_name = "TAG", action = clap::ArgAction::Append, help = "Replace tags (repeatable).")]
+    pub tags: Vec<String>,
+    #[arg(long, help = "Clear tags.")]
+    pub clear_tags: bool,
+
+    #[arg(long, value_name = "USERNAME", help = "Update username.")]
+    pub username: Option<String>,
+    #[arg(long, help = "Clear username.")]
+    pub clear_username: bool,
+
+    #[arg(
+        long,
+        value_name = "SECRET",
+        allow_hyphen_values = true,
+        help = "Update secret (avoid shell history)."
+    )]
+    pub secret: Option<String>,
+
+    #[arg(long = "url", value_name = "URL", action = clap::ArgAction::Append, help = "Replace URLs (repeatable).")]
+    pub urls: Vec<String>,
+    #[arg(long, help = "Clear URLs.")]
+    pub clear_urls: bool,
+
+    #[arg(long, value_name = "NOTES", help = "Update notes.")]
+    pub notes: Option<String>,
+    #[arg(long, help = "Clear notes.")]
+    pub clear_notes: bool,
+
+    #[arg(
+        long = "field",
+        value_name = "KEY=VALUE",
+        value_parser = parse_field,
+        allow_hyphen_values = true,
+        action = clap::ArgAction::Append,
+        help = "Add a custom field or update its value (repeatable)."
+    )]
+    pub fields: Vec<(String, String)>,
+    #[arg(
+        long = "secret-field",
+        value_name = "KEY",
+        action = clap::ArgAction::Append,
+        help = "Mark a custom field concealed (repeatable)."
+    )]
+    pub secret_fields: Vec<String>,
+    #[arg(
+        long = "remove-field",
+        value_name = "KEY",
+        action = clap::ArgAction::Append,
+        help = "Remove a custom field (repeatable)."
+    )]
+    pub remove_fields: Vec<String>,
+
+    #[arg(
+        long,
+        value_name = "YYYY-MM-DD",
+        value_parser = parse_date,
+        help = "Update the date (UTC) the item expires."
+    )]
+    pub expires_at: Option<u64>,
+    #[arg(long, help = "Clear the expiry date.")]
+    pub clear_expires_at: bool,
+
+    #[arg(
+        long,
+        value_name = "DURATION",
+        value_parser = parse_duration,
+        help = "Update how often the secret should be rotated, e.g. 90d."
+    )]
+    pub rotate_every: Option<Duration>,
+    #[arg(long, help = "Stop tracking rotation.")]
+    pub clear_rotate_every: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct VaultRmArgs {
+    #[command(flatten)]
+    pub path: VaultPathOverrideArgs,
+
+    #[command(flatten)]
+    pub keyfile: VaultKeyfileArgs,
+
+    #[arg(value_name = "ITEM", help = "Item: id, id prefix, name or path/name.")]
+    pub item: String,
+}
+
+#[derive(Debug, Args)]
+pub struct VaultHistoryArgs {
+    #[command(flatten)]
+    pub path: VaultPathOverrideArgs,
+
+    #[command(flatten)]
+    pub keyfile: VaultKeyfileArgs,
+
+    #[arg(value_name = "ITEM", help = "Item: id, id prefix, name or path/name.")]
+    pub item: String,
+}
+
+#[derive(Debug, Args)]
+pub struct VaultRestoreArgs {
+    #[command(flatten)]
+    pub path: VaultPathOverrideArgs,
+
+    #[command(flatten)]
+    pub keyfile: VaultKeyfileArgs,
+
+    #[arg(value_name = "ITEM", help = "Item: id, id prefix, name or path/name.")]
+    pub item: String,
+
+    #[arg(
+        long,
+        value_name = "N",
+        help = "Revision number to restore (see `vault history`)."
+    )]
+    pub rev: u32,
+}
+
+#[derive(Debug, Args)]
+pub struct VaultListArgs {
+    #[command(flatten)]
+    pub path: VaultPathOverrideArgs,
+
+    #[command(flatten)]
+    pub keyfile: VaultKeyfileArgs,
+
+    #[arg(
+        value_name = "QUERY",
+        allow_hyphen_values = true,
+        help = "Only list items matching this query (syntax as for `vault search`)."
+    )]
+    pub query: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct VaultSearchArgs {
+    #[command(flatten)]
+    pub path: VaultPathOverrideArgs,
+
+    #[command(flatten)]
+    pub keyfile: VaultKeyfileArgs,
+
+    #[arg(
+        value_name = "QUERY",
+        allow_hyphen_values = true,
+        help = "Search query, e.g. 'type:api-token tag:prod -tag:deprecated updated<30d'."
+    )]
+    pub query: String,
+}
+
+#[derive(Debug, Args)]
+pub struct VaultPasswdArgs {
+    #[command(flatten)]
+    pub path: VaultPathOverrideArgs,
+
+    #[command(flatten)]
+    pub keyfile: VaultKeyfileArgs,
+
+    #[arg(
+        long,
+        help = "Also generate a fresh data key and re-encrypt the payload."
+    )]
+    pub rotate_dek: bool,
+
+    #[arg(
+        long,
+        requires = "rotate_dek",
+        help = "Discard other key slots (they cannot unwrap a new data key)."
+    )]
+    pub drop_other_slots: bool,
+
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Require this keyfile for the slot from now on."
+    )]
+    pub new_keyfile: Option<PathBuf>,
+
+    #[arg(
+        long,
+        conflicts_with = "new_keyfile",
+        help = "Stop requiring a keyfile for the slot."
+    )]
+    pub remove_keyfile: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct VaultRekeyArgs {
+    #[command(flatten)]
+    pub path: VaultPathOverrideArgs,
+
+    #[command(flatten)]
+    pub keyfile: VaultKeyfileArgs,
+
+    #[arg(long, help = "Also prompt for a new master password.")]
+    pub change_password: bool,
+
+    #[arg(
+        long,
+        help = "Discard other key slots (they cannot unwrap a new data key)."
+    )]
+    pub drop_other_slots: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct VaultRecoverArgs {
+    #[command(flatten)]
+    pub path: VaultPathOverrideArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct VaultKdfArgs {
+    #[command(subcommand)]
+    pub command: VaultKdfCommands,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum VaultKdfCommands {
+    #[command(about = "Time Argon2id on this machine and recommend parameters.")]
+    Bench(VaultKdfBenchArgs),
+    #[command(about = "Re-wrap the unlocking key slot with new Argon2id parameters.")]
+    Set(VaultKdfSetArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct VaultKdfBenchArgs {
+    #[arg(
+        long,
+        value_name = "MS",
+        default_value_t = 500,
+        value_parser = clap::value_parser!(u64).range(1..),
+        help = "Target unlock time in milliseconds."
+    )]
+    pub target_ms: u64,
+
+    #[arg(
+        long,
+        value_name = "MIB",
+        default_value_t = 256,
+        value_parser = clap::value_parser!(u32).range(8..=4_194_303),
+        help = "Memory ceiling in MiB (never recommend more than this)."
+    )]
+    pub max_memory_mib: u32,
+
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = 1,
+        value_parser = clap::value_parser!(u32).range(1..=255),
+        help = "Argon2id lanes."
+    )]
+    pub parallelism: u32,
+}
+
+#[derive(Debug, Args)]
+pub struct VaultKdfSetArgs {
+    #[command(flatten)]
+    pub path: VaultPathOverrideArgs,
+
+    #[command(flatten)]
+    pub keyfile: VaultKeyfileArgs,
+
+    #[arg(
+        long,
+        value_name = "MIB",
+        value_parser = clap::value_parser!(u32).range(1..=4_194_303),
+        help = "Memory cost in MiB (defaults to the slot's current value)."
+    )]
+    pub memory_mib: Option<u32>,
+
+    #[arg(
+        long,
+        value_name = "N",
+        help = "Iterations (defaults to the slot's current value)."
+    )]
+    pub iterations: Option<u32>,
+
+    #[arg(
+        long,
+        value_name = "N",
+        help = "Argon2id lanes (defaults to the slot's current value)."
+    )]
+    pub parallelism: Option<u32>,
 }
 
+/// Narrow a command to some items; all criteria must match.
 #[derive(Debug, Args, Clone, Default)]
-pub struct VaultPathOverrideArgs {
+pub struct VaultItemFilterArgs {
     #[arg(
         long,
         value_name = "PATH",
-        help = "Override the vault path (defaults to ~/Library/Application Support/passworder/vault.pwder)."
+        help = "Only items at this path or below it."
     )]
-    pub path: Option<PathBuf>,
+    pub item_path: Option<String>,
+
+    #[arg(long = "tag", value_name = "TAG", action = clap::ArgAction::Append, help = "Only items with this tag (repeatable; all must match).")]
+    pub tags: Vec<String>,
+
+    #[arg(long = "type", value_name = "TYPE", help = "Only items of this type.")]
+    pub item_type: Option<VaultItemType>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
-pub enum EnvFormat {
-    Bash,
-    Json,
+pub enum VaultExportFormat {
+    /// Passphrase-encrypted bundle for `vault import`.
+    Bundle,
+    /// Plaintext CSV, secrets included.
+    Csv,
+    /// Plaintext `pass`-style tree with one file per item at `path/name`.
+    PassDir,
 }
 
 #[derive(Debug, Args)]
-pub struct EnvArgs {
+pub struct VaultExportArgs {
+    #[command(flatten)]
+    pub path: VaultPathOverrideArgs,
+
+    #[command(flatten)]
+    pub keyfile: VaultKeyfileArgs,
+
     #[arg(
         long,
-        value_name = "NAME",
-        help = "Profile name (matches vault item `path`)."
+        value_name = "PATH",
+        help = "File to write, or directory for pass-dir (files are created with 0600 permissions)."
     )]
-    pub profile: String,
+    pub out: PathBuf,
 
-    #[arg(long, value_name = "FORMAT", value_enum, help = "Output format.")]
-    pub format: EnvFormat,
+    #[arg(long, value_enum, default_value = "bundle")]
+    pub format: VaultExportFormat,
 
     #[arg(
         long = "unsafe",
@@ -138,201 +1035,349 @@ pub struct EnvArgs {
     pub unsafe_mode: bool,
 
     #[command(flatten)]
-    pub vault: VaultPathOverrideArgs,
+    pub filter: VaultItemFilterArgs,
+
+    #[arg(
+        long,
+        help = "Include item revision history in a bundle (it may contain old secrets)."
+    )]
+    pub with_history: bool,
+
+    #[command(flatten)]
+    pub kdf: VaultNewKdfArgs,
 }
 
 #[derive(Debug, Args)]
-pub struct RunArgs {
+pub struct VaultImportArgs {
+    #[command(flatten)]
+    pub path: VaultPathOverrideArgs,
+
+    #[command(flatten)]
+    pub keyfile: VaultKeyfileArgs,
+
     #[arg(
-        long,
-        value_name = "NAME",
-        help = "Profile name (matches vault item `path`)."
+        value_name = "FILE",
+        help = "Bundle file written by `vault export`, or an export in the --from format."
     )]
-    pub profile: String,
+    pub file: PathBuf,
 
     #[arg(
-        long = "unsafe",
-        help = "Allow running in CI / non-interactive contexts where env may be logged."
+        long,
+        value_enum,
+        value_name = "FORMAT",
+        help = "Read another password manager's export instead of a bundle."
     )]
-    pub unsafe_mode: bool,
+    pub from: Option<ForeignFormat>,
 
-    #[command(flatten)]
-    pub vault: VaultPathOverrideArgs,
+    #[arg(
+        long,
+        help = "Show what would be imported and which fields cannot be mapped, without writing."
+    )]
+    pub dry_run: bool,
 
     #[arg(
-        value_name = "CMD",
-        required = true,
-        num_args = 1..,
-        last = true,
-        help = "Command to run (must be after `--`)."
+        long,
+        value_enum,
+        value_name = "POLICY",
+        default_value = "skip",
+        help = "What to do with items whose id already exists."
     )]
-    pub cmd: Vec<String>,
+    pub on_conflict: ConflictPolicy,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum MergePreference {
+    /// Keep this vault's value.
+    Ours,
+    /// Take the other copy's value.
+    Theirs,
+    /// Take the value from whichever copy of the item was updated last.
+    Newer,
 }
 
 #[derive(Debug, Args)]
-pub struct InjectArgs {
+pub struct VaultMergeArgs {
+    #[command(flatten)]
+    pub path: VaultPathOverrideArgs,
+
+    #[command(flatten)]
+    pub keyfile: VaultKeyfileArgs,
+
+    #[arg(
+        value_name = "OTHER",
+        help = "Diverged copy of the vault to merge in (opened with the same credentials)."
+    )]
+    pub other: PathBuf,
+
     #[arg(
         long,
-        value_name = "NAME",
-        help = "Profile name (matches vault item `path`)."
+        value_name = "BASE",
+        help = "Common ancestor of both copies, e.g. a backup taken before they diverged."
     )]
-    pub profile: String,
+    pub base: Option<PathBuf>,
 
-    #[arg(long = "in", value_name = "FILE", help = "Template input file.")]
-    pub input: PathBuf,
+    #[command(flatten)]
+    pub resolution: MergeResolutionArgs,
 
-    #[arg(long = "out", value_name = "FILE", help = "Output file path.")]
-    pub output: PathBuf,
+    #[arg(long, help = "Report what would change without writing.")]
+    pub dry_run: bool,
+}
 
+#[derive(Debug, Args, Clone, Default)]
+pub struct MergeResolutionArgs {
     #[arg(
-        long = "unsafe",
-        help = "Required to print secrets or write them to disk; acknowledge CI/logging risks."
+        long,
+        value_enum,
+        value_name = "SIDE",
+        conflicts_with = "interactive",
+        help = "Resolve every conflict this way instead of failing."
     )]
-    pub unsafe_mode: bool,
-
-    #[arg(long, help = "Overwrite output file if it already exists.")]
-    pub force: bool,
+    pub prefer: Option<MergePreference>,
 
-    #[command(flatten)]
-    pub vault: VaultPathOverrideArgs,
+    #[arg(long, help = "Ask how to resolve each conflict.")]
+    pub interactive: bool,
 }
 
 #[derive(Debug, Args)]
-pub struct VaultInitArgs {
+pub struct VaultSyncArgs {
+    #[command(subcommand)]
+    pub command: Option<VaultSyncCommands>,
+
     #[command(flatten)]
     pub path: VaultPathOverrideArgs,
+
+    #[command(flatten)]
+    pub keyfile: VaultKeyfileArgs,
+
+    #[command(flatten)]
+    pub resolution: MergeResolutionArgs,
+
+    #[arg(long, help = "Commit and pull, but do not push to the remote.")]
+    pub no_push: bool,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum VaultSyncCommands {
+    #[command(
+        about = "Track the vault in a git repository in its directory (no password required)."
+    )]
+    Init(VaultSyncInitArgs),
 }
 
 #[derive(Debug, Args)]
-pub struct VaultStatusArgs {
+pub struct VaultSyncInitArgs {
     #[command(flatten)]
     pub path: VaultPathOverrideArgs,
+
+    #[arg(
+        long,
+        value_name = "URL",
+        help = "Git remote to sync with, e.g. file:///mnt/share/vault.git; an existing remote vault is checked out if there is no local one."
+    )]
+    pub remote: Option<String>,
 }
 
 #[derive(Debug, Args)]
-pub struct VaultPathArgs {
+pub struct VaultUpgradeArgs {
     #[command(flatten)]
     pub path: VaultPathOverrideArgs,
+
+    #[command(flatten)]
+    pub keyfile: VaultKeyfileArgs,
 }
 
 #[derive(Debug, Args)]
-pub struct VaultAddArgs {
+pub struct VaultVerifyArgs {
     #[command(flatten)]
     pub path: VaultPathOverrideArgs,
 
-    #[arg(long = "type", value_name = "TYPE", help = "Item type.")]
-    pub item_type: VaultItemType,
+    #[command(flatten)]
+    pub keyfile: VaultKeyfileArgs,
 
-    #[arg(long, value_name = "NAME", help = "Item display name.")]
-    pub name: String,
+    #[arg(
+        long,
+        help = "Only check the header structure (no password; the payload is not decrypted)."
+    )]
+    pub no_unlock: bool,
+}
 
-    #[arg(long, value_name = "PATH", help = "Optional folder/path for grouping.")]
-    pub item_path: Option<String>,
+#[derive(Debug, Args)]
+pub struct VaultBackupArgs {
+    #[command(subcommand)]
+    pub command: VaultBackupCommands,
+}
 
-    #[arg(long = "tag", value_name = "TAG", action = clap::ArgAction::Append, help = "Tag (repeatable).")]
-    pub tags: Vec<String>,
+#[derive(Debug, Subcommand)]
+pub enum VaultBackupCommands {
+    #[command(about = "List vault backups, newest first (no password required).")]
+    List(VaultBackupListArgs),
+    #[command(about = "Replace the vault with a backup (the current vault is backed up first).")]
+    Restore(VaultBackupRestoreArgs),
+}
 
-    #[arg(long, value_name = "USERNAME", help = "Optional username.")]
-    pub username: Option<String>,
+#[derive(Debug, Args)]
+pub struct VaultBackupListArgs {
+    #[command(flatten)]
+    pub path: VaultPathOverrideArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct VaultBackupRestoreArgs {
+    #[command(flatten)]
+    pub path: VaultPathOverrideArgs,
 
     #[arg(
-        long,
-        value_name = "SECRET",
-        help = "Secret value (avoid using this in shell history when possible)."
+        value_name = "NAME",
+        help = "Backup file name (see `vault backup list`)."
     )]
-    pub secret: Option<String>,
+    pub name: String,
+}
 
-    #[arg(long = "url", value_name = "URL", action = clap::ArgAction::Append, help = "Associated URL (repeatable).")]
-    pub urls: Vec<String>,
+#[derive(Debug, Args)]
+pub struct VaultTrashArgs {
+    #[command(subcommand)]
+    pub command: VaultTrashCommands,
+}
 
-    #[arg(long, value_name = "NOTES", help = "Optional notes.")]
-    pub notes: Option<String>,
+#[derive(Debug, Subcommand)]
+pub enum VaultTrashCommands {
+    #[command(about = "List trashed items (no secrets).")]
+    List(VaultTrashListArgs),
+    #[command(about = "Move a trashed item back into the vault.")]
+    Restore(VaultTrashRestoreArgs),
+    #[command(about = "Permanently delete trashed items.")]
+    Empty(VaultTrashEmptyArgs),
 }
 
 #[derive(Debug, Args)]
-pub struct VaultGetArgs {
+pub struct VaultTrashListArgs {
+    #[command(flatten)]
+    pub path: VaultPathOverrideArgs,
+
+    #[command(flatten)]
+    pub keyfile: VaultKeyfileArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct VaultTrashRestoreArgs {
     #[command(flatten)]
     pub path: VaultPathOverrideArgs,
 
+    #[command(flatten)]
+    pub keyfile: VaultKeyfileArgs,
+
     #[arg(value_name = "ID", help = "Item id (UUID).")]
     pub id: Uuid,
+}
+
+#[derive(Debug, Args)]
+pub struct VaultTrashEmptyArgs {
+    #[command(flatten)]
+    pub path: VaultPathOverrideArgs,
+
+    #[command(flatten)]
+    pub keyfile: VaultKeyfileArgs,
 
     #[arg(
         long,
-        help = "Reveal the secret to STDOUT (unsafe unless you know where output goes)."
+        value_name = "DURATION",
+        value_parser = parse_duration,
+        help = "Only delete items trashed at least this long ago (e.g. 30d)."
     )]
-    pub reveal: bool,
+    pub older_than: Option<Duration>,
 }
 
 #[derive(Debug, Args)]
-pub struct VaultEditArgs {
+pub struct VaultSlotArgs {
+    #[command(subcommand)]
+    pub command: VaultSlotCommands,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum VaultSlotCommands {
+    #[command(about = "Add a key slot with its own password.")]
+    Add(VaultSlotAddArgs),
+    #[command(about = "List key slots (no password required).")]
+    List(VaultSlotListArgs),
+    #[command(about = "Remove a key slot by id.")]
+    Rm(VaultSlotRmArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct VaultSlotAddArgs {
     #[command(flatten)]
     pub path: VaultPathOverrideArgs,
 
-    #[arg(value_name = "ID", help = "Item id (UUID).")]
-    pub id: Uuid,
+    #[command(flatten)]
+    pub keyfile: VaultKeyfileArgs,
 
-    #[arg(long = "type", value_name = "TYPE", help = "Update item type.")]
-    pub item_type: Option<VaultItemType>,
+    #[arg(
+        long,
+        value_name = "LABEL",
+        help = "Slot label (defaults to slot-<id>)."
+    )]
+    pub label: Option<String>,
 
-    #[arg(long, value_name = "NAME", help = "Update item name.")]
-    pub name: Option<String>,
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Require this keyfile (in addition to the slot password) for the new slot."
+    )]
+    pub slot_keyfile: Option<PathBuf>,
 
-    #[arg(long, value_name = "PATH", help = "Update item folder/path.")]
-    pub item_path: Option<String>,
-    #[arg(long, help = "Clear folder/path.")]
-    pub clear_path: bool,
+    #[command(flatten)]
+    pub kdf: VaultNewKdfArgs,
+}
 
-    #[arg(long = "tag", value_name = "TAG", action = clap::ArgAction::Append, help = "Replace tags (repeatable).")]
-    pub tags: Vec<String>,
-    #[arg(long, help = "Clear tags.")]
-    pub clear_tags: bool,
+/// Argon2id params for keys a command creates; unset values keep the
+/// command's defaults.
+#[derive(Debug, Args, Clone, Default)]
+pub struct VaultNewKdfArgs {
+    #[arg(
+        long,
+        value_name = "MIB",
+        value_parser = clap::value_parser!(u32).range(1..=4_194_303),
+        help = "Argon2id memory cost in MiB."
+    )]
+    pub kdf_memory_mib: Option<u32>,
 
-    #[arg(long, value_name = "USERNAME", help = "Update username.")]
-    pub username: Option<String>,
-    #[arg(long, help = "Clear username.")]
-    pub clear_username: bool,
+    #[arg(long, value_name = "N", help = "Argon2id iterations.")]
+    pub kdf_iterations: Option<u32>,
 
     #[arg(
         long,
-        value_name = "SECRET",
-        help = "Update secret (avoid shell history)."
+        value_name = "N",
+        value_parser = clap::value_parser!(u32).range(1..=255),
+        help = "Argon2id lanes."
     )]
-    pub secret: Option<String>,
-
-    #[arg(long = "url", value_name = "URL", action = clap::ArgAction::Append, help = "Replace URLs (repeatable).")]
-    pub urls: Vec<String>,
-    #[arg(long, help = "Clear URLs.")]
-    pub clear_urls: bool,
+    pub kdf_parallelism: Option<u32>,
 
-    #[arg(long, value_name = "NOTES", help = "Update notes.")]
-    pub notes: Option<String>,
-    #[arg(long, help = "Clear notes.")]
-    pub clear_notes: bool,
+    #[arg(
+        long,
+        value_name = "MS",
+        value_parser = clap::value_parser!(u64).range(1..),
+        conflicts_with_all = ["kdf_memory_mib", "kdf_iterations"],
+        help = "Calibrate Argon2id to take about MS milliseconds here (see `vault kdf bench`)."
+    )]
+    pub kdf_target_ms: Option<u64>,
 }
 
 #[derive(Debug, Args)]
-pub struct VaultRmArgs {
+pub struct VaultSlotListArgs {
     #[command(flatten)]
     pub path: VaultPathOverrideArgs,
-
-    #[arg(value_name = "ID", help = "Item id (UUID).")]
-    pub id: Uuid,
 }
 
 #[derive(Debug, Args)]
-pub struct VaultListArgs {
+pub struct VaultSlotRmArgs {
     #[command(flatten)]
     pub path: VaultPathOverrideArgs,
-}
 
-#[derive(Debug, Args)]
-pub struct VaultSearchArgs {
     #[command(flatten)]
-    pub path: VaultPathOverrideArgs,
+    pub keyfile: VaultKeyfileArgs,
 
-    #[arg(value_name = "QUERY", help = "Search query (case-insensitive).")]
-    pub query: String,
+    #[arg(value_name = "ID", help = "Key slot id.")]
+    pub id: u8,
 }
 
 #[derive(Debug, Args)]
@@ -525,6 +1570,18 @@ impl PasswordOptionsArgs {
     }
 }
 
+/// Parse a custom field given as `KEY=VALUE`; the value may contain `=`.
+pub fn parse_field(value: &str) -> Result<(String, String), String> {
+    let (key, value) = value
+        .split_once('=')
+        .ok_or_else(|| format!("invalid field '{value}' (expected KEY=VALUE)"))?;
+    let key = key.trim();
+    if key.is_empty() {
+        return Err("field name must not be empty".to_string());
+    }
+    Ok((key.to_string(), value.to_string()))
+}
+
 fn apply_bool_option(choice: Option<bool>, negated: bool, value: &mut bool) {
     if let Some(explicit) = choice {
         *value = explicit;
@@ -0,0 +1,211 @@
+use crate::agent::{self, AgentClient, AgentError};
+use crate::app::AppContext;
+use crate::{cli, exit_codes, output};
+use serde_json::json;
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::process::{ExitCode, Stdio};
+use std::time::{Duration, Instant};
+
+/// How long to wait for a detached agent to start listening.
+const START_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub fn run(args: cli::AgentArgs, ctx: &AppContext) -> ExitCode {
+    match args.command {
+        None => start(args.start, ctx),
+        Some(cli::AgentCommands::Status(args)) => {
+            let Some(client) = client_for(args) else {
+                return ExitCode::from(exit_codes::EXIT_USAGE);
+            };
+
+            match client.status() {
+                Ok(status) => {
+                    let expires = match status.expires_in {
+                        Some(left) => format!("keys expire in {}s", left.as_secs()),
+                        None => "no keys cached".to_string(),
+                    };
+                    output::print_value(
+                        format!(
+                            "running (pid {}), {} vault(s) unlocked, {expires}",
+                            status.pid,
+                            status.vaults.len()
+                        ),
+                        json!({
+                            "kind": "agent-status",
+                            "socket": client.socket().display().to_string(),
+                            "pid": status.pid,
+                            "vaults": status.vaults,
+                            "idle_timeout_secs": status.idle_timeout.as_secs(),
+                            "expires_in_secs": status.expires_in.map(|d| d.as_secs()),
+                        }),
+                        &ctx.output_mode,
+                        false,
+                    )
+                }
+                Err(error) => {
+                    eprintln!("Error: {error}");
+                    exit_codes::exit_code_for_agent_error(&error)
+                }
+            }
+        }
+        Some(cli::AgentCommands::Lock(args)) => {
+            let Some(client) = client_for(args) else {
+                return ExitCode::from(exit_codes::EXIT_USAGE);
+            };
+
+            match client.lock() {
+                Ok(()) => output::print_value(
+                    "Agent locked".to_string(),
+                    json!({
+                        "kind": "agent-lock",
+                        "socket": client.socket().display().to_string(),
+                    }),
+                    &ctx.output_mode,
+                    false,
+                ),
+                Err(error) => {
+                    eprintln!("Error: {error}");
+                    exit_codes::exit_code_for_agent_error(&error)
+                }
+            }
+        }
+        Some(cli::AgentCommands::Stop(args)) => {
+            let Some(client) = client_for(args) else {
+                return ExitCode::from(exit_codes::EXIT_USAGE);
+            };
+
+            match client.stop() {
+                Ok(()) => output::print_value(
+                    "Agent stopped".to_string(),
+                    json!({
+                        "kind": "agent-stop",
+                        "socket": client.socket().display().to_string(),
+                    }),
+                    &ctx.output_mode,
+                    false,
+                ),
+                Err(error) => {
+                    eprintln!("Error: {error}");
+                    exit_codes::exit_code_for_agent_error(&error)
+                }
+            }
+        }
+    }
+}
+
+fn client_for(args: cli::AgentSocketArgs) -> Option<AgentClient> {
+    match args.socket.or_else(agent::socket_from_env) {
+        Some(socket) => Some(AgentClient::new(socket)),
+        None => {
+            eprintln!(
+                "Error: no agent socket (pass --socket or set {})",
+                agent::AGENT_SOCK_ENV
+            );
+            None
+        }
+    }
+}
+
+fn start(args: cli::AgentStartArgs, ctx: &AppContext) -> ExitCode {
+    let socket = match args.socket.map_or_else(agent::default_socket_path, Ok) {
+        Ok(socket) => socket,
+        Err(error) => {
+            eprintln!("Error: {error}");
+            return exit_codes::exit_code_for_agent_error(&error);
+        }
+    };
+
+    if args.foreground {
+        let listener = match agent::bind(&socket) {
+            Ok(listener) => listener,
+            Err(error) => {
+                eprintln!("Error: {error}");
+                return exit_codes::exit_code_for_agent_error(&error);
+            }
+        };
+
+        print_started(&socket, std::process::id(), ctx);
+        let mut state = agent::AgentState::new(args.idle_timeout);
+        let result = agent::serve(listener, &mut state);
+        let _ = std::fs::remove_file(&socket);
+        return match result {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(error) => {
+                eprintln!("Error: {error}");
+                exit_codes::exit_code_for_agent_error(&error)
+            }
+        };
+    }
+
+    if AgentClient::new(&socket).status().is_ok() {
+        let error = AgentError::SocketInUse(socket.display().to_string());
+        eprintln!("Error: {error}");
+        return exit_codes::exit_code_for_agent_error(&error);
+    }
+
+    let exe = match std::env::current_exe() {
+        Ok(exe) => exe,
+        Err(error) => {
+            eprintln!("Error: failed to locate passworder executable: {error}");
+            return ExitCode::from(exit_codes::EXIT_IO);
+        }
+    };
+
+    // Re-exec in the foreground mode, detached from our stdio and process
+    // group so the agent outlives the shell command that started it.
+    let mut child = match std::process::Command::new(exe)
+        .arg("agent")
+        .arg("--foreground")
+        .arg("--socket")
+        .arg(&socket)
+        .arg("--idle-timeout")
+        .arg(format!("{}s", args.idle_timeout.as_secs()))
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .process_group(0)
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(error) => {
+            eprintln!("Error: failed to start agent: {error}");
+            return ExitCode::from(exit_codes::EXIT_IO);
+        }
+    };
+
+    let started = Instant::now();
+    loop {
+        if AgentClient::new(&socket).status().is_ok() {
+            print_started(&socket, child.id(), ctx);
+            return ExitCode::SUCCESS;
+        }
+        if let Ok(Some(status)) = child.try_wait() {
+            eprintln!("Error: agent exited during startup ({status})");
+            return ExitCode::from(exit_codes::EXIT_SOFTWARE);
+        }
+        if started.elapsed() > START_TIMEOUT {
+            let _ = child.kill();
+            eprintln!(
+                "Error: agent did not start listening on {}",
+                socket.display()
+            );
+            return ExitCode::from(exit_codes::EXIT_IO);
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    }
+}
+
+/// Print a shell snippet (ssh-agent style) that points later commands at the agent.
+fn print_started(socket: &Path, pid: u32, ctx: &AppContext) {
+    let socket = socket.display().to_string();
+    let _ = output::print_value(
+        format!("{env}={socket}; export {env};", env = agent::AGENT_SOCK_ENV),
+        json!({
+            "kind": "agent-start",
+            "socket": socket,
+            "pid": pid,
+        }),
+        &ctx.output_mode,
+        false,
+    );
+}
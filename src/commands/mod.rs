@@ -1,7 +1,11 @@
+#[cfg(unix)]
+mod agent;
 mod entropy;
 mod passphrase;
 mod password;
 mod profile;
+#[cfg(unix)]
+mod ssh_agent;
 mod token;
 mod tui;
 mod vault;
@@ -22,6 +26,10 @@ pub fn dispatch(command: cli::Commands, ctx: &AppContext) -> ExitCode {
         cli::Commands::Env(args) => workflows::env(args, ctx),
         cli::Commands::Run(args) => workflows::run(args, ctx),
         cli::Commands::Inject(args) => workflows::inject(args, ctx),
-        cli::Commands::Vault(args) => vault::run(args, ctx),
+        cli::Commands::Vault(args) => vault::run(*args, ctx),
+        #[cfg(unix)]
+        cli::Commands::Agent(args) => agent::run(args, ctx),
+        #[cfg(unix)]
+        cli::Commands::SshAgent(args) => ssh_agent::run(args, ctx),
     }
 }
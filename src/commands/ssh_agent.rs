@@ -0,0 +1,95 @@
+use crate::agent;
+use crate::app::AppContext;
+use crate::{cli, exit_codes, output, vault};
+use serde_json::json;
+use std::process::ExitCode;
+
+const SSH_AUTH_SOCK_ENV: &str = "SSH_AUTH_SOCK";
+
+/// Unlock the vault once, then serve its `ssh-key` items in the foreground.
+pub fn run(args: cli::SshAgentArgs, ctx: &AppContext) -> ExitCode {
+    let path = match vault::vault_path(args.path.path.as_deref()) {
+        Ok(path) => path,
+        Err(error) => {
+            eprintln!("Error: {error}");
+            return exit_codes::exit_code_for_vault_error(&error);
+        }
+    };
+
+    let mut key = match super::vault::unlock_key(&path, &args.keyfile) {
+        Ok(key) => key,
+        Err(code) => return code,
+    };
+
+    super::vault::pull_before_read(&path, &mut key);
+
+    let items = match vault::vault_list_items_v1(&path, &key) {
+        Ok(items) => items,
+        Err(error) => {
+            eprintln!("Error: {error}");
+            return exit_codes::exit_code_for_vault_error(&error);
+        }
+    };
+    drop(key);
+
+    let mut keys = Vec::new();
+    let mut served = Vec::new();
+    for item in items
+        .iter()
+        .filter(|i| i.item_type == vault::VaultItemType::SshKey)
+    {
+        match vault::SshKey::parse(&item.secret) {
+            Ok(key) => {
+                served.push(json!({
+                    "id": item.id.to_string(),
+                    "name": item.name,
+                    "fingerprint": key.fingerprint(),
+                }));
+                keys.push(key);
+            }
+            Err(error) => eprintln!("Warning: skipping item {}: {error}", item.id),
+        }
+    }
+    drop(items);
+    if keys.is_empty() {
+        eprintln!("Warning: the vault holds no ssh-key items; the agent will offer no keys.");
+    }
+
+    let socket = match args.socket.map_or_else(agent::default_ssh_socket_path, Ok) {
+        Ok(socket) => socket,
+        Err(error) => {
+            eprintln!("Error: {error}");
+            return exit_codes::exit_code_for_agent_error(&error);
+        }
+    };
+    let listener = match agent::bind(&socket) {
+        Ok(listener) => listener,
+        Err(error) => {
+            eprintln!("Error: {error}");
+            return exit_codes::exit_code_for_agent_error(&error);
+        }
+    };
+
+    let socket_display = socket.display().to_string();
+    let _ = output::print_value(
+        format!("{SSH_AUTH_SOCK_ENV}={socket_display}; export {SSH_AUTH_SOCK_ENV};"),
+        json!({
+            "kind": "ssh-agent-start",
+            "socket": socket_display,
+            "pid": std::process::id(),
+            "keys": served,
+        }),
+        &ctx.output_mode,
+        false,
+    );
+
+    let result = agent::serve_ssh(listener, agent::SshAgentState::new(keys));
+    let _ = std::fs::remove_file(&socket);
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("Error: {error}");
+            exit_codes::exit_code_for_agent_error(&error)
+        }
+    }
+}
@@ -1,10 +1,19 @@
+#[cfg(unix)]
+use crate::agent;
 use crate::app::AppContext;
-use crate::{cli, exit_codes, output, vault};
+use crate::{cli, config, dev_workflows, exit_codes, output, passphrase, password, token, vault};
 use output::vault_item::{
-    vault_item_json, vault_item_summary_json, vault_item_summary_text, vault_item_text,
+    attachment_json, due_relative_text, item_due_json, vault_item_history_text, vault_item_json,
+    vault_item_revision_json, vault_item_summary_json, vault_item_summary_text, vault_item_text,
 };
+use secrecy::{ExposeSecret, SecretString};
 use serde_json::json;
+use std::path::Path;
 use std::process::ExitCode;
+use std::time::Duration;
+
+/// Random bytes in a generated `token-hex` / `token-b64` secret.
+const GENERATED_TOKEN_BYTES: usize = 32;
 
 pub fn run(args: cli::VaultArgs, ctx: &AppContext) -> ExitCode {
     match args.command {
@@ -37,17 +46,45 @@ pub fn run(args: cli::VaultArgs, ctx: &AppContext) -> ExitCode {
             };
 
             match vault::vault_status_v1(&path) {
-                Ok((status, version)) => output::print_value(
-                    status.as_str().to_string(),
-                    json!({
-                        "kind": "vault-status",
-                        "path": path.display().to_string(),
-                        "status": status.as_str(),
-                        "version": version,
-                    }),
-                    &ctx.output_mode,
-                    ctx.copy_requested,
-                ),
+                Ok(info) => {
+                    let status = info.status.as_str();
+                    let keyfile_slots = info
+                        .key_slots
+                        .iter()
+                        .filter(|s| s.keyfile_required)
+                        .map(|s| s.label.as_str())
+                        .collect::<Vec<_>>();
+                    let value = if ctx.output_mode.json || ctx.output_mode.quiet {
+                        status.to_string()
+                    } else if info.keyfile_required {
+                        format!("{status} (keyfile required)")
+                    } else if !keyfile_slots.is_empty() {
+                        format!(
+                            "{status} (keyfile required for slot(s): {})",
+                            keyfile_slots.join(", ")
+                        )
+                    } else {
+                        status.to_string()
+                    };
+
+                    output::print_value(
+                        value,
+                        json!({
+                            "kind": "vault-status",
+                            "path": path.display().to_string(),
+                            "status": status,
+                            "version": info.version,
+                            "keyfile_required": info.keyfile_required,
+                            "key_slots": info.key_slots.iter().map(|s| json!({
+                                "id": s.id,
+                                "label": s.label,
+                                "keyfile_required": s.keyfile_required,
+                            })).collect::<Vec<_>>(),
+                        }),
+                        &ctx.output_mode,
+                        ctx.copy_requested,
+                    )
+                }
                 Err(error) => {
                     eprintln!("Error: {error}");
                     exit_codes::exit_code_for_vault_error(&error)
@@ -63,16 +100,19 @@ pub fn run(args: cli::VaultArgs, ctx: &AppContext) -> ExitCode {
                 }
             };
 
-            let master_password = match vault::prompt_new_master_password() {
-                Ok(pw) => pw,
-                Err(error) => {
-                    eprintln!("Error: {error}");
-                    return exit_codes::exit_code_for_vault_prompt_error(&error);
-                }
+            let kdf_params = match kdf_params_override(&args.kdf) {
+                Ok(kdf_params) => kdf_params,
+                Err(code) => return code,
             };
 
-            match vault::vault_init_v1(&path, &master_password) {
-                Ok(()) => output::print_value(
+            let credentials =
+                match prompt_credentials(&args.keyfile, vault::prompt_new_master_password) {
+                    Ok(credentials) => credentials,
+                    Err(code) => return code,
+                };
+
+            match vault::vault_init_v1(&path, &credentials, args.recovery_key, kdf_params) {
+                Ok(None) => output::print_value(
                     path.display().to_string(),
                     json!({
                         "kind": "vault-init",
@@ -81,6 +121,24 @@ pub fn run(args: cli::VaultArgs, ctx: &AppContext) -> ExitCode {
                     &ctx.output_mode,
                     ctx.copy_requested,
                 ),
+                Ok(Some(recovery_key)) => {
+                    if !ctx.output_mode.quiet {
+                        eprintln!(
+                            "Recovery key for {} (store it offline; it will not be shown again):",
+                            path.display()
+                        );
+                    }
+                    output::print_value(
+                        recovery_key.expose_secret().to_string(),
+                        json!({
+                            "kind": "vault-init",
+                            "path": path.display().to_string(),
+                            "recovery_key": true,
+                        }),
+                        &ctx.output_mode,
+                        ctx.copy_requested,
+                    )
+                }
                 Err(error) => {
                     eprintln!("Error: {error}");
                     exit_codes::exit_code_for_vault_error(&error)
@@ -96,17 +154,33 @@ pub fn run(args: cli::VaultArgs, ctx: &AppContext) -> ExitCode {
                 }
             };
 
-            let master_password = match vault::prompt_master_password() {
-                Ok(pw) => pw,
-                Err(error) => {
-                    eprintln!("Error: {error}");
-                    return exit_codes::exit_code_for_vault_prompt_error(&error);
+            let generated = match args.generate {
+                Some(explicit) => {
+                    let generator = match resolve_generator(
+                        args.item_type,
+                        explicit,
+                        args.profile.as_deref(),
+                    ) {
+                        Ok(generator) => generator,
+                        Err(code) => return code,
+                    };
+                    match generate_secret(generator, args.profile.as_deref(), &args.name, ctx) {
+                        Ok(secret) => Some((generator, zeroize::Zeroizing::new(secret))),
+                        Err(code) => return code,
+                    }
                 }
+                None => None,
+            };
+
+            let key = match unlock_key(&path, &args.keyfile) {
+                Ok(key) => key,
+                Err(code) => return code,
             };
 
-            let secret = match args.secret {
-                Some(s) => s,
-                None => match vault::prompt_secret("Secret: ") {
+            let secret = match (args.secret, &generated) {
+                (Some(s), _) => s,
+                (None, Some((_, secret))) => secret.to_string(),
+                (None, None) => match vault::prompt_secret("Secret: ") {
                     Ok(s) => s,
                     Err(error) => {
                         eprintln!("Error: {error}");
@@ -115,6 +189,24 @@ pub fn run(args: cli::VaultArgs, ctx: &AppContext) -> ExitCode {
                 },
             };
 
+            let mut fields = custom_fields(args.fields, &args.secret_fields);
+            for name in &args.secret_fields {
+                if fields.iter().any(|f| f.name == *name) {
+                    continue;
+                }
+                match vault::prompt_secret(&format!("{name}: ")) {
+                    Ok(value) => fields.push(vault::VaultItemField {
+                        name: name.clone(),
+                        value,
+                        concealed: true,
+                    }),
+                    Err(error) => {
+                        eprintln!("Error: {error}");
+                        return exit_codes::exit_code_for_vault_prompt_error(&error);
+                    }
+                }
+            }
+
             let input = vault::AddItemInput {
                 item_type: args.item_type,
                 name: args.name,
@@ -124,17 +216,41 @@ pub fn run(args: cli::VaultArgs, ctx: &AppContext) -> ExitCode {
                 secret,
                 urls: args.urls,
                 notes: args.notes,
+                fields,
+                expires_at: args.expires_at,
+                rotate_every: args.rotate_every.map(|d| d.as_secs()),
             };
 
-            match vault::vault_add_item_v1(&path, &master_password, input) {
+            match vault::vault_add_item_v1(&path, &key, input) {
                 Ok(id) => {
                     let value = id.to_string();
-                    let meta = json!({
+                    let mut meta = json!({
                         "kind": "vault-add",
                         "path": path.display().to_string(),
                         "id": value,
                     });
 
+                    if let Some((generator, secret)) = &generated {
+                        let copied = ctx.copy_requested;
+                        if copied && let Err(error) = output::copy_to_clipboard(secret) {
+                            eprintln!("Error: {error}");
+                            return ExitCode::from(exit_codes::EXIT_IO);
+                        }
+                        meta["generator"] = json!(generator_str(*generator));
+                        meta["profile"] = json!(args.profile);
+                        meta["revealed"] = json!(args.reveal);
+                        meta["copied"] = json!(copied);
+                        if args.reveal {
+                            meta["secret"] = json!(secret.as_str());
+                            let value = if ctx.output_mode.quiet {
+                                secret.to_string()
+                            } else {
+                                format!("Added {value}\n{}", secret.as_str())
+                            };
+                            return output::print_value(value, meta, &ctx.output_mode, false);
+                        }
+                    }
+
                     if ctx.output_mode.quiet {
                         output::print_value(value, meta, &ctx.output_mode, false)
                     } else {
@@ -156,15 +272,22 @@ pub fn run(args: cli::VaultArgs, ctx: &AppContext) -> ExitCode {
                 }
             };
 
-            let master_password = match vault::prompt_master_password() {
-                Ok(pw) => pw,
-                Err(error) => {
-                    eprintln!("Error: {error}");
-                    return exit_codes::exit_code_for_vault_prompt_error(&error);
-                }
+            let mut key = match unlock_key(&path, &args.keyfile) {
+                Ok(key) => key,
+                Err(code) => return code,
             };
 
-            match vault::vault_get_item_v1(&path, &master_password, args.id) {
+            pull_before_read(&path, &mut key);
+
+            if let Some(name) = &args.attachment {
+                let out = args
+                    .out
+                    .as_deref()
+                    .expect("clap requires --out with --attachment");
+                return write_attachment(&path, &key, &args.item, name, out, ctx);
+            }
+
+            match vault::vault_get_item_v1(&path, &key, &args.item) {
                 Ok(item) => {
                     let reveal = args.reveal;
                     let copied = ctx.copy_requested;
@@ -204,6 +327,12 @@ pub fn run(args: cli::VaultArgs, ctx: &AppContext) -> ExitCode {
                 }
             }
         }
+        cli::VaultCommands::Totp(args) => run_totp(args, ctx),
+        cli::VaultCommands::Rotate(args) => run_rotate(args, ctx),
+        cli::VaultCommands::Due(args) => run_due(args, ctx),
+        cli::VaultCommands::Attach(args) => run_attach(args, ctx),
+        cli::VaultCommands::Attachments(args) => run_attachments(args, ctx),
+        cli::VaultCommands::Detach(args) => run_detach(args, ctx),
         cli::VaultCommands::Edit(args) => {
             let path = match vault::vault_path(args.path.path.as_deref()) {
                 Ok(path) => path,
@@ -213,16 +342,13 @@ pub fn run(args: cli::VaultArgs, ctx: &AppContext) -> ExitCode {
                 }
             };
 
-            let master_password = match vault::prompt_master_password() {
-                Ok(pw) => pw,
-                Err(error) => {
-                    eprintln!("Error: {error}");
-                    return exit_codes::exit_code_for_vault_prompt_error(&error);
-                }
+            let key = match unlock_key(&path, &args.keyfile) {
+                Ok(key) => key,
+                Err(code) => return code,
             };
 
             let input = vault::EditItemInput {
-                id: args.id,
+                item: args.item,
                 item_type: args.item_type,
                 name: args.name,
                 path: args.item_path,
@@ -244,11 +370,18 @@ pub fn run(args: cli::VaultArgs, ctx: &AppContext) -> ExitCode {
                 clear_urls: args.clear_urls,
                 notes: args.notes,
                 clear_notes: args.clear_notes,
+                set_fields: custom_fields(args.fields, &args.secret_fields),
+                conceal_fields: args.secret_fields,
+                remove_fields: args.remove_fields,
+                expires_at: args.expires_at,
+                clear_expires_at: args.clear_expires_at,
+                rotate_every: args.rotate_every.map(|d| d.as_secs()),
+                clear_rotate_every: args.clear_rotate_every,
             };
 
-            match vault::vault_edit_item_v1(&path, &master_password, input) {
-                Ok(()) => {
-                    let value = args.id.to_string();
+            match vault::vault_edit_item_v1(&path, &key, input) {
+                Ok(id) => {
+                    let value = id.to_string();
                     let meta = json!({
                         "kind": "vault-edit",
                         "path": path.display().to_string(),
@@ -281,17 +414,14 @@ pub fn run(args: cli::VaultArgs, ctx: &AppContext) -> ExitCode {
                 }
             };
 
-            let master_password = match vault::prompt_master_password() {
-                Ok(pw) => pw,
-                Err(error) => {
-                    eprintln!("Error: {error}");
-                    return exit_codes::exit_code_for_vault_prompt_error(&error);
-                }
+            let key = match unlock_key(&path, &args.keyfile) {
+                Ok(key) => key,
+                Err(code) => return code,
             };
 
-            match vault::vault_remove_item_v1(&path, &master_password, args.id) {
-                Ok(()) => {
-                    let value = args.id.to_string();
+            match vault::vault_remove_item_v1(&path, &key, &args.item) {
+                Ok(id) => {
+                    let value = id.to_string();
                     let meta = json!({
                         "kind": "vault-rm",
                         "path": path.display().to_string(),
@@ -302,7 +432,7 @@ pub fn run(args: cli::VaultArgs, ctx: &AppContext) -> ExitCode {
                         output::print_value(value, meta, &ctx.output_mode, false)
                     } else {
                         output::print_value(
-                            format!("Removed {value}"),
+                            format!("Moved {value} to trash"),
                             meta,
                             &ctx.output_mode,
                             false,
@@ -315,7 +445,7 @@ pub fn run(args: cli::VaultArgs, ctx: &AppContext) -> ExitCode {
                 }
             }
         }
-        cli::VaultCommands::List(args) => {
+        cli::VaultCommands::History(args) => {
             let path = match vault::vault_path(args.path.path.as_deref()) {
                 Ok(path) => path,
                 Err(error) => {
@@ -324,15 +454,120 @@ pub fn run(args: cli::VaultArgs, ctx: &AppContext) -> ExitCode {
                 }
             };
 
-            let master_password = match vault::prompt_master_password() {
-                Ok(pw) => pw,
+            let key = match unlock_key(&path, &args.keyfile) {
+                Ok(key) => key,
+                Err(code) => return code,
+            };
+
+            match vault::vault_get_item_v1(&path, &key, &args.item) {
+                Ok(item) => {
+                    let revisions: Vec<_> = item
+                        .history
+                        .iter()
+                        .rev()
+                        .map(vault_item_revision_json)
+                        .collect();
+                    let meta = json!({
+                        "kind": "vault-history",
+                        "path": path.display().to_string(),
+                        "id": item.id.to_string(),
+                        "current_rev": item.revision(),
+                        "count": revisions.len(),
+                        "revisions": revisions,
+                    });
+
+                    output::print_value(
+                        vault_item_history_text(&item),
+                        meta,
+                        &ctx.output_mode,
+                        false,
+                    )
+                }
                 Err(error) => {
                     eprintln!("Error: {error}");
-                    return exit_codes::exit_code_for_vault_prompt_error(&error);
+                    exit_codes::exit_code_for_vault_error(&error)
+                }
+            }
+        }
+        cli::VaultCommands::Restore(args) => {
+            let path = match vault::vault_path(args.path.path.as_deref()) {
+                Ok(path) => path,
+                Err(error) => {
+                    eprintln!("Error: {error}");
+                    return exit_codes::exit_code_for_vault_error(&error);
+                }
+            };
+
+            let key = match unlock_key(&path, &args.keyfile) {
+                Ok(key) => key,
+                Err(code) => return code,
+            };
+
+            match vault::vault_restore_item_v1(&path, &key, &args.item, args.rev) {
+                Ok((id, new_rev, missing)) => {
+                    for name in &missing {
+                        eprintln!(
+                            "Warning: attachment {name} was detached after revision {} and could not be restored.",
+                            args.rev
+                        );
+                    }
+                    let value = id.to_string();
+                    let meta = json!({
+                        "kind": "vault-restore",
+                        "path": path.display().to_string(),
+                        "id": value,
+                        "restored_rev": args.rev,
+                        "rev": new_rev,
+                        "missing_attachments": missing,
+                    });
+
+                    if ctx.output_mode.quiet {
+                        output::print_value(value, meta, &ctx.output_mode, false)
+                    } else {
+                        output::print_value(
+                            format!(
+                                "Restored {value} to revision {} (now revision {new_rev})",
+                                args.rev
+                            ),
+                            meta,
+                            &ctx.output_mode,
+                            false,
+                        )
+                    }
+                }
+                Err(error) => {
+                    eprintln!("Error: {error}");
+                    exit_codes::exit_code_for_vault_error(&error)
+                }
+            }
+        }
+        cli::VaultCommands::List(args) => {
+            let query = args.query.as_deref().filter(|q| !q.trim().is_empty());
+            let parsed = match query.map(parse_query).transpose() {
+                Ok(parsed) => parsed,
+                Err(code) => return code,
+            };
+
+            let path = match vault::vault_path(args.path.path.as_deref()) {
+                Ok(path) => path,
+                Err(error) => {
+                    eprintln!("Error: {error}");
+                    return exit_codes::exit_code_for_vault_error(&error);
                 }
             };
 
-            match vault::vault_list_items_v1(&path, &master_password) {
+            let mut key = match unlock_key(&path, &args.keyfile) {
+                Ok(key) => key,
+                Err(code) => return code,
+            };
+
+            pull_before_read(&path, &mut key);
+
+            let listed = match &parsed {
+                Some(parsed) => vault::vault_search_items_v1(&path, &key, parsed),
+                None => vault::vault_list_item_metadata_v1(&path, &key),
+            };
+            match listed {
                 Ok(items) => {
                     let value = if ctx.output_mode.quiet {
                         items
@@ -351,6 +586,7 @@ pub fn run(args: cli::VaultArgs, ctx: &AppContext) -> ExitCode {
                     let meta = json!({
                         "kind": "vault-list",
                         "path": path.display().to_string(),
+                        "query": query,
                         "count": items.len(),
                         "items": items.iter().map(vault_item_summary_json).collect::<Vec<_>>(),
                     });
@@ -364,6 +600,12 @@ pub fn run(args: cli::VaultArgs, ctx: &AppContext) -> ExitCode {
             }
         }
         cli::VaultCommands::Search(args) => {
+            let query = Some(args.query.as_str()).filter(|q| !q.trim().is_empty());
+            let parsed = match query.map(parse_query).transpose() {
+                Ok(parsed) => parsed,
+                Err(code) => return code,
+            };
+
             let path = match vault::vault_path(args.path.path.as_deref()) {
                 Ok(path) => path,
                 Err(error) => {
@@ -372,15 +614,18 @@ pub fn run(args: cli::VaultArgs, ctx: &AppContext) -> ExitCode {
                 }
             };
 
-            let master_password = match vault::prompt_master_password() {
-                Ok(pw) => pw,
-                Err(error) => {
-                    eprintln!("Error: {error}");
-                    return exit_codes::exit_code_for_vault_prompt_error(&error);
-                }
+            let mut key = match unlock_key(&path, &args.keyfile) {
+                Ok(key) => key,
+                Err(code) => return code,
             };
 
-            match vault::vault_search_items_v1(&path, &master_password, &args.query) {
+            pull_before_read(&path, &mut key);
+
+            let found = match &parsed {
+                Some(parsed) => vault::vault_search_items_v1(&path, &key, parsed),
+                None => Ok(Vec::new()),
+            };
+            match found {
                 Ok(items) => {
                     let value = if ctx.output_mode.quiet {
                         items
@@ -412,5 +657,2069 @@ pub fn run(args: cli::VaultArgs, ctx: &AppContext) -> ExitCode {
                 }
             }
         }
+        cli::VaultCommands::Export(args) => run_export(args, ctx),
+        cli::VaultCommands::Import(args) => run_import(args, ctx),
+        cli::VaultCommands::Verify(args) => {
+            let path = match vault::vault_path(args.path.path.as_deref()) {
+                Ok(path) => path,
+                Err(error) => {
+                    eprintln!("Error: {error}");
+                    return exit_codes::exit_code_for_vault_error(&error);
+                }
+            };
+
+            let credentials = if args.no_unlock {
+                None
+            } else {
+                match prompt_credentials(&args.keyfile, vault::prompt_master_password) {
+                    Ok(credentials) => Some(credentials),
+                    Err(code) => return code,
+                }
+            };
+
+            let report = match vault::vault_verify_v1(&path, credentials.as_ref()) {
+                Ok(report) => report,
+                Err(error) => {
+                    eprintln!("Error: {error}");
+                    return exit_codes::exit_code_for_vault_error(&error);
+                }
+            };
+
+            let mut lines = report
+                .issues
+                .iter()
+                .map(|i| {
+                    format!(
+                        "{}\t{}\t{}\t{}",
+                        i.severity.as_str(),
+                        i.check,
+                        i.location,
+                        i.message
+                    )
+                })
+                .collect::<Vec<_>>();
+            lines.push(format!(
+                "{}: {} error(s), {} warning(s); unlock {}",
+                if report.is_ok() { "OK" } else { "FAILED" },
+                report.error_count(),
+                report.warning_count(),
+                report.unlock.as_str()
+            ));
+
+            let meta = json!({
+                "kind": "vault-verify",
+                "path": path.display().to_string(),
+                "ok": report.is_ok(),
+                "errors": report.error_count(),
+                "warnings": report.warning_count(),
+                "file_len": report.file_len,
+                "key_slots": report.key_slots,
+                "legacy_layout": report.legacy_layout,
+                "unlock": report.unlock.as_str(),
+                "unlocked_slot": report.unlock.slot_id(),
+                "payload_schema_version": report.payload_schema_version,
+                "items": report.items,
+                "issues": report.issues.iter().map(|i| json!({
+                    "severity": i.severity.as_str(),
+                    "check": i.check,
+                    "location": i.location,
+                    "message": i.message,
+                })).collect::<Vec<_>>(),
+            });
+
+            let printed = output::print_value(lines.join("\n"), meta, &ctx.output_mode, false);
+            match report.unlock {
+                vault::UnlockOutcome::WrongCredentials | vault::UnlockOutcome::KeyfileRequired => {
+                    ExitCode::from(exit_codes::EXIT_USAGE)
+                }
+                _ if !report.is_ok() => ExitCode::from(exit_codes::EXIT_SOFTWARE),
+                _ => printed,
+            }
+        }
+        cli::VaultCommands::Passwd(args) => {
+            let path = match vault::vault_path(args.path.path.as_deref()) {
+                Ok(path) => path,
+                Err(error) => {
+                    eprintln!("Error: {error}");
+                    return exit_codes::exit_code_for_vault_error(&error);
+                }
+            };
+
+            let credentials =
+                match prompt_credentials(&args.keyfile, vault::prompt_current_master_password) {
+                    Ok(credentials) => credentials,
+                    Err(code) => return code,
+                };
+
+            let new_master_password = match vault::prompt_replacement_master_password() {
+                Ok(pw) => pw,
+                Err(error) => {
+                    eprintln!("Error: {error}");
+                    return exit_codes::exit_code_for_vault_prompt_error(&error);
+                }
+            };
+
+            let keyfile = if args.remove_keyfile {
+                vault::KeyfileChange::Remove
+            } else if let Some(path) = args.new_keyfile.as_deref() {
+                match vault::read_keyfile(path) {
+                    Ok(digest) => vault::KeyfileChange::Set(digest),
+                    Err(error) => {
+                        eprintln!("Error: {error}");
+                        return exit_codes::exit_code_for_vault_error(&error);
+                    }
+                }
+            } else {
+                vault::KeyfileChange::Keep
+            };
+
+            let input = vault::RotateKeysInput {
+                new_master_password: Some(new_master_password),
+                keyfile,
+                kdf_params: vault::KdfParamsOverride::default(),
+                rotate_dek: args.rotate_dek,
+                drop_other_slots: args.drop_other_slots,
+            };
+
+            match vault::vault_rotate_keys_v1(&path, &credentials, input) {
+                Ok(_) => {
+                    if args.rotate_dek {
+                        forget_agent_key(&path);
+                    }
+                    output::print_value(
+                        "Master password changed".to_string(),
+                        json!({
+                            "kind": "vault-passwd",
+                            "path": path.display().to_string(),
+                            "dek_rotated": args.rotate_dek,
+                        }),
+                        &ctx.output_mode,
+                        false,
+                    )
+                }
+                Err(error) => {
+                    eprintln!("Error: {error}");
+                    exit_codes::exit_code_for_vault_error(&error)
+                }
+            }
+        }
+        cli::VaultCommands::Rekey(args) => {
+            let path = match vault::vault_path(args.path.path.as_deref()) {
+                Ok(path) => path,
+                Err(error) => {
+                    eprintln!("Error: {error}");
+                    return exit_codes::exit_code_for_vault_error(&error);
+                }
+            };
+
+            let credentials =
+                match prompt_credentials(&args.keyfile, vault::prompt_current_master_password) {
+                    Ok(credentials) => credentials,
+                    Err(code) => return code,
+                };
+
+            let new_master_password = if args.change_password {
+                match vault::prompt_replacement_master_password() {
+                    Ok(pw) => Some(pw),
+                    Err(error) => {
+                        eprintln!("Error: {error}");
+                        return exit_codes::exit_code_for_vault_prompt_error(&error);
+                    }
+                }
+            } else {
+                None
+            };
+
+            let input = vault::RotateKeysInput {
+                new_master_password,
+                keyfile: vault::KeyfileChange::Keep,
+                kdf_params: vault::KdfParamsOverride::default(),
+                rotate_dek: true,
+                drop_other_slots: args.drop_other_slots,
+            };
+
+            match vault::vault_rotate_keys_v1(&path, &credentials, input) {
+                Ok(_) => {
+                    forget_agent_key(&path);
+                    output::print_value(
+                        "Vault keys rotated".to_string(),
+                        json!({
+                            "kind": "vault-rekey",
+                            "path": path.display().to_string(),
+                            "password_changed": args.change_password,
+                        }),
+                        &ctx.output_mode,
+                        false,
+                    )
+                }
+                Err(error) => {
+                    eprintln!("Error: {error}");
+                    exit_codes::exit_code_for_vault_error(&error)
+                }
+            }
+        }
+        cli::VaultCommands::Recover(args) => {
+            let path = match vault::vault_path(args.path.path.as_deref()) {
+                Ok(path) => path,
+                Err(error) => {
+                    eprintln!("Error: {error}");
+                    return exit_codes::exit_code_for_vault_error(&error);
+                }
+            };
+
+            let recovery_key = match vault::prompt_recovery_key() {
+                Ok(key) => key,
+                Err(error) => {
+                    eprintln!("Error: {error}");
+                    return exit_codes::exit_code_for_vault_prompt_error(&error);
+                }
+            };
+
+            let new_master_password = match vault::prompt_replacement_master_password() {
+                Ok(pw) => pw,
+                Err(error) => {
+                    eprintln!("Error: {error}");
+                    return exit_codes::exit_code_for_vault_prompt_error(&error);
+                }
+            };
+
+            match vault::vault_recover_v1(&path, recovery_key.expose_secret(), new_master_password)
+            {
+                Ok(slot_id) => output::print_value(
+                    path.display().to_string(),
+                    json!({
+                        "kind": "vault-recover",
+                        "path": path.display().to_string(),
+                        "slot": slot_id,
+                    }),
+                    &ctx.output_mode,
+                    ctx.copy_requested,
+                ),
+                Err(error) => {
+                    eprintln!("Error: {error}");
+                    exit_codes::exit_code_for_vault_error(&error)
+                }
+            }
+        }
+        cli::VaultCommands::Backup(args) => run_backup(args, ctx),
+        cli::VaultCommands::Merge(args) => run_merge(args, ctx),
+        cli::VaultCommands::Sync(args) => run_sync(args, ctx),
+        cli::VaultCommands::Upgrade(args) => run_upgrade(args, ctx),
+        cli::VaultCommands::Trash(args) => run_trash(args, ctx),
+        cli::VaultCommands::Slot(args) => run_slot(args, ctx),
+        cli::VaultCommands::Kdf(args) => run_kdf(args, ctx),
+    }
+}
+
+fn run_backup(args: cli::VaultBackupArgs, ctx: &AppContext) -> ExitCode {
+    match args.command {
+        cli::VaultBackupCommands::List(args) => {
+            let path = match vault::vault_path(args.path.path.as_deref()) {
+                Ok(path) => path,
+                Err(error) => {
+                    eprintln!("Error: {error}");
+                    return exit_codes::exit_code_for_vault_error(&error);
+                }
+            };
+
+            match vault::vault_list_backups_v1(&path) {
+                Ok(backups) => {
+                    let value = if ctx.output_mode.quiet {
+                        backups
+                            .iter()
+                            .map(|b| b.name.clone())
+                            .collect::<Vec<_>>()
+                            .join("\n")
+                    } else {
+                        backups
+                            .iter()
+                            .map(|b| format!("{}\t{}\t{}", b.name, b.created_at, b.size))
+                            .collect::<Vec<_>>()
+                            .join("\n")
+                    };
+
+                    let meta = json!({
+                        "kind": "vault-backup-list",
+                        "path": path.display().to_string(),
+                        "count": backups.len(),
+                        "backups": backups
+                            .iter()
+                            .map(|b| json!({
+                                "name": b.name,
+                                "path": b.path.display().to_string(),
+                                "created_at": b.created_at,
+                                "size": b.size,
+                            }))
+                            .collect::<Vec<_>>(),
+                    });
+
+                    output::print_value(value, meta, &ctx.output_mode, false)
+                }
+                Err(error) => {
+                    eprintln!("Error: {error}");
+                    exit_codes::exit_code_for_vault_error(&error)
+                }
+            }
+        }
+        cli::VaultBackupCommands::Restore(args) => {
+            let path = match vault::vault_path(args.path.path.as_deref()) {
+                Ok(path) => path,
+                Err(error) => {
+                    eprintln!("Error: {error}");
+                    return exit_codes::exit_code_for_vault_error(&error);
+                }
+            };
+
+            match vault::vault_restore_backup_v1(&path, &args.name) {
+                Ok(backup) => {
+                    // The restored file may be wrapped under a different data key.
+                    forget_agent_key(&path);
+                    output::print_value(
+                        format!("Restored vault from {}", backup.name),
+                        json!({
+                            "kind": "vault-backup-restore",
+                            "path": path.display().to_string(),
+                            "backup": backup.name,
+                            "created_at": backup.created_at,
+                        }),
+                        &ctx.output_mode,
+                        false,
+                    )
+                }
+                Err(error) => {
+                    eprintln!("Error: {error}");
+                    exit_codes::exit_code_for_vault_error(&error)
+                }
+            }
+        }
+    }
+}
+
+fn run_trash(args: cli::VaultTrashArgs, ctx: &AppContext) -> ExitCode {
+    match args.command {
+        cli::VaultTrashCommands::List(args) => {
+            let path = match vault::vault_path(args.path.path.as_deref()) {
+                Ok(path) => path,
+                Err(error) => {
+                    eprintln!("Error: {error}");
+                    return exit_codes::exit_code_for_vault_error(&error);
+                }
+            };
+
+            let key = match unlock_key(&path, &args.keyfile) {
+                Ok(key) => key,
+                Err(code) => return code,
+            };
+
+            match vault::vault_list_trash_v1(&path, &key) {
+                Ok(items) => {
+                    let value = if ctx.output_mode.quiet {
+                        items
+                            .iter()
+                            .map(|i| i.id.to_string())
+                            .collect::<Vec<_>>()
+                            .join("\n")
+                    } else {
+                        items
+                            .iter()
+                            .map(|i| {
+                                format!(
+                                    "{}\t{}",
+                                    vault_item_summary_text(i),
+                                    i.deleted_at.unwrap_or_default()
+                                )
+                            })
+                            .collect::<Vec<_>>()
+                            .join("\n")
+                    };
+
+                    let meta = json!({
+                        "kind": "vault-trash-list",
+                        "path": path.display().to_string(),
+                        "count": items.len(),
+                        "items": items
+                            .iter()
+                            .map(|i| {
+                                let mut summary = vault_item_summary_json(i);
+                                summary["deleted_at"] = json!(i.deleted_at);
+                                summary
+                            })
+                            .collect::<Vec<_>>(),
+                    });
+
+                    output::print_value(value, meta, &ctx.output_mode, false)
+                }
+                Err(error) => {
+                    eprintln!("Error: {error}");
+                    exit_codes::exit_code_for_vault_error(&error)
+                }
+            }
+        }
+        cli::VaultTrashCommands::Restore(args) => {
+            let path = match vault::vault_path(args.path.path.as_deref()) {
+                Ok(path) => path,
+                Err(error) => {
+                    eprintln!("Error: {error}");
+                    return exit_codes::exit_code_for_vault_error(&error);
+                }
+            };
+
+            let key = match unlock_key(&path, &args.keyfile) {
+                Ok(key) => key,
+                Err(code) => return code,
+            };
+
+            match vault::vault_restore_trashed_item_v1(&path, &key, args.id) {
+                Ok(()) => {
+                    let value = args.id.to_string();
+                    let meta = json!({
+                        "kind": "vault-trash-restore",
+                        "path": path.display().to_string(),
+                        "id": value,
+                    });
+
+                    if ctx.output_mode.quiet {
+                        output::print_value(value, meta, &ctx.output_mode, false)
+                    } else {
+                        output::print_value(
+                            format!("Restored {value} from trash"),
+                            meta,
+                            &ctx.output_mode,
+                            false,
+                        )
+                    }
+                }
+                Err(error) => {
+                    eprintln!("Error: {error}");
+                    exit_codes::exit_code_for_vault_error(&error)
+                }
+            }
+        }
+        cli::VaultTrashCommands::Empty(args) => {
+            let path = match vault::vault_path(args.path.path.as_deref()) {
+                Ok(path) => path,
+                Err(error) => {
+                    eprintln!("Error: {error}");
+                    return exit_codes::exit_code_for_vault_error(&error);
+                }
+            };
+
+            let key = match unlock_key(&path, &args.keyfile) {
+                Ok(key) => key,
+                Err(code) => return code,
+            };
+
+            match vault::vault_empty_trash_v1(&path, &key, args.older_than) {
+                Ok(removed) => output::print_value(
+                    format!("Deleted {removed} trashed item(s)"),
+                    json!({
+                        "kind": "vault-trash-empty",
+                        "path": path.display().to_string(),
+                        "removed": removed,
+                        "older_than_secs": args.older_than.map(|d| d.as_secs()),
+                    }),
+                    &ctx.output_mode,
+                    false,
+                ),
+                Err(error) => {
+                    eprintln!("Error: {error}");
+                    exit_codes::exit_code_for_vault_error(&error)
+                }
+            }
+        }
+    }
+}
+
+fn run_slot(args: cli::VaultSlotArgs, ctx: &AppContext) -> ExitCode {
+    match args.command {
+        cli::VaultSlotCommands::List(args) => {
+            let path = match vault::vault_path(args.path.path.as_deref()) {
+                Ok(path) => path,
+                Err(error) => {
+                    eprintln!("Error: {error}");
+                    return exit_codes::exit_code_for_vault_error(&error);
+                }
+            };
+
+            match vault::vault_list_key_slots_v1(&path) {
+                Ok(slots) => {
+                    let value = slots
+                        .iter()
+                        .map(|s| {
+                            if ctx.output_mode.quiet {
+                                s.id.to_string()
+                            } else {
+                                format!(
+                                    "{}\t{}\t{}\targon2id m={} t={} p={}",
+                                    s.id,
+                                    s.label,
+                                    if s.keyfile_required {
+                                        "password+keyfile"
+                                    } else {
+                                        "password"
+                                    },
+                                    s.kdf_params.memory_kib,
+                                    s.kdf_params.iterations,
+                                    s.kdf_params.parallelism
+                                )
+                            }
+                        })
+                        .collect::<Vec<_>>()
+                        .join("\n");
+
+                    let meta = json!({
+                        "kind": "vault-slot-list",
+                        "path": path.display().to_string(),
+                        "count": slots.len(),
+                        "slots": slots.iter().map(|s| json!({
+                            "id": s.id,
+                            "label": s.label,
+                            "keyfile_required": s.keyfile_required,
+                            "kdf": {
+                                "memory_kib": s.kdf_params.memory_kib,
+                                "iterations": s.kdf_params.iterations,
+                                "parallelism": s.kdf_params.parallelism,
+                            },
+                        })).collect::<Vec<_>>(),
+                    });
+
+                    output::print_value(value, meta, &ctx.output_mode, false)
+                }
+                Err(error) => {
+                    eprintln!("Error: {error}");
+                    exit_codes::exit_code_for_vault_error(&error)
+                }
+            }
+        }
+        cli::VaultSlotCommands::Add(args) => {
+            let path = match vault::vault_path(args.path.path.as_deref()) {
+                Ok(path) => path,
+                Err(error) => {
+                    eprintln!("Error: {error}");
+                    return exit_codes::exit_code_for_vault_error(&error);
+                }
+            };
+
+            let kdf_params = match kdf_params_override(&args.kdf) {
+                Ok(kdf_params) => kdf_params,
+                Err(code) => return code,
+            };
+
+            let credentials = match prompt_credentials(&args.keyfile, vault::prompt_master_password)
+            {
+                Ok(credentials) => credentials,
+                Err(code) => return code,
+            };
+
+            let slot_keyfile = match args.slot_keyfile.as_deref().map(vault::read_keyfile) {
+                Some(Ok(digest)) => Some(digest),
+                Some(Err(error)) => {
+                    eprintln!("Error: {error}");
+                    return exit_codes::exit_code_for_vault_error(&error);
+                }
+                None => None,
+            };
+
+            let slot_password = match vault::prompt_new_slot_password() {
+                Ok(pw) => pw,
+                Err(error) => {
+                    eprintln!("Error: {error}");
+                    return exit_codes::exit_code_for_vault_prompt_error(&error);
+                }
+            };
+            let slot_credentials = vault::Credentials {
+                master_password: slot_password,
+                keyfile: slot_keyfile,
+            };
+
+            match vault::vault_add_key_slot_v1(
+                &path,
+                &credentials,
+                &slot_credentials,
+                args.label,
+                kdf_params,
+            ) {
+                Ok(id) => {
+                    let meta = json!({
+                        "kind": "vault-slot-add",
+                        "path": path.display().to_string(),
+                        "id": id,
+                    });
+
+                    if ctx.output_mode.quiet {
+                        output::print_value(id.to_string(), meta, &ctx.output_mode, false)
+                    } else {
+                        output::print_value(
+                            format!("Added key slot {id}"),
+                            meta,
+                            &ctx.output_mode,
+                            false,
+                        )
+                    }
+                }
+                Err(error) => {
+                    eprintln!("Error: {error}");
+                    exit_codes::exit_code_for_vault_error(&error)
+                }
+            }
+        }
+        cli::VaultSlotCommands::Rm(args) => {
+            let path = match vault::vault_path(args.path.path.as_deref()) {
+                Ok(path) => path,
+                Err(error) => {
+                    eprintln!("Error: {error}");
+                    return exit_codes::exit_code_for_vault_error(&error);
+                }
+            };
+
+            let credentials = match prompt_credentials(&args.keyfile, vault::prompt_master_password)
+            {
+                Ok(credentials) => credentials,
+                Err(code) => return code,
+            };
+
+            match vault::vault_remove_key_slot_v1(&path, &credentials, args.id) {
+                Ok(()) => {
+                    let meta = json!({
+                        "kind": "vault-slot-rm",
+                        "path": path.display().to_string(),
+                        "id": args.id,
+                    });
+
+                    if ctx.output_mode.quiet {
+                        output::print_value(args.id.to_string(), meta, &ctx.output_mode, false)
+                    } else {
+                        output::print_value(
+                            format!("Removed key slot {}", args.id),
+                            meta,
+                            &ctx.output_mode,
+                            false,
+                        )
+                    }
+                }
+                Err(error) => {
+                    eprintln!("Error: {error}");
+                    exit_codes::exit_code_for_vault_error(&error)
+                }
+            }
+        }
+    }
+}
+
+fn run_kdf(args: cli::VaultKdfArgs, ctx: &AppContext) -> ExitCode {
+    match args.command {
+        cli::VaultKdfCommands::Bench(args) => {
+            let target = Duration::from_millis(args.target_ms);
+            let recommended = vault::crypto::calibrate_kdf_params(
+                target,
+                args.max_memory_mib * 1024,
+                args.parallelism,
+                vault::crypto::benchmark_kdf,
+            )
+            .and_then(|params| {
+                vault::crypto::benchmark_kdf(params).map(|elapsed| (params, elapsed))
+            });
+
+            match recommended {
+                Ok((params, elapsed)) => {
+                    let elapsed_ms = elapsed.as_millis();
+                    let value = if ctx.output_mode.quiet {
+                        format!(
+                            "{} {} {}",
+                            params.memory_kib, params.iterations, params.parallelism
+                        )
+                    } else {
+                        format!(
+                            "argon2id m={} t={} p={} (~{elapsed_ms}ms)",
+                            params.memory_kib, params.iterations, params.parallelism
+                        )
+                    };
+
+                    output::print_value(
+                        value,
+                        json!({
+                            "kind": "vault-kdf-bench",
+                            "target_ms": args.target_ms,
+                            "max_memory_kib": args.max_memory_mib * 1024,
+                            "elapsed_ms": elapsed_ms,
+                            "kdf": {
+                                "memory_kib": params.memory_kib,
+                                "iterations": params.iterations,
+                                "parallelism": params.parallelism,
+                            },
+                        }),
+                        &ctx.output_mode,
+                        false,
+                    )
+                }
+                Err(error) => {
+                    eprintln!("Error: {error}");
+                    exit_codes::exit_code_for_vault_error(&vault::VaultError::Crypto(error))
+                }
+            }
+        }
+        cli::VaultKdfCommands::Set(args) => {
+            let path = match vault::vault_path(args.path.path.as_deref()) {
+                Ok(path) => path,
+                Err(error) => {
+                    eprintln!("Error: {error}");
+                    return exit_codes::exit_code_for_vault_error(&error);
+                }
+            };
+
+            let credentials = match prompt_credentials(&args.keyfile, vault::prompt_master_password)
+            {
+                Ok(credentials) => credentials,
+                Err(code) => return code,
+            };
+
+            let input = vault::RotateKeysInput {
+                new_master_password: None,
+                keyfile: vault::KeyfileChange::Keep,
+                kdf_params: vault::KdfParamsOverride {
+                    memory_kib: args.memory_mib.map(|mib| mib * 1024),
+                    iterations: args.iterations,
+                    parallelism: args.parallelism,
+                },
+                rotate_dek: false,
+                drop_other_slots: false,
+            };
+
+            match vault::vault_rotate_keys_v1(&path, &credentials, input) {
+                Ok(slot) => {
+                    let params = slot.kdf_params;
+                    output::print_value(
+                        format!(
+                            "argon2id m={} t={} p={}",
+                            params.memory_kib, params.iterations, params.parallelism
+                        ),
+                        json!({
+                            "kind": "vault-kdf-set",
+                            "path": path.display().to_string(),
+                            "slot": slot.id,
+                            "kdf": {
+                                "memory_kib": params.memory_kib,
+                                "iterations": params.iterations,
+                                "parallelism": params.parallelism,
+                            },
+                        }),
+                        &ctx.output_mode,
+                        false,
+                    )
+                }
+                Err(error) => {
+                    eprintln!("Error: {error}");
+                    exit_codes::exit_code_for_vault_error(&error)
+                }
+            }
+        }
+    }
+}
+
+/// Memory ceiling for `--kdf-target-ms`, the `vault kdf bench` default.
+const KDF_TARGET_MAX_MEMORY_KIB: u32 = 256 * 1024;
+
+/// Turn `--kdf-*` flags into params for new keys, timing Argon2id on this
+/// machine when a target is given.
+///
+/// Errors are reported on STDERR; the caller only has to return the exit code.
+fn kdf_params_override(args: &cli::VaultNewKdfArgs) -> Result<vault::KdfParamsOverride, ExitCode> {
+    let Some(target_ms) = args.kdf_target_ms else {
+        return Ok(vault::KdfParamsOverride {
+            memory_kib: args.kdf_memory_mib.map(|mib| mib * 1024),
+            iterations: args.kdf_iterations,
+            parallelism: args.kdf_parallelism,
+        });
+    };
+
+    match vault::crypto::calibrate_kdf_params(
+        Duration::from_millis(target_ms),
+        KDF_TARGET_MAX_MEMORY_KIB,
+        args.kdf_parallelism.unwrap_or(1),
+        vault::crypto::benchmark_kdf,
+    ) {
+        Ok(params) => Ok(vault::KdfParamsOverride {
+            memory_kib: Some(params.memory_kib),
+            iterations: Some(params.iterations),
+            parallelism: Some(params.parallelism),
+        }),
+        Err(error) => {
+            eprintln!("Error: {error}");
+            Err(exit_codes::exit_code_for_vault_error(
+                &vault::VaultError::Crypto(error),
+            ))
+        }
+    }
+}
+
+/// Read the keyfile (flag or `PASSWORDER_KEYFILE`), then prompt for the password.
+///
+/// Errors are reported on STDERR; the caller only has to return the exit code.
+pub(super) fn prompt_credentials(
+    args: &cli::VaultKeyfileArgs,
+    prompt: fn() -> Result<SecretString, vault::PromptError>,
+) -> Result<vault::Credentials, ExitCode> {
+    let keyfile = match vault::keyfile_path(args.keyfile.as_deref()) {
+        Some(path) => match vault::read_keyfile(&path) {
+            Ok(digest) => Some(digest),
+            Err(error) => {
+                eprintln!("Error: {error}");
+                return Err(exit_codes::exit_code_for_vault_error(&error));
+            }
+        },
+        None => None,
+    };
+
+    let master_password = match prompt() {
+        Ok(pw) => pw,
+        Err(error) => {
+            eprintln!("Error: {error}");
+            return Err(exit_codes::exit_code_for_vault_prompt_error(&error));
+        }
+    };
+
+    Ok(vault::Credentials {
+        master_password,
+        keyfile,
+    })
+}
+
+/// Resolve how to unlock `path` for an item operation.
+///
+/// With `PASSWORDER_AGENT_SOCK` set, a cached key is used when the agent has
+/// one that still matches the vault; otherwise the password is prompted for
+/// once and the resulting key handed to the agent. An unreachable agent only
+/// produces a warning.
+pub(super) fn unlock_key(
+    path: &Path,
+    keyfile: &cli::VaultKeyfileArgs,
+) -> Result<vault::VaultKey, ExitCode> {
+    #[cfg(unix)]
+    if let Some(socket) = agent::socket_from_env() {
+        let client = agent::AgentClient::new(socket);
+        match client.get_key(path) {
+            Ok(Some(dek)) => match vault::vault_verify_session_key_v1(path, &dek) {
+                Ok(()) => return Ok(vault::VaultKey::Session(dek)),
+                Err(vault::VaultError::StaleSessionKey) => {
+                    let _ = client.forget(path);
+                }
+                Err(error) => {
+                    eprintln!("Error: {error}");
+                    return Err(exit_codes::exit_code_for_vault_error(&error));
+                }
+            },
+            Ok(None) => {}
+            Err(error) => {
+                eprintln!("Warning: {error}; continuing without the agent.");
+                let credentials = prompt_credentials(keyfile, vault::prompt_master_password)?;
+                return Ok(vault::VaultKey::Credentials(credentials));
+            }
+        }
+
+        let credentials = prompt_credentials(keyfile, vault::prompt_master_password)?;
+        let dek = match vault::vault_unlock_dek_v1(path, &credentials) {
+            Ok(dek) => dek,
+            Err(error) => {
+                eprintln!("Error: {error}");
+                return Err(exit_codes::exit_code_for_vault_error(&error));
+            }
+        };
+        if let Err(error) = client.add_key(path, &dek) {
+            eprintln!("Warning: {error}; the key was not cached.");
+        }
+        return Ok(vault::VaultKey::Session(dek));
+    }
+
+    let credentials = prompt_credentials(keyfile, vault::prompt_master_password)?;
+    Ok(vault::VaultKey::Credentials(credentials))
+}
+
+/// `--field KEY=VALUE` pairs, concealed when named by `--secret-field`.
+fn custom_fields(pairs: Vec<(String, String)>, concealed: &[String]) -> Vec<vault::VaultItemField> {
+    pairs
+        .into_iter()
+        .map(|(name, value)| vault::VaultItemField {
+            concealed: concealed.contains(&name),
+            name,
+            value,
+        })
+        .collect()
+}
+
+fn item_filter(args: cli::VaultItemFilterArgs) -> vault::ItemFilter {
+    vault::ItemFilter {
+        path: args.item_path,
+        tags: args.tags,
+        item_type: args.item_type,
+    }
+}
+
+fn run_export(args: cli::VaultExportArgs, ctx: &AppContext) -> ExitCode {
+    let plaintext = args.format != cli::VaultExportFormat::Bundle;
+    if plaintext {
+        if !args.unsafe_mode {
+            eprintln!(
+                "Error: plaintext export writes secrets to disk; re-run with `--unsafe` to proceed."
+            );
+            return ExitCode::from(exit_codes::EXIT_USAGE);
+        }
+        if args.with_history {
+            eprintln!("Error: `--with-history` only applies to bundle exports.");
+            return ExitCode::from(exit_codes::EXIT_USAGE);
+        }
+        if args.kdf.kdf_memory_mib.is_some()
+            || args.kdf.kdf_iterations.is_some()
+            || args.kdf.kdf_parallelism.is_some()
+            || args.kdf.kdf_target_ms.is_some()
+        {
+            eprintln!("Error: `--kdf-*` options only apply to bundle exports.");
+            return ExitCode::from(exit_codes::EXIT_USAGE);
+        }
+        if std::env::var_os("CI").is_some() {
+            eprintln!("Warning: CI detected; written secrets may be logged or cached.");
+        }
+    }
+
+    let path = match vault::vault_path(args.path.path.as_deref()) {
+        Ok(path) => path,
+        Err(error) => {
+            eprintln!("Error: {error}");
+            return exit_codes::exit_code_for_vault_error(&error);
+        }
+    };
+
+    let key = match unlock_key(&path, &args.keyfile) {
+        Ok(key) => key,
+        Err(code) => return code,
+    };
+
+    let kdf_params = match kdf_params_override(&args.kdf) {
+        Ok(kdf_params) => kdf_params,
+        Err(code) => return code,
+    };
+
+    let filter = item_filter(args.filter);
+    let count = if plaintext {
+        let items = match vault::vault_list_items_v1(&path, &key) {
+            Ok(items) => items
+                .into_iter()
+                .filter(|i| filter.matches(i))
+                .collect::<Vec<_>>(),
+            Err(error) => {
+                eprintln!("Error: {error}");
+                return exit_codes::exit_code_for_vault_error(&error);
+            }
+        };
+
+        let written = if args.format == cli::VaultExportFormat::Csv {
+            let csv = match dev_workflows::items_csv(&items) {
+                Ok(csv) => csv,
+                Err(error @ dev_workflows::DevWorkflowError::CsvField { .. }) => {
+                    eprintln!("Error: {error}");
+                    return ExitCode::from(exit_codes::EXIT_USAGE);
+                }
+                Err(error) => {
+                    eprintln!("Error: {error}");
+                    return ExitCode::from(exit_codes::EXIT_SOFTWARE);
+                }
+            };
+            dev_workflows::write_sensitive_file_atomic(&args.out, &csv)
+        } else {
+            dev_workflows::write_pass_dir(&args.out, &items).map(|_| ())
+        };
+        if let Err(error) = written {
+            eprintln!("Error: {error}");
+            return ExitCode::from(exit_codes::EXIT_IO);
+        }
+        items.len()
+    } else {
+        let passphrase = match vault::prompt_new_bundle_passphrase() {
+            Ok(passphrase) => passphrase,
+            Err(error) => {
+                eprintln!("Error: {error}");
+                return exit_codes::exit_code_for_vault_prompt_error(&error);
+            }
+        };
+
+        match vault::vault_export_bundle_v1(
+            &path,
+            &key,
+            &args.out,
+            &passphrase,
+            &filter,
+            args.with_history,
+            kdf_params,
+        ) {
+            Ok(count) => count,
+            Err(error) => {
+                eprintln!("Error: {error}");
+                return exit_codes::exit_code_for_vault_error(&error);
+            }
+        }
+    };
+
+    let format = match args.format {
+        cli::VaultExportFormat::Bundle => "bundle",
+        cli::VaultExportFormat::Csv => "csv",
+        cli::VaultExportFormat::PassDir => "pass-dir",
+    };
+    output::print_value(
+        format!("Exported {count} item(s) to {}", args.out.display()),
+        json!({
+            "kind": "vault-export",
+            "path": path.display().to_string(),
+            "out": args.out.display().to_string(),
+            "format": format,
+            "count": count,
+            "with_history": args.with_history,
+        }),
+        &ctx.output_mode,
+        false,
+    )
+}
+
+fn run_merge(args: cli::VaultMergeArgs, ctx: &AppContext) -> ExitCode {
+    let path = match vault::vault_path(args.path.path.as_deref()) {
+        Ok(path) => path,
+        Err(error) => {
+            eprintln!("Error: {error}");
+            return exit_codes::exit_code_for_vault_error(&error);
+        }
+    };
+
+    let key = match unlock_key(&path, &args.keyfile) {
+        Ok(key) => key,
+        Err(code) => return code,
+    };
+
+    let mut resolve = |c: &vault::MergeConflict| resolve_merge_conflict(&args.resolution, c);
+
+    let report = match vault::vault_merge_v1(
+        &path,
+        &key,
+        &args.other,
+        args.base.as_deref(),
+        &mut resolve,
+        args.dry_run,
+    ) {
+        Ok(report) => report,
+        Err(error) => {
+            eprintln!("Error: {error}");
+            if matches!(error, vault::VaultError::MergeConflicts(_)) {
+                eprintln!(
+                    "Re-run with --prefer ours|theirs|newer or --interactive to resolve them."
+                );
+            }
+            return exit_codes::exit_code_for_vault_error(&error);
+        }
+    };
+
+    let mut lines = vec![format!(
+        "{} {}: {} added, {} updated, {} removed, {} conflict(s) resolved",
+        if args.dry_run {
+            "Would merge"
+        } else {
+            "Merged"
+        },
+        args.other.display(),
+        report.added,
+        report.updated,
+        report.removed,
+        report.resolved.len()
+    )];
+    lines.extend(resolved_conflict_lines(&report));
+
+    output::print_value(
+        lines.join("\n"),
+        json!({
+            "kind": "vault-merge",
+            "path": path.display().to_string(),
+            "other": args.other.display().to_string(),
+            "base": args.base.as_ref().map(|b| b.display().to_string()),
+            "dry_run": args.dry_run,
+            "added": report.added,
+            "updated": report.updated,
+            "removed": report.removed,
+            "conflicts": resolved_conflicts_json(&report),
+        }),
+        &ctx.output_mode,
+        false,
+    )
+}
+
+fn run_sync(args: cli::VaultSyncArgs, ctx: &AppContext) -> ExitCode {
+    if let Some(cli::VaultSyncCommands::Init(args)) = args.command {
+        return run_sync_init(args, ctx);
+    }
+
+    let path = match vault::vault_path(args.path.path.as_deref()) {
+        Ok(path) => path,
+        Err(error) => {
+            eprintln!("Error: {error}");
+            return exit_codes::exit_code_for_vault_error(&error);
+        }
+    };
+
+    let key = match unlock_key(&path, &args.keyfile) {
+        Ok(key) => key,
+        Err(code) => return code,
+    };
+
+    let mut resolve = |c: &vault::MergeConflict| resolve_merge_conflict(&args.resolution, c);
+    let report = match vault::vault_sync_v1(&path, &key, &mut resolve, !args.no_push) {
+        Ok(report) => report,
+        Err(error) => {
+            eprintln!("Error: {error}");
+            if matches!(error, vault::VaultError::MergeConflicts(_)) {
+                eprintln!(
+                    "Re-run with --prefer ours|theirs|newer or --interactive to resolve them."
+                );
+            }
+            return exit_codes::exit_code_for_vault_error(&error);
+        }
+    };
+
+    let status = match report.outcome {
+        vault::SyncOutcome::NoRemote => "No remote configured; changes are committed locally",
+        vault::SyncOutcome::UpToDate => "Already up to date",
+        vault::SyncOutcome::Ahead => "Local changes are newer than the remote",
+        vault::SyncOutcome::Pulled => "Pulled remote changes",
+        vault::SyncOutcome::Merged => "Merged remote changes",
+    };
+    let mut lines = vec![format!(
+        "{status}{}",
+        if report.pushed { "; pushed" } else { "" }
+    )];
+    if let Some(merge) = &report.merge {
+        lines.push(format!(
+            "{} added, {} updated, {} removed, {} conflict(s) resolved",
+            merge.added,
+            merge.updated,
+            merge.removed,
+            merge.resolved.len()
+        ));
+        lines.extend(resolved_conflict_lines(merge));
+    }
+
+    output::print_value(
+        lines.join("\n"),
+        json!({
+            "kind": "vault-sync",
+            "path": path.display().to_string(),
+            "committed": report.committed,
+            "outcome": report.outcome.as_str(),
+            "pushed": report.pushed,
+            "merge": report.merge.as_ref().map(|merge| json!({
+                "added": merge.added,
+                "updated": merge.updated,
+                "removed": merge.removed,
+                "conflicts": resolved_conflicts_json(merge),
+            })),
+        }),
+        &ctx.output_mode,
+        false,
+    )
+}
+
+fn run_sync_init(args: cli::VaultSyncInitArgs, ctx: &AppContext) -> ExitCode {
+    let path = match vault::vault_path(args.path.path.as_deref()) {
+        Ok(path) => path,
+        Err(error) => {
+            eprintln!("Error: {error}");
+            return exit_codes::exit_code_for_vault_error(&error);
+        }
+    };
+
+    match vault::vault_sync_init_v1(&path, args.remote.as_deref()) {
+        Ok(report) => {
+            let mut lines = vec![format!(
+                "Syncing {} through git in {}",
+                path.display(),
+                report.repo_dir.display()
+            )];
+            if let Some(remote) = &report.remote {
+                lines.push(format!("Remote: {remote}"));
+            }
+            if report.pulled {
+                lines.push("Checked out the vault from the remote".to_string());
+            }
+
+            output::print_value(
+                lines.join("\n"),
+                json!({
+                    "kind": "vault-sync-init",
+                    "path": path.display().to_string(),
+                    "repo": report.repo_dir.display().to_string(),
+                    "remote": report.remote,
+                    "pulled": report.pulled,
+                }),
+                &ctx.output_mode,
+                false,
+            )
+        }
+        Err(error) => {
+            eprintln!("Error: {error}");
+            exit_codes::exit_code_for_vault_error(&error)
+        }
+    }
+}
+
+fn run_totp(args: cli::VaultTotpArgs, ctx: &AppContext) -> ExitCode {
+    let path = match vault::vault_path(args.path.path.as_deref()) {
+        Ok(path) => path,
+        Err(error) => {
+            eprintln!("Error: {error}");
+            return exit_codes::exit_code_for_vault_error(&error);
+        }
+    };
+
+    let mut key = match unlock_key(&path, &args.keyfile) {
+        Ok(key) => key,
+        Err(code) => return code,
+    };
+
+    pull_before_read(&path, &mut key);
+
+    match vault::vault_totp_v1(&path, &key, &args.item) {
+        Ok((item, totp)) => {
+            let now = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs());
+            let code = totp.at(now);
+
+            let copied = ctx.copy_requested;
+            if copied && let Err(error) = output::copy_to_clipboard(&code.code) {
+                eprintln!("Error: {error}");
+                return ExitCode::from(exit_codes::EXIT_IO);
+            }
+
+            let meta = json!({
+                "kind": "vault-totp",
+                "path": path.display().to_string(),
+                "id": item.id.to_string(),
+                "name": item.name,
+                "remaining": code.remaining,
+                "period": totp.period,
+                "digits": totp.digits,
+                "algorithm": totp.algorithm.as_str(),
+                "copied": copied,
+            });
+
+            let value = if ctx.output_mode.quiet || ctx.output_mode.json {
+                code.code
+            } else {
+                format!("{} ({}s remaining)", code.code, code.remaining)
+            };
+            output::print_value(value, meta, &ctx.output_mode, false)
+        }
+        Err(error) => {
+            eprintln!("Error: {error}");
+            exit_codes::exit_code_for_vault_error(&error)
+        }
+    }
+}
+
+fn run_rotate(args: cli::VaultRotateArgs, ctx: &AppContext) -> ExitCode {
+    let path = match vault::vault_path(args.path.path.as_deref()) {
+        Ok(path) => path,
+        Err(error) => {
+            eprintln!("Error: {error}");
+            return exit_codes::exit_code_for_vault_error(&error);
+        }
+    };
+
+    let key = match unlock_key(&path, &args.keyfile) {
+        Ok(key) => key,
+        Err(code) => return code,
+    };
+
+    // Generate inside the write so the vault is unlocked only once. On a
+    // generator error the vault is left untouched and the error code kept.
+    let mut generated = None;
+    let mut failure = None;
+    let rotated = vault::vault_rotate_item_v1(&path, &key, &args.item, &mut |item| {
+        let result = resolve_generator(item.item_type, args.generate, args.profile.as_deref())
+            .and_then(|generator| {
+                generate_secret(generator, args.profile.as_deref(), &item.name, ctx)
+                    .map(|secret| (generator, zeroize::Zeroizing::new(secret)))
+            });
+        match result {
+            Ok((generator, secret)) => {
+                let value = secret.to_string();
+                generated = Some((generator, secret));
+                Some(value)
+            }
+            Err(code) => {
+                failure = Some(code);
+                None
+            }
+        }
+    });
+    let item = match rotated {
+        Ok(Some(item)) => item,
+        Ok(None) => return failure.expect("generation failed"),
+        Err(error) => {
+            eprintln!("Error: {error}");
+            return exit_codes::exit_code_for_vault_error(&error);
+        }
+    };
+    let (generator, secret) = generated.expect("a rotated item has a generated secret");
+
+    let copied = ctx.copy_requested;
+    if copied && let Err(error) = output::copy_to_clipboard(&secret) {
+        eprintln!("Error: {error}");
+        return ExitCode::from(exit_codes::EXIT_IO);
+    }
+
+    let mut meta = json!({
+        "kind": "vault-rotate",
+        "path": path.display().to_string(),
+        "id": item.id.to_string(),
+        "name": item.name,
+        "generator": generator_str(generator),
+        "profile": args.profile,
+        "revealed": args.reveal,
+        "copied": copied,
+    });
+    if args.reveal {
+        meta["secret"] = json!(secret.as_str());
+    }
+
+    let value = match (args.reveal, ctx.output_mode.quiet) {
+        (true, _) => secret.to_string(),
+        (false, true) => item.id.to_string(),
+        (false, false) => format!("Rotated the secret of {} ({})", item.name, item.id),
+    };
+    output::print_value(value, meta, &ctx.output_mode, false)
+}
+
+/// Pick the generator for a new secret of `item_type`: the one asked for,
+/// `password` when a profile is given, or the type's default.
+fn resolve_generator(
+    item_type: vault::VaultItemType,
+    explicit: Option<cli::SecretGenerator>,
+    profile: Option<&str>,
+) -> Result<cli::SecretGenerator, ExitCode> {
+    let generator = match (explicit, profile) {
+        (Some(generator), _) => generator,
+        (None, Some(_)) => cli::SecretGenerator::Password,
+        (None, None) => match item_type {
+            vault::VaultItemType::Login => cli::SecretGenerator::Password,
+            vault::VaultItemType::ApiToken => cli::SecretGenerator::TokenB64,
+            vault::VaultItemType::SshKey => cli::SecretGenerator::SshKey,
+            // A note is free text and a totp seed comes from the issuer.
+            vault::VaultItemType::SecureNote | vault::VaultItemType::Totp => {
+                eprintln!(
+                    "Error: {} items have no default generator; pass --generate KIND",
+                    item_type.as_str()
+                );
+                return Err(ExitCode::from(exit_codes::EXIT_USAGE));
+            }
+        },
+    };
+
+    let is_ssh_key = item_type == vault::VaultItemType::SshKey;
+    if is_ssh_key != (generator == cli::SecretGenerator::SshKey) {
+        eprintln!("Error: ssh-key items need --generate ssh-key, and only they can use it");
+        return Err(ExitCode::from(exit_codes::EXIT_USAGE));
+    }
+    Ok(generator)
+}
+
+fn generator_str(generator: cli::SecretGenerator) -> &'static str {
+    match generator {
+        cli::SecretGenerator::Password => "password",
+        cli::SecretGenerator::Passphrase => "passphrase",
+        cli::SecretGenerator::TokenHex => "token-hex",
+        cli::SecretGenerator::TokenB64 => "token-b64",
+        cli::SecretGenerator::SshKey => "ssh-key",
+    }
+}
+
+/// Run one of the standalone generators for an item secret. `comment` labels
+/// a generated ssh key.
+fn generate_secret(
+    generator: cli::SecretGenerator,
+    profile: Option<&str>,
+    comment: &str,
+    ctx: &AppContext,
+) -> Result<String, ExitCode> {
+    if profile.is_some() && generator != cli::SecretGenerator::Password {
+        eprintln!("Error: --profile only applies to --generate password");
+        return Err(ExitCode::from(exit_codes::EXIT_USAGE));
+    }
+
+    match generator {
+        cli::SecretGenerator::Password => {
+            let config = match profile {
+                Some(name) => config::get_profile(name).map_err(|error| {
+                    eprintln!("Error: {error}");
+                    exit_codes::exit_code_for_config_error(&error)
+                })?,
+                None => password::PasswordConfig::default(),
+            };
+            password::generate(config, ctx.dev_seed).map_err(|error| {
+                eprintln!("Error: {error}");
+                exit_codes::exit_code_for_password_error(&error)
+            })
+        }
+        cli::SecretGenerator::Passphrase => {
+            passphrase::generate(passphrase::PassphraseConfig::default(), ctx.dev_seed).map_err(
+                |error| {
+                    eprintln!("Error: {error}");
+                    exit_codes::exit_code_for_passphrase_error(&error)
+                },
+            )
+        }
+        cli::SecretGenerator::TokenHex | cli::SecretGenerator::TokenB64 => {
+            let bytes = cli::TokenBytesArgs {
+                bytes: GENERATED_TOKEN_BYTES,
+            };
+            let command = match generator {
+                cli::SecretGenerator::TokenHex => cli::TokenCommands::Hex(bytes),
+                _ => cli::TokenCommands::B64(bytes),
+            };
+            token::handle(command, ctx.dev_seed).map_err(|error| {
+                eprintln!("Error: {error}");
+                exit_codes::exit_code_for_token_error(&error)
+            })
+        }
+        cli::SecretGenerator::SshKey => Ok(vault::SshKey::generate(comment).to_openssh()),
+    }
+}
+
+fn run_due(args: cli::VaultDueArgs, ctx: &AppContext) -> ExitCode {
+    let path = match vault::vault_path(args.path.path.as_deref()) {
+        Ok(path) => path,
+        Err(error) => {
+            eprintln!("Error: {error}");
+            return exit_codes::exit_code_for_vault_error(&error);
+        }
+    };
+
+    let mut key = match unlock_key(&path, &args.keyfile) {
+        Ok(key) => key,
+        Err(code) => return code,
+    };
+
+    pull_before_read(&path, &mut key);
+
+    let items = match vault::vault_list_item_metadata_v1(&path, &key) {
+        Ok(items) => items,
+        Err(error) => {
+            eprintln!("Error: {error}");
+            return exit_codes::exit_code_for_vault_error(&error);
+        }
+    };
+
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let mut due = items
+        .iter()
+        .filter_map(|item| Some((item, item.due(now, args.within.as_secs())?)))
+        .filter(|(_, due)| due.state != vault::DueState::Ok)
+        .collect::<Vec<_>>();
+    due.sort_by_key(|(item, due)| (due.due_at, item.id));
+    let overdue = due
+        .iter()
+        .filter(|(_, due)| due.state == vault::DueState::Overdue)
+        .count();
+
+    let value = if ctx.output_mode.quiet {
+        due.iter()
+            .map(|(item, _)| item.id.to_string())
+            .collect::<Vec<_>>()
+            .join("\n")
+    } else if due.is_empty() {
+        "Nothing is due.".to_string()
+    } else {
+        due.iter()
+            .map(|(item, due)| {
+                format!(
+                    "{}\t{}\t{}\t{}\t{}\t{}",
+                    item.id,
+                    due.state.as_str(),
+                    due.reason.as_str(),
+                    due_relative_text(due.due_at, now),
+                    item.path.as_deref().unwrap_or(""),
+                    item.name
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+
+    let meta = json!({
+        "kind": "vault-due",
+        "path": path.display().to_string(),
+        "within": args.within.as_secs(),
+        "count": due.len(),
+        "overdue": overdue,
+        "items": due.iter().map(|(item, _)| {
+            let mut summary = vault_item_summary_json(item);
+            summary["due"] = item_due_json(item, now, args.within.as_secs());
+            summary
+        }).collect::<Vec<_>>(),
+    });
+
+    let printed = output::print_value(value, meta, &ctx.output_mode, false);
+    if overdue > 0 {
+        ExitCode::from(exit_codes::EXIT_OVERDUE)
+    } else {
+        printed
+    }
+}
+
+fn run_attach(args: cli::VaultAttachArgs, ctx: &AppContext) -> ExitCode {
+    let path = match vault::vault_path(args.path.path.as_deref()) {
+        Ok(path) => path,
+        Err(error) => {
+            eprintln!("Error: {error}");
+            return exit_codes::exit_code_for_vault_error(&error);
+        }
+    };
+
+    let name = match args.name.clone().or_else(|| {
+        args.file
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+    }) {
+        Some(name) => name,
+        None => {
+            eprintln!(
+                "Error: cannot derive an attachment name from {}; pass --name",
+                args.file.display()
+            );
+            return ExitCode::from(exit_codes::EXIT_USAGE);
+        }
+    };
+
+    let contents = match std::fs::read(&args.file) {
+        Ok(contents) => zeroize::Zeroizing::new(contents),
+        Err(error) => {
+            eprintln!("Error: failed to read {}: {error}", args.file.display());
+            return ExitCode::from(exit_codes::EXIT_IO);
+        }
+    };
+
+    let key = match unlock_key(&path, &args.keyfile) {
+        Ok(key) => key,
+        Err(code) => return code,
+    };
+
+    match vault::vault_attach_v1(&path, &key, &args.item, &name, &contents) {
+        Ok((id, attachment, replaced)) => output::print_value(
+            format!(
+                "{} {} ({} bytes, sha256 {})",
+                if replaced { "Replaced" } else { "Attached" },
+                attachment.name,
+                attachment.size,
+                attachment.sha256
+            ),
+            json!({
+                "kind": "vault-attach",
+                "path": path.display().to_string(),
+                "id": id.to_string(),
+                "attachment": attachment_json(&attachment),
+                "replaced": replaced,
+            }),
+            &ctx.output_mode,
+            false,
+        ),
+        Err(error) => {
+            eprintln!("Error: {error}");
+            exit_codes::exit_code_for_vault_error(&error)
+        }
+    }
+}
+
+fn run_attachments(args: cli::VaultAttachmentsArgs, ctx: &AppContext) -> ExitCode {
+    let path = match vault::vault_path(args.path.path.as_deref()) {
+        Ok(path) => path,
+        Err(error) => {
+            eprintln!("Error: {error}");
+            return exit_codes::exit_code_for_vault_error(&error);
+        }
+    };
+
+    let mut key = match unlock_key(&path, &args.keyfile) {
+        Ok(key) => key,
+        Err(code) => return code,
+    };
+
+    pull_before_read(&path, &mut key);
+
+    match vault::vault_get_item_v1(&path, &key, &args.item) {
+        Ok(item) => {
+            let lines = item
+                .attachments
+                .iter()
+                .map(|a| format!("{}\t{}\t{}", a.sha256, a.size, a.name))
+                .collect::<Vec<_>>();
+            output::print_value(
+                lines.join("\n"),
+                json!({
+                    "kind": "vault-attachments",
+                    "path": path.display().to_string(),
+                    "id": item.id.to_string(),
+                    "count": item.attachments.len(),
+                    "attachments": item.attachments.iter().map(attachment_json).collect::<Vec<_>>(),
+                }),
+                &ctx.output_mode,
+                false,
+            )
+        }
+        Err(error) => {
+            eprintln!("Error: {error}");
+            exit_codes::exit_code_for_vault_error(&error)
+        }
+    }
+}
+
+/// Parse a `vault list`/`vault search` query up front, so a typo is reported
+/// before the master password is asked for.
+fn parse_query(query: &str) -> Result<vault::ItemQuery, ExitCode> {
+    vault::ItemQuery::parse(query).map_err(|error| {
+        let error = vault::VaultError::from(error);
+        eprintln!("Error: {error}");
+        exit_codes::exit_code_for_vault_error(&error)
+    })
+}
+
+fn run_detach(args: cli::VaultDetachArgs, ctx: &AppContext) -> ExitCode {
+    if !args.force {
+        eprintln!(
+            "Error: detaching deletes {} permanently; history keeps only its name and hash (pass --force to confirm)",
+            args.name
+        );
+        return ExitCode::from(exit_codes::EXIT_USAGE);
+    }
+
+    let path = match vault::vault_path(args.path.path.as_deref()) {
+        Ok(path) => path,
+        Err(error) => {
+            eprintln!("Error: {error}");
+            return exit_codes::exit_code_for_vault_error(&error);
+        }
+    };
+
+    let key = match unlock_key(&path, &args.keyfile) {
+        Ok(key) => key,
+        Err(code) => return code,
+    };
+
+    match vault::vault_detach_v1(&path, &key, &args.item, &args.name) {
+        Ok(id) => output::print_value(
+            format!("Detached {}", args.name),
+            json!({
+                "kind": "vault-detach",
+                "path": path.display().to_string(),
+                "id": id.to_string(),
+                "name": args.name,
+            }),
+            &ctx.output_mode,
+            false,
+        ),
+        Err(error) => {
+            eprintln!("Error: {error}");
+            exit_codes::exit_code_for_vault_error(&error)
+        }
+    }
+}
+
+/// `vault get ID --attachment NAME --out PATH`.
+fn write_attachment(
+    path: &Path,
+    key: &vault::VaultKey,
+    item: &str,
+    name: &str,
+    out: &Path,
+    ctx: &AppContext,
+) -> ExitCode {
+    let (id, contents) = match vault::vault_read_attachment_v1(path, key, item, name) {
+        Ok(read) => read,
+        Err(error) => {
+            eprintln!("Error: {error}");
+            return exit_codes::exit_code_for_vault_error(&error);
+        }
+    };
+
+    if let Err(error) = dev_workflows::write_sensitive_file_atomic(out, &contents) {
+        eprintln!("Error: {error}");
+        return ExitCode::from(exit_codes::EXIT_IO);
+    }
+
+    output::print_value(
+        format!("Wrote {name} to {}", out.display()),
+        json!({
+            "kind": "vault-get-attachment",
+            "path": path.display().to_string(),
+            "id": id.to_string(),
+            "name": name,
+            "out": out.display().to_string(),
+            "size": contents.len(),
+        }),
+        &ctx.output_mode,
+        false,
+    )
+}
+
+fn run_upgrade(args: cli::VaultUpgradeArgs, ctx: &AppContext) -> ExitCode {
+    let path = match vault::vault_path(args.path.path.as_deref()) {
+        Ok(path) => path,
+        Err(error) => {
+            eprintln!("Error: {error}");
+            return exit_codes::exit_code_for_vault_error(&error);
+        }
+    };
+
+    let key = match unlock_key(&path, &args.keyfile) {
+        Ok(key) => key,
+        Err(code) => return code,
+    };
+
+    match vault::vault_upgrade_v1(&path, &key) {
+        Ok(report) => {
+            let upgraded = report.from_version != report.to_version;
+            let value = if upgraded {
+                format!(
+                    "Upgraded {} from v{} to v{} ({} items)",
+                    path.display(),
+                    report.from_version,
+                    report.to_version,
+                    report.items
+                )
+            } else {
+                format!("{} is already v{}", path.display(), report.to_version)
+            };
+
+            output::print_value(
+                value,
+                json!({
+                    "kind": "vault-upgrade",
+                    "path": path.display().to_string(),
+                    "from_version": report.from_version,
+                    "to_version": report.to_version,
+                    "upgraded": upgraded,
+                    "items": report.items,
+                }),
+                &ctx.output_mode,
+                false,
+            )
+        }
+        Err(error) => {
+            eprintln!("Error: {error}");
+            exit_codes::exit_code_for_vault_error(&error)
+        }
+    }
+}
+
+/// Overrides `[vault] pull_on_read` from the config file.
+const PULL_ON_READ_ENV: &str = "PASSWORDER_VAULT_PULL_ON_READ";
+
+/// With `pull_on_read` enabled, bring a synced vault up to date before
+/// reading it. A password `key` is first swapped for the DEK it unlocks, so
+/// the pull and the read that follows share one KDF run. Failures (offline
+/// remote, unresolved conflicts) only warn: the local copy is still readable.
+pub(super) fn pull_before_read(path: &Path, key: &mut vault::VaultKey) {
+    if !pull_on_read_enabled() || !vault::vault_sync_enabled(path) {
+        return;
+    }
+    if let vault::VaultKey::Credentials(credentials) = key {
+        match vault::vault_unlock_dek_v1(path, credentials) {
+            Ok(dek) => *key = vault::VaultKey::Session(dek),
+            // The read reports the same error.
+            Err(_) => return,
+        }
+    }
+    let mut resolve = |_: &vault::MergeConflict| None;
+    if let Err(error) = vault::vault_sync_v1(path, key, &mut resolve, false) {
+        eprintln!("Warning: vault sync skipped: {error}; run `vault sync` to resolve.");
+    }
+}
+
+fn pull_on_read_enabled() -> bool {
+    if let Some(value) = std::env::var_os(PULL_ON_READ_ENV) {
+        return matches!(value.to_str().map(str::trim), Some("1" | "true" | "yes"));
+    }
+    match config::vault_config() {
+        Ok(config) => config.pull_on_read.unwrap_or(false),
+        Err(error) => {
+            eprintln!("Warning: {error}; not pulling before the read.");
+            false
+        }
+    }
+}
+
+fn resolve_merge_conflict(
+    args: &cli::MergeResolutionArgs,
+    c: &vault::MergeConflict,
+) -> Option<vault::MergeSide> {
+    match args.prefer {
+        Some(cli::MergePreference::Ours) => Some(vault::MergeSide::Ours),
+        Some(cli::MergePreference::Theirs) => Some(vault::MergeSide::Theirs),
+        Some(cli::MergePreference::Newer) => Some(if c.theirs_updated_at > c.ours_updated_at {
+            vault::MergeSide::Theirs
+        } else {
+            vault::MergeSide::Ours
+        }),
+        None if args.interactive => ask_merge_side(c),
+        None => {
+            eprintln!(
+                "Conflict: {} ({}) {}: ours {:?}, theirs {:?}",
+                c.name, c.id, c.field, c.ours, c.theirs
+            );
+            None
+        }
+    }
+}
+
+fn merge_side_str(side: vault::MergeSide) -> &'static str {
+    match side {
+        vault::MergeSide::Ours => "ours",
+        vault::MergeSide::Theirs => "theirs",
+    }
+}
+
+fn resolved_conflict_lines(report: &vault::MergeReport) -> Vec<String> {
+    report
+        .resolved
+        .iter()
+        .map(|(c, side)| format!("  {}\t{}\t{}", c.name, c.field, merge_side_str(*side)))
+        .collect()
+}
+
+fn resolved_conflicts_json(report: &vault::MergeReport) -> Vec<serde_json::Value> {
+    report
+        .resolved
+        .iter()
+        .map(|(c, side)| {
+            json!({
+                "id": c.id.to_string(),
+                "name": c.name,
+                "field": c.field,
+                "resolution": merge_side_str(*side),
+            })
+        })
+        .collect()
+}
+
+fn ask_merge_side(conflict: &vault::MergeConflict) -> Option<vault::MergeSide> {
+    loop {
+        let answer = vault::prompt_line(&format!(
+            "{} ({}) {}: keep [o]urs {:?} or take [t]heirs {:?}? ",
+            conflict.name, conflict.id, conflict.field, conflict.ours, conflict.theirs
+        ))
+        .ok()?;
+        match answer.trim() {
+            "o" | "ours" => return Some(vault::MergeSide::Ours),
+            "t" | "theirs" => return Some(vault::MergeSide::Theirs),
+            // End of input leaves the conflict unresolved.
+            "" => return None,
+            _ => continue,
+        }
+    }
+}
+
+fn run_import(args: cli::VaultImportArgs, ctx: &AppContext) -> ExitCode {
+    let path = match vault::vault_path(args.path.path.as_deref()) {
+        Ok(path) => path,
+        Err(error) => {
+            eprintln!("Error: {error}");
+            return exit_codes::exit_code_for_vault_error(&error);
+        }
+    };
+
+    let Some(format) = args.from else {
+        let key = match unlock_key(&path, &args.keyfile) {
+            Ok(key) => key,
+            Err(code) => return code,
+        };
+
+        let passphrase = match vault::prompt_bundle_passphrase() {
+            Ok(passphrase) => passphrase,
+            Err(error) => {
+                eprintln!("Error: {error}");
+                return exit_codes::exit_code_for_vault_prompt_error(&error);
+            }
+        };
+
+        let items = match vault::read_bundle_v1(&args.file, &passphrase) {
+            Ok(items) => items,
+            Err(error) => {
+                eprintln!("Error: {error}");
+                return exit_codes::exit_code_for_vault_error(&error);
+            }
+        };
+
+        return match vault::vault_import_items_v1(
+            &path,
+            &key,
+            items,
+            args.on_conflict,
+            args.dry_run,
+        ) {
+            Ok(summary) => print_import_summary(&path, &summary, None, args.dry_run, ctx),
+            Err(error) => {
+                eprintln!("Error: {error}");
+                exit_codes::exit_code_for_vault_error(&error)
+            }
+        };
+    };
+
+    let mut import = match vault::read_foreign_export_v1(&args.file, format) {
+        Ok(import) => import,
+        Err(error) => {
+            eprintln!("Error: {error}");
+            return exit_codes::exit_code_for_vault_error(&error);
+        }
+    };
+
+    // Foreign items all get fresh ids, so a dry run needs nothing from the vault.
+    if args.dry_run {
+        return print_foreign_dry_run(&path, format, &import, ctx);
+    }
+
+    let key = match unlock_key(&path, &args.keyfile) {
+        Ok(key) => key,
+        Err(code) => return code,
+    };
+
+    let items = std::mem::take(&mut import.items);
+    match vault::vault_import_items_v1(&path, &key, items, args.on_conflict, false) {
+        Ok(summary) => print_import_summary(&path, &summary, Some((format, &import)), false, ctx),
+        Err(error) => {
+            eprintln!("Error: {error}");
+            exit_codes::exit_code_for_vault_error(&error)
+        }
+    }
+}
+
+fn print_import_summary(
+    path: &Path,
+    summary: &vault::ImportSummary,
+    foreign: Option<(vault::ForeignFormat, &vault::ForeignImport)>,
+    dry_run: bool,
+    ctx: &AppContext,
+) -> ExitCode {
+    let mut lines = vec![format!(
+        "{} {} new, {} overwritten, {} duplicated, {} skipped",
+        if dry_run { "Would import" } else { "Imported" },
+        summary.added,
+        summary.overwritten,
+        summary.duplicated,
+        summary.skipped
+    )];
+    let mut meta = json!({
+        "kind": "vault-import",
+        "path": path.display().to_string(),
+        "dry_run": dry_run,
+        "added": summary.added,
+        "overwritten": summary.overwritten,
+        "duplicated": summary.duplicated,
+        "skipped": summary.skipped,
+    });
+    if let Some((format, import)) = foreign {
+        lines.extend(foreign_import_notes(import));
+        meta["format"] = json!(format.as_str());
+        meta["unmapped"] = unmapped_json(import);
+        meta["skipped_records"] = skipped_json(import);
+    }
+
+    output::print_value(lines.join("\n"), meta, &ctx.output_mode, false)
+}
+
+fn print_foreign_dry_run(
+    path: &Path,
+    format: vault::ForeignFormat,
+    import: &vault::ForeignImport,
+    ctx: &AppContext,
+) -> ExitCode {
+    let mut by_type = std::collections::BTreeMap::new();
+    for item in &import.items {
+        *by_type.entry(item.item_type.as_str()).or_insert(0usize) += 1;
+    }
+    let counts = by_type
+        .iter()
+        .map(|(t, n)| format!("{n} {t}"))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let mut lines = vec![format!(
+        "Would import {} item(s) from {}{}",
+        import.items.len(),
+        format.as_str(),
+        if counts.is_empty() {
+            String::new()
+        } else {
+            format!(" ({counts})")
+        }
+    )];
+    lines.extend(import.items.iter().map(vault_item_summary_text));
+    lines.extend(foreign_import_notes(import));
+
+    let meta = json!({
+        "kind": "vault-import",
+        "path": path.display().to_string(),
+        "dry_run": true,
+        "format": format.as_str(),
+        "added": import.items.len(),
+        "by_type": by_type,
+        "items": import.items.iter().map(vault_item_summary_json).collect::<Vec<_>>(),
+        "unmapped": unmapped_json(import),
+        "skipped_records": skipped_json(import),
+    });
+
+    output::print_value(lines.join("\n"), meta, &ctx.output_mode, false)
+}
+
+/// Text lines listing the fields and records a foreign import leaves behind.
+fn foreign_import_notes(import: &vault::ForeignImport) -> Vec<String> {
+    let mut lines = Vec::new();
+    if !import.unmapped.is_empty() {
+        lines.push(format!("Unmapped fields ({}):", import.unmapped.len()));
+        lines.extend(
+            import
+                .unmapped
+                .iter()
+                .map(|u| format!("  {}\t{}", u.item, u.field)),
+        );
+    }
+    if !import.skipped.is_empty() {
+        lines.push(format!("Skipped records ({}):", import.skipped.len()));
+        lines.extend(
+            import
+                .skipped
+                .iter()
+                .map(|s| format!("  {}\t{}", s.name, s.reason)),
+        );
+    }
+    lines
+}
+
+fn unmapped_json(import: &vault::ForeignImport) -> serde_json::Value {
+    import
+        .unmapped
+        .iter()
+        .map(|u| json!({ "item": u.item, "field": u.field }))
+        .collect()
+}
+
+fn skipped_json(import: &vault::ForeignImport) -> serde_json::Value {
+    import
+        .skipped
+        .iter()
+        .map(|s| json!({ "name": s.name, "reason": s.reason }))
+        .collect()
+}
+
+/// Tell a running agent to drop a key that no longer matches the vault.
+fn forget_agent_key(path: &Path) {
+    #[cfg(unix)]
+    if let Some(socket) = agent::socket_from_env() {
+        let _ = agent::AgentClient::new(socket).forget(path);
     }
+    #[cfg(not(unix))]
+    let _ = path;
 }
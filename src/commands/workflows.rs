@@ -23,15 +23,12 @@ pub fn env(args: cli::EnvArgs, ctx: &AppContext) -> ExitCode {
         }
     };
 
-    let master_password = match vault::prompt_master_password() {
-        Ok(pw) => pw,
-        Err(error) => {
-            eprintln!("Error: {error}");
-            return exit_codes::exit_code_for_vault_prompt_error(&error);
-        }
+    let key = match super::vault::unlock_key(&vault_path, &args.keyfile) {
+        Ok(key) => key,
+        Err(code) => return code,
     };
 
-    let items = match vault::vault_list_items_v1(&vault_path, &master_password) {
+    let items = match vault::vault_list_items_v1(&vault_path, &key) {
         Ok(items) => items,
         Err(error) => {
             eprintln!("Error: {error}");
@@ -80,15 +77,12 @@ pub fn run(args: cli::RunArgs, ctx: &AppContext) -> ExitCode {
         }
     };
 
-    let master_password = match vault::prompt_master_password() {
-        Ok(pw) => pw,
-        Err(error) => {
-            eprintln!("Error: {error}");
-            return exit_codes::exit_code_for_vault_prompt_error(&error);
-        }
+    let key = match super::vault::unlock_key(&vault_path, &args.keyfile) {
+        Ok(key) => key,
+        Err(code) => return code,
     };
 
-    let items = match vault::vault_list_items_v1(&vault_path, &master_password) {
+    let items = match vault::vault_list_items_v1(&vault_path, &key) {
         Ok(items) => items,
         Err(error) => {
             eprintln!("Error: {error}");
@@ -159,15 +153,12 @@ pub fn inject(args: cli::InjectArgs, ctx: &AppContext) -> ExitCode {
         }
     };
 
-    let master_password = match vault::prompt_master_password() {
-        Ok(pw) => pw,
-        Err(error) => {
-            eprintln!("Error: {error}");
-            return exit_codes::exit_code_for_vault_prompt_error(&error);
-        }
+    let key = match super::vault::unlock_key(&vault_path, &args.keyfile) {
+        Ok(key) => key,
+        Err(code) => return code,
     };
 
-    let items = match vault::vault_list_items_v1(&vault_path, &master_password) {
+    let items = match vault::vault_list_items_v1(&vault_path, &key) {
         Ok(items) => items,
         Err(error) => {
             eprintln!("Error: {error}");
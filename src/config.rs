@@ -64,6 +64,8 @@ struct FileConfig {
     schema_version: Option<u32>,
     #[serde(default)]
     profiles: HashMap<String, PasswordConfig>,
+    #[serde(default, skip_serializing_if = "VaultConfig::is_default")]
+    vault: VaultConfig,
 }
 
 impl Default for FileConfig {
@@ -71,10 +73,31 @@ impl Default for FileConfig {
         Self {
             schema_version: Some(CURRENT_SCHEMA_VERSION),
             profiles: HashMap::new(),
+            vault: VaultConfig::default(),
         }
     }
 }
 
+/// Settings from the `[vault]` table; unset values keep the built-in defaults.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VaultConfig {
+    /// Number of backups kept on each write; `0` disables backups.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backups: Option<usize>,
+    /// Backups older than this many days are deleted on the next write.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backup_max_age_days: Option<u64>,
+    /// Pull and merge a synced vault before commands that read it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pull_on_read: Option<bool>,
+}
+
+impl VaultConfig {
+    fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
 impl FileConfig {
     fn schema_version(&self) -> u32 {
         self.schema_version.unwrap_or(0)
@@ -86,15 +109,24 @@ impl FileConfig {
 }
 
 pub fn config_path() -> Result<PathBuf, ConfigError> {
+    let path = resolve_config_path()?;
+    if env::var_os(CONFIG_ENV).is_none()
+        && let Some(dir) = path.parent()
+    {
+        fs::create_dir_all(dir).map_err(ConfigError::Io)?;
+    }
+    Ok(path)
+}
+
+fn resolve_config_path() -> Result<PathBuf, ConfigError> {
     if let Ok(path) = env::var(CONFIG_ENV) {
         return Ok(PathBuf::from(path));
     }
 
-    let mut dir = config_dir().ok_or(ConfigError::ConfigDirUnavailable)?;
-    dir.push(APP_DIR);
-    fs::create_dir_all(&dir).map_err(ConfigError::Io)?;
-    dir.push(CONFIG_FILE_NAME);
-    Ok(dir)
+    let mut path = config_dir().ok_or(ConfigError::ConfigDirUnavailable)?;
+    path.push(APP_DIR);
+    path.push(CONFIG_FILE_NAME);
+    Ok(path)
 }
 
 fn load_config(path: &Path) -> Result<FileConfig, ConfigError> {
@@ -246,6 +278,17 @@ pub fn remove_profile(name: &str) -> Result<(), ConfigError> {
     persist_config(&path, &config)
 }
 
+/// The `[vault]` settings. Unlike the profile functions this never creates
+/// the config directory, and a platform without one simply has no settings.
+pub fn vault_config() -> Result<VaultConfig, ConfigError> {
+    let path = match resolve_config_path() {
+        Ok(path) => path,
+        Err(ConfigError::ConfigDirUnavailable) => return Ok(VaultConfig::default()),
+        Err(err) => return Err(err),
+    };
+    Ok(load_config(&path)?.vault)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -290,6 +333,30 @@ include_symbols = false
         assert_eq!(backup_contents, old_config);
     }
 
+    #[test]
+    fn vault_settings_round_trip_and_are_omitted_when_unset() {
+        let dir = tempdir().expect("temp dir");
+        let path = dir.path().join("config.toml");
+
+        fs::write(
+            &path,
+            "schema_version = 2\n\n[vault]\nbackups = 3\nbackup_max_age_days = 30\npull_on_read = true\n",
+        )
+        .expect("write config");
+        let config = load_config(&path).expect("load config");
+        assert_eq!(
+            config.vault,
+            VaultConfig {
+                backups: Some(3),
+                backup_max_age_days: Some(30),
+                pull_on_read: Some(true),
+            }
+        );
+
+        let toml = toml::to_string_pretty(&FileConfig::default()).expect("serialize");
+        assert!(!toml.contains("[vault]"));
+    }
+
     #[test]
     fn default_config_sets_schema_version() {
         let config = FileConfig::default();
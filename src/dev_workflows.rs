@@ -1,6 +1,6 @@
 use crate::vault;
-use std::collections::BTreeMap;
-use std::path::Path;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -13,8 +13,19 @@ pub enum DevWorkflowError {
 
     #[error("template references unknown variable: {0}")]
     UnknownVariable(String),
+
+    #[error(
+        "item {item}: field {field:?} cannot be written to CSV (names may not contain ':' and values may not span lines)"
+    )]
+    CsvField { item: String, field: String },
+
+    #[error("failed to write CSV: {0}")]
+    Csv(#[from] csv::Error),
 }
 
+/// Variables for the items filed under `profile`: an item contributes
+/// `name=secret`, or, when it has custom fields, one variable per field
+/// instead (so one item can carry `DB_HOST`, `DB_USER` and `DB_PASSWORD`).
 pub fn env_vars_for_profile(
     items: &[vault::VaultItemV1],
     profile: &str,
@@ -22,10 +33,16 @@ pub fn env_vars_for_profile(
     let mut vars = BTreeMap::new();
 
     for item in items {
-        if item.path.as_deref() != Some(profile) {
+        if item.is_trashed() || item.path.as_deref() != Some(profile) {
             continue;
         }
-        vars.insert(item.name.clone(), item.secret.clone());
+        if item.fields.is_empty() {
+            vars.insert(item.name.clone(), item.secret.clone());
+        } else {
+            for field in &item.fields {
+                vars.insert(field.name.clone(), field.value.clone());
+            }
+        }
     }
 
     vars
@@ -87,11 +104,177 @@ pub fn render_template(
     Ok(out)
 }
 
+/// Columns of a plaintext CSV export, in order. The names match what
+/// `vault import --from csv` reads back.
+pub const CSV_EXPORT_COLUMNS: [&str; 13] = [
+    "id",
+    "type",
+    "path",
+    "name",
+    "username",
+    "secret",
+    "urls",
+    "notes",
+    "tags",
+    "fields",
+    "concealed_fields",
+    "created_at",
+    "updated_at",
+];
+
+/// Render items as CSV, secrets included.
+///
+/// Custom fields go one `name: value` per line (Bitwarden's layout), plain
+/// and concealed ones in separate columns. A field that layout cannot hold
+/// is an error rather than silently dropped.
+pub fn items_csv(items: &[vault::VaultItemV1]) -> Result<Vec<u8>, DevWorkflowError> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record(CSV_EXPORT_COLUMNS)?;
+    for item in items {
+        writer.write_record([
+            item.id.to_string().as_str(),
+            item.item_type.as_str(),
+            item.path.as_deref().unwrap_or_default(),
+            &item.name,
+            item.username.as_deref().unwrap_or_default(),
+            &item.secret,
+            &item.urls.join(","),
+            item.notes.as_deref().unwrap_or_default(),
+            &item.tags.join(","),
+            &csv_fields(item, false)?,
+            &csv_fields(item, true)?,
+            &item.created_at.to_string(),
+            &item.updated_at.to_string(),
+        ])?;
+    }
+    writer
+        .into_inner()
+        .map_err(|e| csv::Error::from(e.into_error()).into())
+}
+
+fn csv_fields(item: &vault::VaultItemV1, concealed: bool) -> Result<String, DevWorkflowError> {
+    let mut lines = Vec::new();
+    for field in item.fields.iter().filter(|f| f.concealed == concealed) {
+        if field.name.contains([':', '\n', '\r']) || field.value.contains(['\n', '\r']) {
+            return Err(DevWorkflowError::CsvField {
+                item: item.name.clone(),
+                field: field.name.clone(),
+            });
+        }
+        lines.push(format!("{}: {}", field.name, field.value));
+    }
+    Ok(lines.join("\n"))
+}
+
+/// One `pass`-style entry: the secret on the first line, then `key: value`
+/// lines, then free-form notes.
+pub fn pass_entry_text(item: &vault::VaultItemV1) -> String {
+    let mut out = String::new();
+    out.push_str(&item.secret);
+    out.push('\n');
+    if let Some(username) = item.username.as_deref() {
+        out.push_str(&format!("login: {username}\n"));
+    }
+    for url in &item.urls {
+        out.push_str(&format!("url: {url}\n"));
+    }
+    for field in &item.fields {
+        out.push_str(&format!("{}: {}\n", field.name, field.value));
+    }
+    out.push_str(&format!("type: {}\n", item.item_type.as_str()));
+    if !item.tags.is_empty() {
+        out.push_str(&format!("tags: {}\n", item.tags.join(", ")));
+    }
+    if let Some(notes) = item.notes.as_deref() {
+        out.push('\n');
+        out.push_str(notes);
+        if !notes.ends_with('\n') {
+            out.push('\n');
+        }
+    }
+    out
+}
+
+/// Relative file path for each item in a `pass`-style tree: `path/name`,
+/// with segments that could escape the tree replaced and clashing names
+/// suffixed with the start of the item id.
+pub fn pass_entry_paths(items: &[vault::VaultItemV1]) -> Vec<PathBuf> {
+    let mut seen = BTreeSet::new();
+    items
+        .iter()
+        .map(|item| {
+            let mut rel = item
+                .path
+                .as_deref()
+                .unwrap_or_default()
+                .split('/')
+                .filter(|s| !s.trim().is_empty())
+                .map(pass_path_segment)
+                .collect::<PathBuf>();
+            let name = pass_path_segment(&item.name);
+            if seen.contains(&rel.join(&name)) {
+                let id = item.id.simple().to_string();
+                rel.push(format!("{name}-{}", &id[..8]));
+            } else {
+                rel.push(name);
+            }
+            seen.insert(rel.clone());
+            rel
+        })
+        .collect()
+}
+
+/// Write every item as a file under `dir` (see [`pass_entry_paths`]).
+/// Directories are created `0700` and files `0600`. Returns the files written.
+pub fn write_pass_dir(
+    dir: &Path,
+    items: &[vault::VaultItemV1],
+) -> Result<Vec<PathBuf>, vault::io::VaultIoError> {
+    let mut written = Vec::with_capacity(items.len());
+    for (item, rel) in items.iter().zip(pass_entry_paths(items)) {
+        let path = dir.join(rel);
+        if let Some(parent) = path.parent() {
+            create_private_dir_all(parent)?;
+        }
+        write_sensitive_file_atomic(&path, pass_entry_text(item).as_bytes())?;
+        written.push(path);
+    }
+    Ok(written)
+}
+
 pub fn write_sensitive_file_atomic(
     path: &Path,
     contents: &[u8],
 ) -> Result<(), vault::io::VaultIoError> {
-    vault::io::write_vault_bytes_atomic_unlocked(path, contents)
+    vault::io::write_sensitive_bytes_atomic(path, contents)
+}
+
+fn create_private_dir_all(dir: &Path) -> std::io::Result<()> {
+    let mut builder = std::fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt as _;
+        builder.mode(0o700);
+    }
+    builder.create(dir)
+}
+
+fn pass_path_segment(segment: &str) -> String {
+    let segment = segment.trim();
+    if segment.is_empty() || segment == "." || segment == ".." {
+        return "_".to_string();
+    }
+    segment
+        .chars()
+        .map(|c| {
+            if matches!(c, '/' | '\\' | '\0') {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect()
 }
 
 fn is_valid_env_var_name(s: &str) -> bool {
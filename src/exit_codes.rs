@@ -4,6 +4,9 @@ use std::process::ExitCode;
 pub const EXIT_USAGE: u8 = 64;
 pub const EXIT_IO: u8 = 2;
 pub const EXIT_SOFTWARE: u8 = 1;
+/// `vault due` ran fine but found overdue items, so scripts and CI jobs can
+/// tell "something needs rotating" apart from a failure.
+pub const EXIT_OVERDUE: u8 = 3;
 
 pub fn exit_code_for_config_error(error: &config::ConfigError) -> ExitCode {
     use config::ConfigError::*;
@@ -69,11 +72,60 @@ pub fn exit_code_for_vault_error(error: &vault::VaultError) -> ExitCode {
     use vault::VaultError::*;
 
     match error {
-        VaultDirUnavailable | Io(_) => ExitCode::from(EXIT_IO),
-        AlreadyExists(_) | NotInitialized | AuthFailed | ItemNotFound(_) | Prompt(_) => {
-            ExitCode::from(EXIT_USAGE)
+        VaultDirUnavailable
+        | Io(_)
+        | KeyfileRead { .. }
+        | ForeignImport(vault::ForeignImportError::Read { .. })
+        | Sync(
+            vault::SyncError::Spawn(_)
+            | vault::SyncError::Io(_)
+            | vault::SyncError::Git { .. }
+            | vault::SyncError::CommitAfterSave(_),
+        ) => ExitCode::from(EXIT_IO),
+        AlreadyExists(_)
+        | NotInitialized
+        | AuthFailed
+        | StaleSessionKey
+        | KeyfileRequired
+        | EmptyKeyfile(_)
+        | InvalidRecoveryKey
+        | InvalidKdfParams(_)
+        | ItemNotFound(_)
+        | AmbiguousItem { .. }
+        | RevisionNotFound { .. }
+        | BackupNotFound(_)
+        | MergeConflicts(_)
+        | FieldNotFound(_)
+        | AttachmentNotFound(_)
+        | InvalidAttachmentName(_)
+        | AttachmentTooLarge { .. }
+        | InvalidAttachmentLimit(_)
+        | NotTotpItem(_)
+        | Totp(_)
+        | SshKey(_)
+        | Query(_)
+        | Bundle(_)
+        | ForeignImport(_)
+        | Sync(_)
+        | KeySlotNotFound(_)
+        | NoFreeKeySlot(_)
+        | LastKeySlot
+        | OtherKeySlots(_)
+        | Prompt(_) => ExitCode::from(EXIT_USAGE),
+        UnsupportedPayloadSchema(_) | AttachmentCorrupt(_) | Crypto(_) | Format(_) | Json(_) => {
+            ExitCode::from(EXIT_SOFTWARE)
         }
-        UnsupportedPayloadSchema(_) | Crypto(_) | Format(_) | Json(_) => {
+    }
+}
+
+#[cfg(unix)]
+pub fn exit_code_for_agent_error(error: &crate::agent::AgentError) -> ExitCode {
+    use crate::agent::AgentError::*;
+
+    match error {
+        Io(_) | NoResponse | InsecureDir(_) => ExitCode::from(EXIT_IO),
+        SocketInUse(_) => ExitCode::from(EXIT_USAGE),
+        Json(_) | MessageTooLarge(_) | Remote(_) | UnexpectedResponse => {
             ExitCode::from(EXIT_SOFTWARE)
         }
     }
@@ -1,3 +1,5 @@
+#[cfg(unix)]
+pub mod agent;
 pub mod app;
 pub mod cli;
 mod commands;
@@ -8,6 +10,7 @@ mod exit_codes;
 mod output;
 pub mod passphrase;
 pub mod password;
+pub mod timespec;
 pub mod token;
 mod tui;
 pub mod vault;
@@ -6,7 +6,7 @@ pub fn vault_item_summary_text(item: &vault::VaultItemV1) -> String {
     format!(
         "{}\t{}\t{}\t{}",
         item.id,
-        vault_item_type_str(item.item_type),
+        item.item_type.as_str(),
         path,
         item.name
     )
@@ -15,7 +15,7 @@ pub fn vault_item_summary_text(item: &vault::VaultItemV1) -> String {
 pub fn vault_item_summary_json(item: &vault::VaultItemV1) -> serde_json::Value {
     json!({
         "id": item.id.to_string(),
-        "type": vault_item_type_str(item.item_type),
+        "type": item.item_type.as_str(),
         "name": item.name.as_str(),
         "path": item.path.as_deref(),
         "tags": &item.tags,
@@ -23,14 +23,76 @@ pub fn vault_item_summary_json(item: &vault::VaultItemV1) -> serde_json::Value {
         "urls": &item.urls,
         "created_at": item.created_at,
         "updated_at": item.updated_at,
+        "due": item_due_json(item, now_unix_seconds(), vault::DUE_SOON_WINDOW.as_secs()),
     })
 }
 
+/// Expiry/rotation state of an item, or `null` when it tracks neither.
+pub fn item_due_json(item: &vault::VaultItemV1, now: u64, within: u64) -> serde_json::Value {
+    match item.due(now, within) {
+        Some(due) => json!({
+            "state": due.state.as_str(),
+            "reason": due.reason.as_str(),
+            "due_at": due.due_at,
+        }),
+        None => serde_json::Value::Null,
+    }
+}
+
+/// `overdue 3d` / `due in 5d`, rounded to whole days (hours when under one).
+pub fn due_relative_text(due_at: u64, now: u64) -> String {
+    let span = |secs: u64| match secs / 86_400 {
+        0 => format!("{}h", secs / 3_600),
+        days => format!("{days}d"),
+    };
+    if due_at <= now {
+        format!("overdue {}", span(now - due_at))
+    } else {
+        format!("due in {}", span(due_at - now))
+    }
+}
+
+/// Whole days as `90d`, anything else in seconds.
+fn duration_text(secs: u64) -> String {
+    if secs.is_multiple_of(86_400) {
+        format!("{}d", secs / 86_400)
+    } else {
+        format!("{secs}s")
+    }
+}
+
+fn now_unix_seconds() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
 pub fn vault_item_json(item: &vault::VaultItemV1, reveal: bool) -> serde_json::Value {
+    let mut value = vault_item_fields_json(item, reveal);
+    if !item.fields.is_empty() {
+        value["fields"] = custom_fields_json(&item.fields, reveal);
+    }
+    if !item.attachments.is_empty() {
+        value["attachments"] = item.attachments.iter().map(attachment_json).collect();
+    }
+    if let Some(key) = ssh_public_key(item) {
+        value["public_key"] = json!(key.public_key_line());
+        value["fingerprint"] = json!(key.fingerprint());
+    }
+    if item.expires_at.is_some() || item.rotate_every.is_some() {
+        value["expires_at"] = json!(item.expires_at);
+        value["rotate_every"] = json!(item.rotate_every);
+        value["secret_changed_at"] = json!(item.secret_changed_at.unwrap_or(item.created_at));
+        value["due"] = item_due_json(item, now_unix_seconds(), vault::DUE_SOON_WINDOW.as_secs());
+    }
+    value
+}
+
+fn vault_item_fields_json(item: &vault::VaultItemV1, reveal: bool) -> serde_json::Value {
     if reveal {
         json!({
             "id": item.id.to_string(),
-            "type": vault_item_type_str(item.item_type),
+            "type": item.item_type.as_str(),
             "name": item.name.as_str(),
             "path": item.path.as_deref(),
             "tags": &item.tags,
@@ -44,7 +106,7 @@ pub fn vault_item_json(item: &vault::VaultItemV1, reveal: bool) -> serde_json::V
     } else {
         json!({
             "id": item.id.to_string(),
-            "type": vault_item_type_str(item.item_type),
+            "type": item.item_type.as_str(),
             "name": item.name.as_str(),
             "path": item.path.as_deref(),
             "tags": &item.tags,
@@ -61,7 +123,7 @@ pub fn vault_item_json(item: &vault::VaultItemV1, reveal: bool) -> serde_json::V
 pub fn vault_item_text(item: &vault::VaultItemV1, reveal: bool) -> String {
     let mut out = String::new();
     out.push_str(&format!("id:\t{}\n", item.id));
-    out.push_str(&format!("type:\t{}\n", vault_item_type_str(item.item_type)));
+    out.push_str(&format!("type:\t{}\n", item.item_type.as_str()));
     out.push_str(&format!("name:\t{}\n", item.name));
     if let Some(path) = &item.path {
         out.push_str(&format!("path:\t{}\n", path));
@@ -78,19 +140,122 @@ pub fn vault_item_text(item: &vault::VaultItemV1, reveal: bool) -> String {
     if let Some(notes) = &item.notes {
         out.push_str(&format!("notes:\t{}\n", notes));
     }
+    for field in &item.fields {
+        let value = if field.concealed && !reveal {
+            "[REDACTED]"
+        } else {
+            &field.value
+        };
+        out.push_str(&format!("field.{}:\t{}\n", field.name, value));
+    }
+    for attachment in &item.attachments {
+        out.push_str(&format!(
+            "attachment.{}:\t{} bytes, sha256 {}\n",
+            attachment.name, attachment.size, attachment.sha256
+        ));
+    }
+    if let Some(key) = ssh_public_key(item) {
+        out.push_str(&format!("public_key:\t{}\n", key.public_key_line()));
+        out.push_str(&format!("fingerprint:\t{}\n", key.fingerprint()));
+    }
     out.push_str(&format!(
         "secret:\t{}\n",
         if reveal { &item.secret } else { "[REDACTED]" }
     ));
+    if let Some(expires_at) = item.expires_at {
+        out.push_str(&format!("expires_at:\t{}\n", expires_at));
+    }
+    if let Some(rotate_every) = item.rotate_every {
+        out.push_str(&format!("rotate_every:\t{}\n", duration_text(rotate_every)));
+    }
+    if let Some(due) = item.due(now_unix_seconds(), vault::DUE_SOON_WINDOW.as_secs()) {
+        out.push_str(&format!(
+            "due:\t{} ({}, {})\n",
+            due.state.as_str(),
+            due.reason.as_str(),
+            due_relative_text(due.due_at, now_unix_seconds())
+        ));
+    }
     out.push_str(&format!("created_at:\t{}\n", item.created_at));
     out.push_str(&format!("updated_at:\t{}", item.updated_at));
     out
 }
 
-pub fn vault_item_type_str(t: vault::VaultItemType) -> &'static str {
-    match t {
-        vault::VaultItemType::Login => "login",
-        vault::VaultItemType::SecureNote => "secure-note",
-        vault::VaultItemType::ApiToken => "api-token",
+/// Attachment metadata; the contents are only written out by
+/// `vault get --attachment`.
+pub fn attachment_json(attachment: &vault::VaultAttachment) -> serde_json::Value {
+    json!({
+        "name": attachment.name.as_str(),
+        "size": attachment.size,
+        "sha256": attachment.sha256.as_str(),
+        "added_at": attachment.added_at,
+    })
+}
+
+/// Custom fields in order; concealed values are redacted unless `reveal`.
+fn custom_fields_json(fields: &[vault::VaultItemField], reveal: bool) -> serde_json::Value {
+    fields
+        .iter()
+        .map(|field| {
+            if field.concealed && !reveal {
+                json!({
+                    "name": field.name.as_str(),
+                    "concealed": true,
+                    "value_redacted": true,
+                })
+            } else {
+                json!({
+                    "name": field.name.as_str(),
+                    "concealed": field.concealed,
+                    "value": field.value.as_str(),
+                })
+            }
+        })
+        .collect()
+}
+
+/// The parsed key of an `ssh-key` item, for showing its public half.
+fn ssh_public_key(item: &vault::VaultItemV1) -> Option<vault::SshKey> {
+    if item.item_type != vault::VaultItemType::SshKey {
+        return None;
     }
+    vault::SshKey::parse(&item.secret).ok()
+}
+
+pub fn vault_item_revision_json(revision: &vault::VaultItemRevision) -> serde_json::Value {
+    json!({
+        "rev": revision.rev,
+        "type": revision.item_type.as_str(),
+        "name": revision.name.as_str(),
+        "path": revision.path.as_deref(),
+        "tags": &revision.tags,
+        "username": revision.username.as_deref(),
+        "secret_redacted": true,
+        "urls": &revision.urls,
+        "notes": revision.notes.as_deref(),
+        "fields": custom_fields_json(&revision.fields, false),
+        "expires_at": revision.expires_at,
+        "rotate_every": revision.rotate_every,
+        "updated_at": revision.updated_at,
+    })
+}
+
+/// One line per revision, newest first; the current state is marked with `*`.
+pub fn vault_item_history_text(item: &vault::VaultItemV1) -> String {
+    let current = item.snapshot();
+    std::iter::once((&current, true))
+        .chain(item.history.iter().rev().map(|r| (r, false)))
+        .map(|(r, is_current)| {
+            format!(
+                "{}{}\t{}\t{}\t{}\t{}",
+                if is_current { "*" } else { " " },
+                r.rev,
+                r.updated_at,
+                r.item_type.as_str(),
+                r.path.as_deref().unwrap_or(""),
+                r.name
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
 }
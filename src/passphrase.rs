@@ -23,6 +23,18 @@ pub struct PassphraseConfig {
     pub wordlist: Option<PathBuf>,
 }
 
+impl Default for PassphraseConfig {
+    /// The `passphrase` command's defaults.
+    fn default() -> Self {
+        Self {
+            word_count: 6,
+            separator: "-".to_string(),
+            title_case: false,
+            wordlist: None,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum PassphraseError {
     WordCountZero,
@@ -274,6 +274,7 @@ fn filtered_chars(chars: Vec<char>, allow_ambiguous: bool) -> Vec<char> {
 }
 
 #[cfg(test)]
+#[allow(clippy::field_reassign_with_default)]
 mod tests {
     use super::*;
     use rand::rngs::mock::StepRng;
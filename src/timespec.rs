@@ -0,0 +1,75 @@
+//! Durations and dates as typed on the command line and in vault queries.
+
+use std::time::Duration;
+
+/// Parse a duration such as `90`, `90s`, `15m`, `2h` or `30d` (bare numbers are seconds).
+pub fn parse_duration(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("invalid duration '{value}' (expected e.g. 90s, 15m, 2h, 30d)"))?;
+    let seconds_per_unit = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => return Err(format!("unknown duration unit '{unit}' (use s, m, h or d)")),
+    };
+    number
+        .checked_mul(seconds_per_unit)
+        .map(Duration::from_secs)
+        .ok_or_else(|| format!("duration '{value}' is too large"))
+}
+
+/// Parse a `YYYY-MM-DD` date as the unix time of its midnight UTC.
+pub fn parse_date(value: &str) -> Result<u64, String> {
+    let invalid = || format!("invalid date '{value}' (expected YYYY-MM-DD)");
+    let mut parts = value.trim().splitn(3, '-');
+    let mut next = |len: usize| {
+        parts
+            .next()
+            .filter(|p| p.len() == len && p.bytes().all(|b| b.is_ascii_digit()))
+            .and_then(|p| p.parse::<u64>().ok())
+            .ok_or_else(invalid)
+    };
+    let (year, month, day) = (next(4)?, next(2)?, next(2)?);
+
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let days_in_month = [
+        31,
+        if leap { 29 } else { 28 },
+        31,
+        30,
+        31,
+        30,
+        31,
+        31,
+        30,
+        31,
+        30,
+        31,
+    ];
+    if year < 1970
+        || !(1..=12).contains(&month)
+        || day == 0
+        || day > days_in_month[month as usize - 1]
+    {
+        return Err(invalid());
+    }
+
+    let days_before_year = (1970..year)
+        .map(|y| {
+            if y % 4 == 0 && (y % 100 != 0 || y % 400 == 0) {
+                366
+            } else {
+                365
+            }
+        })
+        .sum::<u64>();
+    let days_before_month = days_in_month[..month as usize - 1].iter().sum::<u64>();
+    Ok((days_before_year + days_before_month + day - 1) * 24 * 60 * 60)
+}
@@ -272,11 +272,9 @@ fn render_splash(frame: &mut Frame, area: Rect, state: &AppState) {
 
     // How many columns of the banner to reveal (left-to-right wipe)
     let progress = state.splash.tick.min(SPLASH_TOTAL_TICKS);
-    let cols_to_show = if SPLASH_TOTAL_TICKS > 0 {
-        (banner_width * progress) / SPLASH_TOTAL_TICKS
-    } else {
-        banner_width
-    };
+    let cols_to_show = (banner_width * progress)
+        .checked_div(SPLASH_TOTAL_TICKS)
+        .unwrap_or(banner_width);
 
     // Gradient colors for the reveal — cycles through these
     let colors = [
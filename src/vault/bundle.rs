@@ -0,0 +1,259 @@
+//! Passphrase-encrypted export bundles.
+//!
+//! A bundle is a self-describing JSON document:
+//!
+//! ```json
+//! {
+//!   "format": "passworder-bundle",
+//!   "version": 1,
+//!   "kdf": {"alg": "argon2id", "memory_kib": 65536, "iterations": 3, "parallelism": 1, "salt": "<base64>"},
+//!   "aead": {"alg": "xchacha20poly1305", "nonce": "<base64>"},
+//!   "ciphertext": "<base64>"
+//! }
+//! ```
+//!
+//! Everything but `ciphertext` is bound as AAD, so the parameters cannot be
+//! swapped without failing authentication. The plaintext is a JSON
+//! [`BundleContents`] holding the exported items.
+
+use crate::vault::{crypto, items};
+use base64::Engine as _;
+use base64::engine::general_purpose::STANDARD;
+use secrecy::SecretString;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+pub const BUNDLE_FORMAT: &str = "passworder-bundle";
+pub const BUNDLE_VERSION: u32 = 1;
+
+/// Refuse bundles asking for more Argon2 memory than this (4 GiB), so a
+/// crafted file cannot exhaust memory before authentication fails.
+const MAX_KDF_MEMORY_KIB: u32 = 4 * 1024 * 1024;
+
+const KDF_ALG_ARGON2ID: &str = "argon2id";
+const AEAD_ALG_XCHACHA20POLY1305: &str = "xchacha20poly1305";
+
+#[derive(Debug, Error)]
+pub enum BundleError {
+    #[error("not a passworder bundle")]
+    UnknownFormat,
+
+    #[error("unsupported bundle version {0}")]
+    UnsupportedVersion(u32),
+
+    #[error("invalid bundle field: {0}")]
+    InvalidField(&'static str),
+
+    #[error("wrong passphrase or bundle corrupted")]
+    AuthFailed,
+
+    #[error("bundle items use unsupported payload schema version {0}")]
+    UnsupportedPayloadSchema(u32),
+
+    #[error(transparent)]
+    Crypto(#[from] crypto::CryptoError),
+
+    #[error("invalid bundle JSON: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+/// The decrypted body of a bundle.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BundleContents {
+    /// Payload schema the items were written with (see `items`).
+    pub schema_version: u32,
+    pub exported_at: u64,
+    pub items: Vec<items::VaultItemV1>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct BundleHeader {
+    format: String,
+    version: u32,
+    kdf: BundleKdf,
+    aead: BundleAead,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct BundleKdf {
+    alg: String,
+    memory_kib: u32,
+    iterations: u32,
+    parallelism: u32,
+    salt: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct BundleAead {
+    alg: String,
+    nonce: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct BundleFile {
+    #[serde(flatten)]
+    header: BundleHeader,
+    ciphertext: String,
+}
+
+/// Encrypt `contents` under `passphrase` and return the bundle JSON.
+pub fn seal_bundle(
+    contents: &BundleContents,
+    passphrase: &SecretString,
+    kdf_params: crypto::KdfParams,
+) -> Result<Vec<u8>, BundleError> {
+    let salt = crypto::random_bytes::<16>();
+    let nonce = crypto::random_bytes::<{ crypto::XCHACHA_NONCE_LEN }>();
+    let header = BundleHeader {
+        format: BUNDLE_FORMAT.to_string(),
+        version: BUNDLE_VERSION,
+        kdf: BundleKdf {
+            alg: KDF_ALG_ARGON2ID.to_string(),
+            memory_kib: kdf_params.memory_kib,
+            iterations: kdf_params.iterations,
+            parallelism: kdf_params.parallelism,
+            salt: STANDARD.encode(salt),
+        },
+        aead: BundleAead {
+            alg: AEAD_ALG_XCHACHA20POLY1305.to_string(),
+            nonce: STANDARD.encode(nonce),
+        },
+    };
+
+    let key = bundle_key(passphrase, &salt, kdf_params)?;
+    let aad = serde_json::to_vec(&header)?;
+    let plaintext = zeroize::Zeroizing::new(serde_json::to_vec(contents)?);
+    let ciphertext = crypto::encrypt_payload(&key, &nonce, &aad, &plaintext)?;
+
+    let file = BundleFile {
+        header,
+        ciphertext: STANDARD.encode(ciphertext),
+    };
+    let mut out = serde_json::to_vec_pretty(&file)?;
+    out.push(b'\n');
+    Ok(out)
+}
+
+/// Decrypt a bundle produced by [`seal_bundle`].
+pub fn open_bundle(bytes: &[u8], passphrase: &SecretString) -> Result<BundleContents, BundleError> {
+    let value: serde_json::Value = serde_json::from_slice(bytes)?;
+    if value.get("format").and_then(serde_json::Value::as_str) != Some(BUNDLE_FORMAT) {
+        return Err(BundleError::UnknownFormat);
+    }
+    let file: BundleFile = serde_json::from_value(value)?;
+    let header = &file.header;
+    if header.version != BUNDLE_VERSION {
+        return Err(BundleError::UnsupportedVersion(header.version));
+    }
+    if header.kdf.alg != KDF_ALG_ARGON2ID {
+        return Err(BundleError::InvalidField("kdf.alg"));
+    }
+    if header.aead.alg != AEAD_ALG_XCHACHA20POLY1305 {
+        return Err(BundleError::InvalidField("aead.alg"));
+    }
+
+    let salt = decode_field(&header.kdf.salt, "kdf.salt")?;
+    let nonce = crypto::nonce_from_slice(&decode_field(&header.aead.nonce, "aead.nonce")?)
+        .map_err(|_| BundleError::InvalidField("aead.nonce"))?;
+    let ciphertext = decode_field(&file.ciphertext, "ciphertext")?;
+    let kdf_params = crypto::KdfParams {
+        memory_kib: header.kdf.memory_kib,
+        iterations: header.kdf.iterations,
+        parallelism: header.kdf.parallelism,
+    };
+    if kdf_params.memory_kib > MAX_KDF_MEMORY_KIB {
+        return Err(BundleError::InvalidField("kdf.memory_kib"));
+    }
+    kdf_params
+        .validate()
+        .map_err(|_| BundleError::InvalidField("kdf"))?;
+
+    let key = bundle_key(passphrase, &salt, kdf_params)?;
+    let aad = serde_json::to_vec(header)?;
+    let plaintext =
+        crypto::decrypt_payload(&key, &nonce, &aad, &ciphertext).map_err(|e| match e {
+            crypto::CryptoError::Aead => BundleError::AuthFailed,
+            other => BundleError::Crypto(other),
+        })?;
+
+    let contents: BundleContents = serde_json::from_slice(&plaintext)?;
+    if contents.schema_version == 0
+        || contents.schema_version > items::CURRENT_PAYLOAD_SCHEMA_VERSION
+    {
+        return Err(BundleError::UnsupportedPayloadSchema(
+            contents.schema_version,
+        ));
+    }
+    Ok(contents)
+}
+
+fn bundle_key(
+    passphrase: &SecretString,
+    salt: &[u8],
+    kdf_params: crypto::KdfParams,
+) -> Result<crypto::SecretBytes, BundleError> {
+    let kdf_out = crypto::derive_kdf_out_from_password(passphrase, salt, kdf_params)?;
+    Ok(crypto::derive_kek(&kdf_out, None)?)
+}
+
+fn decode_field(value: &str, field: &'static str) -> Result<Vec<u8>, BundleError> {
+    STANDARD
+        .decode(value)
+        .map_err(|_| BundleError::InvalidField(field))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn passphrase(s: &str) -> SecretString {
+        SecretString::new(s.to_string().into_boxed_str())
+    }
+
+    fn contents() -> BundleContents {
+        BundleContents {
+            schema_version: items::CURRENT_PAYLOAD_SCHEMA_VERSION,
+            exported_at: 1,
+            items: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn bundle_roundtrips_and_rejects_wrong_passphrase() {
+        let bytes = seal_bundle(
+            &contents(),
+            &passphrase("share"),
+            crypto::KdfParams::for_tests(),
+        )
+        .unwrap();
+
+        let opened = open_bundle(&bytes, &passphrase("share")).unwrap();
+        assert_eq!(opened.exported_at, 1);
+        assert!(matches!(
+            open_bundle(&bytes, &passphrase("other")),
+            Err(BundleError::AuthFailed)
+        ));
+    }
+
+    #[test]
+    fn header_fields_are_authenticated() {
+        let bytes = seal_bundle(
+            &contents(),
+            &passphrase("share"),
+            crypto::KdfParams::for_tests(),
+        )
+        .unwrap();
+        let mut value: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        value["kdf"]["iterations"] = serde_json::json!(2);
+        let tampered = serde_json::to_vec(&value).unwrap();
+
+        assert!(matches!(
+            open_bundle(&tampered, &passphrase("share")),
+            Err(BundleError::AuthFailed)
+        ));
+        assert!(matches!(
+            open_bundle(b"{\"format\":\"other\"}", &passphrase("share")),
+            Err(BundleError::UnknownFormat)
+        ));
+    }
+}
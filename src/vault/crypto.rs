@@ -8,8 +8,14 @@
 //!
 //! - KDF: Argon2id derives `kdf_out` from the master password + per-vault salt.
 //! - Key separation: HKDF-SHA256 derives independent subkeys from `kdf_out`.
+//! - Keyfile (optional second factor): the SHA-256 digest of the keyfile is
+//!   used as the HKDF salt, so the KEK depends on both the password and the file.
 //! - Key hierarchy: a randomly generated DEK encrypts the vault payload; the
 //!   DEK is wrapped (encrypted) with a KEK derived from the master password.
+//! - Per-item keys (v2): HKDF-SHA256 derives one key per item, and one for
+//!   the manifest, from the DEK, so records can be sealed and opened on their own.
+//! - Recovery key: 160 random bits rendered as grouped base32; it is used in
+//!   place of a password for its own key slot and never stored.
 //! - AEAD: XChaCha20-Poly1305 provides authenticated encryption (confidentiality
 //!   + integrity). Nonces must be unique per key.
 //! - AAD: callers pass associated data (e.g., full header bytes) to bind
@@ -29,7 +35,8 @@ use hkdf::Hkdf;
 use rand::RngCore;
 use rand::rngs::OsRng;
 use secrecy::{ExposeSecret, SecretSlice, SecretString};
-use sha2::Sha256;
+use sha2::{Digest, Sha256};
+use std::time::{Duration, Instant};
 use thiserror::Error;
 use zeroize::Zeroizing;
 
@@ -39,11 +46,25 @@ pub const KDF_OUT_LEN: usize = 32;
 pub const DEK_LEN: usize = 32;
 /// Size (bytes) of XChaCha20-Poly1305 nonces.
 pub const XCHACHA_NONCE_LEN: usize = 24;
+/// Size (bytes) of the Poly1305 tag appended to every ciphertext.
+pub const AEAD_TAG_LEN: usize = 16;
+
+/// Size (bytes) of the entropy behind a printable recovery key.
+pub const RECOVERY_KEY_LEN: usize = 20;
+
+/// RFC 4648 base32 alphabet used for recovery keys.
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+/// Characters per dash-separated recovery key group.
+const RECOVERY_KEY_GROUP: usize = 4;
 
 /// HKDF `info` label for deriving the key-encryption-key (KEK).
 ///
 /// This provides domain separation from other keys we may derive later.
 const HKDF_INFO_KEK: &[u8] = b"passworder/vault/v1/kek";
+/// HKDF `info` prefix for per-item record keys; the item id is appended.
+const HKDF_INFO_ITEM_KEY: &[u8] = b"passworder/vault/v2/item/";
+/// HKDF `info` label for the key sealing the v2 manifest record.
+const HKDF_INFO_MANIFEST_KEY: &[u8] = b"passworder/vault/v2/manifest";
 
 /// Secret bytes held in memory with zeroize-on-drop semantics.
 ///
@@ -55,8 +76,8 @@ pub type SecretBytes = SecretSlice<u8>;
 /// Argon2id tuning parameters (persisted in the vault header).
 ///
 /// These defaults are chosen to be secure-by-default for a local CLI tool on
-/// macOS, but they are still policy, not truth: the vault header is the
-/// source of record for a given vault file.
+/// the platform it is built for, but they are still policy, not truth: the
+/// vault header is the source of record for a given vault file.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct KdfParams {
     pub memory_kib: u32,
@@ -65,6 +86,19 @@ pub struct KdfParams {
 }
 
 impl KdfParams {
+    /// Recommended default parameters for the target platform.
+    ///
+    /// macOS machines reliably have memory to spare; elsewhere (small Linux
+    /// hosts, containers, CI runners) 256 MiB per unlock is often too much,
+    /// so we use OWASP's 64 MiB / t=3 / p=4 profile instead.
+    pub fn recommended() -> Self {
+        if cfg!(target_os = "macos") {
+            Self::recommended_macos()
+        } else {
+            Self::recommended_portable()
+        }
+    }
+
     /// Recommended default parameters for macOS (interactive CLI).
     ///
     /// This should be calibrated over time; it’s intentionally centralized so
@@ -77,6 +111,15 @@ impl KdfParams {
         }
     }
 
+    /// Recommended default parameters for other platforms (interactive CLI).
+    pub fn recommended_portable() -> Self {
+        Self {
+            memory_kib: 64 * 1024,
+            iterations: 3,
+            parallelism: 4,
+        }
+    }
+
     pub fn for_tests() -> Self {
         Self {
             memory_kib: 32 * 1024,
@@ -85,6 +128,11 @@ impl KdfParams {
         }
     }
 
+    /// Check that Argon2 accepts these parameters.
+    pub fn validate(self) -> Result<(), CryptoError> {
+        self.to_argon2_params(KDF_OUT_LEN).map(|_| ())
+    }
+
     fn to_argon2_params(self, output_len: usize) -> Result<Argon2Params, CryptoError> {
         Ok(Argon2Params::new(
             self.memory_kib,
@@ -122,6 +170,130 @@ pub fn generate_dek() -> SecretBytes {
     SecretBytes::from(random_bytes::<DEK_LEN>().to_vec())
 }
 
+/// Generate a recovery key such as `ABCD-EFGH-...` (8 groups of 4 base32 characters).
+///
+/// The returned string is the canonical form: it is what gets fed to the KDF,
+/// so `normalize_recovery_key` must map user input back onto it exactly.
+pub fn generate_recovery_key() -> SecretString {
+    let bytes = Zeroizing::new(random_bytes::<RECOVERY_KEY_LEN>());
+    let encoded = Zeroizing::new(base32_encode(bytes.as_ref()));
+    group_recovery_key(&encoded)
+}
+
+/// Canonicalize a typed recovery key: case, whitespace and dashes are ignored.
+///
+/// Returns `None` when the input is not a well-formed recovery key.
+pub fn normalize_recovery_key(input: &str) -> Option<SecretString> {
+    let compact: Zeroizing<String> = Zeroizing::new(
+        input
+            .chars()
+            .filter(|c| !c.is_whitespace() && *c != '-')
+            .map(|c| c.to_ascii_uppercase())
+            .collect(),
+    );
+
+    let expected_len = (RECOVERY_KEY_LEN * 8).div_ceil(5);
+    if compact.len() != expected_len || !compact.bytes().all(|b| BASE32_ALPHABET.contains(&b)) {
+        return None;
+    }
+    Some(group_recovery_key(&compact))
+}
+
+pub(super) fn base32_encode(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity((bytes.len() * 8).div_ceil(5));
+    let mut buffer: u16 = 0;
+    let mut bits = 0;
+    for &byte in bytes {
+        buffer = (buffer << 8) | u16::from(byte);
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            out.push(BASE32_ALPHABET[usize::from((buffer >> bits) & 0x1f)] as char);
+        }
+    }
+    if bits > 0 {
+        out.push(BASE32_ALPHABET[usize::from((buffer << (5 - bits)) & 0x1f)] as char);
+    }
+    out
+}
+
+/// Decode unpadded RFC 4648 base32 (upper case; callers normalize first).
+/// Returns `None` on a character outside the alphabet.
+pub(super) fn base32_decode(encoded: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(encoded.len() * 5 / 8);
+    let mut buffer: u16 = 0;
+    let mut bits = 0;
+    for c in encoded.bytes() {
+        let value = BASE32_ALPHABET.iter().position(|&a| a == c)?;
+        buffer = (buffer << 5) | value as u16;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buffer >> bits) as u8);
+        }
+    }
+    Some(out)
+}
+
+fn group_recovery_key(compact: &str) -> SecretString {
+    let grouped = compact
+        .as_bytes()
+        .chunks(RECOVERY_KEY_GROUP)
+        .map(|chunk| std::str::from_utf8(chunk).expect("base32 is ascii"))
+        .collect::<Vec<_>>()
+        .join("-");
+    SecretString::new(grouped.into_boxed_str())
+}
+
+/// Smallest memory cost `calibrate_kdf_params` will settle on (8 MiB).
+pub const MIN_CALIBRATION_MEMORY_KIB: u32 = 8 * 1024;
+/// Upper bound on iterations chosen by `calibrate_kdf_params`.
+const MAX_CALIBRATION_ITERATIONS: u32 = 64;
+
+/// Time a single Argon2id derivation with `params` on this machine.
+pub fn benchmark_kdf(params: KdfParams) -> Result<Duration, CryptoError> {
+    let salt = random_bytes::<16>();
+    let started = Instant::now();
+    derive_kdf_out(b"passworder-kdf-benchmark", &salt, params)?;
+    Ok(started.elapsed())
+}
+
+/// Pick Argon2id params that take about `target` per unlock.
+///
+/// Memory is preferred over iterations (it is what makes GPU attacks
+/// expensive): start at `max_memory_kib` with one pass, halve memory while a
+/// single pass is too slow, then spend any remaining budget on iterations.
+/// `measure` is `benchmark_kdf` in production and a fake clock in tests.
+pub fn calibrate_kdf_params<F>(
+    target: Duration,
+    max_memory_kib: u32,
+    parallelism: u32,
+    mut measure: F,
+) -> Result<KdfParams, CryptoError>
+where
+    F: FnMut(KdfParams) -> Result<Duration, CryptoError>,
+{
+    let mut params = KdfParams {
+        memory_kib: max_memory_kib.max(MIN_CALIBRATION_MEMORY_KIB),
+        iterations: 1,
+        parallelism,
+    };
+    params.validate()?;
+
+    let mut elapsed = measure(params)?;
+    while elapsed > target && params.memory_kib / 2 >= MIN_CALIBRATION_MEMORY_KIB {
+        params.memory_kib /= 2;
+        elapsed = measure(params)?;
+    }
+
+    if elapsed < target {
+        let per_pass = elapsed.as_nanos().max(1);
+        let passes = target.as_nanos() / per_pass;
+        params.iterations = passes.clamp(1, u128::from(MAX_CALIBRATION_ITERATIONS)) as u32;
+    }
+    Ok(params)
+}
+
 /// Derive `kdf_out` (32 bytes) from the master password using Argon2id.
 ///
 /// Callers are expected to:
@@ -149,11 +321,23 @@ pub fn derive_kdf_out_from_password(
     derive_kdf_out(master_password.expose_secret().as_bytes(), salt, params)
 }
 
+/// Digest of a keyfile's contents, as mixed into `derive_kek`.
+pub fn keyfile_digest(contents: &[u8]) -> SecretBytes {
+    SecretBytes::from(Sha256::digest(contents).to_vec())
+}
+
 /// Derive the vault KEK (key-encryption-key) from `kdf_out` using HKDF-SHA256.
 ///
-/// The KEK is used to wrap/unwrap the randomly generated DEK.
-pub fn derive_kek(kdf_out: &SecretBytes) -> Result<SecretBytes, CryptoError> {
-    let hk = Hkdf::<Sha256>::new(None, kdf_out.expose_secret());
+/// The KEK is used to wrap/unwrap the randomly generated DEK. When a keyfile
+/// digest is supplied it becomes the HKDF salt; without it the salt is empty.
+pub fn derive_kek(
+    kdf_out: &SecretBytes,
+    keyfile_digest: Option<&SecretBytes>,
+) -> Result<SecretBytes, CryptoError> {
+    let hk = Hkdf::<Sha256>::new(
+        keyfile_digest.map(|d| d.expose_secret()),
+        kdf_out.expose_secret(),
+    );
 
     let mut kek = vec![0u8; 32];
     hk.expand(HKDF_INFO_KEK, &mut kek)
@@ -161,6 +345,33 @@ pub fn derive_kek(kdf_out: &SecretBytes) -> Result<SecretBytes, CryptoError> {
     Ok(SecretBytes::from(kek))
 }
 
+/// Derive the key that seals one item's records in a v2 vault.
+pub fn derive_item_key(dek: &SecretBytes, item_id: &[u8]) -> Result<SecretBytes, CryptoError> {
+    let mut info = HKDF_INFO_ITEM_KEY.to_vec();
+    info.extend_from_slice(item_id);
+    derive_subkey(dek, &info)
+}
+
+/// Derive the key that seals the manifest record in a v2 vault.
+pub fn derive_manifest_key(dek: &SecretBytes) -> Result<SecretBytes, CryptoError> {
+    derive_subkey(dek, HKDF_INFO_MANIFEST_KEY)
+}
+
+fn derive_subkey(dek: &SecretBytes, info: &[u8]) -> Result<SecretBytes, CryptoError> {
+    let hk = Hkdf::<Sha256>::new(None, dek.expose_secret());
+    let mut key = vec![0u8; 32];
+    hk.expand(info, &mut key).map_err(|_| CryptoError::Hkdf)?;
+    Ok(SecretBytes::from(key))
+}
+
+/// SHA-256 over a sealed record (nonce and ciphertext), as listed in the v2 manifest.
+pub fn record_digest(nonce: &[u8], ciphertext: &[u8]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(nonce);
+    hasher.update(ciphertext);
+    hasher.finalize().into()
+}
+
 /// Wrap (encrypt) the DEK with the KEK using XChaCha20-Poly1305.
 ///
 /// - `wrap_nonce` must be unique per KEK.
@@ -268,12 +479,41 @@ pub fn nonce_from_slice(bytes: &[u8]) -> Result<[u8; XCHACHA_NONCE_LEN], CryptoE
 mod tests {
     use super::*;
 
+    #[test]
+    fn item_keys_are_separated_by_id_and_from_the_manifest_key() {
+        let dek = generate_dek();
+        let a = derive_item_key(&dek, &[1u8; 16]).unwrap();
+        let b = derive_item_key(&dek, &[2u8; 16]).unwrap();
+        let manifest = derive_manifest_key(&dek).unwrap();
+
+        assert_ne!(a.expose_secret(), b.expose_secret());
+        assert_ne!(a.expose_secret(), manifest.expose_secret());
+        assert_eq!(
+            a.expose_secret(),
+            derive_item_key(&dek, &[1u8; 16]).unwrap().expose_secret()
+        );
+    }
+
+    #[test]
+    fn recommended_params_are_accepted_by_argon2() {
+        for params in [
+            KdfParams::recommended(),
+            KdfParams::recommended_macos(),
+            KdfParams::recommended_portable(),
+        ] {
+            params.validate().unwrap();
+        }
+        if !cfg!(target_os = "macos") {
+            assert_eq!(KdfParams::recommended(), KdfParams::recommended_portable());
+        }
+    }
+
     #[test]
     fn wrap_unwrap_dek_roundtrip() {
         let salt = random_bytes::<16>();
         let password = b"correct horse battery staple";
         let kdf_out = derive_kdf_out(password, &salt, KdfParams::for_tests()).unwrap();
-        let kek = derive_kek(&kdf_out).unwrap();
+        let kek = derive_kek(&kdf_out, None).unwrap();
 
         let dek = generate_dek();
         let nonce = random_bytes::<XCHACHA_NONCE_LEN>();
@@ -289,7 +529,7 @@ mod tests {
         let salt = random_bytes::<16>();
         let password = b"pw";
         let kdf_out = derive_kdf_out(password, &salt, KdfParams::for_tests()).unwrap();
-        let kek = derive_kek(&kdf_out).unwrap();
+        let kek = derive_kek(&kdf_out, None).unwrap();
 
         let dek = SecretBytes::from(vec![42u8; DEK_LEN]);
         let nonce = random_bytes::<XCHACHA_NONCE_LEN>();
@@ -302,6 +542,84 @@ mod tests {
         assert!(matches!(err, CryptoError::Aead));
     }
 
+    #[test]
+    fn base32_matches_rfc4648_vectors() {
+        assert_eq!(base32_encode(b"f"), "MY");
+        assert_eq!(base32_encode(b"foob"), "MZXW6YQ");
+        assert_eq!(base32_encode(b"foobar"), "MZXW6YTBOI");
+        assert_eq!(base32_decode("MZXW6YQ").unwrap(), b"foob");
+        assert_eq!(base32_decode("MZXW6YTBOI").unwrap(), b"foobar");
+        assert!(base32_decode("MZ1").is_none());
+    }
+
+    #[test]
+    fn recovery_key_is_grouped_and_normalizes_to_itself() {
+        let key = generate_recovery_key();
+        let key = key.expose_secret();
+        assert_eq!(key.len(), 32 + 7);
+        assert!(key.split('-').all(|group| group.len() == 4));
+
+        let typed = key.to_lowercase().replace('-', " ");
+        let normalized = normalize_recovery_key(&typed).expect("valid recovery key");
+        assert_eq!(normalized.expose_secret(), key);
+    }
+
+    #[test]
+    fn recovery_key_rejects_malformed_input() {
+        assert!(normalize_recovery_key("ABCD-EFGH").is_none());
+        assert!(normalize_recovery_key(&"0".repeat(32)).is_none());
+    }
+
+    #[test]
+    fn calibration_spends_spare_time_on_iterations() {
+        // 1 ms per MiB per pass.
+        let clock = |p: KdfParams| {
+            Ok(Duration::from_millis(u64::from(
+                p.memory_kib / 1024 * p.iterations,
+            )))
+        };
+        let params = calibrate_kdf_params(Duration::from_millis(500), 64 * 1024, 1, clock).unwrap();
+        assert_eq!(params.memory_kib, 64 * 1024);
+        assert_eq!(params.iterations, 7);
+    }
+
+    #[test]
+    fn calibration_halves_memory_when_too_slow() {
+        let clock = |p: KdfParams| {
+            Ok(Duration::from_millis(u64::from(
+                p.memory_kib / 1024 * p.iterations,
+            )))
+        };
+        let params =
+            calibrate_kdf_params(Duration::from_millis(100), 1024 * 1024, 2, clock).unwrap();
+        assert_eq!(params.memory_kib, 64 * 1024);
+        assert_eq!(params.iterations, 1);
+        assert_eq!(params.parallelism, 2);
+    }
+
+    #[test]
+    fn calibration_never_goes_below_minimum_memory() {
+        let clock = |_: KdfParams| Ok(Duration::from_secs(1));
+        let params = calibrate_kdf_params(Duration::from_millis(10), 64 * 1024, 1, clock).unwrap();
+        assert_eq!(params.memory_kib, MIN_CALIBRATION_MEMORY_KIB);
+        assert_eq!(params.iterations, 1);
+    }
+
+    #[test]
+    fn keyfile_changes_kek() {
+        let salt = random_bytes::<16>();
+        let kdf_out = derive_kdf_out(b"pw", &salt, KdfParams::for_tests()).unwrap();
+
+        let plain = derive_kek(&kdf_out, None).unwrap();
+        let a = derive_kek(&kdf_out, Some(&keyfile_digest(b"keyfile-a"))).unwrap();
+        let b = derive_kek(&kdf_out, Some(&keyfile_digest(b"keyfile-b"))).unwrap();
+
+        assert_ne!(plain.expose_secret(), a.expose_secret());
+        assert_ne!(a.expose_secret(), b.expose_secret());
+        let again = derive_kek(&kdf_out, Some(&keyfile_digest(b"keyfile-a"))).unwrap();
+        assert_eq!(a.expose_secret(), again.expose_secret());
+    }
+
     #[test]
     fn decrypt_payload_fails_on_aad_mismatch() {
         let dek = generate_dek();
@@ -0,0 +1,145 @@
+use super::{ForeignFormat, ForeignImport, ForeignImportError, display_name, new_item, non_empty};
+use crate::vault::items::VaultItemType;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+const LOGIN: u32 = 1;
+const SECURE_NOTE: u32 = 2;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Export {
+    #[serde(default)]
+    encrypted: bool,
+    #[serde(default)]
+    folders: Vec<Folder>,
+    #[serde(default)]
+    items: Vec<Item>,
+}
+
+#[derive(Deserialize)]
+struct Folder {
+    id: String,
+    name: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Item {
+    #[serde(rename = "type")]
+    item_type: u32,
+    name: Option<String>,
+    notes: Option<String>,
+    folder_id: Option<String>,
+    #[serde(default)]
+    favorite: bool,
+    #[serde(default)]
+    fields: Vec<Field>,
+    login: Option<Login>,
+}
+
+#[derive(Deserialize)]
+struct Field {
+    name: Option<String>,
+    value: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Login {
+    #[serde(default)]
+    uris: Vec<Uri>,
+    username: Option<String>,
+    password: Option<String>,
+    totp: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Uri {
+    uri: Option<String>,
+}
+
+pub(super) fn parse(text: &str) -> Result<ForeignImport, ForeignImportError> {
+    let export: Export = serde_json::from_str(text)
+        .map_err(|e| ForeignImportError::invalid(ForeignFormat::BitwardenJson, e))?;
+    if export.encrypted {
+        return Err(ForeignImportError::invalid(
+            ForeignFormat::BitwardenJson,
+            "encrypted exports are not supported; export as unencrypted JSON",
+        ));
+    }
+
+    let folders = export
+        .folders
+        .iter()
+        .map(|f| (f.id.as_str(), f.name.as_str()))
+        .collect::<HashMap<_, _>>();
+
+    let mut out = ForeignImport::default();
+    for record in export.items {
+        let urls = record
+            .login
+            .iter()
+            .flat_map(|l| &l.uris)
+            .filter_map(|u| non_empty(u.uri.as_deref()))
+            .collect::<Vec<_>>();
+        let name = display_name(record.name.as_deref(), &urls);
+
+        let item_type = match record.item_type {
+            LOGIN => VaultItemType::Login,
+            SECURE_NOTE => VaultItemType::SecureNote,
+            other => {
+                out.skipped(
+                    &name,
+                    format!("unsupported Bitwarden item type {}", kind(other)),
+                );
+                continue;
+            }
+        };
+
+        let mut item = new_item(item_type, &name);
+        item.path = record
+            .folder_id
+            .as_deref()
+            .and_then(|id| folders.get(id))
+            .and_then(|name| super::join_path(name.split('/')));
+        item.urls = urls;
+        if record.favorite {
+            item.tags.push("favorite".to_string());
+        }
+
+        match item_type {
+            VaultItemType::SecureNote => {
+                item.secret = record.notes.unwrap_or_default();
+            }
+            _ => {
+                item.notes = non_empty(record.notes.as_deref());
+                if let Some(login) = record.login {
+                    item.username = non_empty(login.username.as_deref());
+                    item.secret = login.password.unwrap_or_default();
+                    if non_empty(login.totp.as_deref()).is_some() {
+                        out.unmapped(&name, "login.totp");
+                    }
+                }
+            }
+        }
+
+        for field in record.fields {
+            if non_empty(field.value.as_deref()).is_some() {
+                let label = field.name.unwrap_or_default();
+                out.unmapped(&name, format!("fields.{label}"));
+            }
+        }
+        out.items.push(item);
+    }
+
+    Ok(out)
+}
+
+fn kind(item_type: u32) -> String {
+    match item_type {
+        3 => "card".to_string(),
+        4 => "identity".to_string(),
+        5 => "ssh-key".to_string(),
+        other => other.to_string(),
+    }
+}
@@ -0,0 +1,191 @@
+//! CSV exports, matched by header name so the common layouts work unchanged:
+//! Bitwarden (`folder,favorite,type,name,notes,fields,...,login_uri,...`),
+//! LastPass (`url,username,password,totp,extra,name,grouping,fav`), browser
+//! password exports (`name,url,username,password`) and hand-written files.
+
+use super::{ForeignFormat, ForeignImport, ForeignImportError, display_name, new_item, non_empty};
+use crate::vault::items::{VaultItemField, VaultItemType};
+
+const LASTPASS_NOTE_URL: &str = "http://sn";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Column {
+    Name,
+    Username,
+    Password,
+    Url,
+    Notes,
+    Path,
+    Tags,
+    Type,
+    Favorite,
+    Fields,
+    ConcealedFields,
+    /// Known to carry no data worth keeping (e.g. Bitwarden's `reprompt`).
+    Ignored,
+    Unmapped,
+}
+
+fn column_for(header: &str) -> Column {
+    match header.trim().to_ascii_lowercase().as_str() {
+        "name" | "title" => Column::Name,
+        "username" | "login_username" | "login" | "user" | "email" => Column::Username,
+        "password" | "login_password" | "secret" => Column::Password,
+        "url" | "urls" | "uri" | "login_uri" | "website" => Column::Url,
+        "notes" | "note" | "extra" | "comments" => Column::Notes,
+        "folder" | "path" | "group" | "grouping" => Column::Path,
+        "tags" | "tag" => Column::Tags,
+        "type" => Column::Type,
+        "favorite" | "fav" => Column::Favorite,
+        "fields" => Column::Fields,
+        "concealed_fields" | "hidden_fields" => Column::ConcealedFields,
+        "reprompt" => Column::Ignored,
+        _ => Column::Unmapped,
+    }
+}
+
+pub(super) fn parse(text: &str) -> Result<ForeignImport, ForeignImportError> {
+    let invalid = |e: ::csv::Error| ForeignImportError::invalid(ForeignFormat::Csv, e);
+
+    let mut reader = ::csv::ReaderBuilder::new()
+        .flexible(true)
+        .from_reader(text.as_bytes());
+    let headers = reader.headers().map_err(invalid)?.clone();
+    let columns = headers.iter().map(column_for).collect::<Vec<_>>();
+    if !columns
+        .iter()
+        .any(|c| matches!(c, Column::Name | Column::Password))
+    {
+        return Err(ForeignImportError::invalid(
+            ForeignFormat::Csv,
+            "header row needs a name/title or password column",
+        ));
+    }
+
+    let mut out = ForeignImport::default();
+    for record in reader.records() {
+        let record = record.map_err(invalid)?;
+        if record.iter().all(|v| v.trim().is_empty()) {
+            continue;
+        }
+
+        let value = |column: Column| {
+            columns
+                .iter()
+                .zip(record.iter())
+                .find(|(c, _)| **c == column)
+                .and_then(|(_, v)| non_empty(Some(v)))
+        };
+
+        let mut urls = value(Column::Url)
+            .map(|u| u.split(',').map(str::to_string).collect::<Vec<_>>())
+            .unwrap_or_default();
+        // LastPass marks secure notes with this placeholder URL.
+        let lastpass_note = urls == [LASTPASS_NOTE_URL];
+        if lastpass_note {
+            urls.clear();
+        }
+        let name = display_name(value(Column::Name).as_deref(), &urls);
+
+        let item_type = match value(Column::Type).as_deref().map(str::trim) {
+            None if lastpass_note => VaultItemType::SecureNote,
+            None | Some("login") => VaultItemType::Login,
+            Some("note" | "secure-note" | "securenote") => VaultItemType::SecureNote,
+            Some("api-token" | "token") => VaultItemType::ApiToken,
+            Some("totp") => VaultItemType::Totp,
+            Some("ssh-key" | "ssh") => VaultItemType::SshKey,
+            Some(other) => {
+                out.skipped(&name, format!("unsupported item type {other}"));
+                continue;
+            }
+        };
+
+        let mut item = new_item(item_type, &name);
+        item.path = value(Column::Path).and_then(|p| super::join_path(p.split(['/', '\\'])));
+        item.urls = urls;
+        item.tags = value(Column::Tags)
+            .map(|t| t.split([',', ';']).map(str::to_string).collect())
+            .unwrap_or_default();
+        if value(Column::Favorite).is_some_and(|f| matches!(f.trim(), "1" | "true" | "yes")) {
+            item.tags.push("favorite".to_string());
+        }
+        item.username = value(Column::Username);
+        let notes = value(Column::Notes);
+        match (item_type, value(Column::Password)) {
+            (VaultItemType::SecureNote, None) => item.secret = notes.unwrap_or_default(),
+            (_, password) => {
+                item.secret = password.unwrap_or_default();
+                item.notes = notes;
+            }
+        }
+
+        for (column, concealed) in [(Column::Fields, false), (Column::ConcealedFields, true)] {
+            for line in value(column).iter().flat_map(|v| v.lines()) {
+                match line.split_once(':') {
+                    Some((field, v)) if !field.trim().is_empty() => {
+                        item.fields.push(VaultItemField {
+                            name: field.trim().to_string(),
+                            value: v.strip_prefix(' ').unwrap_or(v).to_string(),
+                            concealed,
+                        });
+                    }
+                    _ if line.trim().is_empty() => {}
+                    _ => out.unmapped(&name, format!("fields.{}", line.trim())),
+                }
+            }
+        }
+
+        for (header, (column, v)) in headers.iter().zip(columns.iter().zip(record.iter())) {
+            if *column == Column::Unmapped && non_empty(Some(v)).is_some() {
+                out.unmapped(&name, header.trim());
+            }
+        }
+        out.items.push(item);
+    }
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_columns_by_header_and_reports_the_rest() {
+        let csv = "Title,User,Password,URL,Group,Custom\n\
+                   GitHub,octo,pw,\"https://github.com,https://gist.github.com\",Work\\Dev,x\n\
+                   ,,,,,\n";
+        let import = parse(csv).unwrap();
+
+        assert_eq!(import.items.len(), 1);
+        let item = &import.items[0];
+        assert_eq!(item.name, "GitHub");
+        assert_eq!(item.username.as_deref(), Some("octo"));
+        assert_eq!(item.secret, "pw");
+        assert_eq!(item.path.as_deref(), Some("Work/Dev"));
+        assert_eq!(item.urls.len(), 2);
+        assert_eq!(import.unmapped.len(), 1);
+        assert_eq!(import.unmapped[0].field, "Custom");
+    }
+
+    #[test]
+    fn reads_plain_and_concealed_fields_one_per_line() {
+        let csv = "name,password,fields,concealed_fields\n\
+                   db,pw,\"DB_HOST: db.internal\nDB_PORT: 5432\",DB_PASSWORD: s:e:c\n";
+        let import = parse(csv).unwrap();
+
+        let fields = &import.items[0].fields;
+        let field = |name: &str| fields.iter().find(|f| f.name == name).unwrap();
+        assert_eq!(fields.len(), 3);
+        assert_eq!(field("DB_HOST").value, "db.internal");
+        assert!(!field("DB_PORT").concealed);
+        assert_eq!(field("DB_PASSWORD").value, "s:e:c");
+        assert!(field("DB_PASSWORD").concealed);
+        assert!(import.unmapped.is_empty());
+    }
+
+    #[test]
+    fn rejects_files_without_usable_columns() {
+        assert!(parse("a,b\n1,2\n").is_err());
+    }
+}
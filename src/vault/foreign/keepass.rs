@@ -0,0 +1,124 @@
+use super::{ForeignFormat, ForeignImport, ForeignImportError, display_name, new_item, non_empty};
+use crate::vault::items::VaultItemType;
+use roxmltree::{Document, Node};
+
+/// Entry string keys with a place on a vault item.
+const TITLE: &str = "Title";
+const USERNAME: &str = "UserName";
+const PASSWORD: &str = "Password";
+const URL: &str = "URL";
+const NOTES: &str = "Notes";
+
+pub(super) fn parse(text: &str) -> Result<ForeignImport, ForeignImportError> {
+    let doc = Document::parse(text)
+        .map_err(|e| ForeignImportError::invalid(ForeignFormat::KeepassXml, e))?;
+    let root = doc.root_element();
+    if !root.has_tag_name("KeePassFile") {
+        return Err(ForeignImportError::invalid(
+            ForeignFormat::KeepassXml,
+            "missing <KeePassFile> root element",
+        ));
+    }
+
+    let recycle_bin = child(root, "Meta")
+        .filter(|meta| child_text(*meta, "RecycleBinEnabled") != Some("False"))
+        .and_then(|meta| child_text(meta, "RecycleBinUUID"))
+        .filter(|uuid| !uuid.is_empty());
+
+    let mut out = ForeignImport::default();
+    // The top-level group is the database itself, so it does not become
+    // part of item paths.
+    for top in child(root, "Root")
+        .into_iter()
+        .flat_map(|r| r.children().filter(|n| n.has_tag_name("Group")))
+    {
+        walk_group(top, &mut Vec::new(), recycle_bin, &mut out);
+    }
+    Ok(out)
+}
+
+fn walk_group<'a>(
+    group: Node<'a, '_>,
+    path: &mut Vec<&'a str>,
+    recycle_bin: Option<&str>,
+    out: &mut ForeignImport,
+) {
+    for node in group.children().filter(Node::is_element) {
+        match node.tag_name().name() {
+            "Entry" => map_entry(node, path, out),
+            "Group" => {
+                if recycle_bin.is_some() && child_text(node, "UUID") == recycle_bin {
+                    continue;
+                }
+                path.push(child_text(node, "Name").unwrap_or_default());
+                walk_group(node, path, recycle_bin, out);
+                path.pop();
+            }
+            _ => {}
+        }
+    }
+}
+
+fn map_entry(entry: Node<'_, '_>, path: &[&str], out: &mut ForeignImport) {
+    let mut title = None;
+    let mut username = None;
+    let mut password = None;
+    let mut url = None;
+    let mut notes = None;
+    let mut extra = Vec::new();
+
+    for string in entry.children().filter(|n| n.has_tag_name("String")) {
+        let key = child_text(string, "Key").unwrap_or_default();
+        let value = non_empty(child_text(string, "Value"));
+        match key {
+            TITLE => title = value,
+            USERNAME => username = value,
+            PASSWORD => password = value,
+            URL => url = value,
+            NOTES => notes = value,
+            other if value.is_some() => extra.push(other.to_string()),
+            _ => {}
+        }
+    }
+
+    let urls = url.into_iter().collect::<Vec<_>>();
+    let name = display_name(title.as_deref(), &urls);
+    let is_note = password.is_none() && username.is_none() && urls.is_empty() && notes.is_some();
+
+    let mut item = if is_note {
+        let mut item = new_item(VaultItemType::SecureNote, &name);
+        item.secret = notes.unwrap_or_default();
+        item
+    } else {
+        let mut item = new_item(VaultItemType::Login, &name);
+        item.username = username;
+        item.secret = password.unwrap_or_default();
+        item.notes = notes;
+        item
+    };
+    item.path = super::join_path(path.iter().copied());
+    item.urls = urls;
+    item.tags = child_text(entry, "Tags")
+        .map(|tags| {
+            tags.split([';', ','])
+                .map(str::to_string)
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+
+    for key in extra {
+        out.unmapped(&name, format!("String.{key}"));
+    }
+    if entry.children().any(|n| n.has_tag_name("Binary")) {
+        out.unmapped(&name, "Binary");
+    }
+    out.items.push(item);
+}
+
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.children().find(|n| n.has_tag_name(name))
+}
+
+fn child_text<'a>(node: Node<'a, '_>, name: &str) -> Option<&'a str> {
+    child(node, name).map(|n| n.text().unwrap_or_default())
+}
@@ -0,0 +1,194 @@
+//! Readers for other password managers' export files.
+//!
+//! Each reader maps records onto [`VaultItemV1`] with fresh ids and notes
+//! every non-empty source field it had no place for, so the caller can show
+//! what an import would lose before writing anything.
+
+mod bitwarden;
+mod csv;
+mod keepass;
+mod onepassword;
+
+use crate::vault::items::{VaultItemType, VaultItemV1};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ForeignFormat {
+    /// Unencrypted Bitwarden JSON export.
+    #[value(name = "bitwarden-json")]
+    BitwardenJson,
+    /// 1Password `.1pux` export archive.
+    #[value(name = "1password-1pux")]
+    OnePassword1pux,
+    /// KeePass 2 XML export.
+    #[value(name = "keepass-xml")]
+    KeepassXml,
+    /// CSV with a header row (Bitwarden, LastPass, browser and generic layouts).
+    #[value(name = "csv")]
+    Csv,
+}
+
+impl ForeignFormat {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ForeignFormat::BitwardenJson => "bitwarden-json",
+            ForeignFormat::OnePassword1pux => "1password-1pux",
+            ForeignFormat::KeepassXml => "keepass-xml",
+            ForeignFormat::Csv => "csv",
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ForeignImportError {
+    #[error("failed to read {path}: {source}")]
+    Read {
+        path: String,
+        source: std::io::Error,
+    },
+
+    #[error("invalid {format} export: {message}")]
+    Invalid {
+        format: &'static str,
+        message: String,
+    },
+}
+
+impl ForeignImportError {
+    fn invalid(format: ForeignFormat, message: impl std::fmt::Display) -> Self {
+        ForeignImportError::Invalid {
+            format: format.as_str(),
+            message: message.to_string(),
+        }
+    }
+}
+
+/// A source field that has no counterpart on a vault item.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnmappedField {
+    /// Name of the item the field belonged to.
+    pub item: String,
+    pub field: String,
+}
+
+/// A source record that was not turned into an item at all.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SkippedRecord {
+    pub name: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ForeignImport {
+    pub items: Vec<VaultItemV1>,
+    pub unmapped: Vec<UnmappedField>,
+    pub skipped: Vec<SkippedRecord>,
+}
+
+impl ForeignImport {
+    fn unmapped(&mut self, item: &str, field: impl Into<String>) {
+        self.unmapped.push(UnmappedField {
+            item: item.to_string(),
+            field: field.into(),
+        });
+    }
+
+    fn skipped(&mut self, name: &str, reason: impl Into<String>) {
+        self.skipped.push(SkippedRecord {
+            name: name.to_string(),
+            reason: reason.into(),
+        });
+    }
+}
+
+/// Parse an export file written by another password manager.
+pub fn read_foreign_export(
+    path: &Path,
+    format: ForeignFormat,
+) -> Result<ForeignImport, ForeignImportError> {
+    let bytes = zeroize::Zeroizing::new(std::fs::read(path).map_err(|source| {
+        ForeignImportError::Read {
+            path: path.display().to_string(),
+            source,
+        }
+    })?);
+    parse_foreign_export(&bytes, format)
+}
+
+fn parse_foreign_export(
+    bytes: &[u8],
+    format: ForeignFormat,
+) -> Result<ForeignImport, ForeignImportError> {
+    match format {
+        ForeignFormat::OnePassword1pux => onepassword::parse(bytes),
+        _ => {
+            let text = std::str::from_utf8(bytes)
+                .map_err(|_| ForeignImportError::invalid(format, "file is not valid UTF-8"))?;
+            let text = text.strip_prefix('\u{feff}').unwrap_or(text);
+            match format {
+                ForeignFormat::BitwardenJson => bitwarden::parse(text),
+                ForeignFormat::KeepassXml => keepass::parse(text),
+                ForeignFormat::Csv => csv::parse(text),
+                ForeignFormat::OnePassword1pux => unreachable!(),
+            }
+        }
+    }
+}
+
+/// A new item stamped with the current time; readers fill in the rest.
+fn new_item(item_type: VaultItemType, name: &str) -> VaultItemV1 {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    VaultItemV1 {
+        id: Uuid::new_v4(),
+        item_type,
+        name: name.to_string(),
+        path: None,
+        tags: Vec::new(),
+        username: None,
+        secret: String::new(),
+        urls: Vec::new(),
+        notes: None,
+        fields: Vec::new(),
+        attachments: Vec::new(),
+        expires_at: None,
+        rotate_every: None,
+        secret_changed_at: None,
+        created_at: now,
+        updated_at: now,
+        history: Vec::new(),
+        deleted_at: None,
+    }
+}
+
+/// `Some(s)` for text with anything but whitespace in it.
+fn non_empty(s: Option<&str>) -> Option<String> {
+    s.filter(|s| !s.trim().is_empty()).map(str::to_string)
+}
+
+/// Join folder or group names into an item path, dropping empty segments.
+fn join_path<'a>(segments: impl IntoIterator<Item = &'a str>) -> Option<String> {
+    let joined = segments
+        .into_iter()
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>()
+        .join("/");
+    if joined.is_empty() {
+        None
+    } else {
+        Some(joined)
+    }
+}
+
+/// Fall back to something recognisable for records without a title.
+fn display_name(name: Option<&str>, urls: &[String]) -> String {
+    non_empty(name)
+        .or_else(|| urls.first().cloned())
+        .unwrap_or_else(|| "Untitled".to_string())
+}
@@ -0,0 +1,213 @@
+use super::{ForeignFormat, ForeignImport, ForeignImportError, display_name, new_item, non_empty};
+use crate::vault::items::VaultItemType;
+use serde::Deserialize;
+use std::io::{Cursor, Read};
+
+const EXPORT_DATA: &str = "export.data";
+/// Refuse to inflate `export.data` beyond this, so a crafted archive cannot
+/// exhaust memory.
+const MAX_EXPORT_DATA_BYTES: u64 = 64 * 1024 * 1024;
+
+const CATEGORY_LOGIN: &str = "001";
+const CATEGORY_SECURE_NOTE: &str = "003";
+const CATEGORY_PASSWORD: &str = "005";
+const CATEGORY_API_CREDENTIAL: &str = "112";
+
+#[derive(Deserialize)]
+struct Export {
+    #[serde(default)]
+    accounts: Vec<Account>,
+}
+
+#[derive(Deserialize)]
+struct Account {
+    #[serde(default)]
+    vaults: Vec<Vault>,
+}
+
+#[derive(Deserialize)]
+struct Vault {
+    attrs: VaultAttrs,
+    #[serde(default)]
+    items: Vec<Item>,
+}
+
+#[derive(Deserialize)]
+struct VaultAttrs {
+    name: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Item {
+    created_at: Option<u64>,
+    updated_at: Option<u64>,
+    state: Option<String>,
+    category_uuid: String,
+    #[serde(default)]
+    details: Details,
+    #[serde(default)]
+    overview: Overview,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Details {
+    #[serde(default)]
+    login_fields: Vec<LoginField>,
+    notes_plain: Option<String>,
+    password: Option<String>,
+    #[serde(default)]
+    sections: Vec<Section>,
+}
+
+#[derive(Deserialize)]
+struct LoginField {
+    value: Option<String>,
+    name: Option<String>,
+    designation: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Section {
+    #[serde(default)]
+    fields: Vec<SectionField>,
+}
+
+#[derive(Deserialize)]
+struct SectionField {
+    title: Option<String>,
+    id: Option<String>,
+    #[serde(default)]
+    value: serde_json::Map<String, serde_json::Value>,
+}
+
+impl SectionField {
+    /// Text of string-like values (`string`, `concealed`, `url`, `totp`, ...).
+    fn text(&self) -> Option<String> {
+        self.value
+            .values()
+            .find_map(|v| v.as_str())
+            .and_then(|s| non_empty(Some(s)))
+    }
+
+    fn label(&self) -> String {
+        non_empty(self.title.as_deref())
+            .or_else(|| self.id.clone())
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Default, Deserialize)]
+struct Overview {
+    title: Option<String>,
+    url: Option<String>,
+    #[serde(default)]
+    urls: Vec<OverviewUrl>,
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct OverviewUrl {
+    url: Option<String>,
+}
+
+pub(super) fn parse(bytes: &[u8]) -> Result<ForeignImport, ForeignImportError> {
+    let invalid =
+        |e: &dyn std::fmt::Display| ForeignImportError::invalid(ForeignFormat::OnePassword1pux, e);
+
+    let mut archive = zip::ZipArchive::new(Cursor::new(bytes)).map_err(|e| invalid(&e))?;
+    let entry = archive
+        .by_name(EXPORT_DATA)
+        .map_err(|_| invalid(&format!("archive has no {EXPORT_DATA}")))?;
+    let mut data = zeroize::Zeroizing::new(Vec::new());
+    entry
+        .take(MAX_EXPORT_DATA_BYTES + 1)
+        .read_to_end(&mut data)
+        .map_err(|e| invalid(&e))?;
+    if data.len() as u64 > MAX_EXPORT_DATA_BYTES {
+        return Err(invalid(&format!("{EXPORT_DATA} is too large")));
+    }
+    let export: Export = serde_json::from_slice(&data).map_err(|e| invalid(&e))?;
+
+    let mut out = ForeignImport::default();
+    for vault in export.accounts.into_iter().flat_map(|a| a.vaults) {
+        let vault_name = vault.attrs.name;
+        for record in vault.items {
+            map_item(record, vault_name.as_deref(), &mut out);
+        }
+    }
+    Ok(out)
+}
+
+fn map_item(record: Item, vault_name: Option<&str>, out: &mut ForeignImport) {
+    let mut urls = record
+        .overview
+        .urls
+        .iter()
+        .filter_map(|u| non_empty(u.url.as_deref()))
+        .collect::<Vec<_>>();
+    urls.extend(non_empty(record.overview.url.as_deref()));
+    let name = display_name(record.overview.title.as_deref(), &urls);
+
+    if record.state.as_deref() == Some("archived") {
+        out.skipped(&name, "archived item");
+        return;
+    }
+    let item_type = match record.category_uuid.as_str() {
+        CATEGORY_LOGIN | CATEGORY_PASSWORD => VaultItemType::Login,
+        CATEGORY_SECURE_NOTE => VaultItemType::SecureNote,
+        CATEGORY_API_CREDENTIAL => VaultItemType::ApiToken,
+        other => {
+            out.skipped(&name, format!("unsupported 1Password category {other}"));
+            return;
+        }
+    };
+
+    let mut item = new_item(item_type, &name);
+    item.path = vault_name.and_then(|v| super::join_path([v]));
+    item.tags = record.overview.tags;
+    item.urls = urls;
+    if let Some(created_at) = record.created_at {
+        item.created_at = created_at;
+    }
+    item.updated_at = record.updated_at.unwrap_or(item.created_at);
+
+    let details = record.details;
+    if item_type == VaultItemType::SecureNote {
+        item.secret = details.notes_plain.unwrap_or_default();
+    } else {
+        item.notes = non_empty(details.notes_plain.as_deref());
+    }
+
+    for field in details.login_fields {
+        let Some(value) = non_empty(field.value.as_deref()) else {
+            continue;
+        };
+        match field.designation.as_deref() {
+            Some("username") => item.username = Some(value),
+            Some("password") => item.secret = value,
+            _ => out.unmapped(
+                &name,
+                format!("loginFields.{}", field.name.unwrap_or_default()),
+            ),
+        }
+    }
+    if let Some(password) = non_empty(details.password.as_deref()) {
+        item.secret = password;
+    }
+
+    for field in details.sections.iter().flat_map(|s| &s.fields) {
+        let Some(value) = field.text() else {
+            continue;
+        };
+        match (item_type, field.id.as_deref()) {
+            (VaultItemType::ApiToken, Some("credential")) => item.secret = value,
+            (VaultItemType::ApiToken, Some("username")) => item.username = Some(value),
+            _ => out.unmapped(&name, format!("sections.{}", field.label())),
+        }
+    }
+
+    out.items.push(item);
+}
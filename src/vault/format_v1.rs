@@ -1,4 +1,5 @@
 use crate::vault::crypto;
+use crate::vault::format_v2::VERSION_V2;
 use thiserror::Error;
 
 pub const MAGIC: &[u8; 8] = b"PWDERVLT";
@@ -7,15 +8,24 @@ pub const FIXED_HEADER_LEN: usize = 8 + 2 + 4;
 
 const TLV_ARGON2_PARAMS: u16 = 0x0001;
 const TLV_KDF_SALT: u16 = 0x0002;
-const TLV_KDF_ALG: u16 = 0x0003;
-const TLV_AEAD_ALG: u16 = 0x0010;
-const TLV_HKDF_ALG: u16 = 0x0020;
+pub(super) const TLV_KDF_ALG: u16 = 0x0003;
+pub(super) const TLV_AEAD_ALG: u16 = 0x0010;
+pub(super) const TLV_HKDF_ALG: u16 = 0x0020;
 const TLV_WRAPPED_DEK: u16 = 0x0100;
+const TLV_SLOT_ID: u16 = 0x0101;
+const TLV_SLOT_LABEL: u16 = 0x0102;
+const TLV_SLOT_KEYFILE_REQUIRED: u16 = 0x0103;
+/// Repeatable; the value is itself a TLV list (slot id, label, keyfile flag,
+/// Argon2 params, salt and wrapped DEK), reusing the top-level type codes
+/// where they overlap.
+pub(super) const TLV_KEY_SLOT: u16 = 0x0110;
 const TLV_PAYLOAD_NONCE: u16 = 0x0200;
 
-const KDF_ALG_ARGON2ID: &[u8] = b"argon2id";
-const AEAD_ALG_XCHACHA20POLY1305: &[u8] = b"xchacha20poly1305";
-const HKDF_ALG_SHA256: &[u8] = b"hkdf-sha256";
+pub(super) const KDF_ALG_ARGON2ID: &[u8] = b"argon2id";
+pub(super) const AEAD_ALG_XCHACHA20POLY1305: &[u8] = b"xchacha20poly1305";
+pub(super) const HKDF_ALG_SHA256: &[u8] = b"hkdf-sha256";
+
+const LEGACY_SLOT_LABEL: &str = "master";
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct FixedHeader {
@@ -57,7 +67,7 @@ pub fn parse_fixed_header(bytes: &[u8]) -> Result<FixedHeader, VaultFormatError>
     }
 
     let version = u16::from_le_bytes(bytes[8..10].try_into().expect("slice is 2 bytes"));
-    if version != VERSION_V1 {
+    if !matches!(version, VERSION_V1 | VERSION_V2) {
         return Err(VaultFormatError::UnsupportedVersion(version));
     }
 
@@ -72,12 +82,32 @@ pub fn parse_fixed_header(bytes: &[u8]) -> Result<FixedHeader, VaultFormatError>
     })
 }
 
-pub struct VaultHeaderV1 {
+/// Maximum number of key slots a single vault header may carry.
+pub const MAX_KEY_SLOTS: usize = 8;
+
+/// One wrapping of the vault DEK under a KEK derived from its own secret.
+///
+/// Every slot has an independent Argon2id salt/params, so slots can be added
+/// or removed without touching the others.
+#[derive(Debug, Clone)]
+pub struct KeySlot {
+    pub id: u8,
+    pub label: String,
+    /// The slot's KEK also mixes in a keyfile digest (see `crypto::derive_kek`).
+    pub keyfile_required: bool,
     pub kdf_params: crypto::KdfParams,
     pub kdf_salt: [u8; 16],
     pub wrap_nonce: [u8; crypto::XCHACHA_NONCE_LEN],
     pub wrapped_dek: Vec<u8>,
+}
+
+pub struct VaultHeaderV1 {
+    pub key_slots: Vec<KeySlot>,
     pub payload_nonce: [u8; crypto::XCHACHA_NONCE_LEN],
+    /// Header used the original single-wrapper layout (top-level
+    /// `TLV_ARGON2_PARAMS`/`TLV_KDF_SALT`/`TLV_WRAPPED_DEK`). Such a header
+    /// always has exactly one slot and is rewritten in slot layout on save.
+    pub legacy_layout: bool,
 }
 
 pub struct ParsedVaultV1<'a> {
@@ -87,6 +117,9 @@ pub struct ParsedVaultV1<'a> {
 
 pub fn parse_vault_v1(bytes: &[u8]) -> Result<ParsedVaultV1<'_>, VaultFormatError> {
     let fixed = parse_fixed_header(bytes)?;
+    if fixed.version != VERSION_V1 {
+        return Err(VaultFormatError::UnsupportedVersion(fixed.version));
+    }
     let header_len = fixed.header_len as usize;
     let tlvs = &bytes[FIXED_HEADER_LEN..header_len];
     let payload_ciphertext = &bytes[header_len..];
@@ -96,49 +129,14 @@ pub fn parse_vault_v1(bytes: &[u8]) -> Result<ParsedVaultV1<'_>, VaultFormatErro
     let mut kdf_alg_ok = false;
     let mut aead_alg_ok = false;
     let mut hkdf_alg_ok = false;
-    let mut wrap_nonce: Option<[u8; crypto::XCHACHA_NONCE_LEN]> = None;
-    let mut wrapped_dek: Option<Vec<u8>> = None;
+    let mut legacy_wrapped: Option<([u8; crypto::XCHACHA_NONCE_LEN], Vec<u8>)> = None;
+    let mut key_slots: Vec<KeySlot> = Vec::new();
     let mut payload_nonce: Option<[u8; crypto::XCHACHA_NONCE_LEN]> = None;
 
-    let mut pos = 0usize;
-    while pos < tlvs.len() {
-        if tlvs.len() - pos < 2 + 4 {
-            return Err(VaultFormatError::InvalidTlv);
-        }
-
-        let typ = u16::from_le_bytes(tlvs[pos..pos + 2].try_into().expect("2 bytes"));
-        let len = u32::from_le_bytes(tlvs[pos + 2..pos + 6].try_into().expect("4 bytes")) as usize;
-        pos += 6;
-        if tlvs.len() - pos < len {
-            return Err(VaultFormatError::InvalidTlv);
-        }
-        let value = &tlvs[pos..pos + len];
-        pos += len;
-
+    for (typ, value) in read_tlvs(tlvs)? {
         match typ {
-            TLV_ARGON2_PARAMS => {
-                if value.len() != 16 {
-                    return Err(VaultFormatError::InvalidField("argon2_params"));
-                }
-                let memory_kib = u32::from_le_bytes(value[0..4].try_into().expect("4 bytes"));
-                let iterations = u32::from_le_bytes(value[4..8].try_into().expect("4 bytes"));
-                let parallelism = u32::from_le_bytes(value[8..12].try_into().expect("4 bytes"));
-                let out_len = u32::from_le_bytes(value[12..16].try_into().expect("4 bytes"));
-                if out_len as usize != crypto::KDF_OUT_LEN {
-                    return Err(VaultFormatError::InvalidField("argon2_params.out_len"));
-                }
-                kdf_params = Some(crypto::KdfParams {
-                    memory_kib,
-                    iterations,
-                    parallelism,
-                });
-            }
-            TLV_KDF_SALT => {
-                let salt: [u8; 16] = value
-                    .try_into()
-                    .map_err(|_| VaultFormatError::InvalidField("kdf_salt"))?;
-                kdf_salt = Some(salt);
-            }
+            TLV_ARGON2_PARAMS => kdf_params = Some(parse_argon2_params(value)?),
+            TLV_KDF_SALT => kdf_salt = Some(parse_kdf_salt(value)?),
             TLV_KDF_ALG => {
                 if value == KDF_ALG_ARGON2ID {
                     kdf_alg_ok = true;
@@ -160,24 +158,13 @@ pub fn parse_vault_v1(bytes: &[u8]) -> Result<ParsedVaultV1<'_>, VaultFormatErro
                     return Err(VaultFormatError::InvalidField("hkdf_alg"));
                 }
             }
-            TLV_WRAPPED_DEK => {
-                if value.len() < crypto::XCHACHA_NONCE_LEN + 4 {
-                    return Err(VaultFormatError::InvalidField("wrapped_dek"));
+            TLV_WRAPPED_DEK => legacy_wrapped = Some(parse_wrapped_dek(value)?),
+            TLV_KEY_SLOT => {
+                let slot = parse_key_slot(value)?;
+                if key_slots.iter().any(|s| s.id == slot.id) {
+                    return Err(VaultFormatError::InvalidField("key_slot.id"));
                 }
-                let nonce: [u8; crypto::XCHACHA_NONCE_LEN] = value[0..crypto::XCHACHA_NONCE_LEN]
-                    .try_into()
-                    .map_err(|_| VaultFormatError::InvalidField("wrapped_dek.wrap_nonce"))?;
-                let ct_len = u32::from_le_bytes(
-                    value[crypto::XCHACHA_NONCE_LEN..crypto::XCHACHA_NONCE_LEN + 4]
-                        .try_into()
-                        .expect("4 bytes"),
-                ) as usize;
-                let ct = &value[crypto::XCHACHA_NONCE_LEN + 4..];
-                if ct.len() != ct_len {
-                    return Err(VaultFormatError::InvalidField("wrapped_dek.ct_len"));
-                }
-                wrap_nonce = Some(nonce);
-                wrapped_dek = Some(ct.to_vec());
+                key_slots.push(slot);
             }
             TLV_PAYLOAD_NONCE => {
                 let nonce: [u8; crypto::XCHACHA_NONCE_LEN] = value
@@ -201,12 +188,31 @@ pub fn parse_vault_v1(bytes: &[u8]) -> Result<ParsedVaultV1<'_>, VaultFormatErro
         return Err(VaultFormatError::MissingField("hkdf_alg"));
     }
 
+    let legacy_layout = legacy_wrapped.is_some();
+    if legacy_layout {
+        if !key_slots.is_empty() {
+            return Err(VaultFormatError::InvalidField("key_slot"));
+        }
+        let (wrap_nonce, wrapped_dek) = legacy_wrapped.expect("checked above");
+        key_slots.push(KeySlot {
+            id: 0,
+            label: LEGACY_SLOT_LABEL.to_string(),
+            keyfile_required: false,
+            kdf_params: kdf_params.ok_or(VaultFormatError::MissingField("argon2_params"))?,
+            kdf_salt: kdf_salt.ok_or(VaultFormatError::MissingField("kdf_salt"))?,
+            wrap_nonce,
+            wrapped_dek,
+        });
+    } else if key_slots.is_empty() {
+        return Err(VaultFormatError::MissingField("key_slot"));
+    } else if key_slots.len() > MAX_KEY_SLOTS {
+        return Err(VaultFormatError::InvalidField("key_slot"));
+    }
+
     let header = VaultHeaderV1 {
-        kdf_params: kdf_params.ok_or(VaultFormatError::MissingField("argon2_params"))?,
-        kdf_salt: kdf_salt.ok_or(VaultFormatError::MissingField("kdf_salt"))?,
-        wrap_nonce: wrap_nonce.ok_or(VaultFormatError::MissingField("wrapped_dek.wrap_nonce"))?,
-        wrapped_dek: wrapped_dek.ok_or(VaultFormatError::MissingField("wrapped_dek"))?,
+        key_slots,
         payload_nonce: payload_nonce.ok_or(VaultFormatError::MissingField("payload_nonce"))?,
+        legacy_layout,
     };
 
     Ok(ParsedVaultV1 {
@@ -215,26 +221,153 @@ pub fn parse_vault_v1(bytes: &[u8]) -> Result<ParsedVaultV1<'_>, VaultFormatErro
     })
 }
 
-pub fn encode_header_v1(h: &VaultHeaderV1) -> Vec<u8> {
-    let mut tlvs = Vec::new();
+/// A header problem found by [`diagnose_vault_v1`], with where it was found.
+#[derive(Debug)]
+pub struct HeaderIssue {
+    pub location: String,
+    pub error: VaultFormatError,
+}
 
-    let mut params = Vec::with_capacity(16);
-    params.extend_from_slice(&h.kdf_params.memory_kib.to_le_bytes());
-    params.extend_from_slice(&h.kdf_params.iterations.to_le_bytes());
-    params.extend_from_slice(&h.kdf_params.parallelism.to_le_bytes());
-    params.extend_from_slice(&(crypto::KDF_OUT_LEN as u32).to_le_bytes());
-    push_tlv(&mut tlvs, TLV_ARGON2_PARAMS, &params);
+/// Walk the whole header and report every problem instead of stopping at the
+/// first one like [`parse_vault_v1`] does.
+///
+/// Only a broken fixed header or a truncated TLV ends the walk early, since
+/// nothing after them can be located.
+pub fn diagnose_vault_v1(bytes: &[u8]) -> Vec<HeaderIssue> {
+    let mut issues = Vec::new();
+    let mut report = |location: String, error: VaultFormatError| {
+        issues.push(HeaderIssue { location, error });
+    };
 
-    push_tlv(&mut tlvs, TLV_KDF_SALT, &h.kdf_salt);
-    push_tlv(&mut tlvs, TLV_KDF_ALG, KDF_ALG_ARGON2ID);
-    push_tlv(&mut tlvs, TLV_AEAD_ALG, AEAD_ALG_XCHACHA20POLY1305);
-    push_tlv(&mut tlvs, TLV_HKDF_ALG, HKDF_ALG_SHA256);
+    let fixed = match parse_fixed_header(bytes) {
+        Ok(fixed) => fixed,
+        Err(error) => {
+            report("fixed_header".to_string(), error);
+            return issues;
+        }
+    };
+    let header_len = fixed.header_len as usize;
 
-    let mut wrapped = Vec::with_capacity(crypto::XCHACHA_NONCE_LEN + 4 + h.wrapped_dek.len());
-    wrapped.extend_from_slice(&h.wrap_nonce);
-    wrapped.extend_from_slice(&(h.wrapped_dek.len() as u32).to_le_bytes());
-    wrapped.extend_from_slice(&h.wrapped_dek);
-    push_tlv(&mut tlvs, TLV_WRAPPED_DEK, &wrapped);
+    let (tlvs, truncated_at) = split_tlvs(&bytes[FIXED_HEADER_LEN..header_len]);
+    if let Some(offset) = truncated_at {
+        report(
+            format!("header+{}", FIXED_HEADER_LEN + offset),
+            VaultFormatError::InvalidTlv,
+        );
+    }
+
+    let mut seen = std::collections::BTreeSet::new();
+    let mut slot_ids = std::collections::BTreeSet::new();
+    let mut slot_count = 0usize;
+    for (typ, value) in tlvs {
+        seen.insert(typ);
+        let result = match typ {
+            TLV_ARGON2_PARAMS => parse_argon2_params(value).map(|_| ()),
+            TLV_KDF_SALT => parse_kdf_salt(value).map(|_| ()),
+            TLV_KDF_ALG if value != KDF_ALG_ARGON2ID => {
+                Err(VaultFormatError::InvalidField("kdf_alg"))
+            }
+            TLV_AEAD_ALG if value != AEAD_ALG_XCHACHA20POLY1305 => {
+                Err(VaultFormatError::InvalidField("aead_alg"))
+            }
+            TLV_HKDF_ALG if value != HKDF_ALG_SHA256 => {
+                Err(VaultFormatError::InvalidField("hkdf_alg"))
+            }
+            TLV_WRAPPED_DEK => parse_wrapped_dek(value).map(|_| ()),
+            TLV_KEY_SLOT => {
+                slot_count += 1;
+                match parse_key_slot(value) {
+                    Ok(slot) if !slot_ids.insert(slot.id) => {
+                        Err(VaultFormatError::InvalidField("key_slot.id"))
+                    }
+                    other => other.map(|_| ()),
+                }
+            }
+            TLV_PAYLOAD_NONCE => <[u8; crypto::XCHACHA_NONCE_LEN]>::try_from(value)
+                .map(|_| ())
+                .map_err(|_| VaultFormatError::InvalidField("payload_nonce")),
+            _ => Ok(()),
+        };
+        if let Err(error) = result {
+            let location = if typ == TLV_KEY_SLOT {
+                format!("key_slot[{}]", slot_count - 1)
+            } else {
+                format!("tlv 0x{typ:04x}")
+            };
+            report(location, error);
+        }
+    }
+
+    for (typ, field) in [
+        (TLV_KDF_ALG, "kdf_alg"),
+        (TLV_AEAD_ALG, "aead_alg"),
+        (TLV_HKDF_ALG, "hkdf_alg"),
+        (TLV_PAYLOAD_NONCE, "payload_nonce"),
+    ] {
+        if !seen.contains(&typ) {
+            report("header".to_string(), VaultFormatError::MissingField(field));
+        }
+    }
+
+    if seen.contains(&TLV_WRAPPED_DEK) {
+        if slot_count > 0 {
+            report(
+                "header".to_string(),
+                VaultFormatError::InvalidField("key_slot"),
+            );
+        }
+        for (typ, field) in [
+            (TLV_ARGON2_PARAMS, "argon2_params"),
+            (TLV_KDF_SALT, "kdf_salt"),
+        ] {
+            if !seen.contains(&typ) {
+                report("header".to_string(), VaultFormatError::MissingField(field));
+            }
+        }
+    } else if slot_count == 0 {
+        report(
+            "header".to_string(),
+            VaultFormatError::MissingField("key_slot"),
+        );
+    } else if slot_count > MAX_KEY_SLOTS {
+        report(
+            "header".to_string(),
+            VaultFormatError::InvalidField("key_slot"),
+        );
+    }
+
+    if bytes.len() - header_len < crypto::AEAD_TAG_LEN {
+        report("payload".to_string(), VaultFormatError::TooSmall);
+    }
+
+    issues
+}
+
+pub fn encode_header_v1(h: &VaultHeaderV1) -> Vec<u8> {
+    let mut tlvs = Vec::new();
+
+    if h.legacy_layout {
+        // Byte-for-byte the original layout, so AAD computed over legacy
+        // headers still matches what was authenticated when they were written.
+        let slot = &h.key_slots[0];
+        push_tlv(
+            &mut tlvs,
+            TLV_ARGON2_PARAMS,
+            &encode_argon2_params(slot.kdf_params),
+        );
+        push_tlv(&mut tlvs, TLV_KDF_SALT, &slot.kdf_salt);
+        push_tlv(&mut tlvs, TLV_KDF_ALG, KDF_ALG_ARGON2ID);
+        push_tlv(&mut tlvs, TLV_AEAD_ALG, AEAD_ALG_XCHACHA20POLY1305);
+        push_tlv(&mut tlvs, TLV_HKDF_ALG, HKDF_ALG_SHA256);
+        push_tlv(&mut tlvs, TLV_WRAPPED_DEK, &encode_wrapped_dek(slot));
+    } else {
+        push_tlv(&mut tlvs, TLV_KDF_ALG, KDF_ALG_ARGON2ID);
+        push_tlv(&mut tlvs, TLV_AEAD_ALG, AEAD_ALG_XCHACHA20POLY1305);
+        push_tlv(&mut tlvs, TLV_HKDF_ALG, HKDF_ALG_SHA256);
+        for slot in &h.key_slots {
+            push_tlv(&mut tlvs, TLV_KEY_SLOT, &encode_key_slot(slot));
+        }
+    }
 
     push_tlv(&mut tlvs, TLV_PAYLOAD_NONCE, &h.payload_nonce);
 
@@ -248,8 +381,296 @@ pub fn encode_header_v1(h: &VaultHeaderV1) -> Vec<u8> {
     out
 }
 
-fn push_tlv(buf: &mut Vec<u8>, typ: u16, value: &[u8]) {
+/// Associated data used when wrapping/unwrapping the DEK in a slot-layout key slot.
+///
+/// Only the slot's own fields (with the wrapped ciphertext zeroed) are bound,
+/// so adding or removing other slots, or re-sealing the payload under a new
+/// nonce, never invalidates an existing wrapping. The version tag stays
+/// `VERSION_V1` in v2 files too, so `vault upgrade` carries slots over as-is.
+pub fn key_slot_aad(slot: &KeySlot) -> Vec<u8> {
+    let placeholder = KeySlot {
+        wrapped_dek: vec![0u8; slot.wrapped_dek.len()],
+        ..slot.clone()
+    };
+
+    let mut out = Vec::new();
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&VERSION_V1.to_le_bytes());
+    out.extend_from_slice(&encode_key_slot(&placeholder));
+    out
+}
+
+pub(super) fn read_tlvs(bytes: &[u8]) -> Result<Vec<(u16, &[u8])>, VaultFormatError> {
+    match split_tlvs(bytes) {
+        (tlvs, None) => Ok(tlvs),
+        (_, Some(_)) => Err(VaultFormatError::InvalidTlv),
+    }
+}
+
+/// Split `bytes` into TLVs; also returns the offset of a truncated trailing
+/// TLV, after which nothing more can be read.
+pub(super) fn split_tlvs(bytes: &[u8]) -> (Vec<(u16, &[u8])>, Option<usize>) {
+    let mut out = Vec::new();
+    let mut pos = 0usize;
+    while pos < bytes.len() {
+        if bytes.len() - pos < 2 + 4 {
+            return (out, Some(pos));
+        }
+
+        let typ = u16::from_le_bytes(bytes[pos..pos + 2].try_into().expect("2 bytes"));
+        let len = u32::from_le_bytes(bytes[pos + 2..pos + 6].try_into().expect("4 bytes")) as usize;
+        if bytes.len() - pos - 6 < len {
+            return (out, Some(pos));
+        }
+        out.push((typ, &bytes[pos + 6..pos + 6 + len]));
+        pos += 6 + len;
+    }
+    (out, None)
+}
+
+pub(super) fn parse_key_slot(value: &[u8]) -> Result<KeySlot, VaultFormatError> {
+    let mut id: Option<u8> = None;
+    let mut label: Option<String> = None;
+    let mut keyfile_required = false;
+    let mut kdf_params: Option<crypto::KdfParams> = None;
+    let mut kdf_salt: Option<[u8; 16]> = None;
+    let mut wrapped: Option<([u8; crypto::XCHACHA_NONCE_LEN], Vec<u8>)> = None;
+
+    for (typ, value) in read_tlvs(value)? {
+        match typ {
+            TLV_SLOT_ID => {
+                let [slot_id] = value else {
+                    return Err(VaultFormatError::InvalidField("key_slot.id"));
+                };
+                id = Some(*slot_id);
+            }
+            TLV_SLOT_LABEL => {
+                let s = std::str::from_utf8(value)
+                    .map_err(|_| VaultFormatError::InvalidField("key_slot.label"))?;
+                label = Some(s.to_string());
+            }
+            TLV_SLOT_KEYFILE_REQUIRED => {
+                keyfile_required = match value {
+                    [0] => false,
+                    [1] => true,
+                    _ => return Err(VaultFormatError::InvalidField("key_slot.keyfile_required")),
+                };
+            }
+            TLV_ARGON2_PARAMS => kdf_params = Some(parse_argon2_params(value)?),
+            TLV_KDF_SALT => kdf_salt = Some(parse_kdf_salt(value)?),
+            TLV_WRAPPED_DEK => wrapped = Some(parse_wrapped_dek(value)?),
+            _ => {}
+        }
+    }
+
+    let (wrap_nonce, wrapped_dek) =
+        wrapped.ok_or(VaultFormatError::MissingField("key_slot.wrapped_dek"))?;
+    Ok(KeySlot {
+        id: id.ok_or(VaultFormatError::MissingField("key_slot.id"))?,
+        label: label.unwrap_or_default(),
+        keyfile_required,
+        kdf_params: kdf_params.ok_or(VaultFormatError::MissingField("key_slot.argon2_params"))?,
+        kdf_salt: kdf_salt.ok_or(VaultFormatError::MissingField("key_slot.kdf_salt"))?,
+        wrap_nonce,
+        wrapped_dek,
+    })
+}
+
+pub(super) fn encode_key_slot(slot: &KeySlot) -> Vec<u8> {
+    let mut out = Vec::new();
+    push_tlv(&mut out, TLV_SLOT_ID, &[slot.id]);
+    push_tlv(&mut out, TLV_SLOT_LABEL, slot.label.as_bytes());
+    if slot.keyfile_required {
+        push_tlv(&mut out, TLV_SLOT_KEYFILE_REQUIRED, &[1]);
+    }
+    push_tlv(
+        &mut out,
+        TLV_ARGON2_PARAMS,
+        &encode_argon2_params(slot.kdf_params),
+    );
+    push_tlv(&mut out, TLV_KDF_SALT, &slot.kdf_salt);
+    push_tlv(&mut out, TLV_WRAPPED_DEK, &encode_wrapped_dek(slot));
+    out
+}
+
+fn parse_argon2_params(value: &[u8]) -> Result<crypto::KdfParams, VaultFormatError> {
+    if value.len() != 16 {
+        return Err(VaultFormatError::InvalidField("argon2_params"));
+    }
+    let memory_kib = u32::from_le_bytes(value[0..4].try_into().expect("4 bytes"));
+    let iterations = u32::from_le_bytes(value[4..8].try_into().expect("4 bytes"));
+    let parallelism = u32::from_le_bytes(value[8..12].try_into().expect("4 bytes"));
+    let out_len = u32::from_le_bytes(value[12..16].try_into().expect("4 bytes"));
+    if out_len as usize != crypto::KDF_OUT_LEN {
+        return Err(VaultFormatError::InvalidField("argon2_params.out_len"));
+    }
+    Ok(crypto::KdfParams {
+        memory_kib,
+        iterations,
+        parallelism,
+    })
+}
+
+fn encode_argon2_params(params: crypto::KdfParams) -> Vec<u8> {
+    let mut out = Vec::with_capacity(16);
+    out.extend_from_slice(&params.memory_kib.to_le_bytes());
+    out.extend_from_slice(&params.iterations.to_le_bytes());
+    out.extend_from_slice(&params.parallelism.to_le_bytes());
+    out.extend_from_slice(&(crypto::KDF_OUT_LEN as u32).to_le_bytes());
+    out
+}
+
+fn parse_kdf_salt(value: &[u8]) -> Result<[u8; 16], VaultFormatError> {
+    value
+        .try_into()
+        .map_err(|_| VaultFormatError::InvalidField("kdf_salt"))
+}
+
+fn parse_wrapped_dek(
+    value: &[u8],
+) -> Result<([u8; crypto::XCHACHA_NONCE_LEN], Vec<u8>), VaultFormatError> {
+    if value.len() < crypto::XCHACHA_NONCE_LEN + 4 {
+        return Err(VaultFormatError::InvalidField("wrapped_dek"));
+    }
+    let nonce: [u8; crypto::XCHACHA_NONCE_LEN] = value[0..crypto::XCHACHA_NONCE_LEN]
+        .try_into()
+        .map_err(|_| VaultFormatError::InvalidField("wrapped_dek.wrap_nonce"))?;
+    let ct_len = u32::from_le_bytes(
+        value[crypto::XCHACHA_NONCE_LEN..crypto::XCHACHA_NONCE_LEN + 4]
+            .try_into()
+            .expect("4 bytes"),
+    ) as usize;
+    let ct = &value[crypto::XCHACHA_NONCE_LEN + 4..];
+    if ct.len() != ct_len {
+        return Err(VaultFormatError::InvalidField("wrapped_dek.ct_len"));
+    }
+    Ok((nonce, ct.to_vec()))
+}
+
+fn encode_wrapped_dek(slot: &KeySlot) -> Vec<u8> {
+    let mut wrapped = Vec::with_capacity(crypto::XCHACHA_NONCE_LEN + 4 + slot.wrapped_dek.len());
+    wrapped.extend_from_slice(&slot.wrap_nonce);
+    wrapped.extend_from_slice(&(slot.wrapped_dek.len() as u32).to_le_bytes());
+    wrapped.extend_from_slice(&slot.wrapped_dek);
+    wrapped
+}
+
+pub(super) fn push_tlv(buf: &mut Vec<u8>, typ: u16, value: &[u8]) {
     buf.extend_from_slice(&typ.to_le_bytes());
     buf.extend_from_slice(&(value.len() as u32).to_le_bytes());
     buf.extend_from_slice(value);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn slot(id: u8, label: &str) -> KeySlot {
+        KeySlot {
+            id,
+            label: label.to_string(),
+            keyfile_required: id % 2 == 1,
+            kdf_params: crypto::KdfParams::for_tests(),
+            kdf_salt: [id; 16],
+            wrap_nonce: [id; crypto::XCHACHA_NONCE_LEN],
+            wrapped_dek: vec![id; crypto::DEK_LEN + 16],
+        }
+    }
+
+    #[test]
+    fn slot_layout_roundtrips() {
+        let header = VaultHeaderV1 {
+            key_slots: vec![slot(0, "master"), slot(3, "recovery")],
+            payload_nonce: [9u8; crypto::XCHACHA_NONCE_LEN],
+            legacy_layout: false,
+        };
+        let mut bytes = encode_header_v1(&header);
+        bytes.extend_from_slice(b"ciphertext");
+
+        let parsed = parse_vault_v1(&bytes).unwrap();
+        assert!(!parsed.header.legacy_layout);
+        assert_eq!(parsed.payload_ciphertext, b"ciphertext");
+        let ids = parsed
+            .header
+            .key_slots
+            .iter()
+            .map(|s| (s.id, s.label.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec![(0, "master"), (3, "recovery")]);
+        assert_eq!(parsed.header.key_slots[1].kdf_salt, [3u8; 16]);
+        assert!(!parsed.header.key_slots[0].keyfile_required);
+        assert!(parsed.header.key_slots[1].keyfile_required);
+    }
+
+    #[test]
+    fn legacy_layout_parses_as_single_slot_and_reencodes_identically() {
+        let legacy = VaultHeaderV1 {
+            key_slots: vec![slot(0, LEGACY_SLOT_LABEL)],
+            payload_nonce: [1u8; crypto::XCHACHA_NONCE_LEN],
+            legacy_layout: true,
+        };
+        let bytes = encode_header_v1(&legacy);
+
+        let parsed = parse_vault_v1(&bytes).unwrap();
+        assert!(parsed.header.legacy_layout);
+        assert_eq!(parsed.header.key_slots.len(), 1);
+        assert_eq!(encode_header_v1(&parsed.header), bytes);
+    }
+
+    #[test]
+    fn duplicate_slot_ids_are_rejected() {
+        let header = VaultHeaderV1 {
+            key_slots: vec![slot(1, "a"), slot(1, "b")],
+            payload_nonce: [0u8; crypto::XCHACHA_NONCE_LEN],
+            legacy_layout: false,
+        };
+        let bytes = encode_header_v1(&header);
+        assert!(matches!(
+            parse_vault_v1(&bytes),
+            Err(VaultFormatError::InvalidField("key_slot.id"))
+        ));
+    }
+
+    #[test]
+    fn slot_aad_ignores_other_slots_and_wrapped_ciphertext() {
+        let a = slot(0, "master");
+        let mut a_rewrapped = a.clone();
+        a_rewrapped.wrapped_dek = vec![0xff; a.wrapped_dek.len()];
+        assert_eq!(key_slot_aad(&a), key_slot_aad(&a_rewrapped));
+
+        let mut relabeled = a.clone();
+        relabeled.label = "other".to_string();
+        assert_ne!(key_slot_aad(&a), key_slot_aad(&relabeled));
+
+        let mut keyfile_toggled = a.clone();
+        keyfile_toggled.keyfile_required = !a.keyfile_required;
+        assert_ne!(key_slot_aad(&a), key_slot_aad(&keyfile_toggled));
+    }
+
+    #[test]
+    fn diagnose_reports_every_header_problem() {
+        let mut tlvs = Vec::new();
+        push_tlv(&mut tlvs, TLV_KDF_ALG, b"scrypt");
+        push_tlv(&mut tlvs, TLV_AEAD_ALG, AEAD_ALG_XCHACHA20POLY1305);
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&VERSION_V1.to_le_bytes());
+        bytes.extend_from_slice(&((FIXED_HEADER_LEN + tlvs.len()) as u32).to_le_bytes());
+        bytes.extend_from_slice(&tlvs);
+
+        let issues = diagnose_vault_v1(&bytes)
+            .into_iter()
+            .map(|i| format!("{}: {}", i.location, i.error))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            issues,
+            vec![
+                "tlv 0x0003: invalid header field: kdf_alg",
+                "header: missing required header field: hkdf_alg",
+                "header: missing required header field: payload_nonce",
+                "header: missing required header field: key_slot",
+                "payload: vault file too small",
+            ]
+        );
+    }
+}
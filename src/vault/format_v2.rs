@@ -0,0 +1,524 @@
+//! Vault file format v2: items sealed record by record.
+//!
+//! The fixed header and key slots are the same as v1's slot layout, minus the
+//! payload nonce. After the header comes a TLV list of sealed records:
+//!
+//! - one manifest record (payload schema version, tombstones and the SHA-256
+//!   of every item record), sealed under a key derived from the DEK;
+//! - per item, a metadata record (everything `vault list`/`search` show) and a
+//!   secrets record (`items::SECRET_ITEM_FIELDS`), both sealed under a key
+//!   derived from the DEK and the item id.
+//!
+//! Listing therefore never decrypts a secret, and a write only re-seals the
+//! records that changed plus the manifest. The manifest binds the set of
+//! records, so none can be dropped, duplicated or rolled back on its own.
+
+use crate::vault::crypto;
+use crate::vault::format_v1::{
+    self, AEAD_ALG_XCHACHA20POLY1305, FIXED_HEADER_LEN, HKDF_ALG_SHA256, HeaderIssue,
+    KDF_ALG_ARGON2ID, KeySlot, MAGIC, MAX_KEY_SLOTS, ParsedVaultV1, TLV_AEAD_ALG, TLV_HKDF_ALG,
+    TLV_KDF_ALG, TLV_KEY_SLOT, VERSION_V1, VaultFormatError,
+};
+use crate::vault::items::{VaultTombstone, uuid_as_string};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use uuid::Uuid;
+
+pub const VERSION_V2: u16 = 2;
+
+const TLV_MANIFEST: u16 = 0x0300;
+/// Repeatable; the value is itself a TLV list (item id, metadata record,
+/// secrets record).
+const TLV_ITEM: u16 = 0x0310;
+const TLV_ITEM_ID: u16 = 0x0311;
+const TLV_ITEM_META: u16 = 0x0312;
+const TLV_ITEM_SECRETS: u16 = 0x0313;
+
+const RECORD_MANIFEST: &[u8] = b"manifest";
+const RECORD_META: &[u8] = b"meta";
+const RECORD_SECRETS: &[u8] = b"secrets";
+
+/// One AEAD-sealed record: a fresh nonce and the ciphertext with its tag.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SealedRecord {
+    pub nonce: [u8; crypto::XCHACHA_NONCE_LEN],
+    pub ciphertext: Vec<u8>,
+}
+
+impl SealedRecord {
+    pub fn digest(&self) -> [u8; 32] {
+        crypto::record_digest(&self.nonce, &self.ciphertext)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SealedItemV2 {
+    pub id: Uuid,
+    pub meta: SealedRecord,
+    pub secrets: SealedRecord,
+}
+
+pub struct VaultHeaderV2 {
+    pub key_slots: Vec<KeySlot>,
+}
+
+pub struct ParsedVaultV2 {
+    pub header: VaultHeaderV2,
+    pub manifest: SealedRecord,
+    pub items: Vec<SealedItemV2>,
+}
+
+/// Plaintext of the manifest record.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ManifestV2 {
+    pub schema_version: u32,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tombstones: Vec<VaultTombstone>,
+    pub items: Vec<ManifestEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    #[serde(with = "uuid_as_string")]
+    pub id: Uuid,
+    /// Base64 SHA-256 of the sealed metadata record.
+    pub meta: String,
+    /// Base64 SHA-256 of the sealed secrets record.
+    pub secrets: String,
+}
+
+/// A vault file of either version.
+pub enum ParsedVault<'a> {
+    V1(ParsedVaultV1<'a>),
+    V2(ParsedVaultV2),
+}
+
+impl ParsedVault<'_> {
+    pub fn version(&self) -> u16 {
+        match self {
+            ParsedVault::V1(_) => VERSION_V1,
+            ParsedVault::V2(_) => VERSION_V2,
+        }
+    }
+
+    pub fn key_slots(&self) -> &[KeySlot] {
+        match self {
+            ParsedVault::V1(parsed) => &parsed.header.key_slots,
+            ParsedVault::V2(parsed) => &parsed.header.key_slots,
+        }
+    }
+
+    /// Only v1 files can carry the original single-wrapper header.
+    pub fn legacy_layout(&self) -> bool {
+        match self {
+            ParsedVault::V1(parsed) => parsed.header.legacy_layout,
+            ParsedVault::V2(_) => false,
+        }
+    }
+}
+
+/// Parse a vault file of any supported version.
+pub fn parse_vault(bytes: &[u8]) -> Result<ParsedVault<'_>, VaultFormatError> {
+    match format_v1::parse_fixed_header(bytes)?.version {
+        VERSION_V1 => format_v1::parse_vault_v1(bytes).map(ParsedVault::V1),
+        VERSION_V2 => parse_vault_v2(bytes).map(ParsedVault::V2),
+        other => Err(VaultFormatError::UnsupportedVersion(other)),
+    }
+}
+
+pub fn parse_vault_v2(bytes: &[u8]) -> Result<ParsedVaultV2, VaultFormatError> {
+    let fixed = format_v1::parse_fixed_header(bytes)?;
+    if fixed.version != VERSION_V2 {
+        return Err(VaultFormatError::UnsupportedVersion(fixed.version));
+    }
+    let header_len = fixed.header_len as usize;
+    let key_slots = parse_header_tlvs(&bytes[FIXED_HEADER_LEN..header_len])?;
+
+    let mut manifest: Option<SealedRecord> = None;
+    let mut items: Vec<SealedItemV2> = Vec::new();
+    for (typ, value) in format_v1::read_tlvs(&bytes[header_len..])? {
+        match typ {
+            TLV_MANIFEST => {
+                if manifest.is_some() {
+                    return Err(VaultFormatError::InvalidField("manifest"));
+                }
+                manifest = Some(parse_record(value, "manifest")?);
+            }
+            TLV_ITEM => {
+                let item = parse_item(value)?;
+                if items.iter().any(|i| i.id == item.id) {
+                    return Err(VaultFormatError::InvalidField("item.id"));
+                }
+                items.push(item);
+            }
+            _ => {
+                // Unknown TLVs are ignored (forward-compatible).
+            }
+        }
+    }
+
+    Ok(ParsedVaultV2 {
+        header: VaultHeaderV2 { key_slots },
+        manifest: manifest.ok_or(VaultFormatError::MissingField("manifest"))?,
+        items,
+    })
+}
+
+fn parse_header_tlvs(tlvs: &[u8]) -> Result<Vec<KeySlot>, VaultFormatError> {
+    let mut kdf_alg_ok = false;
+    let mut aead_alg_ok = false;
+    let mut hkdf_alg_ok = false;
+    let mut key_slots: Vec<KeySlot> = Vec::new();
+
+    for (typ, value) in format_v1::read_tlvs(tlvs)? {
+        match typ {
+            TLV_KDF_ALG => kdf_alg_ok = check_alg(value, KDF_ALG_ARGON2ID, "kdf_alg")?,
+            TLV_AEAD_ALG => aead_alg_ok = check_alg(value, AEAD_ALG_XCHACHA20POLY1305, "aead_alg")?,
+            TLV_HKDF_ALG => hkdf_alg_ok = check_alg(value, HKDF_ALG_SHA256, "hkdf_alg")?,
+            TLV_KEY_SLOT => {
+                let slot = format_v1::parse_key_slot(value)?;
+                if key_slots.iter().any(|s| s.id == slot.id) {
+                    return Err(VaultFormatError::InvalidField("key_slot.id"));
+                }
+                key_slots.push(slot);
+            }
+            _ => {}
+        }
+    }
+
+    if !kdf_alg_ok {
+        return Err(VaultFormatError::MissingField("kdf_alg"));
+    }
+    if !aead_alg_ok {
+        return Err(VaultFormatError::MissingField("aead_alg"));
+    }
+    if !hkdf_alg_ok {
+        return Err(VaultFormatError::MissingField("hkdf_alg"));
+    }
+    if key_slots.is_empty() {
+        return Err(VaultFormatError::MissingField("key_slot"));
+    }
+    if key_slots.len() > MAX_KEY_SLOTS {
+        return Err(VaultFormatError::InvalidField("key_slot"));
+    }
+    Ok(key_slots)
+}
+
+fn check_alg(value: &[u8], expected: &[u8], field: &'static str) -> Result<bool, VaultFormatError> {
+    if value == expected {
+        Ok(true)
+    } else {
+        Err(VaultFormatError::InvalidField(field))
+    }
+}
+
+fn parse_record(value: &[u8], field: &'static str) -> Result<SealedRecord, VaultFormatError> {
+    if value.len() < crypto::XCHACHA_NONCE_LEN + crypto::AEAD_TAG_LEN {
+        return Err(VaultFormatError::InvalidField(field));
+    }
+    let (nonce, ciphertext) = value.split_at(crypto::XCHACHA_NONCE_LEN);
+    Ok(SealedRecord {
+        nonce: nonce.try_into().expect("split at nonce length"),
+        ciphertext: ciphertext.to_vec(),
+    })
+}
+
+fn parse_item(value: &[u8]) -> Result<SealedItemV2, VaultFormatError> {
+    let mut id: Option<Uuid> = None;
+    let mut meta: Option<SealedRecord> = None;
+    let mut secrets: Option<SealedRecord> = None;
+
+    for (typ, value) in format_v1::read_tlvs(value)? {
+        match typ {
+            TLV_ITEM_ID => {
+                let bytes: [u8; 16] = value
+                    .try_into()
+                    .map_err(|_| VaultFormatError::InvalidField("item.id"))?;
+                id = Some(Uuid::from_bytes(bytes));
+            }
+            TLV_ITEM_META => meta = Some(parse_record(value, "item.meta")?),
+            TLV_ITEM_SECRETS => secrets = Some(parse_record(value, "item.secrets")?),
+            _ => {}
+        }
+    }
+
+    Ok(SealedItemV2 {
+        id: id.ok_or(VaultFormatError::MissingField("item.id"))?,
+        meta: meta.ok_or(VaultFormatError::MissingField("item.meta"))?,
+        secrets: secrets.ok_or(VaultFormatError::MissingField("item.secrets"))?,
+    })
+}
+
+/// Walk the whole file and report every structural problem instead of
+/// stopping at the first one like [`parse_vault_v2`] does.
+pub fn diagnose_vault_v2(bytes: &[u8]) -> Vec<HeaderIssue> {
+    let mut issues = Vec::new();
+    let mut report = |location: String, error: VaultFormatError| {
+        issues.push(HeaderIssue { location, error });
+    };
+
+    let fixed = match format_v1::parse_fixed_header(bytes) {
+        Ok(fixed) => fixed,
+        Err(error) => {
+            report("fixed_header".to_string(), error);
+            return issues;
+        }
+    };
+    let header_len = fixed.header_len as usize;
+
+    let (tlvs, truncated_at) = format_v1::split_tlvs(&bytes[FIXED_HEADER_LEN..header_len]);
+    if let Some(offset) = truncated_at {
+        report(
+            format!("header+{}", FIXED_HEADER_LEN + offset),
+            VaultFormatError::InvalidTlv,
+        );
+    }
+    let mut seen = BTreeSet::new();
+    let mut slot_ids = BTreeSet::new();
+    let mut slot_count = 0usize;
+    for (typ, value) in tlvs {
+        seen.insert(typ);
+        let result = match typ {
+            TLV_KDF_ALG => check_alg(value, KDF_ALG_ARGON2ID, "kdf_alg").map(|_| ()),
+            TLV_AEAD_ALG => check_alg(value, AEAD_ALG_XCHACHA20POLY1305, "aead_alg").map(|_| ()),
+            TLV_HKDF_ALG => check_alg(value, HKDF_ALG_SHA256, "hkdf_alg").map(|_| ()),
+            TLV_KEY_SLOT => {
+                slot_count += 1;
+                match format_v1::parse_key_slot(value) {
+                    Ok(slot) if !slot_ids.insert(slot.id) => {
+                        Err(VaultFormatError::InvalidField("key_slot.id"))
+                    }
+                    other => other.map(|_| ()),
+                }
+            }
+            _ => Ok(()),
+        };
+        if let Err(error) = result {
+            let location = if typ == TLV_KEY_SLOT {
+                format!("key_slot[{}]", slot_count - 1)
+            } else {
+                format!("tlv 0x{typ:04x}")
+            };
+            report(location, error);
+        }
+    }
+    for (typ, field) in [
+        (TLV_KDF_ALG, "kdf_alg"),
+        (TLV_AEAD_ALG, "aead_alg"),
+        (TLV_HKDF_ALG, "hkdf_alg"),
+    ] {
+        if !seen.contains(&typ) {
+            report("header".to_string(), VaultFormatError::MissingField(field));
+        }
+    }
+    if slot_count == 0 {
+        report(
+            "header".to_string(),
+            VaultFormatError::MissingField("key_slot"),
+        );
+    } else if slot_count > MAX_KEY_SLOTS {
+        report(
+            "header".to_string(),
+            VaultFormatError::InvalidField("key_slot"),
+        );
+    }
+
+    let (records, truncated_at) = format_v1::split_tlvs(&bytes[header_len..]);
+    if let Some(offset) = truncated_at {
+        report(format!("body+{offset}"), VaultFormatError::InvalidTlv);
+    }
+    let mut manifests = 0usize;
+    let mut item_ids = BTreeSet::new();
+    let mut item_count = 0usize;
+    for (typ, value) in records {
+        match typ {
+            TLV_MANIFEST => {
+                manifests += 1;
+                if let Err(error) = parse_record(value, "manifest") {
+                    report("manifest".to_string(), error);
+                }
+            }
+            TLV_ITEM => {
+                let location = format!("item[{item_count}]");
+                item_count += 1;
+                match parse_item(value) {
+                    Ok(item) if !item_ids.insert(item.id) => {
+                        report(location, VaultFormatError::InvalidField("item.id"));
+                    }
+                    Ok(_) => {}
+                    Err(error) => report(location, error),
+                }
+            }
+            _ => {}
+        }
+    }
+    match manifests {
+        0 => report(
+            "body".to_string(),
+            VaultFormatError::MissingField("manifest"),
+        ),
+        1 => {}
+        _ => report(
+            "body".to_string(),
+            VaultFormatError::InvalidField("manifest"),
+        ),
+    }
+
+    issues
+}
+
+pub fn encode_vault_v2(
+    header: &VaultHeaderV2,
+    manifest: &SealedRecord,
+    items: &[SealedItemV2],
+) -> Vec<u8> {
+    let mut tlvs = Vec::new();
+    format_v1::push_tlv(&mut tlvs, TLV_KDF_ALG, KDF_ALG_ARGON2ID);
+    format_v1::push_tlv(&mut tlvs, TLV_AEAD_ALG, AEAD_ALG_XCHACHA20POLY1305);
+    format_v1::push_tlv(&mut tlvs, TLV_HKDF_ALG, HKDF_ALG_SHA256);
+    for slot in &header.key_slots {
+        format_v1::push_tlv(&mut tlvs, TLV_KEY_SLOT, &format_v1::encode_key_slot(slot));
+    }
+    let header_len = (FIXED_HEADER_LEN + tlvs.len()) as u32;
+
+    let mut out = Vec::new();
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&VERSION_V2.to_le_bytes());
+    out.extend_from_slice(&header_len.to_le_bytes());
+    out.extend_from_slice(&tlvs);
+
+    format_v1::push_tlv(&mut out, TLV_MANIFEST, &encode_record(manifest));
+    for item in items {
+        let mut value = Vec::new();
+        format_v1::push_tlv(&mut value, TLV_ITEM_ID, item.id.as_bytes());
+        format_v1::push_tlv(&mut value, TLV_ITEM_META, &encode_record(&item.meta));
+        format_v1::push_tlv(&mut value, TLV_ITEM_SECRETS, &encode_record(&item.secrets));
+        format_v1::push_tlv(&mut out, TLV_ITEM, &value);
+    }
+    out
+}
+
+fn encode_record(record: &SealedRecord) -> Vec<u8> {
+    let mut out = Vec::with_capacity(record.nonce.len() + record.ciphertext.len());
+    out.extend_from_slice(&record.nonce);
+    out.extend_from_slice(&record.ciphertext);
+    out
+}
+
+/// Associated data for the manifest record.
+pub fn manifest_aad() -> Vec<u8> {
+    record_aad(RECORD_MANIFEST, None)
+}
+
+/// Associated data for an item's metadata record, binding it to the item.
+pub fn item_meta_aad(id: &Uuid) -> Vec<u8> {
+    record_aad(RECORD_META, Some(id))
+}
+
+/// Associated data for an item's secrets record, binding it to the item.
+pub fn item_secrets_aad(id: &Uuid) -> Vec<u8> {
+    record_aad(RECORD_SECRETS, Some(id))
+}
+
+fn record_aad(kind: &[u8], id: Option<&Uuid>) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&VERSION_V2.to_le_bytes());
+    out.extend_from_slice(kind);
+    if let Some(id) = id {
+        out.extend_from_slice(id.as_bytes());
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(fill: u8) -> SealedRecord {
+        SealedRecord {
+            nonce: [fill; crypto::XCHACHA_NONCE_LEN],
+            ciphertext: vec![fill; crypto::AEAD_TAG_LEN + 4],
+        }
+    }
+
+    fn header() -> VaultHeaderV2 {
+        VaultHeaderV2 {
+            key_slots: vec![KeySlot {
+                id: 0,
+                label: "master".to_string(),
+                keyfile_required: false,
+                kdf_params: crypto::KdfParams::for_tests(),
+                kdf_salt: [7u8; 16],
+                wrap_nonce: [7u8; crypto::XCHACHA_NONCE_LEN],
+                wrapped_dek: vec![7u8; crypto::DEK_LEN + 16],
+            }],
+        }
+    }
+
+    #[test]
+    fn records_roundtrip_and_dispatch_on_version() {
+        let items = vec![SealedItemV2 {
+            id: Uuid::new_v4(),
+            meta: record(1),
+            secrets: record(2),
+        }];
+        let bytes = encode_vault_v2(&header(), &record(9), &items);
+
+        let ParsedVault::V2(parsed) = parse_vault(&bytes).unwrap() else {
+            panic!("expected a v2 vault");
+        };
+        assert_eq!(parsed.header.key_slots[0].label, "master");
+        assert_eq!(parsed.manifest, record(9));
+        assert_eq!(parsed.items[0].id, items[0].id);
+        assert_eq!(parsed.items[0].secrets, record(2));
+        assert!(diagnose_vault_v2(&bytes).is_empty());
+
+        assert!(matches!(
+            format_v1::parse_vault_v1(&bytes),
+            Err(VaultFormatError::UnsupportedVersion(2))
+        ));
+        let mut future = bytes.clone();
+        future[8] = 3;
+        assert!(matches!(
+            parse_vault(&future),
+            Err(VaultFormatError::UnsupportedVersion(3))
+        ));
+    }
+
+    #[test]
+    fn record_aad_is_bound_to_kind_and_item() {
+        let a = Uuid::new_v4();
+        let b = Uuid::new_v4();
+        assert_ne!(item_meta_aad(&a), item_secrets_aad(&a));
+        assert_ne!(item_meta_aad(&a), item_meta_aad(&b));
+        assert_ne!(manifest_aad(), item_meta_aad(&a));
+    }
+
+    #[test]
+    fn diagnose_reports_missing_manifest_and_duplicate_items() {
+        let id = Uuid::new_v4();
+        let item = SealedItemV2 {
+            id,
+            meta: record(1),
+            secrets: record(2),
+        };
+        let mut bytes = encode_vault_v2(&header(), &record(9), &[item.clone(), item]);
+        // Drop the manifest TLV, which directly follows the header.
+        let header_len = format_v1::parse_fixed_header(&bytes).unwrap().header_len as usize;
+        let manifest_len = 6 + crypto::XCHACHA_NONCE_LEN + crypto::AEAD_TAG_LEN + 4;
+        bytes.drain(header_len..header_len + manifest_len);
+
+        let issues = diagnose_vault_v2(&bytes)
+            .into_iter()
+            .map(|i| format!("{}: {}", i.location, i.error))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            issues,
+            vec![
+                "item[1]: invalid header field: item.id",
+                "body: missing required header field: manifest",
+            ]
+        );
+    }
+}
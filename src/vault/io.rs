@@ -4,6 +4,7 @@
 //! - Restrictive file permissions (0600) for both vault and lock files.
 //! - Safe concurrent usage via advisory file locks.
 //! - Crash-safe writes via the write-temp, fsync, atomic-rename pattern.
+//! - Rotating backups of the previous vault file in a sibling `backups/` dir.
 //!
 //! This module is intentionally low-level and format-agnostic: it reads/writes
 //! raw bytes. Higher layers own parsing, encryption, and schema decisions.
@@ -11,6 +12,7 @@
 use std::fs::{self, File, OpenOptions};
 use std::io::{Read, Write};
 use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use thiserror::Error;
 
@@ -72,6 +74,98 @@ pub enum VaultIoError {
 
     #[error("vault path has no parent directory")]
     NoParentDir,
+
+    #[error("invalid {name} value {value:?} (expected {expected})")]
+    InvalidBackupSetting {
+        name: &'static str,
+        value: String,
+        expected: &'static str,
+    },
+
+    #[error("failed to read backup settings: {0}")]
+    Config(#[from] crate::config::ConfigError),
+}
+
+/// Overrides `[vault] backups` from the config file.
+pub const BACKUP_KEEP_ENV: &str = "PASSWORDER_VAULT_BACKUPS";
+/// Overrides `[vault] backup_max_age_days` from the config file.
+pub const BACKUP_MAX_AGE_DAYS_ENV: &str = "PASSWORDER_VAULT_BACKUP_MAX_AGE_DAYS";
+
+pub const DEFAULT_BACKUP_KEEP: usize = 10;
+
+const BACKUP_DIR: &str = "backups";
+
+/// How many backups survive pruning: the newest `keep`, minus any older than `max_age`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BackupRetention {
+    pub keep: usize,
+    pub max_age: Option<Duration>,
+}
+
+impl Default for BackupRetention {
+    fn default() -> Self {
+        Self {
+            keep: DEFAULT_BACKUP_KEEP,
+            max_age: None,
+        }
+    }
+}
+
+impl BackupRetention {
+    /// Retention from the `[vault]` config table; the environment variables,
+    /// when set, take precedence.
+    pub fn load() -> Result<Self, VaultIoError> {
+        let config = crate::config::vault_config()?;
+        let mut keep = config.backups;
+        let mut max_age_days = config.backup_max_age_days;
+        if let Some(value) = std::env::var_os(BACKUP_KEEP_ENV) {
+            keep = Some(parse_setting(
+                BACKUP_KEEP_ENV,
+                value,
+                "a number of backups",
+            )?);
+        }
+        if let Some(value) = std::env::var_os(BACKUP_MAX_AGE_DAYS_ENV) {
+            max_age_days = Some(parse_setting(
+                BACKUP_MAX_AGE_DAYS_ENV,
+                value,
+                "a number of days",
+            )?);
+        }
+
+        Ok(Self {
+            keep: keep.unwrap_or(DEFAULT_BACKUP_KEEP),
+            max_age: max_age_days
+                .map(|days| Duration::from_secs(days.saturating_mul(24 * 60 * 60))),
+        })
+    }
+}
+
+fn parse_setting<T: std::str::FromStr>(
+    name: &'static str,
+    value: std::ffi::OsString,
+    expected: &'static str,
+) -> Result<T, VaultIoError> {
+    let value = value.to_string_lossy();
+    value
+        .trim()
+        .parse()
+        .map_err(|_| VaultIoError::InvalidBackupSetting {
+            name,
+            value: value.to_string(),
+            expected,
+        })
+}
+
+/// A previous vault file kept in the backup directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VaultBackup {
+    /// File name inside the backup directory; doubles as the backup's id.
+    pub name: String,
+    pub path: PathBuf,
+    /// Unix seconds when the backup was taken.
+    pub created_at: u64,
+    pub size: u64,
 }
 
 pub fn lock_path_for_vault(vault_path: &Path) -> PathBuf {
@@ -97,13 +191,21 @@ pub fn read_vault_bytes_unlocked(vault_path: &Path) -> Result<Vec<u8>, VaultIoEr
     Ok(buf)
 }
 
+/// Replace the vault file, first keeping the current one as a backup.
 pub fn write_vault_bytes_atomic_unlocked(
     vault_path: &Path,
     bytes: &[u8],
 ) -> Result<(), VaultIoError> {
-    ensure_parent_dir(vault_path)?;
+    let retention = BackupRetention::load()?;
+    backup_vault_file_unlocked(vault_path, &retention)?;
+    write_sensitive_bytes_atomic(vault_path, bytes)
+}
 
-    let dir = vault_path.parent().ok_or(VaultIoError::NoParentDir)?;
+/// Write `bytes` to `path` as a 0600 file via temp file, fsync and rename.
+pub fn write_sensitive_bytes_atomic(path: &Path, bytes: &[u8]) -> Result<(), VaultIoError> {
+    ensure_parent_dir(path)?;
+
+    let dir = path.parent().ok_or(VaultIoError::NoParentDir)?;
     let mut tmp = tempfile::NamedTempFile::new_in(dir)?;
 
     tmp.as_file_mut().write_all(bytes)?;
@@ -113,13 +215,153 @@ pub fn write_vault_bytes_atomic_unlocked(
     tmp.as_file()
         .set_permissions(fs::Permissions::from_mode(0o600))?;
 
-    let _persisted = tmp.persist(vault_path).map_err(std::io::Error::from)?;
-    set_permissions_0600(vault_path)?;
+    let _persisted = tmp.persist(path).map_err(std::io::Error::from)?;
+    set_permissions_0600(path)?;
 
     fsync_dir(dir)?;
     Ok(())
 }
 
+pub fn backup_dir_for_vault(vault_path: &Path) -> Result<PathBuf, VaultIoError> {
+    let parent = vault_path.parent().ok_or(VaultIoError::NoParentDir)?;
+    Ok(parent.join(BACKUP_DIR))
+}
+
+/// Keep the current vault file (if any) as a timestamped backup, then prune
+/// old backups. Must be called with the vault lock held.
+///
+/// The backup is a hard link to the file about to be replaced, so taking it
+/// costs no copy; filesystems without hard links fall back to copying.
+pub fn backup_vault_file_unlocked(
+    vault_path: &Path,
+    retention: &BackupRetention,
+) -> Result<Option<PathBuf>, VaultIoError> {
+    if retention.keep == 0 || !vault_path.exists() {
+        return Ok(None);
+    }
+
+    let dir = backup_dir_for_vault(vault_path)?;
+    create_private_dir(&dir)?;
+
+    let (stem, ext) = backup_name_parts(vault_path);
+    let timestamp = now_unix_seconds();
+    let mut backup_path = dir.join(format!("{stem}.backup-{timestamp}.{ext}"));
+    let mut counter = 0u32;
+    while backup_path.exists() {
+        counter += 1;
+        backup_path = dir.join(format!("{stem}.backup-{timestamp}-{counter}.{ext}"));
+    }
+
+    if fs::hard_link(vault_path, &backup_path).is_err() {
+        fs::copy(vault_path, &backup_path)?;
+    }
+    set_permissions_0600(&backup_path)?;
+    fsync_dir(&dir)?;
+
+    prune_vault_backups(vault_path, retention)?;
+    Ok(Some(backup_path))
+}
+
+/// Backups of `vault_path`, newest first.
+pub fn list_vault_backups(vault_path: &Path) -> Result<Vec<VaultBackup>, VaultIoError> {
+    let dir = backup_dir_for_vault(vault_path)?;
+    let entries = match fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err.into()),
+    };
+
+    let (stem, ext) = backup_name_parts(vault_path);
+    let prefix = format!("{stem}.backup-");
+    let suffix = format!(".{ext}");
+
+    let mut backups = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let Some(stamp) = name
+            .strip_prefix(&prefix)
+            .and_then(|rest| rest.strip_suffix(&suffix))
+        else {
+            continue;
+        };
+        let (created_at, counter) = match stamp.split_once('-') {
+            Some((ts, n)) => (ts.parse::<u64>(), n.parse::<u32>()),
+            None => (stamp.parse::<u64>(), Ok(0)),
+        };
+        let (Ok(created_at), Ok(counter)) = (created_at, counter) else {
+            continue;
+        };
+
+        let metadata = entry.metadata()?;
+        if !metadata.is_file() {
+            continue;
+        }
+        backups.push((
+            counter,
+            VaultBackup {
+                name,
+                path: entry.path(),
+                created_at,
+                size: metadata.len(),
+            },
+        ));
+    }
+
+    backups.sort_by(|(ca, a), (cb, b)| (b.created_at, cb).cmp(&(a.created_at, ca)));
+    Ok(backups.into_iter().map(|(_, backup)| backup).collect())
+}
+
+/// Delete backups beyond the newest `retention.keep` or older than `retention.max_age`.
+pub fn prune_vault_backups(
+    vault_path: &Path,
+    retention: &BackupRetention,
+) -> Result<usize, VaultIoError> {
+    let cutoff = retention
+        .max_age
+        .map(|age| now_unix_seconds().saturating_sub(age.as_secs()));
+
+    let mut removed = 0;
+    for (index, backup) in list_vault_backups(vault_path)?.into_iter().enumerate() {
+        let expired = cutoff.is_some_and(|cutoff| backup.created_at < cutoff);
+        if index >= retention.keep || expired {
+            fs::remove_file(&backup.path)?;
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+fn backup_name_parts(vault_path: &Path) -> (String, String) {
+    let stem = vault_path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("vault")
+        .to_string();
+    let ext = vault_path
+        .extension()
+        .and_then(|s| s.to_str())
+        .unwrap_or("pwder")
+        .to_string();
+    (stem, ext)
+}
+
+fn create_private_dir(dir: &Path) -> Result<(), VaultIoError> {
+    fs::create_dir_all(dir)?;
+    #[cfg(unix)]
+    {
+        fs::set_permissions(dir, fs::Permissions::from_mode(0o700))?;
+    }
+    Ok(())
+}
+
+fn now_unix_seconds() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
 fn ensure_parent_dir(path: &Path) -> Result<(), VaultIoError> {
     let parent = path.parent().ok_or(VaultIoError::NoParentDir)?;
     fs::create_dir_all(parent)?;
@@ -229,4 +471,70 @@ mod tests {
         let final_bytes = read_vault_bytes(&vault_path).unwrap();
         assert!(final_bytes.iter().all(|b| *b == b'x') || final_bytes.iter().all(|b| *b == b'y'));
     }
+
+    #[test]
+    fn backups_keep_previous_contents_and_are_pruned() {
+        let dir = tempfile::tempdir().unwrap();
+        let vault_path = dir.path().join("vault.pwder");
+        let retention = BackupRetention {
+            keep: 2,
+            max_age: None,
+        };
+
+        assert!(
+            backup_vault_file_unlocked(&vault_path, &retention)
+                .unwrap()
+                .is_none()
+        );
+
+        for contents in [b"one", b"two", b"six"] {
+            write_sensitive_bytes_atomic(&vault_path, contents).unwrap();
+            backup_vault_file_unlocked(&vault_path, &retention).unwrap();
+        }
+
+        let backups = list_vault_backups(&vault_path).unwrap();
+        let contents = backups
+            .iter()
+            .map(|b| fs::read(&b.path).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(contents, vec![b"six".to_vec(), b"two".to_vec()]);
+        assert!(backups[0].name.starts_with("vault.backup-"));
+        assert!(backups[0].name.ends_with(".pwder"));
+
+        #[cfg(unix)]
+        {
+            let dir_mode = fs::metadata(backup_dir_for_vault(&vault_path).unwrap())
+                .unwrap()
+                .permissions()
+                .mode()
+                & 0o777;
+            assert_eq!(dir_mode, 0o700);
+        }
+    }
+
+    #[test]
+    fn backups_older_than_max_age_are_pruned() {
+        let dir = tempfile::tempdir().unwrap();
+        let vault_path = dir.path().join("vault.pwder");
+        let backups = backup_dir_for_vault(&vault_path).unwrap();
+        fs::create_dir_all(&backups).unwrap();
+        fs::write(backups.join("vault.backup-1000.pwder"), b"old").unwrap();
+        fs::write(backups.join("unrelated.txt"), b"keep me").unwrap();
+        write_sensitive_bytes_atomic(&vault_path, b"current").unwrap();
+
+        let retention = BackupRetention {
+            keep: 10,
+            max_age: Some(Duration::from_secs(24 * 60 * 60)),
+        };
+        backup_vault_file_unlocked(&vault_path, &retention).unwrap();
+
+        let names = list_vault_backups(&vault_path)
+            .unwrap()
+            .into_iter()
+            .map(|b| b.name)
+            .collect::<Vec<_>>();
+        assert_eq!(names.len(), 1);
+        assert_ne!(names[0], "vault.backup-1000.pwder");
+        assert!(backups.join("unrelated.txt").exists());
+    }
 }
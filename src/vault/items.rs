@@ -1,12 +1,53 @@
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+pub(super) type JsonObject = serde_json::Map<String, serde_json::Value>;
+
+/// Payload schema written by this version; older payloads are migrated on load.
+///
+/// - 1: initial schema.
+/// - 2: per-item revision `history`.
+/// - 3: `deleted_at` for items moved to the trash.
+/// - 4: payload `tombstones` for items removed for good.
+/// - 5: custom `fields` on items.
+/// - 6: file `attachments` on items.
+/// - 7: `expires_at`, `rotate_every` and `secret_changed_at` on items.
+pub const CURRENT_PAYLOAD_SCHEMA_VERSION: u32 = 7;
+
+/// How far ahead `vault list` and `vault get` flag items as due soon.
+pub const DUE_SOON_WINDOW: std::time::Duration = std::time::Duration::from_secs(14 * 24 * 60 * 60);
+
+/// Prior revisions kept per item; the oldest are dropped first.
+pub const MAX_ITEM_HISTORY: usize = 10;
+
+/// Item fields a v2 vault seals apart from the rest, so listing and searching
+/// never decrypt them. Revisions are included because they hold old secrets;
+/// custom fields are sealed whole, since any of them may be concealed.
+pub(super) const SECRET_ITEM_FIELDS: &[&str] = &["secret", "fields", "attachments", "history"];
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
 #[serde(rename_all = "kebab-case")]
 pub enum VaultItemType {
     Login,
     SecureNote,
     ApiToken,
+    /// RFC 6238 seed, stored as an `otpauth://totp/` URI.
+    Totp,
+    /// Ed25519 private key, stored as an unencrypted OpenSSH key block.
+    SshKey,
+}
+
+impl VaultItemType {
+    /// The kebab-case name used in JSON, exports and on the command line.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Login => "login",
+            Self::SecureNote => "secure-note",
+            Self::ApiToken => "api-token",
+            Self::Totp => "totp",
+            Self::SshKey => "ssh-key",
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -27,8 +68,339 @@ pub struct VaultItemV1 {
     pub urls: Vec<String>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub notes: Option<String>,
+    /// Named custom fields, in the order they were added.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<VaultItemField>,
+    /// Attached files, sorted by name.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attachments: Vec<VaultAttachment>,
+    /// When the item stops being valid (e.g. an account or certificate end
+    /// date).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<u64>,
+    /// Seconds a secret may stay in use before it is due for rotation.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rotate_every: Option<u64>,
+    /// When the secret last changed; unset on items from before schema 7,
+    /// which count from `created_at`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret_changed_at: Option<u64>,
     pub created_at: u64,
     pub updated_at: u64,
+    /// Earlier revisions, oldest first.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub history: Vec<VaultItemRevision>,
+    /// Set when the item was moved to the trash.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<u64>,
+}
+
+impl VaultItemV1 {
+    pub fn is_trashed(&self) -> bool {
+        self.deleted_at.is_some()
+    }
+
+    /// Revision number of the current field values.
+    pub fn revision(&self) -> u32 {
+        self.history.last().map_or(1, |r| r.rev + 1)
+    }
+
+    /// Snapshot the current field values as a history entry.
+    pub fn snapshot(&self) -> VaultItemRevision {
+        VaultItemRevision {
+            rev: self.revision(),
+            item_type: self.item_type,
+            name: self.name.clone(),
+            path: self.path.clone(),
+            tags: self.tags.clone(),
+            username: self.username.clone(),
+            secret: self.secret.clone(),
+            urls: self.urls.clone(),
+            notes: self.notes.clone(),
+            fields: self.fields.clone(),
+            attachments: self.attachments.iter().map(VaultAttachment::info).collect(),
+            expires_at: self.expires_at,
+            rotate_every: self.rotate_every,
+            updated_at: self.updated_at,
+        }
+    }
+
+    /// Record `previous` (taken before an edit) if the edit changed anything.
+    pub fn push_history(&mut self, previous: VaultItemRevision) {
+        let current = self.snapshot();
+        if previous.same_fields(&current) {
+            return;
+        }
+        self.history.push(previous);
+        if self.history.len() > MAX_ITEM_HISTORY {
+            let excess = self.history.len() - MAX_ITEM_HISTORY;
+            self.history.drain(..excess);
+        }
+    }
+
+    /// Split into the JSON objects sealed as a v2 item's metadata and
+    /// secrets records.
+    pub(super) fn to_records_v2(&self) -> Result<(JsonObject, JsonObject), serde_json::Error> {
+        let serde_json::Value::Object(mut meta) = serde_json::to_value(self)? else {
+            unreachable!("items serialize to JSON objects");
+        };
+        let secrets = SECRET_ITEM_FIELDS
+            .iter()
+            .filter_map(|field| meta.remove_entry(*field))
+            .collect();
+        Ok((meta, secrets))
+    }
+
+    /// Rebuild an item from its v2 records; without `secrets` the secret
+    /// fields are left empty.
+    pub(super) fn from_records_v2(
+        mut meta: JsonObject,
+        secrets: Option<JsonObject>,
+    ) -> Result<Self, serde_json::Error> {
+        match secrets {
+            Some(secrets) => meta.extend(secrets),
+            None => {
+                meta.insert("secret".into(), serde_json::Value::String(String::new()));
+            }
+        }
+        serde_json::from_value(serde_json::Value::Object(meta))
+    }
+
+    /// Clear the fields listed in [`SECRET_ITEM_FIELDS`].
+    pub(super) fn strip_secrets(&mut self) {
+        self.secret = String::new();
+        self.fields.clear();
+        self.attachments.clear();
+        self.history.clear();
+    }
+
+    /// Overwrite the current field values with those of `revision`.
+    ///
+    /// Revisions only record attachment metadata, so each attachment's
+    /// contents are looked up by hash on this item, then in `contents`;
+    /// attachments found in neither are left out.
+    pub fn apply_revision(&mut self, revision: &VaultItemRevision, contents: &[VaultAttachment]) {
+        self.item_type = revision.item_type;
+        self.name = revision.name.clone();
+        self.path = revision.path.clone();
+        self.tags = revision.tags.clone();
+        self.username = revision.username.clone();
+        self.secret = revision.secret.clone();
+        self.urls = revision.urls.clone();
+        self.notes = revision.notes.clone();
+        self.fields = revision.fields.clone();
+        self.attachments = revision
+            .attachments
+            .iter()
+            .filter_map(|info| {
+                let stored = self
+                    .attachments
+                    .iter()
+                    .chain(contents)
+                    .find(|a| a.sha256 == info.sha256)?;
+                Some(VaultAttachment {
+                    name: info.name.clone(),
+                    size: info.size,
+                    sha256: info.sha256.clone(),
+                    added_at: info.added_at,
+                    data: stored.data.clone(),
+                })
+            })
+            .collect();
+        self.expires_at = revision.expires_at;
+        self.rotate_every = revision.rotate_every;
+    }
+
+    pub fn field(&self, name: &str) -> Option<&VaultItemField> {
+        self.fields.iter().find(|f| f.name == name)
+    }
+
+    pub fn attachment(&self, name: &str) -> Option<&VaultAttachment> {
+        self.attachments.iter().find(|a| a.name == name)
+    }
+
+    /// Record that the secret was just replaced, if it differs from
+    /// `previous`.
+    pub(super) fn note_secret_change(&mut self, previous: &str, now: u64) {
+        if self.secret != previous {
+            self.secret_changed_at = Some(now);
+        }
+    }
+
+    /// The nearest of the item's expiry and next rotation, classified
+    /// against `now`; `None` when neither is set.
+    ///
+    /// Needs only metadata, so it works on items listed without their
+    /// secrets.
+    pub fn due(&self, now: u64, within: u64) -> Option<ItemDue> {
+        let rotate_at = self.rotate_every.map(|every| {
+            self.secret_changed_at
+                .unwrap_or(self.created_at)
+                .saturating_add(every)
+        });
+        let (reason, due_at) = match (self.expires_at, rotate_at) {
+            (Some(expires), Some(rotate)) if rotate < expires => (DueReason::Rotation, rotate),
+            (Some(expires), _) => (DueReason::Expiry, expires),
+            (None, Some(rotate)) => (DueReason::Rotation, rotate),
+            (None, None) => return None,
+        };
+        let state = if due_at <= now {
+            DueState::Overdue
+        } else if due_at <= now.saturating_add(within) {
+            DueState::DueSoon
+        } else {
+            DueState::Ok
+        };
+        Some(ItemDue {
+            reason,
+            due_at,
+            state,
+        })
+    }
+}
+
+/// See [`VaultItemV1::due`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ItemDue {
+    pub reason: DueReason,
+    pub due_at: u64,
+    pub state: DueState,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DueReason {
+    /// `expires_at` is reached.
+    Expiry,
+    /// The secret is older than `rotate_every`.
+    Rotation,
+}
+
+impl DueReason {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DueReason::Expiry => "expiry",
+            DueReason::Rotation => "rotation",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DueState {
+    Overdue,
+    DueSoon,
+    Ok,
+}
+
+impl DueState {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DueState::Overdue => "overdue",
+            DueState::DueSoon => "due-soon",
+            DueState::Ok => "ok",
+        }
+    }
+}
+
+/// A named value attached to an item; concealed values are redacted like
+/// the item secret.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VaultItemField {
+    pub name: String,
+    pub value: String,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub concealed: bool,
+}
+
+/// A file stored with an item. Its contents are sealed with the rest of the
+/// item's secrets.
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VaultAttachment {
+    pub name: String,
+    pub size: u64,
+    /// Hex SHA-256 of the contents, checked when the file is read back.
+    pub sha256: String,
+    pub added_at: u64,
+    /// Base64 contents.
+    pub data: String,
+}
+
+impl VaultAttachment {
+    pub fn info(&self) -> VaultAttachmentInfo {
+        VaultAttachmentInfo {
+            name: self.name.clone(),
+            size: self.size,
+            sha256: self.sha256.clone(),
+            added_at: self.added_at,
+        }
+    }
+}
+
+impl std::fmt::Debug for VaultAttachment {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("VaultAttachment")
+            .field("name", &self.name)
+            .field("size", &self.size)
+            .field("sha256", &self.sha256)
+            .field("added_at", &self.added_at)
+            .finish_non_exhaustive()
+    }
+}
+
+/// An attachment as recorded in a revision: its contents are stored once, on
+/// the live item, rather than copied into every history entry.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VaultAttachmentInfo {
+    pub name: String,
+    pub size: u64,
+    pub sha256: String,
+    pub added_at: u64,
+}
+
+/// Field values of an item as they were before an edit.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VaultItemRevision {
+    pub rev: u32,
+    #[serde(rename = "type")]
+    pub item_type: VaultItemType,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    pub secret: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub urls: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<VaultItemField>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attachments: Vec<VaultAttachmentInfo>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rotate_every: Option<u64>,
+    /// When these values were last written.
+    pub updated_at: u64,
+}
+
+impl VaultItemRevision {
+    pub(super) fn same_fields(&self, other: &VaultItemRevision) -> bool {
+        self.item_type == other.item_type
+            && self.name == other.name
+            && self.path == other.path
+            && self.tags == other.tags
+            && self.username == other.username
+            && self.secret == other.secret
+            && self.urls == other.urls
+            && self.notes == other.notes
+            && self.fields == other.fields
+            && self.attachments == other.attachments
+            && self.expires_at == other.expires_at
+            && self.rotate_every == other.rotate_every
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -36,17 +408,29 @@ pub struct VaultPayloadV1 {
     pub schema_version: u32,
     #[serde(default)]
     pub items: Vec<VaultItemV1>,
+    /// Ids of items purged from the trash, so a merge with an older copy
+    /// does not bring them back.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tombstones: Vec<VaultTombstone>,
 }
 
 impl Default for VaultPayloadV1 {
     fn default() -> Self {
         Self {
-            schema_version: 1,
+            schema_version: CURRENT_PAYLOAD_SCHEMA_VERSION,
             items: Vec::new(),
+            tombstones: Vec::new(),
         }
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VaultTombstone {
+    #[serde(with = "uuid_as_string")]
+    pub id: Uuid,
+    pub deleted_at: u64,
+}
+
 pub mod uuid_as_string {
     use serde::{Deserialize, Deserializer, Serializer, de};
     use uuid::Uuid;
@@ -0,0 +1,543 @@
+//! Three-way merge of two copies of a vault payload.
+//!
+//! Items are matched by id and merged field by field against a common
+//! ancestor: the item in `--base` when one is given, otherwise the newest
+//! revision both copies share in their history. A field changed on one side
+//! only takes that side's value; a field changed on both sides is a conflict
+//! handed to the caller's resolver. Purged items are recognised through the
+//! payload's tombstones, so they do not come back from the other copy.
+
+use crate::vault::items::{VaultItemRevision, VaultItemV1, VaultPayloadV1, VaultTombstone};
+use std::collections::BTreeMap;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum MergeSide {
+    /// Keep the value from the vault being merged into.
+    Ours,
+    /// Take the value from the other copy.
+    Theirs,
+}
+
+/// A field both copies changed in different ways.
+#[derive(Debug, Clone)]
+pub struct MergeConflict {
+    pub id: Uuid,
+    pub name: String,
+    pub field: &'static str,
+    /// Display forms of the two values; secrets are never shown.
+    pub ours: String,
+    pub theirs: String,
+    pub ours_updated_at: u64,
+    pub theirs_updated_at: u64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MergeReport {
+    /// Items that only existed in the other copy.
+    pub added: usize,
+    /// Items that took at least one field from the other copy.
+    pub updated: usize,
+    /// Items removed because the other copy deleted them.
+    pub removed: usize,
+    pub resolved: Vec<(MergeConflict, MergeSide)>,
+    pub unresolved: Vec<MergeConflict>,
+}
+
+impl MergeReport {
+    pub fn changed(&self) -> bool {
+        self.added + self.updated + self.removed > 0
+    }
+}
+
+/// Merge `theirs` into `ours`. `resolve` is asked about every conflict;
+/// conflicts it returns `None` for are left in [`MergeReport::unresolved`]
+/// and `ours` must then not be written.
+pub(super) fn merge_payloads(
+    ours: &mut VaultPayloadV1,
+    theirs: &VaultPayloadV1,
+    base: Option<&VaultPayloadV1>,
+    resolve: &mut dyn FnMut(&MergeConflict) -> Option<MergeSide>,
+    now: u64,
+) -> MergeReport {
+    let mut report = MergeReport::default();
+    let their_items = theirs
+        .items
+        .iter()
+        .map(|i| (i.id, i))
+        .collect::<BTreeMap<_, _>>();
+    let base_items = base
+        .map(|b| {
+            b.items
+                .iter()
+                .map(|i| (i.id, i))
+                .collect::<BTreeMap<_, _>>()
+        })
+        .unwrap_or_default();
+    let their_tombstones = tombstones_by_id(&theirs.tombstones);
+    let our_tombstones = tombstones_by_id(&ours.tombstones);
+
+    let mut merged = Vec::with_capacity(ours.items.len());
+    for mut item in std::mem::take(&mut ours.items) {
+        match their_items.get(&item.id) {
+            Some(their) => {
+                let base = base_items.get(&item.id).copied();
+                merge_item(&mut item, their, base, resolve, &mut report, now);
+                merged.push(item);
+            }
+            None => {
+                let deleted_there = match their_tombstones.get(&item.id) {
+                    Some(deleted_at) => item.updated_at <= *deleted_at,
+                    None => base_items
+                        .get(&item.id)
+                        .is_some_and(|b| same_item(&item, b)),
+                };
+                if deleted_there {
+                    report.removed += 1;
+                } else {
+                    merged.push(item);
+                }
+            }
+        }
+    }
+
+    let our_ids = merged.iter().map(|i| i.id).collect::<Vec<_>>();
+    for their in &theirs.items {
+        if our_ids.contains(&their.id) {
+            continue;
+        }
+        let deleted_here = match our_tombstones.get(&their.id) {
+            Some(deleted_at) => their.updated_at <= *deleted_at,
+            None => base_items
+                .get(&their.id)
+                .is_some_and(|b| same_item(their, b)),
+        };
+        if !deleted_here {
+            merged.push(their.clone());
+            report.added += 1;
+        }
+    }
+
+    let mut tombstones = our_tombstones;
+    for (id, deleted_at) in their_tombstones {
+        let entry = tombstones.entry(id).or_insert(deleted_at);
+        *entry = (*entry).max(deleted_at);
+    }
+    ours.tombstones = tombstones
+        .into_iter()
+        .filter(|(id, _)| !merged.iter().any(|i| i.id == *id))
+        .map(|(id, deleted_at)| VaultTombstone { id, deleted_at })
+        .collect();
+    ours.items = merged;
+    report
+}
+
+fn tombstones_by_id(tombstones: &[VaultTombstone]) -> BTreeMap<Uuid, u64> {
+    let mut out = BTreeMap::new();
+    for t in tombstones {
+        let entry = out.entry(t.id).or_insert(t.deleted_at);
+        *entry = (*entry).max(t.deleted_at);
+    }
+    out
+}
+
+fn same_item(a: &VaultItemV1, b: &VaultItemV1) -> bool {
+    a.snapshot().same_fields(&b.snapshot()) && a.deleted_at == b.deleted_at
+}
+
+/// The common ancestor of two copies of an item, from the base vault or
+/// failing that from their shared history.
+fn ancestor(
+    ours: &VaultItemV1,
+    theirs: &VaultItemV1,
+    base: Option<&VaultItemV1>,
+) -> Option<(VaultItemRevision, Option<u64>)> {
+    if let Some(base) = base {
+        return Some((base.snapshot(), base.deleted_at));
+    }
+
+    // History only records edits to live items, so the ancestor was live.
+    let our_current = ours.snapshot();
+    let their_current = theirs.snapshot();
+    if our_current.same_fields(&their_current) {
+        return Some((our_current, None));
+    }
+    if ours.history.iter().any(|r| r.same_fields(&their_current)) {
+        return Some((their_current, None));
+    }
+    if theirs.history.iter().any(|r| r.same_fields(&our_current)) {
+        return Some((our_current, None));
+    }
+    ours.history
+        .iter()
+        .rev()
+        .find(|r| theirs.history.iter().any(|t| t.same_fields(r)))
+        .map(|r| (r.clone(), None))
+}
+
+fn merge_item(
+    ours: &mut VaultItemV1,
+    theirs: &VaultItemV1,
+    base: Option<&VaultItemV1>,
+    resolve: &mut dyn FnMut(&MergeConflict) -> Option<MergeSide>,
+    report: &mut MergeReport,
+    now: u64,
+) {
+    if same_item(ours, theirs) {
+        return;
+    }
+
+    let ancestor = ancestor(ours, theirs, base);
+    let (base_fields, base_deleted_at) = match &ancestor {
+        Some((fields, deleted_at)) => (Some(fields), Some(*deleted_at)),
+        None => (None, None),
+    };
+    let previous = ours.snapshot();
+    let mut merged = previous.clone();
+    let their_fields = theirs.snapshot();
+    let mut fields = FieldMerge {
+        id: ours.id,
+        name: ours.name.clone(),
+        ours_updated_at: ours.updated_at,
+        theirs_updated_at: theirs.updated_at,
+        resolve,
+        report,
+    };
+
+    fields.merge(
+        "type",
+        &mut merged.item_type,
+        &their_fields.item_type,
+        base_fields.map(|b| &b.item_type),
+        |t| t.as_str().to_string(),
+    );
+    fields.merge(
+        "name",
+        &mut merged.name,
+        &their_fields.name,
+        base_fields.map(|b| &b.name),
+        Clone::clone,
+    );
+    fields.merge(
+        "path",
+        &mut merged.path,
+        &their_fields.path,
+        base_fields.map(|b| &b.path),
+        |p| p.clone().unwrap_or_default(),
+    );
+    fields.merge(
+        "tags",
+        &mut merged.tags,
+        &their_fields.tags,
+        base_fields.map(|b| &b.tags),
+        |t| t.join(","),
+    );
+    fields.merge(
+        "username",
+        &mut merged.username,
+        &their_fields.username,
+        base_fields.map(|b| &b.username),
+        |u| u.clone().unwrap_or_default(),
+    );
+    fields.merge(
+        "secret",
+        &mut merged.secret,
+        &their_fields.secret,
+        base_fields.map(|b| &b.secret),
+        |_| "(hidden)".to_string(),
+    );
+    fields.merge(
+        "urls",
+        &mut merged.urls,
+        &their_fields.urls,
+        base_fields.map(|b| &b.urls),
+        |u| u.join(","),
+    );
+    fields.merge(
+        "notes",
+        &mut merged.notes,
+        &their_fields.notes,
+        base_fields.map(|b| &b.notes),
+        |n| n.clone().unwrap_or_default(),
+    );
+    fields.merge(
+        "fields",
+        &mut merged.fields,
+        &their_fields.fields,
+        base_fields.map(|b| &b.fields),
+        |f| {
+            f.iter()
+                .map(|f| match f.concealed {
+                    true => format!("{}=(hidden)", f.name),
+                    false => format!("{}={}", f.name, f.value),
+                })
+                .collect::<Vec<_>>()
+                .join(",")
+        },
+    );
+    fields.merge(
+        "attachments",
+        &mut merged.attachments,
+        &their_fields.attachments,
+        base_fields.map(|b| &b.attachments),
+        |a| {
+            a.iter()
+                .map(|a| {
+                    format!(
+                        "{} (sha256 {})",
+                        a.name,
+                        &a.sha256[..12.min(a.sha256.len())]
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join(",")
+        },
+    );
+    fields.merge(
+        "expires_at",
+        &mut merged.expires_at,
+        &their_fields.expires_at,
+        base_fields.map(|b| &b.expires_at),
+        |e| e.map(|at| at.to_string()).unwrap_or_default(),
+    );
+    fields.merge(
+        "rotate_every",
+        &mut merged.rotate_every,
+        &their_fields.rotate_every,
+        base_fields.map(|b| &b.rotate_every),
+        |r| r.map(|secs| format!("{secs}s")).unwrap_or_default(),
+    );
+
+    let mut deleted_at = ours.deleted_at;
+    if let (Some(_), Some(_)) = (ours.deleted_at, theirs.deleted_at) {
+        // Trashed on both sides; when does not matter.
+    } else {
+        fields.merge(
+            "deleted_at",
+            &mut deleted_at,
+            &theirs.deleted_at,
+            base_deleted_at.as_ref(),
+            |d| match d {
+                Some(at) => format!("trashed at {at}"),
+                None => "live".to_string(),
+            },
+        );
+    }
+
+    if merged.same_fields(&their_fields) && deleted_at == theirs.deleted_at {
+        *ours = theirs.clone();
+        report.updated += 1;
+    } else if !merged.same_fields(&previous) || deleted_at != ours.deleted_at {
+        ours.apply_revision(&merged, &theirs.attachments);
+        if ours.secret != previous.secret {
+            ours.secret_changed_at = theirs.secret_changed_at;
+        }
+        ours.push_history(previous);
+        ours.deleted_at = deleted_at;
+        ours.updated_at = now;
+        report.updated += 1;
+    }
+}
+
+struct FieldMerge<'a> {
+    id: Uuid,
+    name: String,
+    ours_updated_at: u64,
+    theirs_updated_at: u64,
+    resolve: &'a mut dyn FnMut(&MergeConflict) -> Option<MergeSide>,
+    report: &'a mut MergeReport,
+}
+
+impl FieldMerge<'_> {
+    fn merge<T: Clone + PartialEq>(
+        &mut self,
+        field: &'static str,
+        ours: &mut T,
+        theirs: &T,
+        base: Option<&T>,
+        show: impl Fn(&T) -> String,
+    ) {
+        if ours == theirs || base == Some(theirs) {
+            return;
+        }
+        if base == Some(ours) {
+            *ours = theirs.clone();
+            return;
+        }
+
+        let conflict = MergeConflict {
+            id: self.id,
+            name: self.name.clone(),
+            field,
+            ours: show(ours),
+            theirs: show(theirs),
+            ours_updated_at: self.ours_updated_at,
+            theirs_updated_at: self.theirs_updated_at,
+        };
+        match (self.resolve)(&conflict) {
+            Some(side) => {
+                if side == MergeSide::Theirs {
+                    *ours = theirs.clone();
+                }
+                self.report.resolved.push((conflict, side));
+            }
+            None => self.report.unresolved.push(conflict),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vault::items::VaultItemType;
+
+    fn item(name: &str, secret: &str) -> VaultItemV1 {
+        VaultItemV1 {
+            id: Uuid::new_v4(),
+            item_type: VaultItemType::Login,
+            name: name.into(),
+            path: None,
+            tags: Vec::new(),
+            username: None,
+            secret: secret.into(),
+            urls: Vec::new(),
+            notes: None,
+            fields: Vec::new(),
+            attachments: Vec::new(),
+            expires_at: None,
+            rotate_every: None,
+            secret_changed_at: None,
+            created_at: 1,
+            updated_at: 1,
+            history: Vec::new(),
+            deleted_at: None,
+        }
+    }
+
+    fn edit(item: &VaultItemV1, at: u64, change: impl FnOnce(&mut VaultItemV1)) -> VaultItemV1 {
+        let mut edited = item.clone();
+        let previous = edited.snapshot();
+        change(&mut edited);
+        edited.updated_at = at;
+        edited.push_history(previous);
+        edited
+    }
+
+    fn payload(items: Vec<VaultItemV1>) -> VaultPayloadV1 {
+        VaultPayloadV1 {
+            items,
+            ..Default::default()
+        }
+    }
+
+    fn never(_: &MergeConflict) -> Option<MergeSide> {
+        None
+    }
+
+    #[test]
+    fn edits_to_different_fields_merge_through_shared_history() {
+        let original = item("github", "old");
+        let ours = edit(&original, 10, |i| i.username = Some("octo".into()));
+        let theirs = edit(&original, 20, |i| i.secret = "new".into());
+
+        let mut merged = payload(vec![ours]);
+        let report = merge_payloads(&mut merged, &payload(vec![theirs]), None, &mut never, 30);
+
+        assert!(report.unresolved.is_empty());
+        assert_eq!(report.updated, 1);
+        let item = &merged.items[0];
+        assert_eq!(item.username.as_deref(), Some("octo"));
+        assert_eq!(item.secret, "new");
+        assert_eq!(item.updated_at, 30);
+    }
+
+    #[test]
+    fn newer_copy_of_an_item_is_taken_whole() {
+        let original = item("github", "old");
+        let theirs = edit(&original, 20, |i| i.secret = "new".into());
+
+        let mut merged = payload(vec![original]);
+        let report = merge_payloads(
+            &mut merged,
+            &payload(vec![theirs.clone()]),
+            None,
+            &mut never,
+            30,
+        );
+
+        assert_eq!(report.updated, 1);
+        assert_eq!(merged.items[0], theirs);
+    }
+
+    #[test]
+    fn same_field_changed_on_both_sides_is_a_conflict() {
+        let original = item("github", "old");
+        let ours = edit(&original, 10, |i| i.secret = "mine".into());
+        let theirs = edit(&original, 20, |i| i.secret = "yours".into());
+
+        let mut unresolved = payload(vec![ours.clone()]);
+        let report = merge_payloads(
+            &mut unresolved,
+            &payload(vec![theirs.clone()]),
+            None,
+            &mut never,
+            30,
+        );
+        assert_eq!(report.unresolved.len(), 1);
+        assert_eq!(report.unresolved[0].field, "secret");
+        assert_eq!(report.unresolved[0].ours, "(hidden)");
+
+        let mut resolved = payload(vec![ours]);
+        let report = merge_payloads(
+            &mut resolved,
+            &payload(vec![theirs]),
+            None,
+            &mut |_| Some(MergeSide::Theirs),
+            30,
+        );
+        assert_eq!(report.resolved.len(), 1);
+        assert_eq!(resolved.items[0].secret, "yours");
+    }
+
+    #[test]
+    fn tombstones_keep_purged_items_from_coming_back() {
+        let kept = item("kept", "s");
+        let purged = item("purged", "s");
+        let mut ours = payload(vec![kept.clone()]);
+        ours.tombstones.push(VaultTombstone {
+            id: purged.id,
+            deleted_at: 5,
+        });
+        let theirs = payload(vec![kept, purged.clone()]);
+
+        let report = merge_payloads(&mut ours, &theirs, None, &mut never, 30);
+        assert_eq!(report.added, 0);
+        assert_eq!(ours.items.len(), 1);
+        assert_eq!(ours.tombstones.len(), 1);
+
+        // An edit made after the purge wins over it.
+        let edited = edit(&purged, 9, |i| i.secret = "new".into());
+        let report = merge_payloads(&mut ours, &payload(vec![edited]), None, &mut never, 30);
+        assert_eq!(report.added, 1);
+        assert!(ours.tombstones.is_empty());
+    }
+
+    #[test]
+    fn base_shows_which_side_deleted_an_item() {
+        let a = item("a", "s");
+        let b = item("b", "s");
+        let base = payload(vec![a.clone(), b.clone()]);
+        let mut ours = payload(vec![a.clone()]);
+        let theirs = payload(vec![b.clone()]);
+
+        let report = merge_payloads(&mut ours, &theirs, Some(&base), &mut never, 30);
+        assert_eq!(report.removed, 1);
+        assert_eq!(report.added, 0);
+        assert!(ours.items.is_empty());
+
+        // Without a base both items look newly added on one side.
+        let mut ours = payload(vec![a]);
+        merge_payloads(&mut ours, &payload(vec![b]), None, &mut never, 30);
+        assert_eq!(ours.items.len(), 2);
+    }
+}
@@ -1,15 +1,51 @@
 pub mod crypto;
 pub mod io;
 
+mod bundle;
+mod foreign;
 mod format_v1;
+mod format_v2;
 mod items;
+mod merge;
 mod ops;
 mod prompt;
+mod query;
+mod ssh_key;
+mod sync;
+mod totp;
+mod verify;
 
-pub use items::{VaultItemType, VaultItemV1, VaultPayloadV1};
+pub use bundle::BundleError;
+pub use foreign::{ForeignFormat, ForeignImport, ForeignImportError, SkippedRecord, UnmappedField};
+pub use items::{
+    CURRENT_PAYLOAD_SCHEMA_VERSION, DUE_SOON_WINDOW, DueReason, DueState, ItemDue,
+    MAX_ITEM_HISTORY, VaultAttachment, VaultAttachmentInfo, VaultItemField, VaultItemRevision,
+    VaultItemType, VaultItemV1, VaultPayloadV1, VaultTombstone,
+};
+pub use merge::{MergeConflict, MergeReport, MergeSide};
 pub use ops::{
-    AddItemInput, EditItemInput, VaultError, vault_add_item_v1, vault_edit_item_v1,
-    vault_get_item_v1, vault_init_v1, vault_list_items_v1, vault_path, vault_remove_item_v1,
-    vault_search_items_v1, vault_status_v1,
+    AddItemInput, ConflictPolicy, Credentials, EditItemInput, ImportSummary, ItemFilter,
+    KdfParamsOverride, KeySlotInfo, KeyfileChange, RotateKeysInput, SyncInitReport, SyncOutcome,
+    SyncReport, UpgradeReport, VaultError, VaultKey, VaultStatusInfo, keyfile_path, read_bundle_v1,
+    read_foreign_export_v1, read_keyfile, vault_add_item_v1, vault_add_key_slot_v1,
+    vault_attach_v1, vault_detach_v1, vault_edit_item_v1, vault_empty_trash_v1,
+    vault_export_bundle_v1, vault_get_item_v1, vault_import_items_v1, vault_init_v1,
+    vault_list_backups_v1, vault_list_item_metadata_v1, vault_list_items_v1,
+    vault_list_key_slots_v1, vault_list_trash_v1, vault_merge_v1, vault_path,
+    vault_read_attachment_v1, vault_recover_v1, vault_remove_item_v1, vault_remove_key_slot_v1,
+    vault_restore_backup_v1, vault_restore_item_v1, vault_restore_trashed_item_v1,
+    vault_rotate_item_v1, vault_rotate_keys_v1, vault_search_items_v1, vault_status_v1,
+    vault_sync_enabled, vault_sync_init_v1, vault_sync_v1, vault_totp_v1, vault_unlock_dek_v1,
+    vault_upgrade_v1, vault_verify_session_key_v1,
+};
+pub use prompt::{
+    PromptError, prompt_bundle_passphrase, prompt_current_master_password, prompt_line,
+    prompt_master_password, prompt_new_bundle_passphrase, prompt_new_master_password,
+    prompt_new_slot_password, prompt_recovery_key, prompt_replacement_master_password,
+    prompt_secret,
 };
-pub use prompt::{PromptError, prompt_master_password, prompt_new_master_password, prompt_secret};
+pub use query::{ItemQuery, QueryError};
+pub use ssh_key::{SshKey, SshKeyError};
+pub use sync::SyncError;
+pub use totp::{Totp, TotpAlgorithm, TotpCode, TotpError};
+pub use verify::{UnlockOutcome, VerifyIssue, VerifyReport, VerifySeverity, vault_verify_v1};
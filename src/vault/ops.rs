@@ -1,15 +1,28 @@
-use crate::vault::{crypto, format_v1, io, items, prompt};
-use secrecy::SecretString;
+use crate::vault::{
+    bundle, crypto, foreign, format_v1, format_v2, io, items, merge, prompt, query, ssh_key, sync,
+    totp,
+};
+use base64::Engine;
+use secrecy::{ExposeSecret, SecretString};
+use sha2::{Digest, Sha256};
 use std::cmp::Ordering;
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use thiserror::Error;
 use uuid::Uuid;
+use zeroize::Zeroizing;
 
 const VAULT_ENV: &str = "PASSWORDER_VAULT";
 const APP_DIR: &str = "passworder";
 const DEFAULT_VAULT_FILE: &str = "vault.pwder";
+const KEYFILE_ENV: &str = "PASSWORDER_KEYFILE";
 const TEST_KDF_ENV: &str = "PASSWORDER_VAULT_TEST_KDF";
+const ATTACHMENT_LIMIT_ENV: &str = "PASSWORDER_VAULT_ATTACHMENT_LIMIT";
+/// Largest file `vault attach` accepts, in bytes.
+const DEFAULT_ATTACHMENT_LIMIT: u64 = 1024 * 1024;
+const DEFAULT_SLOT_LABEL: &str = "master";
+const RECOVERY_SLOT_LABEL: &str = "recovery";
 
 #[derive(Debug, Error)]
 pub enum VaultError {
@@ -25,12 +38,102 @@ pub enum VaultError {
     #[error("unlock failed (wrong password or vault corrupted)")]
     AuthFailed,
 
+    #[error("this vault requires a keyfile (pass --keyfile or set PASSWORDER_KEYFILE)")]
+    KeyfileRequired,
+
+    #[error("failed to read keyfile {path}: {source}")]
+    KeyfileRead {
+        path: String,
+        source: std::io::Error,
+    },
+
+    #[error("keyfile is empty: {0}")]
+    EmptyKeyfile(String),
+
+    #[error("cached vault key does not match this vault")]
+    StaleSessionKey,
+
+    #[error("invalid KDF parameters: {0}")]
+    InvalidKdfParams(String),
+
+    #[error("invalid recovery key (expected groups like XXXX-XXXX-...)")]
+    InvalidRecoveryKey,
+
     #[error("unsupported vault payload schema version {0}")]
     UnsupportedPayloadSchema(u32),
 
     #[error("item not found: {0}")]
     ItemNotFound(String),
 
+    #[error("'{selector}' matches more than one item:\n{}", .candidates.join("\n"))]
+    AmbiguousItem {
+        selector: String,
+        /// `id<TAB>path/name` of each match.
+        candidates: Vec<String>,
+    },
+
+    #[error("item {id} has no revision {rev}")]
+    RevisionNotFound { id: Uuid, rev: u32 },
+
+    #[error("backup not found: {0}")]
+    BackupNotFound(String),
+
+    #[error("key slot not found: {0}")]
+    KeySlotNotFound(u8),
+
+    #[error("no free key slot (a vault holds at most {0})")]
+    NoFreeKeySlot(usize),
+
+    #[error("cannot remove the last key slot")]
+    LastKeySlot,
+
+    #[error("rotating the data key would invalidate {0} other key slot(s)")]
+    OtherKeySlots(usize),
+
+    #[error("{0} merge conflict(s) left unresolved")]
+    MergeConflicts(usize),
+
+    #[error("item has no field named {0}")]
+    FieldNotFound(String),
+
+    #[error("item has no attachment named {0}")]
+    AttachmentNotFound(String),
+
+    #[error("invalid attachment name {0:?}")]
+    InvalidAttachmentName(String),
+
+    #[error(
+        "attachment is {size} bytes, over the {limit}-byte limit (set {ATTACHMENT_LIMIT_ENV} to raise it)"
+    )]
+    AttachmentTooLarge { size: u64, limit: u64 },
+
+    #[error("invalid {ATTACHMENT_LIMIT_ENV} value {0:?} (expected a number of bytes)")]
+    InvalidAttachmentLimit(String),
+
+    #[error("attachment {0} does not match its recorded hash")]
+    AttachmentCorrupt(String),
+
+    #[error("item {0} is not a totp item")]
+    NotTotpItem(Uuid),
+
+    #[error(transparent)]
+    Totp(#[from] totp::TotpError),
+
+    #[error(transparent)]
+    SshKey(#[from] ssh_key::SshKeyError),
+
+    #[error(transparent)]
+    Query(#[from] query::QueryError),
+
+    #[error(transparent)]
+    Bundle(#[from] bundle::BundleError),
+
+    #[error(transparent)]
+    ForeignImport(#[from] foreign::ForeignImportError),
+
+    #[error(transparent)]
+    Sync(#[from] sync::SyncError),
+
     #[error(transparent)]
     Io(#[from] io::VaultIoError),
 
@@ -77,66 +180,184 @@ pub fn vault_path(override_path: Option<&Path>) -> Result<PathBuf, VaultError> {
     Ok(dir)
 }
 
-pub fn vault_status_v1(vault_path: &Path) -> Result<(VaultStatus, Option<u16>), VaultError> {
+/// Secrets presented to unlock (or initialize) a vault.
+pub struct Credentials {
+    pub master_password: SecretString,
+    /// SHA-256 digest of the keyfile, when one was supplied.
+    pub keyfile: Option<crypto::SecretBytes>,
+}
+
+/// Resolve the keyfile path from an explicit flag or `PASSWORDER_KEYFILE`.
+pub fn keyfile_path(override_path: Option<&Path>) -> Option<PathBuf> {
+    if let Some(path) = override_path {
+        return Some(path.to_path_buf());
+    }
+
+    std::env::var_os(KEYFILE_ENV)
+        .filter(|p| !p.is_empty())
+        .map(PathBuf::from)
+}
+
+/// Read a keyfile and return its digest; the contents are never kept.
+pub fn read_keyfile(path: &Path) -> Result<crypto::SecretBytes, VaultError> {
+    let contents =
+        zeroize::Zeroizing::new(
+            std::fs::read(path).map_err(|source| VaultError::KeyfileRead {
+                path: path.display().to_string(),
+                source,
+            })?,
+        );
+    if contents.is_empty() {
+        return Err(VaultError::EmptyKeyfile(path.display().to_string()));
+    }
+    Ok(crypto::keyfile_digest(&contents))
+}
+
+/// How an item operation unlocks the vault.
+pub enum VaultKey {
+    /// Derive a KEK from a password (and keyfile) and unwrap the DEK from a key slot.
+    Credentials(Credentials),
+    /// A DEK handed out by the unlock agent; no KDF work is needed.
+    Session(crypto::SecretBytes),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VaultStatusInfo {
+    pub status: VaultStatus,
+    pub version: Option<u16>,
+    /// Every key slot needs a keyfile in addition to its password.
+    pub keyfile_required: bool,
+    /// Key slots from the unencrypted header, each saying whether it needs
+    /// a keyfile.
+    pub key_slots: Vec<KeySlotInfo>,
+}
+
+pub fn vault_status_v1(vault_path: &Path) -> Result<VaultStatusInfo, VaultError> {
     if !vault_path.exists() {
-        return Ok((VaultStatus::Missing, None));
+        return Ok(VaultStatusInfo {
+            status: VaultStatus::Missing,
+            version: None,
+            keyfile_required: false,
+            key_slots: Vec::new(),
+        });
     }
 
     let bytes = io::read_vault_bytes(vault_path)?;
-    let fixed = format_v1::parse_fixed_header(&bytes)?;
-    Ok((VaultStatus::Locked, Some(fixed.version)))
+    let parsed = format_v2::parse_vault(&bytes)?;
+    Ok(VaultStatusInfo {
+        status: VaultStatus::Locked,
+        version: Some(parsed.version()),
+        keyfile_required: parsed.key_slots().iter().all(|s| s.keyfile_required),
+        key_slots: parsed.key_slots().iter().map(KeySlotInfo::from).collect(),
+    })
 }
 
-pub fn vault_init_v1(vault_path: &Path, master_password: &SecretString) -> Result<(), VaultError> {
+/// Create a new vault; with `with_recovery_key` the DEK is also wrapped under a
+/// freshly generated recovery key, which is returned to be shown exactly once.
+/// Both slots use the default Argon2id params with `kdf_params` applied.
+pub fn vault_init_v1(
+    vault_path: &Path,
+    credentials: &Credentials,
+    with_recovery_key: bool,
+    kdf_params: KdfParamsOverride,
+) -> Result<Option<SecretString>, VaultError> {
     if vault_path.exists() {
         return Err(VaultError::AlreadyExists(vault_path.display().to_string()));
     }
+    let kdf_params = kdf_params.apply(default_kdf_params())?;
 
-    let kdf_params = if std::env::var_os(TEST_KDF_ENV).is_some() {
-        crypto::KdfParams::for_tests()
+    let dek = crypto::generate_dek();
+    let mut key_slots = vec![new_key_slot(
+        0,
+        DEFAULT_SLOT_LABEL.to_string(),
+        credentials,
+        kdf_params,
+        &dek,
+    )?];
+
+    let recovery_key = if with_recovery_key {
+        let recovery_key = crypto::generate_recovery_key();
+        let recovery_credentials = Credentials {
+            master_password: recovery_key.clone(),
+            keyfile: None,
+        };
+        key_slots.push(new_key_slot(
+            1,
+            RECOVERY_SLOT_LABEL.to_string(),
+            &recovery_credentials,
+            kdf_params,
+            &dek,
+        )?);
+        Some(recovery_key)
     } else {
-        crypto::KdfParams::recommended_macos()
+        None
     };
 
-    let kdf_salt = crypto::random_bytes::<16>();
-    let wrap_nonce = crypto::random_bytes::<{ crypto::XCHACHA_NONCE_LEN }>();
-    let payload_nonce = crypto::random_bytes::<{ crypto::XCHACHA_NONCE_LEN }>();
-
-    let kdf_out = crypto::derive_kdf_out_from_password(master_password, &kdf_salt, kdf_params)?;
-    let kek = crypto::derive_kek(&kdf_out)?;
-    let dek = crypto::generate_dek();
+    let vault_bytes = seal_vault_v2(&key_slots, &dek, &items::VaultPayloadV1::default(), None)?;
+    io::write_vault_bytes_atomic(vault_path, &vault_bytes)?;
+    Ok(recovery_key)
+}
 
-    // v1: ciphertext length is plaintext length + 16-byte Poly1305 tag.
-    let wrapped_dek_len = crypto::DEK_LEN + 16;
-    let placeholder_header = format_v1::VaultHeaderV1 {
-        kdf_params,
-        kdf_salt,
-        wrap_nonce,
-        wrapped_dek: vec![0u8; wrapped_dek_len],
-        payload_nonce,
+/// Unlock with a recovery key and set a new master password.
+///
+/// The new password replaces the slot labelled `master` (or takes the lowest
+/// free slot if it was removed) and does not require a keyfile, since a lost
+/// keyfile is one of the reasons to recover. It keeps the master slot's
+/// Argon2id params, or takes the recovery slot's when recreated. The recovery
+/// slot itself is kept. Returns the id of the master slot.
+pub fn vault_recover_v1(
+    vault_path: &Path,
+    recovery_key: &str,
+    new_master_password: SecretString,
+) -> Result<u8, VaultError> {
+    let recovery_key =
+        crypto::normalize_recovery_key(recovery_key).ok_or(VaultError::InvalidRecoveryKey)?;
+    let recovery_credentials = Credentials {
+        master_password: recovery_key,
+        keyfile: None,
     };
-    let aad = format_v1::encode_header_v1(&placeholder_header);
-    let wrapped_dek = crypto::wrap_dek(&kek, &wrap_nonce, &aad, &dek)?;
 
-    let header = format_v1::VaultHeaderV1 {
-        wrapped_dek,
-        ..placeholder_header
-    };
-    let header_bytes = format_v1::encode_header_v1(&header);
+    let _lock = io::VaultLock::acquire(
+        &io::lock_path_for_vault(vault_path),
+        io::LockMode::Exclusive,
+    )?;
 
-    let payload_plaintext = serde_json::to_vec(&serde_json::json!({
-        "schema_version": 1,
-        "items": [],
-    }))?;
-    let payload_ciphertext =
-        crypto::encrypt_payload(&dek, &payload_nonce, &aad, &payload_plaintext)?;
+    let bytes = read_existing_vault_bytes_unlocked(vault_path)?;
+    let mut unlocked = unlock_vault_v1(&bytes, &recovery_credentials)?;
+
+    let existing = unlocked
+        .key_slots
+        .iter()
+        .find(|s| s.label == DEFAULT_SLOT_LABEL && Some(s.id) != unlocked.slot_id)
+        .map(|s| (s.id, s.kdf_params));
+    let (id, kdf_params) = match existing {
+        Some(found) => found,
+        None => {
+            let id = (0..format_v1::MAX_KEY_SLOTS as u8)
+                .find(|id| unlocked.key_slots.iter().all(|s| s.id != *id))
+                .ok_or(VaultError::NoFreeKeySlot(format_v1::MAX_KEY_SLOTS))?;
+            (id, unlocked.unlocking_slot().kdf_params)
+        }
+    };
 
-    let mut vault_bytes = Vec::with_capacity(header_bytes.len() + payload_ciphertext.len());
-    vault_bytes.extend_from_slice(&header_bytes);
-    vault_bytes.extend_from_slice(&payload_ciphertext);
+    let master_credentials = Credentials {
+        master_password: new_master_password,
+        keyfile: None,
+    };
+    let slot = new_key_slot(
+        id,
+        DEFAULT_SLOT_LABEL.to_string(),
+        &master_credentials,
+        kdf_params,
+        &unlocked.dek,
+    )?;
+    unlocked.key_slots.retain(|s| s.id != id);
+    unlocked.key_slots.push(slot);
+    unlocked.key_slots.sort_by_key(|s| s.id);
 
-    io::write_vault_bytes_atomic(vault_path, &vault_bytes)?;
-    Ok(())
+    let new_bytes = unlocked.seal()?;
+    save_vault_bytes_unlocked(vault_path, &new_bytes)?;
+    Ok(id)
 }
 
 pub struct AddItemInput {
@@ -148,10 +369,16 @@ pub struct AddItemInput {
     pub secret: String,
     pub urls: Vec<String>,
     pub notes: Option<String>,
+    pub fields: Vec<items::VaultItemField>,
+    pub expires_at: Option<u64>,
+    /// Seconds.
+    pub rotate_every: Option<u64>,
 }
 
+#[derive(Default)]
 pub struct EditItemInput {
-    pub id: Uuid,
+    /// The item to edit: an id, id prefix, name or `path/name`.
+    pub item: String,
     pub item_type: Option<items::VaultItemType>,
     pub name: Option<String>,
     pub path: Option<String>,
@@ -165,11 +392,21 @@ pub struct EditItemInput {
     pub clear_urls: bool,
     pub notes: Option<String>,
     pub clear_notes: bool,
+    /// Added, or replacing the value of a field with the same name (which
+    /// keeps its `concealed` flag unless this one sets it).
+    pub set_fields: Vec<items::VaultItemField>,
+    /// Names of fields to mark concealed.
+    pub conceal_fields: Vec<String>,
+    pub remove_fields: Vec<String>,
+    pub expires_at: Option<u64>,
+    pub clear_expires_at: bool,
+    pub rotate_every: Option<u64>,
+    pub clear_rotate_every: bool,
 }
 
 pub fn vault_add_item_v1(
     vault_path: &Path,
-    master_password: &SecretString,
+    key: &VaultKey,
     input: AddItemInput,
 ) -> Result<Uuid, VaultError> {
     let _lock = io::VaultLock::acquire(
@@ -178,7 +415,7 @@ pub fn vault_add_item_v1(
     )?;
 
     let bytes = read_existing_vault_bytes_unlocked(vault_path)?;
-    let (mut payload, header) = load_payload_v1(&bytes, master_password)?;
+    let mut unlocked = unlock_with_key_v1(&bytes, key)?;
 
     let now = now_unix_seconds();
     let id = Uuid::new_v4();
@@ -189,89 +426,312 @@ pub fn vault_add_item_v1(
         path: input.path,
         tags: normalize_tags(input.tags),
         username: input.username,
-        secret: input.secret,
+        secret: normalize_secret(input.item_type, input.secret)?,
         urls: normalize_urls(input.urls),
         notes: input.notes,
+        fields: normalize_fields(input.fields),
+        attachments: Vec::new(),
+        expires_at: input.expires_at,
+        rotate_every: input.rotate_every,
+        secret_changed_at: Some(now),
         created_at: now,
         updated_at: now,
+        history: Vec::new(),
+        deleted_at: None,
     };
 
-    payload.items.push(item);
-    payload.items.sort_by(item_sort_cmp);
+    unlocked.payload.items.push(item);
+    unlocked.payload.items.sort_by(item_sort_cmp);
 
-    let new_bytes = seal_vault_v1(
-        header.header.kdf_params,
-        header.header.kdf_salt,
-        master_password,
-        &payload,
-    )?;
-    io::write_vault_bytes_atomic_unlocked(vault_path, &new_bytes)?;
+    let new_bytes = unlocked.seal()?;
+    save_vault_bytes_unlocked(vault_path, &new_bytes)?;
     Ok(id)
 }
 
+/// Read the live item `item` names; see [`resolve_item_selector`].
 pub fn vault_get_item_v1(
     vault_path: &Path,
-    master_password: &SecretString,
-    id: Uuid,
+    key: &VaultKey,
+    item: &str,
 ) -> Result<items::VaultItemV1, VaultError> {
     let bytes = read_existing_vault_bytes(vault_path)?;
-    let (payload, _) = load_payload_v1(&bytes, master_password)?;
+    let mut payload = unlock_with_key_v1(&bytes, key)?.payload;
 
-    payload
+    let id = resolve_item_selector(&payload.items, item)?;
+    let index = payload
         .items
-        .into_iter()
-        .find(|i| i.id == id)
-        .ok_or_else(|| VaultError::ItemNotFound(id.to_string()))
+        .iter()
+        .position(|i| i.id == id)
+        .expect("selectors resolve to listed items");
+    Ok(payload.items.swap_remove(index))
+}
+
+/// Read a `totp` item along with its parsed seed.
+pub fn vault_totp_v1(
+    vault_path: &Path,
+    key: &VaultKey,
+    item: &str,
+) -> Result<(items::VaultItemV1, totp::Totp), VaultError> {
+    let item = vault_get_item_v1(vault_path, key, item)?;
+    if item.item_type != items::VaultItemType::Totp {
+        return Err(VaultError::NotTotpItem(item.id));
+    }
+    let totp = totp::Totp::parse(&item.secret)?;
+    Ok((item, totp))
+}
+
+/// Attach `contents` to an item as `name`, replacing an attachment of the
+/// same name. Returns the item's id, the new attachment and whether one was
+/// replaced.
+pub fn vault_attach_v1(
+    vault_path: &Path,
+    key: &VaultKey,
+    item: &str,
+    name: &str,
+    contents: &[u8],
+) -> Result<(Uuid, items::VaultAttachment, bool), VaultError> {
+    let name = name.trim();
+    if name.is_empty() || name.contains(['/', '\\', '\0']) {
+        return Err(VaultError::InvalidAttachmentName(name.to_string()));
+    }
+    let limit = attachment_limit()?;
+    let size = contents.len() as u64;
+    if size > limit {
+        return Err(VaultError::AttachmentTooLarge { size, limit });
+    }
+
+    let _lock = io::VaultLock::acquire(
+        &io::lock_path_for_vault(vault_path),
+        io::LockMode::Exclusive,
+    )?;
+
+    let bytes = read_existing_vault_bytes_unlocked(vault_path)?;
+    let mut unlocked = unlock_with_key_v1(&bytes, key)?;
+
+    let item = live_item_mut(&mut unlocked.payload.items, item)?;
+    let id = item.id;
+    let previous = item.snapshot();
+    let now = now_unix_seconds();
+    let attachment = items::VaultAttachment {
+        name: name.to_string(),
+        size,
+        sha256: sha256_hex(contents),
+        added_at: now,
+        data: base64::engine::general_purpose::STANDARD.encode(contents),
+    };
+    let replaced = item.attachment(name).is_some();
+    item.attachments.retain(|a| a.name != name);
+    item.attachments.push(attachment.clone());
+    item.attachments.sort_by(|a, b| a.name.cmp(&b.name));
+
+    item.push_history(previous);
+    item.updated_at = now;
+
+    let new_bytes = unlocked.seal()?;
+    save_vault_bytes_unlocked(vault_path, &new_bytes)?;
+    Ok((id, attachment, replaced))
+}
+
+/// Remove an attachment. History records only the attachment's metadata, so
+/// its contents are gone for good once the vault is saved. Returns the
+/// item's id.
+pub fn vault_detach_v1(
+    vault_path: &Path,
+    key: &VaultKey,
+    item: &str,
+    name: &str,
+) -> Result<Uuid, VaultError> {
+    let _lock = io::VaultLock::acquire(
+        &io::lock_path_for_vault(vault_path),
+        io::LockMode::Exclusive,
+    )?;
+
+    let bytes = read_existing_vault_bytes_unlocked(vault_path)?;
+    let mut unlocked = unlock_with_key_v1(&bytes, key)?;
+
+    let item = live_item_mut(&mut unlocked.payload.items, item)?;
+    if item.attachment(name).is_none() {
+        return Err(VaultError::AttachmentNotFound(name.to_string()));
+    }
+    let id = item.id;
+    let previous = item.snapshot();
+    item.attachments.retain(|a| a.name != name);
+    item.push_history(previous);
+    item.updated_at = now_unix_seconds();
+
+    let new_bytes = unlocked.seal()?;
+    save_vault_bytes_unlocked(vault_path, &new_bytes)?;
+    Ok(id)
+}
+
+/// Decode an attachment's contents, checking them against the stored hash.
+/// Returns the item's id along with the contents.
+pub fn vault_read_attachment_v1(
+    vault_path: &Path,
+    key: &VaultKey,
+    item: &str,
+    name: &str,
+) -> Result<(Uuid, Zeroizing<Vec<u8>>), VaultError> {
+    let item = vault_get_item_v1(vault_path, key, item)?;
+    let attachment = item
+        .attachment(name)
+        .ok_or_else(|| VaultError::AttachmentNotFound(name.to_string()))?;
+    let contents = base64::engine::general_purpose::STANDARD
+        .decode(&attachment.data)
+        .map(Zeroizing::new)
+        .map_err(|_| VaultError::AttachmentCorrupt(name.to_string()))?;
+    if sha256_hex(&contents) != attachment.sha256 {
+        return Err(VaultError::AttachmentCorrupt(name.to_string()));
+    }
+    Ok((item.id, contents))
+}
+
+/// The attachment size limit from `PASSWORDER_VAULT_ATTACHMENT_LIMIT`.
+fn attachment_limit() -> Result<u64, VaultError> {
+    match std::env::var_os(ATTACHMENT_LIMIT_ENV) {
+        None => Ok(DEFAULT_ATTACHMENT_LIMIT),
+        Some(value) => {
+            let value = value.to_string_lossy();
+            value
+                .trim()
+                .parse()
+                .map_err(|_| VaultError::InvalidAttachmentLimit(value.to_string()))
+        }
+    }
+}
+
+fn sha256_hex(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
 }
 
 pub fn vault_list_items_v1(
     vault_path: &Path,
-    master_password: &SecretString,
+    key: &VaultKey,
 ) -> Result<Vec<items::VaultItemV1>, VaultError> {
     let bytes = read_existing_vault_bytes(vault_path)?;
-    let (payload, _) = load_payload_v1(&bytes, master_password)?;
-    Ok(payload.items)
+    let payload = unlock_with_key_v1(&bytes, key)?.payload;
+    Ok(payload
+        .items
+        .into_iter()
+        .filter(|i| !i.is_trashed())
+        .collect())
 }
 
-pub fn vault_search_items_v1(
+/// Like [`vault_list_items_v1`] but without secrets: `secret` and `history`
+/// come back empty, and a v2 vault never decrypts them.
+pub fn vault_list_item_metadata_v1(
     vault_path: &Path,
-    master_password: &SecretString,
-    query: &str,
+    key: &VaultKey,
 ) -> Result<Vec<items::VaultItemV1>, VaultError> {
-    let q = query.trim().to_lowercase();
-    if q.is_empty() {
-        return Ok(Vec::new());
+    let bytes = read_existing_vault_bytes(vault_path)?;
+    let payload = unlock_metadata_with_key(&bytes, key)?;
+    Ok(payload
+        .items
+        .into_iter()
+        .filter(|i| !i.is_trashed())
+        .collect())
+}
+
+/// Shortest id prefix accepted as a selector, so short names are not
+/// mistaken for ids.
+const MIN_ID_PREFIX_LEN: usize = 4;
+
+/// Find the live item named by `selector`, trying in turn:
+///
+/// 1. a UUID;
+/// 2. the full `path/name` (just `name` for items without a path);
+/// 3. the name alone;
+/// 4. a prefix of the hyphenated id, at least [`MIN_ID_PREFIX_LEN`] long.
+///
+/// The first step with any match decides; more than one match there is
+/// [`VaultError::AmbiguousItem`].
+pub(super) fn resolve_item_selector(
+    items: &[items::VaultItemV1],
+    selector: &str,
+) -> Result<Uuid, VaultError> {
+    let selector = selector.trim();
+    let live = || items.iter().filter(|i| !i.is_trashed());
+
+    if let Ok(id) = Uuid::parse_str(selector) {
+        return live()
+            .find(|i| i.id == id)
+            .map(|i| i.id)
+            .ok_or_else(|| VaultError::ItemNotFound(selector.to_string()));
+    }
+
+    let full_path = selector.trim_matches('/');
+    let id_prefix = selector.to_ascii_lowercase();
+    let steps: [&dyn Fn(&items::VaultItemV1) -> bool; 3] = [
+        &|i| item_full_path(i) == full_path,
+        &|i| i.name == selector,
+        &|i| id_prefix.len() >= MIN_ID_PREFIX_LEN && i.id.to_string().starts_with(&id_prefix),
+    ];
+    for step in steps {
+        let matches = live().filter(|i| step(i)).collect::<Vec<_>>();
+        match matches.as_slice() {
+            [] => continue,
+            [item] => return Ok(item.id),
+            _ => {
+                return Err(VaultError::AmbiguousItem {
+                    selector: selector.to_string(),
+                    candidates: matches
+                        .iter()
+                        .map(|i| format!("{}\t{}", i.id, item_full_path(i)))
+                        .collect(),
+                });
+            }
+        }
+    }
+    Err(VaultError::ItemNotFound(selector.to_string()))
+}
+
+/// `path/name`, or `name` for items without a path.
+fn item_full_path(item: &items::VaultItemV1) -> String {
+    match item.path.as_deref().map(|p| p.trim_matches('/')) {
+        Some(path) if !path.is_empty() => format!("{path}/{}", item.name),
+        _ => item.name.clone(),
     }
+}
 
+/// Search item metadata with a [`query::ItemQuery`]; secrets are not
+/// decrypted and come back empty.
+pub fn vault_search_items_v1(
+    vault_path: &Path,
+    key: &VaultKey,
+    query: &query::ItemQuery,
+) -> Result<Vec<items::VaultItemV1>, VaultError> {
     let bytes = read_existing_vault_bytes(vault_path)?;
-    let (payload, _) = load_payload_v1(&bytes, master_password)?;
+    let payload = unlock_metadata_with_key(&bytes, key)?;
 
+    let now = now_unix_seconds();
     let matches = payload
         .items
         .into_iter()
-        .filter(|item| item_matches_query(item, &q))
+        .filter(|item| !item.is_trashed() && query.matches(item, now))
         .collect::<Vec<_>>();
     Ok(matches)
 }
 
+/// Apply `input` to the item it names. Returns the item's id.
 pub fn vault_edit_item_v1(
     vault_path: &Path,
-    master_password: &SecretString,
+    key: &VaultKey,
     input: EditItemInput,
-) -> Result<(), VaultError> {
+) -> Result<Uuid, VaultError> {
     let _lock = io::VaultLock::acquire(
         &io::lock_path_for_vault(vault_path),
         io::LockMode::Exclusive,
     )?;
 
     let bytes = read_existing_vault_bytes_unlocked(vault_path)?;
-    let (mut payload, header) = load_payload_v1(&bytes, master_password)?;
+    let mut unlocked = unlock_with_key_v1(&bytes, key)?;
 
-    let item = payload
-        .items
-        .iter_mut()
-        .find(|i| i.id == input.id)
-        .ok_or_else(|| VaultError::ItemNotFound(input.id.to_string()))?;
+    let item = live_item_mut(&mut unlocked.payload.items, &input.item)?;
+    let id = item.id;
+    let previous = item.snapshot();
 
     if let Some(t) = input.item_type {
         item.item_type = t;
@@ -307,23 +767,176 @@ pub fn vault_edit_item_v1(
     } else if let Some(notes) = input.notes {
         item.notes = Some(notes);
     }
+    for name in &input.remove_fields {
+        let before = item.fields.len();
+        item.fields.retain(|f| f.name != *name);
+        if item.fields.len() == before {
+            return Err(VaultError::FieldNotFound(name.clone()));
+        }
+    }
+    for field in input.set_fields {
+        match item.fields.iter_mut().find(|f| f.name == field.name) {
+            Some(existing) => {
+                existing.value = field.value;
+                existing.concealed |= field.concealed;
+            }
+            None => item.fields.push(field),
+        }
+    }
+    for name in &input.conceal_fields {
+        match item.fields.iter_mut().find(|f| f.name == *name) {
+            Some(field) => field.concealed = true,
+            None => return Err(VaultError::FieldNotFound(name.clone())),
+        }
+    }
+    if input.clear_expires_at {
+        item.expires_at = None;
+    } else if let Some(expires_at) = input.expires_at {
+        item.expires_at = Some(expires_at);
+    }
+    if input.clear_rotate_every {
+        item.rotate_every = None;
+    } else if let Some(rotate_every) = input.rotate_every {
+        item.rotate_every = Some(rotate_every);
+    }
+    item.secret = normalize_secret(item.item_type, std::mem::take(&mut item.secret))?;
 
-    item.updated_at = now_unix_seconds();
+    let now = now_unix_seconds();
+    item.note_secret_change(&previous.secret, now);
+    item.push_history(previous);
+    item.updated_at = now;
+
+    unlocked.payload.items.sort_by(item_sort_cmp);
+    let new_bytes = unlocked.seal()?;
+    save_vault_bytes_unlocked(vault_path, &new_bytes)?;
+    Ok(id)
+}
 
-    payload.items.sort_by(item_sort_cmp);
-    let new_bytes = seal_vault_v1(
-        header.header.kdf_params,
-        header.header.kdf_salt,
-        master_password,
-        &payload,
+/// Replace an item's secret with the one `generate` makes for it, reading
+/// and writing the vault under a single unlock. If `generate` returns `None`
+/// the vault is left untouched and so is the result. Returns the item as
+/// rotated, new secret included.
+pub fn vault_rotate_item_v1(
+    vault_path: &Path,
+    key: &VaultKey,
+    item: &str,
+    generate: &mut dyn FnMut(&items::VaultItemV1) -> Option<String>,
+) -> Result<Option<items::VaultItemV1>, VaultError> {
+    let _lock = io::VaultLock::acquire(
+        &io::lock_path_for_vault(vault_path),
+        io::LockMode::Exclusive,
     )?;
-    io::write_vault_bytes_atomic_unlocked(vault_path, &new_bytes)?;
-    Ok(())
+
+    let bytes = read_existing_vault_bytes_unlocked(vault_path)?;
+    let mut unlocked = unlock_with_key_v1(&bytes, key)?;
+
+    let item = live_item_mut(&mut unlocked.payload.items, item)?;
+    let Some(secret) = generate(item) else {
+        return Ok(None);
+    };
+    let previous = item.snapshot();
+    item.secret = normalize_secret(item.item_type, secret)?;
+
+    let now = now_unix_seconds();
+    item.note_secret_change(&previous.secret, now);
+    item.push_history(previous);
+    item.updated_at = now;
+    let rotated = item.clone();
+
+    let new_bytes = unlocked.seal()?;
+    save_vault_bytes_unlocked(vault_path, &new_bytes)?;
+    Ok(Some(rotated))
+}
+
+/// Roll an item back to revision `rev` from its history.
+///
+/// The values being replaced are kept as a new revision, so a restore can
+/// itself be undone. History does not keep attachment contents, so an
+/// attachment detached since `rev` cannot come back. Returns the item's id,
+/// its new revision number and the names of the attachments left out.
+pub fn vault_restore_item_v1(
+    vault_path: &Path,
+    key: &VaultKey,
+    item: &str,
+    rev: u32,
+) -> Result<(Uuid, u32, Vec<String>), VaultError> {
+    let _lock = io::VaultLock::acquire(
+        &io::lock_path_for_vault(vault_path),
+        io::LockMode::Exclusive,
+    )?;
+
+    let bytes = read_existing_vault_bytes_unlocked(vault_path)?;
+    let mut unlocked = unlock_with_key_v1(&bytes, key)?;
+
+    let item = live_item_mut(&mut unlocked.payload.items, item)?;
+    let id = item.id;
+    let revision = item
+        .history
+        .iter()
+        .find(|r| r.rev == rev)
+        .cloned()
+        .ok_or(VaultError::RevisionNotFound { id, rev })?;
+
+    let previous = item.snapshot();
+    item.apply_revision(&revision, &[]);
+    let missing = revision
+        .attachments
+        .iter()
+        .filter(|a| item.attachment(&a.name).is_none())
+        .map(|a| a.name.clone())
+        .collect();
+    let now = now_unix_seconds();
+    item.note_secret_change(&previous.secret, now);
+    item.push_history(previous);
+    item.updated_at = now;
+    let new_rev = item.revision();
+
+    unlocked.payload.items.sort_by(item_sort_cmp);
+    let new_bytes = unlocked.seal()?;
+    save_vault_bytes_unlocked(vault_path, &new_bytes)?;
+    Ok((id, new_rev, missing))
 }
 
+/// Move an item to the trash; it stays recoverable until the trash is emptied.
+/// Returns the item's id.
 pub fn vault_remove_item_v1(
     vault_path: &Path,
-    master_password: &SecretString,
+    key: &VaultKey,
+    item: &str,
+) -> Result<Uuid, VaultError> {
+    let _lock = io::VaultLock::acquire(
+        &io::lock_path_for_vault(vault_path),
+        io::LockMode::Exclusive,
+    )?;
+
+    let bytes = read_existing_vault_bytes_unlocked(vault_path)?;
+    let mut unlocked = unlock_with_key_v1(&bytes, key)?;
+
+    let item = live_item_mut(&mut unlocked.payload.items, item)?;
+    item.deleted_at = Some(now_unix_seconds());
+    let id = item.id;
+
+    let new_bytes = unlocked.seal()?;
+    save_vault_bytes_unlocked(vault_path, &new_bytes)?;
+    Ok(id)
+}
+
+pub fn vault_list_trash_v1(
+    vault_path: &Path,
+    key: &VaultKey,
+) -> Result<Vec<items::VaultItemV1>, VaultError> {
+    let bytes = read_existing_vault_bytes(vault_path)?;
+    let payload = unlock_with_key_v1(&bytes, key)?.payload;
+    Ok(payload
+        .items
+        .into_iter()
+        .filter(|i| i.is_trashed())
+        .collect())
+}
+
+pub fn vault_restore_trashed_item_v1(
+    vault_path: &Path,
+    key: &VaultKey,
     id: Uuid,
 ) -> Result<(), VaultError> {
     let _lock = io::VaultLock::acquire(
@@ -332,119 +945,1192 @@ pub fn vault_remove_item_v1(
     )?;
 
     let bytes = read_existing_vault_bytes_unlocked(vault_path)?;
-    let (mut payload, header) = load_payload_v1(&bytes, master_password)?;
+    let mut unlocked = unlock_with_key_v1(&bytes, key)?;
 
-    let before = payload.items.len();
-    payload.items.retain(|i| i.id != id);
-    if payload.items.len() == before {
-        return Err(VaultError::ItemNotFound(id.to_string()));
-    }
+    let item = unlocked
+        .payload
+        .items
+        .iter_mut()
+        .find(|i| i.id == id && i.is_trashed())
+        .ok_or_else(|| VaultError::ItemNotFound(id.to_string()))?;
+    item.deleted_at = None;
 
-    let new_bytes = seal_vault_v1(
-        header.header.kdf_params,
-        header.header.kdf_salt,
-        master_password,
-        &payload,
-    )?;
-    io::write_vault_bytes_atomic_unlocked(vault_path, &new_bytes)?;
+    let new_bytes = unlocked.seal()?;
+    save_vault_bytes_unlocked(vault_path, &new_bytes)?;
     Ok(())
 }
 
-fn read_existing_vault_bytes(vault_path: &Path) -> Result<Vec<u8>, VaultError> {
-    match io::read_vault_bytes(vault_path) {
-        Ok(bytes) => Ok(bytes),
-        Err(io::VaultIoError::Io(err)) if err.kind() == std::io::ErrorKind::NotFound => {
-            Err(VaultError::NotInitialized)
-        }
-        Err(err) => Err(VaultError::Io(err)),
+/// Permanently delete trashed items; with `older_than`, only those trashed
+/// at least that long ago. Returns the number of items deleted.
+pub fn vault_empty_trash_v1(
+    vault_path: &Path,
+    key: &VaultKey,
+    older_than: Option<Duration>,
+) -> Result<usize, VaultError> {
+    let _lock = io::VaultLock::acquire(
+        &io::lock_path_for_vault(vault_path),
+        io::LockMode::Exclusive,
+    )?;
+
+    let bytes = read_existing_vault_bytes_unlocked(vault_path)?;
+    let mut unlocked = unlock_with_key_v1(&bytes, key)?;
+
+    let now = now_unix_seconds();
+    let cutoff = now.saturating_sub(older_than.map_or(0, |d| d.as_secs()));
+    let (purged, kept) = std::mem::take(&mut unlocked.payload.items)
+        .into_iter()
+        .partition::<Vec<_>, _>(|i| i.deleted_at.is_some_and(|at| at <= cutoff));
+    unlocked.payload.items = kept;
+    if purged.is_empty() {
+        return Ok(0);
     }
+    unlocked
+        .payload
+        .tombstones
+        .extend(purged.iter().map(|i| items::VaultTombstone {
+            id: i.id,
+            deleted_at: now,
+        }));
+    let removed = purged.len();
+
+    let new_bytes = unlocked.seal()?;
+    save_vault_bytes_unlocked(vault_path, &new_bytes)?;
+    Ok(removed)
 }
 
-fn read_existing_vault_bytes_unlocked(vault_path: &Path) -> Result<Vec<u8>, VaultError> {
-    match io::read_vault_bytes_unlocked(vault_path) {
-        Ok(bytes) => Ok(bytes),
-        Err(io::VaultIoError::Io(err)) if err.kind() == std::io::ErrorKind::NotFound => {
-            Err(VaultError::NotInitialized)
+/// Selects items for export; unset criteria match everything.
+#[derive(Debug, Clone, Default)]
+pub struct ItemFilter {
+    /// Matches the path itself and everything below it (`services` matches `services/api`).
+    pub path: Option<String>,
+    /// Items must carry all of these tags.
+    pub tags: Vec<String>,
+    pub item_type: Option<items::VaultItemType>,
+}
+
+impl ItemFilter {
+    pub fn matches(&self, item: &items::VaultItemV1) -> bool {
+        if let Some(prefix) = self.path.as_deref() {
+            let prefix = prefix.trim_end_matches('/');
+            let Some(path) = item.path.as_deref() else {
+                return false;
+            };
+            let below = path
+                .strip_prefix(prefix)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'));
+            if !below {
+                return false;
+            }
         }
-        Err(err) => Err(VaultError::Io(err)),
+        if self.item_type.is_some_and(|t| t != item.item_type) {
+            return false;
+        }
+        normalize_tags(self.tags.clone())
+            .iter()
+            .all(|tag| item.tags.contains(tag))
     }
 }
 
-fn load_payload_v1<'a>(
-    vault_bytes: &'a [u8],
-    master_password: &SecretString,
-) -> Result<(items::VaultPayloadV1, format_v1::ParsedVaultV1<'a>), VaultError> {
-    let parsed = format_v1::parse_vault_v1(vault_bytes)?;
-    let aad = aad_for_v1(&parsed.header);
+/// What to do when an imported item has the same id as an existing one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum ConflictPolicy {
+    /// Keep the existing item and drop the imported one.
+    #[default]
+    Skip,
+    /// Replace the existing item's fields; its old values go to history.
+    Overwrite,
+    /// Import under a fresh id next to the existing item.
+    Duplicate,
+}
 
-    let kdf_out = crypto::derive_kdf_out_from_password(
-        master_password,
-        &parsed.header.kdf_salt,
-        parsed.header.kdf_params,
-    )?;
-    let kek = crypto::derive_kek(&kdf_out)?;
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ImportSummary {
+    pub added: usize,
+    pub overwritten: usize,
+    pub duplicated: usize,
+    pub skipped: usize,
+}
 
-    let dek = crypto::unwrap_dek(
-        &kek,
-        &parsed.header.wrap_nonce,
-        &aad,
-        &parsed.header.wrapped_dek,
-    )
-    .map_err(|e| match e {
-        crypto::CryptoError::Aead => VaultError::AuthFailed,
-        other => VaultError::Crypto(other),
-    })?;
+/// Write the live items matching `filter` to a passphrase-encrypted bundle.
+///
+/// Revision history carries old secrets, so it is only included on request.
+/// The passphrase is stretched with the default Argon2id params with
+/// `kdf_params` applied. Returns the number of items exported.
+pub fn vault_export_bundle_v1(
+    vault_path: &Path,
+    key: &VaultKey,
+    out_path: &Path,
+    passphrase: &SecretString,
+    filter: &ItemFilter,
+    with_history: bool,
+    kdf_params: KdfParamsOverride,
+) -> Result<usize, VaultError> {
+    let kdf_params = kdf_params.apply(default_kdf_params())?;
+    let bytes = read_existing_vault_bytes(vault_path)?;
+    let payload = unlock_with_key_v1(&bytes, key)?.payload;
 
-    let plaintext = crypto::decrypt_payload(
-        &dek,
+    let items = payload
+        .items
+        .into_iter()
+        .filter(|i| !i.is_trashed() && filter.matches(i))
+        .map(|mut i| {
+            if !with_history {
+                i.history.clear();
+            }
+            i
+        })
+        .collect::<Vec<_>>();
+    let count = items.len();
+
+    let contents = bundle::BundleContents {
+        schema_version: items::CURRENT_PAYLOAD_SCHEMA_VERSION,
+        exported_at: now_unix_seconds(),
+        items,
+    };
+    let sealed = bundle::seal_bundle(&contents, passphrase, kdf_params)?;
+    io::write_sensitive_bytes_atomic(out_path, &sealed)?;
+    Ok(count)
+}
+
+/// Decrypt a bundle written by [`vault_export_bundle_v1`].
+pub fn read_bundle_v1(
+    bundle_path: &Path,
+    passphrase: &SecretString,
+) -> Result<Vec<items::VaultItemV1>, VaultError> {
+    let bytes = io::read_vault_bytes_unlocked(bundle_path)?;
+    Ok(bundle::open_bundle(&bytes, passphrase)?.items)
+}
+
+/// Map another password manager's export file onto vault items.
+pub fn read_foreign_export_v1(
+    export_path: &Path,
+    format: foreign::ForeignFormat,
+) -> Result<foreign::ForeignImport, VaultError> {
+    let mut import = foreign::read_foreign_export(export_path, format)?;
+    for item in &mut import.items {
+        item.tags = normalize_tags(std::mem::take(&mut item.tags));
+        item.urls = normalize_urls(std::mem::take(&mut item.urls));
+    }
+    Ok(import)
+}
+
+/// Merge `incoming` items into the vault, resolving id clashes with `policy`.
+/// Items whose id was purged are added under a new id. With `dry_run` the
+/// summary is computed but nothing is written.
+pub fn vault_import_items_v1(
+    vault_path: &Path,
+    key: &VaultKey,
+    incoming: Vec<items::VaultItemV1>,
+    policy: ConflictPolicy,
+    dry_run: bool,
+) -> Result<ImportSummary, VaultError> {
+    let _lock = io::VaultLock::acquire(
+        &io::lock_path_for_vault(vault_path),
+        io::LockMode::Exclusive,
+    )?;
+
+    let bytes = read_existing_vault_bytes_unlocked(vault_path)?;
+    let mut unlocked = unlock_with_key_v1(&bytes, key)?;
+
+    let mut summary = ImportSummary::default();
+    for mut item in incoming {
+        item.tags = normalize_tags(item.tags);
+        item.urls = normalize_urls(item.urls);
+        item.deleted_at = None;
+
+        let existing = unlocked.payload.items.iter_mut().find(|i| i.id == item.id);
+        match (existing, policy) {
+            (None, _) => {
+                // A purged id stays tombstoned on other replicas, so the
+                // next sync would delete the item again under its old id.
+                if unlocked.payload.tombstones.iter().any(|t| t.id == item.id) {
+                    item.id = Uuid::new_v4();
+                }
+                unlocked.payload.items.push(item);
+                summary.added += 1;
+            }
+            (Some(_), ConflictPolicy::Skip) => summary.skipped += 1,
+            (Some(existing), ConflictPolicy::Overwrite) => {
+                let previous = existing.snapshot();
+                existing.apply_revision(&item.snapshot(), &item.attachments);
+                let now = now_unix_seconds();
+                existing.note_secret_change(&previous.secret, now);
+                existing.push_history(previous);
+                existing.updated_at = now;
+                existing.deleted_at = None;
+                summary.overwritten += 1;
+            }
+            (Some(_), ConflictPolicy::Duplicate) => {
+                item.id = Uuid::new_v4();
+                unlocked.payload.items.push(item);
+                summary.duplicated += 1;
+            }
+        }
+    }
+
+    if dry_run || summary.added + summary.overwritten + summary.duplicated == 0 {
+        return Ok(summary);
+    }
+
+    unlocked.payload.items.sort_by(item_sort_cmp);
+    let new_bytes = unlocked.seal()?;
+    save_vault_bytes_unlocked(vault_path, &new_bytes)?;
+    Ok(summary)
+}
+
+/// Three-way merge another copy of the vault into this one.
+///
+/// `other_path` and `base_path` are read without locking (they are usually
+/// copies on a shared drive) and must open with the same key. Nothing is
+/// written if any conflict is left unresolved or with `dry_run`.
+pub fn vault_merge_v1(
+    vault_path: &Path,
+    key: &VaultKey,
+    other_path: &Path,
+    base_path: Option<&Path>,
+    resolve: &mut dyn FnMut(&merge::MergeConflict) -> Option<merge::MergeSide>,
+    dry_run: bool,
+) -> Result<merge::MergeReport, VaultError> {
+    let _lock = io::VaultLock::acquire(
+        &io::lock_path_for_vault(vault_path),
+        io::LockMode::Exclusive,
+    )?;
+
+    let bytes = read_existing_vault_bytes_unlocked(vault_path)?;
+    let mut unlocked = unlock_with_key_v1(&bytes, key)?;
+    let theirs = io::read_vault_bytes_unlocked(other_path)?;
+    let base = base_path.map(io::read_vault_bytes_unlocked).transpose()?;
+
+    let report = merge_vault_bytes(&mut unlocked, key, &theirs, base.as_deref(), resolve)?;
+    if dry_run || !report.changed() {
+        return Ok(report);
+    }
+
+    let new_bytes = unlocked.seal()?;
+    save_vault_bytes_unlocked(vault_path, &new_bytes)?;
+    Ok(report)
+}
+
+/// Merge another copy of the vault (and optionally their common ancestor),
+/// both opened with `key`, into `unlocked`.
+fn merge_vault_bytes(
+    unlocked: &mut UnlockedVaultV1,
+    key: &VaultKey,
+    theirs: &[u8],
+    base: Option<&[u8]>,
+    resolve: &mut dyn FnMut(&merge::MergeConflict) -> Option<merge::MergeSide>,
+) -> Result<merge::MergeReport, VaultError> {
+    let theirs = unlock_with_key_v1(theirs, key)?.payload;
+    let base = match base {
+        Some(base) => Some(unlock_with_key_v1(base, key)?.payload),
+        None => None,
+    };
+
+    let report = merge::merge_payloads(
+        &mut unlocked.payload,
+        &theirs,
+        base.as_ref(),
+        resolve,
+        now_unix_seconds(),
+    );
+    if !report.unresolved.is_empty() {
+        return Err(VaultError::MergeConflicts(report.unresolved.len()));
+    }
+    unlocked.payload.items.sort_by(item_sort_cmp);
+    Ok(report)
+}
+
+/// What `vault sync` did with the remote branch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncOutcome {
+    /// No remote is configured; changes were only committed locally.
+    NoRemote,
+    UpToDate,
+    /// Local commits the remote does not have yet.
+    Ahead,
+    /// The remote was ahead and the vault was fast-forwarded to it.
+    Pulled,
+    /// Both sides changed and were merged item by item.
+    Merged,
+}
+
+impl SyncOutcome {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SyncOutcome::NoRemote => "no-remote",
+            SyncOutcome::UpToDate => "up-to-date",
+            SyncOutcome::Ahead => "ahead",
+            SyncOutcome::Pulled => "pulled",
+            SyncOutcome::Merged => "merged",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct SyncReport {
+    /// Whether uncommitted vault changes were committed first.
+    pub committed: bool,
+    pub outcome: SyncOutcome,
+    pub merge: Option<merge::MergeReport>,
+    pub pushed: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyncInitReport {
+    pub repo_dir: PathBuf,
+    pub remote: Option<String>,
+    /// The vault file was missing locally and was taken from the remote.
+    pub pulled: bool,
+}
+
+/// Whether `vault sync init` has set up git sync for this vault.
+pub fn vault_sync_enabled(vault_path: &Path) -> bool {
+    sync::GitRepo::open(vault_path).is_ok()
+}
+
+/// Put the vault's directory under git, optionally pointing it at a remote.
+///
+/// An existing vault is committed as is. Without a local vault, the remote's
+/// copy (if any) is checked out, which is how a second device joins.
+pub fn vault_sync_init_v1(
+    vault_path: &Path,
+    remote: Option<&str>,
+) -> Result<SyncInitReport, VaultError> {
+    let _lock = io::VaultLock::acquire(
+        &io::lock_path_for_vault(vault_path),
+        io::LockMode::Exclusive,
+    )?;
+    let repo = sync::GitRepo::init(vault_path, remote)?;
+
+    let mut pulled = false;
+    if vault_path.exists() {
+        repo.commit_pending("Add vault")?;
+    } else if repo.has_remote()?
+        && let Some(theirs) = repo.fetch()?
+    {
+        checkout_remote_vault(&repo, vault_path, &theirs, None)?;
+        pulled = true;
+    }
+
+    Ok(SyncInitReport {
+        repo_dir: repo.dir().to_path_buf(),
+        remote: repo.remote_url()?,
+        pulled,
+    })
+}
+
+/// Commit pending changes, pull the remote branch and merge it item by item,
+/// then push unless `push` is false.
+///
+/// Git never merges the vault file itself: every save re-encrypts it, so a
+/// byte-level merge would always conflict. Divergent copies are decrypted
+/// with `key` and merged like `vault merge`, using the vault as of the
+/// commits' merge base as the common ancestor.
+pub fn vault_sync_v1(
+    vault_path: &Path,
+    key: &VaultKey,
+    resolve: &mut dyn FnMut(&merge::MergeConflict) -> Option<merge::MergeSide>,
+    push: bool,
+) -> Result<SyncReport, VaultError> {
+    let repo = sync::GitRepo::open(vault_path)?;
+    let _lock = io::VaultLock::acquire(
+        &io::lock_path_for_vault(vault_path),
+        io::LockMode::Exclusive,
+    )?;
+
+    let committed = repo.commit_pending("Update vault")?;
+    let mut report = SyncReport {
+        committed,
+        outcome: SyncOutcome::NoRemote,
+        merge: None,
+        pushed: false,
+    };
+    if !repo.has_remote()? {
+        return Ok(report);
+    }
+
+    let head = repo.rev_parse("HEAD")?;
+    report.outcome = match (repo.fetch()?, head.as_deref()) {
+        (None, None) => SyncOutcome::UpToDate,
+        (None, Some(_)) => SyncOutcome::Ahead,
+        (Some(theirs), None) => {
+            checkout_remote_vault(&repo, vault_path, &theirs, Some(key))?;
+            SyncOutcome::Pulled
+        }
+        (Some(theirs), Some(head)) if theirs == head => SyncOutcome::UpToDate,
+        (Some(theirs), Some(head)) if repo.is_ancestor(&theirs, head)? => SyncOutcome::Ahead,
+        (Some(theirs), Some(head)) if repo.is_ancestor(head, &theirs)? => {
+            checkout_remote_vault(&repo, vault_path, &theirs, Some(key))?;
+            SyncOutcome::Pulled
+        }
+        (Some(theirs), Some(head)) => {
+            let bytes = read_existing_vault_bytes_unlocked(vault_path)?;
+            let mut unlocked = unlock_with_key_v1(&bytes, key)?;
+            let their_bytes = remote_vault_bytes(&repo, &theirs)?;
+            let base = match repo.merge_base(head, &theirs)? {
+                Some(base) => repo.vault_at(&base)?,
+                None => None,
+            };
+            let merged =
+                merge_vault_bytes(&mut unlocked, key, &their_bytes, base.as_deref(), resolve)?;
+            let new_bytes = unlocked.seal()?;
+            // Record the result as a merge commit rather than a plain save.
+            io::write_vault_bytes_atomic_unlocked(vault_path, &new_bytes)?;
+            repo.commit_merge(&theirs, "Merge vault")?;
+            report.merge = Some(merged);
+            SyncOutcome::Merged
+        }
+    };
+
+    if push && matches!(report.outcome, SyncOutcome::Ahead | SyncOutcome::Merged) {
+        repo.push()?;
+        report.pushed = true;
+    }
+    Ok(report)
+}
+
+fn remote_vault_bytes(repo: &sync::GitRepo, commit: &str) -> Result<Vec<u8>, VaultError> {
+    repo.vault_at(commit)?
+        .ok_or_else(|| sync::SyncError::MissingRemoteVault(repo.file().to_string()).into())
+}
+
+/// Write the vault as of `commit` and move the branch there. With a key, the
+/// incoming vault must unlock with it, so a copy re-keyed elsewhere is not
+/// silently swapped in.
+fn checkout_remote_vault(
+    repo: &sync::GitRepo,
+    vault_path: &Path,
+    commit: &str,
+    key: Option<&VaultKey>,
+) -> Result<(), VaultError> {
+    let bytes = remote_vault_bytes(repo, commit)?;
+    match key {
+        Some(key) => {
+            unlock_with_key_v1(&bytes, key)?;
+        }
+        None => {
+            format_v2::parse_vault(&bytes)?;
+        }
+    }
+    io::write_vault_bytes_atomic_unlocked(vault_path, &bytes)?;
+    repo.move_head(commit)?;
+    Ok(())
+}
+
+/// How a key rotation treats the unlocking slot's keyfile requirement.
+pub enum KeyfileChange {
+    Keep,
+    Set(crypto::SecretBytes),
+    Remove,
+}
+
+/// Argon2id params asked for by the user; unset fields keep the values they
+/// are applied to (a slot's current params, or the defaults for new keys).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct KdfParamsOverride {
+    pub memory_kib: Option<u32>,
+    pub iterations: Option<u32>,
+    pub parallelism: Option<u32>,
+}
+
+impl KdfParamsOverride {
+    fn apply(self, current: crypto::KdfParams) -> Result<crypto::KdfParams, VaultError> {
+        let params = crypto::KdfParams {
+            memory_kib: self.memory_kib.unwrap_or(current.memory_kib),
+            iterations: self.iterations.unwrap_or(current.iterations),
+            parallelism: self.parallelism.unwrap_or(current.parallelism),
+        };
+        params.validate().map_err(|e| match e {
+            crypto::CryptoError::Argon2(inner) => VaultError::InvalidKdfParams(inner.to_string()),
+            other => VaultError::Crypto(other),
+        })?;
+        Ok(params)
+    }
+}
+
+pub struct RotateKeysInput {
+    /// Replace the unlocking slot's password; `None` keeps the current one.
+    pub new_master_password: Option<SecretString>,
+    pub keyfile: KeyfileChange,
+    pub kdf_params: KdfParamsOverride,
+    /// Generate a fresh DEK and re-encrypt the payload under it.
+    pub rotate_dek: bool,
+    /// Allow a DEK rotation to discard key slots other than the unlocking one.
+    pub drop_other_slots: bool,
+}
+
+/// Re-wrap the unlocking key slot under a new KEK (fresh salt) and optionally a new DEK.
+/// Returns the re-wrapped slot.
+///
+/// The whole read-modify-write runs under the exclusive vault lock and the
+/// new file replaces the old one atomically, so a crash leaves either the old
+/// or the new vault on disk, never a partially rotated one.
+pub fn vault_rotate_keys_v1(
+    vault_path: &Path,
+    credentials: &Credentials,
+    input: RotateKeysInput,
+) -> Result<KeySlotInfo, VaultError> {
+    let _lock = io::VaultLock::acquire(
+        &io::lock_path_for_vault(vault_path),
+        io::LockMode::Exclusive,
+    )?;
+
+    let bytes = read_existing_vault_bytes_unlocked(vault_path)?;
+    let mut unlocked = unlock_vault_v1(&bytes, credentials)?;
+    let current = unlocked.unlocking_slot().clone();
+
+    let kdf_params = input.kdf_params.apply(current.kdf_params)?;
+
+    if input.rotate_dek {
+        // Other slots wrap the old DEK and we cannot re-wrap them without their secrets.
+        let others = unlocked.key_slots.len() - 1;
+        if others > 0 && !input.drop_other_slots {
+            return Err(VaultError::OtherKeySlots(others));
+        }
+        unlocked.key_slots.retain(|s| s.id == current.id);
+        unlocked.dek = crypto::generate_dek();
+    }
+
+    let new_credentials = Credentials {
+        master_password: input
+            .new_master_password
+            .unwrap_or_else(|| credentials.master_password.clone()),
+        keyfile: match input.keyfile {
+            KeyfileChange::Keep => credentials.keyfile.clone(),
+            KeyfileChange::Set(digest) => Some(digest),
+            KeyfileChange::Remove => None,
+        },
+    };
+    let replacement = new_key_slot(
+        current.id,
+        current.label,
+        &new_credentials,
+        kdf_params,
+        &unlocked.dek,
+    )?;
+    let info = KeySlotInfo::from(&replacement);
+    unlocked.replace_slot(replacement);
+
+    let new_bytes = unlocked.seal()?;
+    save_vault_bytes_unlocked(vault_path, &new_bytes)?;
+    Ok(info)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeySlotInfo {
+    pub id: u8,
+    pub label: String,
+    pub keyfile_required: bool,
+    pub kdf_params: crypto::KdfParams,
+}
+
+impl From<&format_v1::KeySlot> for KeySlotInfo {
+    fn from(slot: &format_v1::KeySlot) -> Self {
+        Self {
+            id: slot.id,
+            label: slot.label.clone(),
+            keyfile_required: slot.keyfile_required,
+            kdf_params: slot.kdf_params,
+        }
+    }
+}
+
+/// List key slots from the (unencrypted) header; no password is required.
+pub fn vault_list_key_slots_v1(vault_path: &Path) -> Result<Vec<KeySlotInfo>, VaultError> {
+    let bytes = read_existing_vault_bytes(vault_path)?;
+    let parsed = format_v2::parse_vault(&bytes)?;
+    Ok(parsed.key_slots().iter().map(KeySlotInfo::from).collect())
+}
+
+/// Wrap the vault DEK under additional credentials in the lowest free slot.
+/// The new slot takes the unlocking slot's Argon2id params with `kdf_params`
+/// applied.
+pub fn vault_add_key_slot_v1(
+    vault_path: &Path,
+    credentials: &Credentials,
+    slot_credentials: &Credentials,
+    label: Option<String>,
+    kdf_params: KdfParamsOverride,
+) -> Result<u8, VaultError> {
+    let _lock = io::VaultLock::acquire(
+        &io::lock_path_for_vault(vault_path),
+        io::LockMode::Exclusive,
+    )?;
+
+    let bytes = read_existing_vault_bytes_unlocked(vault_path)?;
+    let mut unlocked = unlock_vault_v1(&bytes, credentials)?;
+
+    let id = (0..format_v1::MAX_KEY_SLOTS as u8)
+        .find(|id| unlocked.key_slots.iter().all(|s| s.id != *id))
+        .ok_or(VaultError::NoFreeKeySlot(format_v1::MAX_KEY_SLOTS))?;
+    let label = label.unwrap_or_else(|| format!("slot-{id}"));
+    let kdf_params = kdf_params.apply(unlocked.unlocking_slot().kdf_params)?;
+    let slot = new_key_slot(id, label, slot_credentials, kdf_params, &unlocked.dek)?;
+    unlocked.key_slots.push(slot);
+    unlocked.key_slots.sort_by_key(|s| s.id);
+
+    let new_bytes = unlocked.seal()?;
+    save_vault_bytes_unlocked(vault_path, &new_bytes)?;
+    Ok(id)
+}
+
+pub fn vault_remove_key_slot_v1(
+    vault_path: &Path,
+    credentials: &Credentials,
+    id: u8,
+) -> Result<(), VaultError> {
+    let _lock = io::VaultLock::acquire(
+        &io::lock_path_for_vault(vault_path),
+        io::LockMode::Exclusive,
+    )?;
+
+    let bytes = read_existing_vault_bytes_unlocked(vault_path)?;
+    let mut unlocked = unlock_vault_v1(&bytes, credentials)?;
+
+    if unlocked.key_slots.iter().all(|s| s.id != id) {
+        return Err(VaultError::KeySlotNotFound(id));
+    }
+    if unlocked.key_slots.len() == 1 {
+        return Err(VaultError::LastKeySlot);
+    }
+    unlocked.key_slots.retain(|s| s.id != id);
+
+    let new_bytes = unlocked.seal()?;
+    save_vault_bytes_unlocked(vault_path, &new_bytes)?;
+    Ok(())
+}
+
+/// Unlock with credentials and return the DEK, e.g. for caching in the agent.
+///
+/// Vaults still in the legacy single-wrapper layout are rewritten in slot
+/// layout first, because a DEK alone cannot re-wrap a legacy key slot.
+pub fn vault_unlock_dek_v1(
+    vault_path: &Path,
+    credentials: &Credentials,
+) -> Result<crypto::SecretBytes, VaultError> {
+    let _lock = io::VaultLock::acquire(
+        &io::lock_path_for_vault(vault_path),
+        io::LockMode::Exclusive,
+    )?;
+
+    let bytes = read_existing_vault_bytes_unlocked(vault_path)?;
+    let unlocked = unlock_vault_v1(&bytes, credentials)?;
+    if format_v2::parse_vault(&bytes)?.legacy_layout() {
+        let new_bytes = unlocked.seal()?;
+        save_vault_bytes_unlocked(vault_path, &new_bytes)?;
+    }
+    Ok(unlocked.dek)
+}
+
+/// Check that a cached DEK still decrypts the vault (it goes stale after a rekey).
+pub fn vault_verify_session_key_v1(
+    vault_path: &Path,
+    dek: &crypto::SecretBytes,
+) -> Result<(), VaultError> {
+    let bytes = read_existing_vault_bytes(vault_path)?;
+    unlock_vault_with_dek_v1(&bytes, dek).map(|_| ())
+}
+
+/// Backups of the vault file, newest first (no password required).
+pub fn vault_list_backups_v1(vault_path: &Path) -> Result<Vec<io::VaultBackup>, VaultError> {
+    Ok(io::list_vault_backups(vault_path)?)
+}
+
+/// Replace the vault with the backup called `name`.
+///
+/// The backup's header must parse; the vault being replaced is itself backed
+/// up first, so a restore can be undone the same way.
+pub fn vault_restore_backup_v1(
+    vault_path: &Path,
+    name: &str,
+) -> Result<io::VaultBackup, VaultError> {
+    let _lock = io::VaultLock::acquire(
+        &io::lock_path_for_vault(vault_path),
+        io::LockMode::Exclusive,
+    )?;
+
+    let backup = io::list_vault_backups(vault_path)?
+        .into_iter()
+        .find(|b| b.name == name)
+        .ok_or_else(|| VaultError::BackupNotFound(name.to_string()))?;
+
+    let bytes = io::read_vault_bytes_unlocked(&backup.path)?;
+    format_v2::parse_vault(&bytes)?;
+
+    save_vault_bytes_unlocked(vault_path, &bytes)?;
+    Ok(backup)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UpgradeReport {
+    pub from_version: u16,
+    pub to_version: u16,
+    pub items: usize,
+}
+
+/// Rewrite a v1 vault in the v2 format. Key slots and the DEK are kept, so
+/// every password, keyfile and recovery key keeps working; a vault that is
+/// already v2 is left untouched.
+pub fn vault_upgrade_v1(vault_path: &Path, key: &VaultKey) -> Result<UpgradeReport, VaultError> {
+    let _lock = io::VaultLock::acquire(
+        &io::lock_path_for_vault(vault_path),
+        io::LockMode::Exclusive,
+    )?;
+
+    let bytes = read_existing_vault_bytes_unlocked(vault_path)?;
+    let from_version = format_v2::parse_vault(&bytes)?.version();
+    let mut unlocked = unlock_with_key_v1(&bytes, key)?;
+    let report = UpgradeReport {
+        from_version,
+        to_version: format_v2::VERSION_V2,
+        items: unlocked.payload.items.len(),
+    };
+    if from_version == format_v2::VERSION_V2 {
+        return Ok(report);
+    }
+
+    unlocked.layout = PayloadLayout::V2(SealedItemsV2 {
+        dek: unlocked.dek.clone(),
+        items: HashMap::new(),
+    });
+    let new_bytes = unlocked.seal()?;
+    save_vault_bytes_unlocked(vault_path, &new_bytes)?;
+    Ok(report)
+}
+
+pub(super) fn read_existing_vault_bytes(vault_path: &Path) -> Result<Vec<u8>, VaultError> {
+    match io::read_vault_bytes(vault_path) {
+        Ok(bytes) => Ok(bytes),
+        Err(io::VaultIoError::Io(err)) if err.kind() == std::io::ErrorKind::NotFound => {
+            Err(VaultError::NotInitialized)
+        }
+        Err(err) => Err(VaultError::Io(err)),
+    }
+}
+
+fn read_existing_vault_bytes_unlocked(vault_path: &Path) -> Result<Vec<u8>, VaultError> {
+    match io::read_vault_bytes_unlocked(vault_path) {
+        Ok(bytes) => Ok(bytes),
+        Err(io::VaultIoError::Io(err)) if err.kind() == std::io::ErrorKind::NotFound => {
+            Err(VaultError::NotInitialized)
+        }
+        Err(err) => Err(VaultError::Io(err)),
+    }
+}
+
+/// A decrypted vault plus everything needed to write it back.
+struct UnlockedVaultV1 {
+    payload: items::VaultPayloadV1,
+    /// Key slots in slot layout, ready to be written as-is.
+    key_slots: Vec<format_v1::KeySlot>,
+    dek: crypto::SecretBytes,
+    /// Id of the slot whose secret unlocked the vault (`None` for a session key).
+    slot_id: Option<u8>,
+    /// File format to write back, keeping what was read.
+    layout: PayloadLayout,
+}
+
+enum PayloadLayout {
+    V1,
+    /// Item records as read, so a save re-seals only items that changed.
+    V2(SealedItemsV2),
+}
+
+/// Sealed records of a v2 vault with digests of the plaintext they hold.
+struct SealedItemsV2 {
+    /// The records are only reusable while the DEK they were sealed under is.
+    dek: crypto::SecretBytes,
+    items: HashMap<Uuid, CachedItemV2>,
+}
+
+struct CachedItemV2 {
+    sealed: format_v2::SealedItemV2,
+    meta_digest: [u8; 32],
+    secrets_digest: [u8; 32],
+}
+
+impl UnlockedVaultV1 {
+    fn seal(&self) -> Result<Vec<u8>, VaultError> {
+        match &self.layout {
+            PayloadLayout::V1 => seal_vault_v1(&self.key_slots, &self.dek, &self.payload),
+            PayloadLayout::V2(sealed) => {
+                let reusable = (sealed.dek.expose_secret() == self.dek.expose_secret())
+                    .then_some(&sealed.items);
+                seal_vault_v2(&self.key_slots, &self.dek, &self.payload, reusable)
+            }
+        }
+    }
+
+    fn unlocking_slot(&self) -> &format_v1::KeySlot {
+        self.key_slots
+            .iter()
+            .find(|s| Some(s.id) == self.slot_id)
+            .expect("unlocked through a key slot")
+    }
+
+    fn replace_slot(&mut self, slot: format_v1::KeySlot) {
+        if let Some(existing) = self.key_slots.iter_mut().find(|s| s.id == slot.id) {
+            *existing = slot;
+        }
+    }
+}
+
+fn unlock_vault_v1(
+    vault_bytes: &[u8],
+    credentials: &Credentials,
+) -> Result<UnlockedVaultV1, VaultError> {
+    let parsed = format_v2::parse_vault(vault_bytes)?;
+    let (dek, kek, slot) = unwrap_dek_with_credentials(&parsed, credentials)?;
+
+    let (payload, layout) = open_payload(&parsed, &dek, VaultError::AuthFailed, true)?;
+
+    let key_slots = if parsed.legacy_layout() {
+        // Legacy wrappings are bound to the whole header; re-wrap with the KEK
+        // we already derived so the next save can use the slot layout.
+        vec![wrap_key_slot(slot.clone(), &kek, &dek)?]
+    } else {
+        parsed.key_slots().to_vec()
+    };
+
+    Ok(UnlockedVaultV1 {
+        payload,
+        key_slots,
+        dek,
+        slot_id: Some(slot.id),
+        layout,
+    })
+}
+
+/// Try each key slot in turn; returns the DEK, the KEK that unwrapped it and its slot.
+pub(super) fn unwrap_dek_with_credentials<'h>(
+    parsed: &'h format_v2::ParsedVault<'_>,
+    credentials: &Credentials,
+) -> Result<
+    (
+        crypto::SecretBytes,
+        crypto::SecretBytes,
+        &'h format_v1::KeySlot,
+    ),
+    VaultError,
+> {
+    let mut skipped_for_keyfile = false;
+    for slot in parsed.key_slots() {
+        let keyfile = if slot.keyfile_required {
+            let Some(keyfile) = credentials.keyfile.as_ref() else {
+                skipped_for_keyfile = true;
+                continue;
+            };
+            Some(keyfile)
+        } else {
+            None
+        };
+
+        let kdf_out = crypto::derive_kdf_out_from_password(
+            &credentials.master_password,
+            &slot.kdf_salt,
+            slot.kdf_params,
+        )?;
+        let kek = crypto::derive_kek(&kdf_out, keyfile)?;
+        let wrap_aad = if let format_v2::ParsedVault::V1(v1) = parsed
+            && v1.header.legacy_layout
+        {
+            aad_for_v1(&v1.header)
+        } else {
+            format_v1::key_slot_aad(slot)
+        };
+
+        match crypto::unwrap_dek(&kek, &slot.wrap_nonce, &wrap_aad, &slot.wrapped_dek) {
+            Ok(dek) => return Ok((dek, kek, slot)),
+            Err(crypto::CryptoError::Aead) => continue,
+            Err(other) => return Err(VaultError::Crypto(other)),
+        }
+    }
+
+    Err(if skipped_for_keyfile {
+        VaultError::KeyfileRequired
+    } else {
+        VaultError::AuthFailed
+    })
+}
+
+fn unlock_with_key_v1(vault_bytes: &[u8], key: &VaultKey) -> Result<UnlockedVaultV1, VaultError> {
+    match key {
+        VaultKey::Credentials(credentials) => unlock_vault_v1(vault_bytes, credentials),
+        VaultKey::Session(dek) => unlock_vault_with_dek_v1(vault_bytes, dek),
+    }
+}
+
+fn unlock_vault_with_dek_v1(
+    vault_bytes: &[u8],
+    dek: &crypto::SecretBytes,
+) -> Result<UnlockedVaultV1, VaultError> {
+    let parsed = format_v2::parse_vault(vault_bytes)?;
+    if parsed.legacy_layout() {
+        // Writing would re-bind the legacy wrapping to a different AAD.
+        return Err(VaultError::StaleSessionKey);
+    }
+
+    let (payload, layout) = open_payload(&parsed, dek, VaultError::StaleSessionKey, true)?;
+    Ok(UnlockedVaultV1 {
+        payload,
+        key_slots: parsed.key_slots().to_vec(),
+        dek: dek.clone(),
+        slot_id: None,
+        layout,
+    })
+}
+
+/// Decrypt only what listing and searching need. In a v2 vault the secrets
+/// records stay sealed; a v1 payload has to be decrypted whole, and its
+/// secrets are dropped right away. Either way `secret` and `history` come
+/// back empty.
+fn unlock_metadata_with_key(
+    vault_bytes: &[u8],
+    key: &VaultKey,
+) -> Result<items::VaultPayloadV1, VaultError> {
+    let parsed = format_v2::parse_vault(vault_bytes)?;
+    let (payload, _) = match key {
+        VaultKey::Credentials(credentials) => {
+            let (dek, _, _) = unwrap_dek_with_credentials(&parsed, credentials)?;
+            open_payload(&parsed, &dek, VaultError::AuthFailed, false)?
+        }
+        VaultKey::Session(dek) => {
+            if parsed.legacy_layout() {
+                return Err(VaultError::StaleSessionKey);
+            }
+            open_payload(&parsed, dek, VaultError::StaleSessionKey, false)?
+        }
+    };
+    Ok(payload)
+}
+
+/// Decrypt the payload of either format; `auth_error` is returned when the
+/// DEK does not open it. Without `with_secrets`, secret fields are left empty.
+fn open_payload(
+    parsed: &format_v2::ParsedVault<'_>,
+    dek: &crypto::SecretBytes,
+    auth_error: VaultError,
+    with_secrets: bool,
+) -> Result<(items::VaultPayloadV1, PayloadLayout), VaultError> {
+    match parsed {
+        format_v2::ParsedVault::V1(parsed) => {
+            let mut payload = decrypt_payload_v1(parsed, dek, auth_error)?;
+            if !with_secrets {
+                payload
+                    .items
+                    .iter_mut()
+                    .for_each(items::VaultItemV1::strip_secrets);
+            }
+            Ok((payload, PayloadLayout::V1))
+        }
+        format_v2::ParsedVault::V2(parsed) => {
+            let (payload, sealed) = open_records_v2(parsed, dek, auth_error, with_secrets)?;
+            Ok((payload, PayloadLayout::V2(sealed)))
+        }
+    }
+}
+
+/// Decrypt and decode the payload; `auth_error` is returned when the tag does not verify.
+fn decrypt_payload_v1(
+    parsed: &format_v1::ParsedVaultV1<'_>,
+    dek: &crypto::SecretBytes,
+    auth_error: VaultError,
+) -> Result<items::VaultPayloadV1, VaultError> {
+    let aad = aad_for_v1(&parsed.header);
+    let plaintext = crypto::decrypt_payload(
+        dek,
         &parsed.header.payload_nonce,
         &aad,
         parsed.payload_ciphertext,
     )
     .map_err(|e| match e {
-        crypto::CryptoError::Aead => VaultError::AuthFailed,
+        crypto::CryptoError::Aead => auth_error,
         other => VaultError::Crypto(other),
     })?;
 
-    let payload: items::VaultPayloadV1 = serde_json::from_slice(&plaintext)?;
-    if payload.schema_version != 1 {
-        return Err(VaultError::UnsupportedPayloadSchema(payload.schema_version));
+    let mut payload: items::VaultPayloadV1 = serde_json::from_slice(&plaintext)?;
+    migrate_payload(&mut payload)?;
+    Ok(payload)
+}
+
+/// Open the manifest, check it lists exactly the item records present, then
+/// decrypt each item's metadata (and secrets, if asked).
+///
+/// `auth_error` is returned when the manifest does not open (wrong DEK); once
+/// it has, a record that fails to open or match the manifest means the file
+/// was modified.
+fn open_records_v2(
+    parsed: &format_v2::ParsedVaultV2,
+    dek: &crypto::SecretBytes,
+    auth_error: VaultError,
+    with_secrets: bool,
+) -> Result<(items::VaultPayloadV1, SealedItemsV2), VaultError> {
+    let manifest_key = crypto::derive_manifest_key(dek)?;
+    let manifest_json = open_record(&manifest_key, &parsed.manifest, &format_v2::manifest_aad())
+        .map_err(|e| match e {
+            VaultError::AuthFailed => auth_error,
+            other => other,
+        })?;
+    let manifest: format_v2::ManifestV2 = serde_json::from_slice(&manifest_json)?;
+
+    let digests = manifest
+        .items
+        .iter()
+        .map(|entry| (entry.id, (entry.meta.as_str(), entry.secrets.as_str())))
+        .collect::<HashMap<_, _>>();
+    if digests.len() != manifest.items.len() || digests.len() != parsed.items.len() {
+        return Err(VaultError::AuthFailed);
+    }
+
+    let mut items = Vec::with_capacity(parsed.items.len());
+    let mut cache = HashMap::with_capacity(parsed.items.len());
+    for sealed in &parsed.items {
+        let Some((meta_digest, secrets_digest)) = digests.get(&sealed.id) else {
+            return Err(VaultError::AuthFailed);
+        };
+        if *meta_digest != encode_digest(&sealed.meta.digest())
+            || *secrets_digest != encode_digest(&sealed.secrets.digest())
+        {
+            return Err(VaultError::AuthFailed);
+        }
+
+        let item_key = crypto::derive_item_key(dek, sealed.id.as_bytes())?;
+        let meta_json = open_record(
+            &item_key,
+            &sealed.meta,
+            &format_v2::item_meta_aad(&sealed.id),
+        )?;
+        let secrets_json = if with_secrets {
+            Some(open_record(
+                &item_key,
+                &sealed.secrets,
+                &format_v2::item_secrets_aad(&sealed.id),
+            )?)
+        } else {
+            None
+        };
+
+        let meta: items::JsonObject = serde_json::from_slice(&meta_json)?;
+        let secrets: Option<items::JsonObject> = secrets_json
+            .as_ref()
+            .map(|json| serde_json::from_slice(json))
+            .transpose()?;
+        let item = items::VaultItemV1::from_records_v2(meta, secrets)?;
+        if item.id != sealed.id {
+            return Err(VaultError::AuthFailed);
+        }
+        items.push(item);
+
+        if let Some(secrets_json) = secrets_json {
+            cache.insert(
+                sealed.id,
+                CachedItemV2 {
+                    sealed: sealed.clone(),
+                    meta_digest: crypto::record_digest(&[], &meta_json),
+                    secrets_digest: crypto::record_digest(&[], &secrets_json),
+                },
+            );
+        }
+    }
+
+    let mut payload = items::VaultPayloadV1 {
+        schema_version: manifest.schema_version,
+        items,
+        tombstones: manifest.tombstones,
+    };
+    migrate_payload(&mut payload)?;
+    Ok((
+        payload,
+        SealedItemsV2 {
+            dek: dek.clone(),
+            items: cache,
+        },
+    ))
+}
+
+fn open_record(
+    key: &crypto::SecretBytes,
+    record: &format_v2::SealedRecord,
+    aad: &[u8],
+) -> Result<zeroize::Zeroizing<Vec<u8>>, VaultError> {
+    crypto::decrypt_payload(key, &record.nonce, aad, &record.ciphertext).map_err(|e| match e {
+        crypto::CryptoError::Aead => VaultError::AuthFailed,
+        other => VaultError::Crypto(other),
+    })
+}
+
+fn seal_record(
+    key: &crypto::SecretBytes,
+    plaintext: &[u8],
+    aad: &[u8],
+) -> Result<format_v2::SealedRecord, VaultError> {
+    let nonce = crypto::random_bytes::<{ crypto::XCHACHA_NONCE_LEN }>();
+    let ciphertext = crypto::encrypt_payload(key, &nonce, aad, plaintext)?;
+    Ok(format_v2::SealedRecord { nonce, ciphertext })
+}
+
+pub(super) fn encode_digest(digest: &[u8; 32]) -> String {
+    base64::engine::general_purpose::STANDARD.encode(digest)
+}
+
+/// Bring an older payload up to the current schema in memory; the next write persists it.
+pub(super) fn migrate_payload(payload: &mut items::VaultPayloadV1) -> Result<(), VaultError> {
+    let mut version = payload.schema_version;
+    if version == 0 || version > items::CURRENT_PAYLOAD_SCHEMA_VERSION {
+        return Err(VaultError::UnsupportedPayloadSchema(version));
+    }
+
+    while version < items::CURRENT_PAYLOAD_SCHEMA_VERSION {
+        match version {
+            1 => {
+                // Items gained `history`, which defaults to empty.
+                version = 2;
+            }
+            2 => {
+                // Items gained `deleted_at`; nothing was trashed before.
+                version = 3;
+            }
+            3 => {
+                // The payload gained `tombstones`; nothing was purged before.
+                version = 4;
+            }
+            4 => {
+                // Items gained custom `fields`, which default to empty.
+                version = 5;
+            }
+            5 => {
+                // Items gained `attachments`, which default to empty.
+                version = 6;
+            }
+            6 => {
+                // Items gained `expires_at` and `rotate_every`, both unset,
+                // and `secret_changed_at`, which falls back to `created_at`.
+                version = 7;
+            }
+            _ => return Err(VaultError::UnsupportedPayloadSchema(version)),
+        }
     }
 
-    Ok((payload, parsed))
+    payload.schema_version = version;
+    Ok(())
 }
 
 fn seal_vault_v1(
-    kdf_params: crypto::KdfParams,
-    kdf_salt: [u8; 16],
-    master_password: &SecretString,
+    key_slots: &[format_v1::KeySlot],
+    dek: &crypto::SecretBytes,
     payload: &items::VaultPayloadV1,
 ) -> Result<Vec<u8>, VaultError> {
-    let wrap_nonce = crypto::random_bytes::<{ crypto::XCHACHA_NONCE_LEN }>();
-    let payload_nonce = crypto::random_bytes::<{ crypto::XCHACHA_NONCE_LEN }>();
-
-    let kdf_out = crypto::derive_kdf_out_from_password(master_password, &kdf_salt, kdf_params)?;
-    let kek = crypto::derive_kek(&kdf_out)?;
-    let dek = crypto::generate_dek();
-
-    let wrapped_dek_len = crypto::DEK_LEN + 16;
-    let placeholder_header = format_v1::VaultHeaderV1 {
-        kdf_params,
-        kdf_salt,
-        wrap_nonce,
-        wrapped_dek: vec![0u8; wrapped_dek_len],
-        payload_nonce,
-    };
-    let aad = format_v1::encode_header_v1(&placeholder_header);
-    let wrapped_dek = crypto::wrap_dek(&kek, &wrap_nonce, &aad, &dek)?;
     let header = format_v1::VaultHeaderV1 {
-        wrapped_dek,
-        ..placeholder_header
+        key_slots: key_slots.to_vec(),
+        payload_nonce: crypto::random_bytes::<{ crypto::XCHACHA_NONCE_LEN }>(),
+        legacy_layout: false,
     };
+    let aad = aad_for_v1(&header);
     let header_bytes = format_v1::encode_header_v1(&header);
 
     let payload_json = serde_json::to_vec(payload)?;
-    let payload_ciphertext = crypto::encrypt_payload(&dek, &payload_nonce, &aad, &payload_json)?;
+    let payload_ciphertext =
+        crypto::encrypt_payload(dek, &header.payload_nonce, &aad, &payload_json)?;
 
     let mut out = Vec::with_capacity(header_bytes.len() + payload_ciphertext.len());
     out.extend_from_slice(&header_bytes);
@@ -452,17 +2138,161 @@ fn seal_vault_v1(
     Ok(out)
 }
 
-fn aad_for_v1(header: &format_v1::VaultHeaderV1) -> Vec<u8> {
+/// Seal a v2 vault. Items whose plaintext matches a record in `reusable`
+/// (read under the same DEK) keep that record; everything else is sealed
+/// afresh, and so is the manifest.
+fn seal_vault_v2(
+    key_slots: &[format_v1::KeySlot],
+    dek: &crypto::SecretBytes,
+    payload: &items::VaultPayloadV1,
+    reusable: Option<&HashMap<Uuid, CachedItemV2>>,
+) -> Result<Vec<u8>, VaultError> {
+    let mut sealed_items = Vec::with_capacity(payload.items.len());
+    let mut entries = Vec::with_capacity(payload.items.len());
+    for item in &payload.items {
+        let (meta, secrets) = item.to_records_v2()?;
+        let meta_json = zeroize::Zeroizing::new(serde_json::to_vec(&meta)?);
+        let secrets_json = zeroize::Zeroizing::new(serde_json::to_vec(&secrets)?);
+        let cached = reusable.and_then(|cache| cache.get(&item.id));
+
+        let meta_record = match cached {
+            Some(c) if c.meta_digest == crypto::record_digest(&[], &meta_json) => {
+                c.sealed.meta.clone()
+            }
+            _ => seal_record(
+                &crypto::derive_item_key(dek, item.id.as_bytes())?,
+                &meta_json,
+                &format_v2::item_meta_aad(&item.id),
+            )?,
+        };
+        let secrets_record = match cached {
+            Some(c) if c.secrets_digest == crypto::record_digest(&[], &secrets_json) => {
+                c.sealed.secrets.clone()
+            }
+            _ => seal_record(
+                &crypto::derive_item_key(dek, item.id.as_bytes())?,
+                &secrets_json,
+                &format_v2::item_secrets_aad(&item.id),
+            )?,
+        };
+
+        entries.push(format_v2::ManifestEntry {
+            id: item.id,
+            meta: encode_digest(&meta_record.digest()),
+            secrets: encode_digest(&secrets_record.digest()),
+        });
+        sealed_items.push(format_v2::SealedItemV2 {
+            id: item.id,
+            meta: meta_record,
+            secrets: secrets_record,
+        });
+    }
+
+    let manifest = format_v2::ManifestV2 {
+        schema_version: payload.schema_version,
+        tombstones: payload.tombstones.clone(),
+        items: entries,
+    };
+    let manifest_record = seal_record(
+        &crypto::derive_manifest_key(dek)?,
+        &serde_json::to_vec(&manifest)?,
+        &format_v2::manifest_aad(),
+    )?;
+
+    let header = format_v2::VaultHeaderV2 {
+        key_slots: key_slots.to_vec(),
+    };
+    Ok(format_v2::encode_vault_v2(
+        &header,
+        &manifest_record,
+        &sealed_items,
+    ))
+}
+
+/// Create a key slot wrapping `dek` under a KEK derived from `credentials` with a fresh salt.
+///
+/// The slot requires a keyfile exactly when `credentials` carries one.
+fn new_key_slot(
+    id: u8,
+    label: String,
+    credentials: &Credentials,
+    kdf_params: crypto::KdfParams,
+    dek: &crypto::SecretBytes,
+) -> Result<format_v1::KeySlot, VaultError> {
+    let kdf_salt = crypto::random_bytes::<16>();
+    let kdf_out =
+        crypto::derive_kdf_out_from_password(&credentials.master_password, &kdf_salt, kdf_params)?;
+    let kek = crypto::derive_kek(&kdf_out, credentials.keyfile.as_ref())?;
+
+    let slot = format_v1::KeySlot {
+        id,
+        label,
+        keyfile_required: credentials.keyfile.is_some(),
+        kdf_params,
+        kdf_salt,
+        wrap_nonce: [0u8; crypto::XCHACHA_NONCE_LEN],
+        wrapped_dek: Vec::new(),
+    };
+    wrap_key_slot(slot, &kek, dek)
+}
+
+fn wrap_key_slot(
+    slot: format_v1::KeySlot,
+    kek: &crypto::SecretBytes,
+    dek: &crypto::SecretBytes,
+) -> Result<format_v1::KeySlot, VaultError> {
+    // v1: ciphertext length is plaintext length + 16-byte Poly1305 tag.
+    let placeholder = format_v1::KeySlot {
+        wrap_nonce: crypto::random_bytes::<{ crypto::XCHACHA_NONCE_LEN }>(),
+        wrapped_dek: vec![0u8; crypto::DEK_LEN + 16],
+        ..slot
+    };
+    let aad = format_v1::key_slot_aad(&placeholder);
+    let wrapped_dek = crypto::wrap_dek(kek, &placeholder.wrap_nonce, &aad, dek)?;
+    Ok(format_v1::KeySlot {
+        wrapped_dek,
+        ..placeholder
+    })
+}
+
+/// Payload AAD: the full header with every wrapped DEK ciphertext zeroed.
+pub(super) fn aad_for_v1(header: &format_v1::VaultHeaderV1) -> Vec<u8> {
     let placeholder = format_v1::VaultHeaderV1 {
-        kdf_params: header.kdf_params,
-        kdf_salt: header.kdf_salt,
-        wrap_nonce: header.wrap_nonce,
-        wrapped_dek: vec![0u8; header.wrapped_dek.len()],
+        key_slots: header
+            .key_slots
+            .iter()
+            .map(|s| format_v1::KeySlot {
+                wrapped_dek: vec![0u8; s.wrapped_dek.len()],
+                ..s.clone()
+            })
+            .collect(),
         payload_nonce: header.payload_nonce,
+        legacy_layout: header.legacy_layout,
     };
     format_v1::encode_header_v1(&placeholder)
 }
 
+pub(super) fn default_kdf_params() -> crypto::KdfParams {
+    if std::env::var_os(TEST_KDF_ENV).is_some() {
+        crypto::KdfParams::for_tests()
+    } else {
+        crypto::KdfParams::recommended()
+    }
+}
+
+/// Replace the vault file (the caller holds the lock) and, when the vault is
+/// synced, commit the new version. The file is written either way; a failed
+/// commit is still an error so the caller can tell the user.
+fn save_vault_bytes_unlocked(vault_path: &Path, bytes: &[u8]) -> Result<(), VaultError> {
+    io::write_vault_bytes_atomic_unlocked(vault_path, bytes)?;
+    let committed = match sync::GitRepo::open(vault_path) {
+        Ok(repo) => repo.commit_pending("Update vault").map(|_| ()),
+        Err(sync::SyncError::NotInitialized(_)) => Ok(()),
+        Err(error) => Err(error),
+    };
+    committed.map_err(|error| sync::SyncError::CommitAfterSave(Box::new(error)).into())
+}
+
 fn now_unix_seconds() -> u64 {
     SystemTime::now()
         .duration_since(UNIX_EPOCH)
@@ -470,6 +2300,18 @@ fn now_unix_seconds() -> u64 {
         .as_secs()
 }
 
+/// The live item `selector` names; see [`resolve_item_selector`].
+fn live_item_mut<'a>(
+    items: &'a mut [items::VaultItemV1],
+    selector: &str,
+) -> Result<&'a mut items::VaultItemV1, VaultError> {
+    let id = resolve_item_selector(items, selector)?;
+    Ok(items
+        .iter_mut()
+        .find(|i| i.id == id)
+        .expect("selectors resolve to listed items"))
+}
+
 fn item_sort_cmp(a: &items::VaultItemV1, b: &items::VaultItemV1) -> Ordering {
     let ap = a.path.as_deref().unwrap_or("");
     let bp = b.path.as_deref().unwrap_or("");
@@ -482,7 +2324,7 @@ fn item_sort_cmp(a: &items::VaultItemV1, b: &items::VaultItemV1) -> Ordering {
     }
 }
 
-fn normalize_tags(tags: Vec<String>) -> Vec<String> {
+pub(super) fn normalize_tags(tags: Vec<String>) -> Vec<String> {
     let mut out = tags
         .into_iter()
         .filter_map(|t| {
@@ -499,7 +2341,31 @@ fn normalize_tags(tags: Vec<String>) -> Vec<String> {
     out
 }
 
-fn normalize_urls(urls: Vec<String>) -> Vec<String> {
+/// `totp` seeds are stored as canonical `otpauth://` URIs and `ssh-key`
+/// secrets as re-encoded OpenSSH key blocks; anything that does not parse is
+/// rejected before it is saved.
+fn normalize_secret(item_type: items::VaultItemType, secret: String) -> Result<String, VaultError> {
+    match item_type {
+        items::VaultItemType::Totp => Ok(totp::Totp::parse(&secret)?.to_uri()),
+        items::VaultItemType::SshKey => Ok(ssh_key::SshKey::parse(&secret)?.to_openssh()),
+        _ => Ok(secret),
+    }
+}
+
+/// Trim field names and keep the last value given for a repeated name.
+fn normalize_fields(fields: Vec<items::VaultItemField>) -> Vec<items::VaultItemField> {
+    let mut out: Vec<items::VaultItemField> = Vec::with_capacity(fields.len());
+    for mut field in fields {
+        field.name = field.name.trim().to_string();
+        match out.iter_mut().find(|f| f.name == field.name) {
+            Some(existing) => *existing = field,
+            None => out.push(field),
+        }
+    }
+    out
+}
+
+pub(super) fn normalize_urls(urls: Vec<String>) -> Vec<String> {
     let mut out = urls
         .into_iter()
         .filter_map(|u| {
@@ -516,30 +2382,592 @@ fn normalize_urls(urls: Vec<String>) -> Vec<String> {
     out
 }
 
-fn item_matches_query(item: &items::VaultItemV1, q: &str) -> bool {
-    if item.name.to_lowercase().contains(q) {
-        return true;
+/// A single-slot vault sealed with cheap KDF params, for tests in sibling modules.
+#[cfg(test)]
+pub(super) fn test_vault_bytes(
+    credentials: &Credentials,
+    payload: &items::VaultPayloadV1,
+) -> Vec<u8> {
+    let dek = crypto::generate_dek();
+    let slot = new_key_slot(
+        0,
+        DEFAULT_SLOT_LABEL.to_string(),
+        credentials,
+        crypto::KdfParams::for_tests(),
+        &dek,
+    )
+    .unwrap();
+    seal_vault_v1(&[slot], &dek, payload).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Build a vault the way the original single-wrapper writer did.
+    fn legacy_vault_bytes(credentials: &Credentials, payload: &items::VaultPayloadV1) -> Vec<u8> {
+        let dek = crypto::generate_dek();
+        let kdf_params = crypto::KdfParams::for_tests();
+        let kdf_salt = crypto::random_bytes::<16>();
+        let kdf_out = crypto::derive_kdf_out_from_password(
+            &credentials.master_password,
+            &kdf_salt,
+            kdf_params,
+        )
+        .unwrap();
+        let kek = crypto::derive_kek(&kdf_out, None).unwrap();
+
+        let mut header = format_v1::VaultHeaderV1 {
+            key_slots: vec![format_v1::KeySlot {
+                id: 0,
+                label: DEFAULT_SLOT_LABEL.to_string(),
+                keyfile_required: false,
+                kdf_params,
+                kdf_salt,
+                wrap_nonce: crypto::random_bytes::<{ crypto::XCHACHA_NONCE_LEN }>(),
+                wrapped_dek: vec![0u8; crypto::DEK_LEN + 16],
+            }],
+            payload_nonce: crypto::random_bytes::<{ crypto::XCHACHA_NONCE_LEN }>(),
+            legacy_layout: true,
+        };
+        let aad = format_v1::encode_header_v1(&header);
+        let wrap_nonce = header.key_slots[0].wrap_nonce;
+        header.key_slots[0].wrapped_dek = crypto::wrap_dek(&kek, &wrap_nonce, &aad, &dek).unwrap();
+
+        let json = serde_json::to_vec(payload).unwrap();
+        let ct = crypto::encrypt_payload(&dek, &header.payload_nonce, &aad, &json).unwrap();
+        let mut out = format_v1::encode_header_v1(&header);
+        out.extend_from_slice(&ct);
+        out
     }
-    if let Some(path) = &item.path
-        && path.to_lowercase().contains(q)
-    {
-        return true;
+
+    fn password(s: &str) -> Credentials {
+        Credentials {
+            master_password: SecretString::new(s.to_string().into_boxed_str()),
+            keyfile: None,
+        }
     }
-    if item.tags.iter().any(|t| t.contains(q)) {
-        return true;
+
+    #[test]
+    fn legacy_vault_unlocks_and_is_rewritten_in_slot_layout() {
+        let pw = password("pw");
+        let bytes = legacy_vault_bytes(&pw, &items::VaultPayloadV1::default());
+
+        let unlocked = unlock_vault_v1(&bytes, &pw).unwrap();
+        assert_eq!(unlocked.slot_id, Some(0));
+
+        let resealed = unlocked.seal().unwrap();
+        let parsed = format_v1::parse_vault_v1(&resealed).unwrap();
+        assert!(!parsed.header.legacy_layout);
+        assert!(unlock_vault_v1(&resealed, &pw).is_ok());
+        assert!(matches!(
+            unlock_vault_v1(&resealed, &password("nope")),
+            Err(VaultError::AuthFailed)
+        ));
     }
-    if let Some(username) = &item.username
-        && username.to_lowercase().contains(q)
-    {
-        return true;
+
+    #[test]
+    fn any_key_slot_unlocks_the_same_payload() {
+        let dek = crypto::generate_dek();
+        let params = crypto::KdfParams::for_tests();
+        let slots = vec![
+            new_key_slot(0, "master".into(), &password("a"), params, &dek).unwrap(),
+            new_key_slot(1, "team".into(), &password("b"), params, &dek).unwrap(),
+        ];
+        let bytes = seal_vault_v1(&slots, &dek, &items::VaultPayloadV1::default()).unwrap();
+
+        assert_eq!(
+            unlock_vault_v1(&bytes, &password("a")).unwrap().slot_id,
+            Some(0)
+        );
+        assert_eq!(
+            unlock_vault_v1(&bytes, &password("b")).unwrap().slot_id,
+            Some(1)
+        );
+        assert!(matches!(
+            unlock_vault_v1(&bytes, &password("c")),
+            Err(VaultError::AuthFailed)
+        ));
     }
-    if item.urls.iter().any(|u| u.to_lowercase().contains(q)) {
-        return true;
+
+    #[test]
+    fn session_key_unlocks_until_dek_changes_and_refuses_legacy_layout() {
+        let dek = crypto::generate_dek();
+        let slot = new_key_slot(
+            0,
+            "master".into(),
+            &password("a"),
+            crypto::KdfParams::for_tests(),
+            &dek,
+        )
+        .unwrap();
+        let bytes = seal_vault_v1(&[slot], &dek, &items::VaultPayloadV1::default()).unwrap();
+
+        let unlocked = unlock_with_key_v1(&bytes, &VaultKey::Session(dek.clone())).unwrap();
+        assert_eq!(unlocked.slot_id, None);
+        assert!(matches!(
+            unlock_with_key_v1(&bytes, &VaultKey::Session(crypto::generate_dek())),
+            Err(VaultError::StaleSessionKey)
+        ));
+
+        let legacy = legacy_vault_bytes(&password("a"), &items::VaultPayloadV1::default());
+        let legacy_dek = unlock_vault_v1(&legacy, &password("a")).unwrap().dek;
+        assert!(matches!(
+            unlock_with_key_v1(&legacy, &VaultKey::Session(legacy_dek)),
+            Err(VaultError::StaleSessionKey)
+        ));
     }
-    if let Some(notes) = &item.notes
-        && notes.to_lowercase().contains(q)
-    {
-        return true;
+
+    #[test]
+    fn keyfile_slot_reports_missing_keyfile_and_rejects_wrong_one() {
+        let dek = crypto::generate_dek();
+        let with_keyfile = Credentials {
+            keyfile: Some(crypto::keyfile_digest(b"usb stick")),
+            ..password("a")
+        };
+        let slot = new_key_slot(
+            0,
+            "master".into(),
+            &with_keyfile,
+            crypto::KdfParams::for_tests(),
+            &dek,
+        )
+        .unwrap();
+        let bytes = seal_vault_v1(&[slot], &dek, &items::VaultPayloadV1::default()).unwrap();
+
+        assert!(unlock_vault_v1(&bytes, &with_keyfile).is_ok());
+        assert!(matches!(
+            unlock_vault_v1(&bytes, &password("a")),
+            Err(VaultError::KeyfileRequired)
+        ));
+        let wrong_keyfile = Credentials {
+            keyfile: Some(crypto::keyfile_digest(b"other file")),
+            ..password("a")
+        };
+        assert!(matches!(
+            unlock_vault_v1(&bytes, &wrong_keyfile),
+            Err(VaultError::AuthFailed)
+        ));
+    }
+
+    #[test]
+    fn schema_1_payload_is_upgraded_on_unlock() {
+        let dek = crypto::generate_dek();
+        let slot = new_key_slot(
+            0,
+            "master".into(),
+            &password("a"),
+            crypto::KdfParams::for_tests(),
+            &dek,
+        )
+        .unwrap();
+        let payload = items::VaultPayloadV1 {
+            schema_version: 1,
+            ..Default::default()
+        };
+        let bytes = seal_vault_v1(&[slot], &dek, &payload).unwrap();
+
+        let unlocked = unlock_vault_v1(&bytes, &password("a")).unwrap();
+        assert_eq!(
+            unlocked.payload.schema_version,
+            items::CURRENT_PAYLOAD_SCHEMA_VERSION
+        );
+
+        let future = items::VaultPayloadV1 {
+            schema_version: items::CURRENT_PAYLOAD_SCHEMA_VERSION + 1,
+            ..Default::default()
+        };
+        let slot = new_key_slot(
+            0,
+            "master".into(),
+            &password("a"),
+            crypto::KdfParams::for_tests(),
+            &dek,
+        )
+        .unwrap();
+        let bytes = seal_vault_v1(&[slot], &dek, &future).unwrap();
+        assert!(matches!(
+            unlock_vault_v1(&bytes, &password("a")),
+            Err(VaultError::UnsupportedPayloadSchema(_))
+        ));
+    }
+
+    #[test]
+    fn item_history_is_bounded_and_skips_no_op_edits() {
+        let mut item = items::VaultItemV1 {
+            id: Uuid::new_v4(),
+            item_type: items::VaultItemType::Login,
+            name: "github".into(),
+            path: None,
+            tags: Vec::new(),
+            username: None,
+            secret: "v0".into(),
+            urls: Vec::new(),
+            notes: None,
+            fields: Vec::new(),
+            attachments: Vec::new(),
+            expires_at: None,
+            rotate_every: None,
+            secret_changed_at: None,
+            created_at: 0,
+            updated_at: 0,
+            history: Vec::new(),
+            deleted_at: None,
+        };
+
+        let previous = item.snapshot();
+        item.push_history(previous);
+        assert!(item.history.is_empty());
+
+        for n in 1..=(items::MAX_ITEM_HISTORY as u32 + 5) {
+            let previous = item.snapshot();
+            item.secret = format!("v{n}");
+            item.push_history(previous);
+        }
+        assert_eq!(item.history.len(), items::MAX_ITEM_HISTORY);
+        assert_eq!(item.history[0].rev, 6);
+        assert_eq!(item.revision(), items::MAX_ITEM_HISTORY as u32 + 6);
+
+        let oldest = item.history[0].clone();
+        item.apply_revision(&oldest, &[]);
+        assert_eq!(item.secret, "v5");
+    }
+
+    #[test]
+    fn item_history_keeps_attachment_metadata_but_not_contents() {
+        let report = items::VaultAttachment {
+            name: "report.pdf".into(),
+            size: 3,
+            sha256: sha256_hex(b"pdf"),
+            added_at: 1,
+            data: base64::engine::general_purpose::STANDARD.encode(b"pdf"),
+        };
+        let mut item = test_item("github", "s");
+        item.attachments.push(report.clone());
+
+        let with_report = item.snapshot();
+        assert_eq!(with_report.attachments, vec![report.info()]);
+        let json = serde_json::to_string(&with_report).unwrap();
+        assert!(!json.contains(&report.data));
+
+        item.attachments.clear();
+        item.push_history(with_report.clone());
+        let detached = item.snapshot();
+        item.apply_revision(&with_report, &[]);
+        assert!(item.attachments.is_empty());
+        item.apply_revision(&with_report, std::slice::from_ref(&report));
+        assert_eq!(item.attachments, vec![report]);
+        item.apply_revision(&detached, &[]);
+        assert!(item.attachments.is_empty());
+    }
+
+    #[test]
+    fn item_selector_resolves_paths_names_and_id_prefixes() {
+        let mut db = test_item("POSTGRES_PASSWORD", "s");
+        db.path = Some("prod/db".into());
+        let mut staging = test_item("POSTGRES_PASSWORD", "s");
+        staging.path = Some("staging/db".into());
+        let github = test_item("github", "s");
+        let mut trashed = test_item("github", "s");
+        trashed.deleted_at = Some(1);
+        let items = vec![db.clone(), staging.clone(), github.clone(), trashed.clone()];
+
+        let resolve = |selector: &str| resolve_item_selector(&items, selector);
+        assert_eq!(resolve("prod/db/POSTGRES_PASSWORD").unwrap(), db.id);
+        assert_eq!(
+            resolve("/staging/db/POSTGRES_PASSWORD").unwrap(),
+            staging.id
+        );
+        assert_eq!(resolve("github").unwrap(), github.id);
+        assert_eq!(resolve(&github.id.to_string()).unwrap(), github.id);
+        let prefix = &db.id.simple().to_string()[..8];
+        assert_eq!(resolve(&prefix.to_uppercase()).unwrap(), db.id);
+
+        match resolve("POSTGRES_PASSWORD") {
+            Err(VaultError::AmbiguousItem { candidates, .. }) => {
+                assert_eq!(candidates.len(), 2);
+                assert!(candidates.contains(&format!("{}\tprod/db/POSTGRES_PASSWORD", db.id)));
+            }
+            other => panic!("expected an ambiguity error, got {other:?}"),
+        }
+        assert!(matches!(
+            resolve(&trashed.id.to_string()),
+            Err(VaultError::ItemNotFound(_))
+        ));
+        assert!(matches!(
+            resolve("prod/db"),
+            Err(VaultError::ItemNotFound(_))
+        ));
+        assert!(matches!(
+            resolve(&prefix[..3]),
+            Err(VaultError::ItemNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn item_due_takes_the_nearest_of_expiry_and_rotation() {
+        const DAY: u64 = 24 * 60 * 60;
+        let mut item = test_item("token", "s");
+        item.created_at = 10 * DAY;
+        assert_eq!(item.due(100 * DAY, 14 * DAY), None);
+
+        // Rotation counts from `created_at` until the secret is changed.
+        item.rotate_every = Some(90 * DAY);
+        let due = item.due(95 * DAY, 14 * DAY).unwrap();
+        assert_eq!(due.reason, items::DueReason::Rotation);
+        assert_eq!(due.due_at, 100 * DAY);
+        assert_eq!(due.state, items::DueState::DueSoon);
+        assert_eq!(
+            item.due(80 * DAY, 14 * DAY).unwrap().state,
+            items::DueState::Ok
+        );
+
+        item.note_secret_change("s", 120 * DAY);
+        assert_eq!(item.due(121 * DAY, 0).unwrap().due_at, 100 * DAY);
+        item.secret = "rotated".into();
+        item.note_secret_change("s", 120 * DAY);
+        assert_eq!(item.due(121 * DAY, 0).unwrap().due_at, 210 * DAY);
+
+        item.expires_at = Some(150 * DAY);
+        let due = item.due(150 * DAY, 0).unwrap();
+        assert_eq!(due.reason, items::DueReason::Expiry);
+        assert_eq!(due.state, items::DueState::Overdue);
+    }
+
+    fn test_item(name: &str, secret: &str) -> items::VaultItemV1 {
+        items::VaultItemV1 {
+            id: Uuid::new_v4(),
+            item_type: items::VaultItemType::Login,
+            name: name.into(),
+            path: None,
+            tags: Vec::new(),
+            username: None,
+            secret: secret.into(),
+            urls: Vec::new(),
+            notes: None,
+            fields: Vec::new(),
+            attachments: Vec::new(),
+            expires_at: None,
+            rotate_every: None,
+            secret_changed_at: None,
+            created_at: 0,
+            updated_at: 0,
+            history: Vec::new(),
+            deleted_at: None,
+        }
+    }
+
+    #[test]
+    fn v2_save_reseals_only_changed_records() {
+        let pw = password("pw");
+        let dek = crypto::generate_dek();
+        let slot = new_key_slot(
+            0,
+            DEFAULT_SLOT_LABEL.to_string(),
+            &pw,
+            crypto::KdfParams::for_tests(),
+            &dek,
+        )
+        .unwrap();
+        let payload = items::VaultPayloadV1 {
+            items: vec![test_item("a", "alpha"), test_item("b", "bravo")],
+            ..items::VaultPayloadV1::default()
+        };
+        let bytes = seal_vault_v2(&[slot], &dek, &payload, None).unwrap();
+
+        let mut unlocked = unlock_vault_v1(&bytes, &pw).unwrap();
+        unlocked.payload.items[0].name = "a2".into();
+        let resealed = unlocked.seal().unwrap();
+
+        let records = |bytes: &[u8]| match format_v2::parse_vault(bytes).unwrap() {
+            format_v2::ParsedVault::V2(parsed) => parsed.items,
+            format_v2::ParsedVault::V1(_) => panic!("expected a v2 vault"),
+        };
+        let (before, after) = (records(&bytes), records(&resealed));
+        assert_ne!(before[0].meta.digest(), after[0].meta.digest());
+        assert_eq!(before[0].secrets.digest(), after[0].secrets.digest());
+        assert_eq!(before[1].meta.digest(), after[1].meta.digest());
+        assert_eq!(before[1].secrets.digest(), after[1].secrets.digest());
+
+        let metadata = unlock_metadata_with_key(&resealed, &VaultKey::Credentials(pw)).unwrap();
+        assert_eq!(metadata.items[0].name, "a2");
+        assert!(metadata.items.iter().all(|i| i.secret.is_empty()));
+    }
+
+    #[test]
+    fn v2_rejects_records_swapped_between_items() {
+        let pw = password("pw");
+        let dek = crypto::generate_dek();
+        let slot = new_key_slot(
+            0,
+            DEFAULT_SLOT_LABEL.to_string(),
+            &pw,
+            crypto::KdfParams::for_tests(),
+            &dek,
+        )
+        .unwrap();
+        let payload = items::VaultPayloadV1 {
+            items: vec![test_item("a", "alpha"), test_item("b", "bravo")],
+            ..items::VaultPayloadV1::default()
+        };
+        let bytes = seal_vault_v2(std::slice::from_ref(&slot), &dek, &payload, None).unwrap();
+        let format_v2::ParsedVault::V2(mut parsed) = format_v2::parse_vault(&bytes).unwrap() else {
+            panic!("expected a v2 vault");
+        };
+
+        let secrets = parsed.items[0].secrets.clone();
+        parsed.items[0].secrets = parsed.items[1].secrets.clone();
+        parsed.items[1].secrets = secrets;
+        let swapped = format_v2::encode_vault_v2(&parsed.header, &parsed.manifest, &parsed.items);
+        assert!(matches!(
+            unlock_vault_v1(&swapped, &pw),
+            Err(VaultError::AuthFailed)
+        ));
+
+        parsed.items.pop();
+        let dropped = format_v2::encode_vault_v2(&parsed.header, &parsed.manifest, &parsed.items);
+        assert!(matches!(
+            unlock_vault_v1(&dropped, &pw),
+            Err(VaultError::AuthFailed)
+        ));
+    }
+
+    #[test]
+    fn upgrade_rewrites_a_v1_vault_as_v2_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("vault.pwder");
+        let pw = password("pw");
+        let payload = items::VaultPayloadV1 {
+            items: vec![test_item("a", "alpha")],
+            ..items::VaultPayloadV1::default()
+        };
+        std::fs::write(&path, test_vault_bytes(&pw, &payload)).unwrap();
+        let key = VaultKey::Credentials(password("pw"));
+
+        let report = vault_upgrade_v1(&path, &key).unwrap();
+        assert_eq!((report.from_version, report.to_version), (1, 2));
+        assert_eq!(report.items, 1);
+        assert_eq!(vault_status_v1(&path).unwrap().version, Some(2));
+        let items = vault_list_items_v1(&path, &key).unwrap();
+        assert_eq!(items[0].secret, "alpha");
+
+        let report = vault_upgrade_v1(&path, &key).unwrap();
+        assert_eq!(report.from_version, 2);
+    }
+
+    #[test]
+    fn import_overwrite_dates_a_replaced_secret() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("vault.pwder");
+        let pw = password("pw");
+        let mut github = test_item("github", "old");
+        github.secret_changed_at = Some(1);
+        let mut npm = test_item("npm", "same");
+        npm.secret_changed_at = Some(1);
+        let payload = items::VaultPayloadV1 {
+            items: vec![github.clone(), npm.clone()],
+            ..items::VaultPayloadV1::default()
+        };
+        std::fs::write(&path, test_vault_bytes(&pw, &payload)).unwrap();
+        let key = VaultKey::Credentials(password("pw"));
+
+        github.secret = "new".into();
+        npm.notes = Some("moved to the org account".into());
+        let summary = vault_import_items_v1(
+            &path,
+            &key,
+            vec![github, npm],
+            ConflictPolicy::Overwrite,
+            false,
+        )
+        .unwrap();
+        assert_eq!(summary.overwritten, 2);
+
+        let items = vault_list_items_v1(&path, &key).unwrap();
+        let changed_at = |name: &str| {
+            items
+                .iter()
+                .find(|i| i.name == name)
+                .and_then(|i| i.secret_changed_at)
+        };
+        assert!(changed_at("github") > Some(1));
+        assert_eq!(changed_at("npm"), Some(1));
+    }
+
+    #[test]
+    fn import_gives_a_purged_item_a_new_id() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("vault.pwder");
+        let pw = password("pw");
+        let github = test_item("github", "s");
+        let payload = items::VaultPayloadV1 {
+            tombstones: vec![items::VaultTombstone {
+                id: github.id,
+                deleted_at: 1,
+            }],
+            ..items::VaultPayloadV1::default()
+        };
+        std::fs::write(&path, test_vault_bytes(&pw, &payload)).unwrap();
+        let key = VaultKey::Credentials(password("pw"));
+
+        let summary = vault_import_items_v1(
+            &path,
+            &key,
+            vec![github.clone()],
+            ConflictPolicy::Skip,
+            false,
+        )
+        .unwrap();
+        assert_eq!(summary.added, 1);
+
+        let items = vault_list_items_v1(&path, &key).unwrap();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].name, "github");
+        assert_ne!(items[0].id, github.id);
+    }
+
+    #[test]
+    fn item_filter_matches_path_subtree_tags_and_type() {
+        let mut item = items::VaultItemV1 {
+            id: Uuid::new_v4(),
+            item_type: items::VaultItemType::ApiToken,
+            name: "stripe".into(),
+            path: Some("services/api".into()),
+            tags: vec!["prod".into(), "billing".into()],
+            username: None,
+            secret: "s".into(),
+            urls: Vec::new(),
+            notes: None,
+            fields: Vec::new(),
+            attachments: Vec::new(),
+            expires_at: None,
+            rotate_every: None,
+            secret_changed_at: None,
+            created_at: 0,
+            updated_at: 0,
+            history: Vec::new(),
+            deleted_at: None,
+        };
+        let filter = |path: Option<&str>, tags: &[&str]| ItemFilter {
+            path: path.map(str::to_string),
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            item_type: None,
+        };
+
+        assert!(ItemFilter::default().matches(&item));
+        assert!(filter(Some("services"), &[]).matches(&item));
+        assert!(filter(Some("services/api/"), &["PROD"]).matches(&item));
+        assert!(!filter(Some("serv"), &[]).matches(&item));
+        assert!(!filter(None, &["prod", "dev"]).matches(&item));
+        assert!(
+            !ItemFilter {
+                item_type: Some(items::VaultItemType::Login),
+                ..ItemFilter::default()
+            }
+            .matches(&item)
+        );
+
+        item.path = None;
+        assert!(!filter(Some("services"), &[]).matches(&item));
     }
-    false
 }
@@ -16,31 +16,73 @@ pub enum PromptError {
 }
 
 pub fn prompt_new_master_password() -> Result<SecretString, PromptError> {
-    let first = read_secret_line("Master password: ")?;
-    if first.is_empty() {
+    prompt_confirmed_password("Master password: ", "Confirm master password: ")
+}
+
+pub fn prompt_replacement_master_password() -> Result<SecretString, PromptError> {
+    prompt_confirmed_password("New master password: ", "Confirm new master password: ")
+}
+
+pub fn prompt_new_slot_password() -> Result<SecretString, PromptError> {
+    prompt_confirmed_password("New slot password: ", "Confirm slot password: ")
+}
+
+pub fn prompt_new_bundle_passphrase() -> Result<SecretString, PromptError> {
+    prompt_confirmed_password("Export passphrase: ", "Confirm export passphrase: ")
+}
+
+pub fn prompt_bundle_passphrase() -> Result<SecretString, PromptError> {
+    prompt_password("Bundle passphrase: ")
+}
+
+pub fn prompt_master_password() -> Result<SecretString, PromptError> {
+    prompt_password("Master password: ")
+}
+
+pub fn prompt_current_master_password() -> Result<SecretString, PromptError> {
+    prompt_password("Current master password: ")
+}
+
+pub fn prompt_recovery_key() -> Result<SecretString, PromptError> {
+    prompt_password("Recovery key: ")
+}
+
+pub fn prompt_secret(label: &str) -> Result<String, PromptError> {
+    let value = read_secret_line(label)?;
+    if value.is_empty() {
         return Err(PromptError::Empty);
     }
-    let confirm = read_secret_line("Confirm master password: ")?;
-    if first != confirm {
-        return Err(PromptError::Mismatch);
-    }
-    Ok(SecretString::new(first.into_boxed_str()))
+    Ok(value)
 }
 
-pub fn prompt_master_password() -> Result<SecretString, PromptError> {
-    let pw = read_secret_line("Master password: ")?;
+/// Read one visible line, e.g. an answer to a question.
+pub fn prompt_line(label: &str) -> Result<String, PromptError> {
+    eprint!("{label}");
+    io::stderr().flush()?;
+    read_line_plain()
+}
+
+fn prompt_password(label: &str) -> Result<SecretString, PromptError> {
+    let pw = read_secret_line(label)?;
     if pw.is_empty() {
         return Err(PromptError::Empty);
     }
     Ok(SecretString::new(pw.into_boxed_str()))
 }
 
-pub fn prompt_secret(label: &str) -> Result<String, PromptError> {
-    let value = read_secret_line(label)?;
-    if value.is_empty() {
+fn prompt_confirmed_password(
+    label: &str,
+    confirm_label: &str,
+) -> Result<SecretString, PromptError> {
+    let first = read_secret_line(label)?;
+    if first.is_empty() {
         return Err(PromptError::Empty);
     }
-    Ok(value)
+    let confirm = read_secret_line(confirm_label)?;
+    if first != confirm {
+        return Err(PromptError::Mismatch);
+    }
+    Ok(SecretString::new(first.into_boxed_str()))
 }
 
 fn read_secret_line(prompt: &str) -> Result<String, PromptError> {
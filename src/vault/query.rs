@@ -0,0 +1,718 @@
+//! Query language for `vault search` and `vault list`.
+//!
+//! A query is a list of terms, all of which must match:
+//!
+//! ```text
+//! type:api-token tag:prod path:services/* name~"stripe" -tag:deprecated updated<30d
+//! ```
+//!
+//! - A bare word or `"quoted phrase"` matches as a case-insensitive substring
+//!   of the name, path, tags, username, URLs or notes.
+//! - `FIELD:VALUE` matches a field exactly (case-insensitively); `*` in the
+//!   value matches any run of characters. `path:a/b` also matches everything
+//!   below `a/b`.
+//! - `FIELD~VALUE` matches a substring of the field.
+//! - `created` and `updated` compare with `<` and `>`: against a duration
+//!   they compare age (`updated<30d` is "changed in the last 30 days"),
+//!   against a `YYYY-MM-DD` date they compare time (`created<2024-01-01`).
+//! - Terms combine with `AND` (implied between terms), `OR` and `NOT` / a
+//!   leading `-`, grouped with parentheses. `AND` binds tighter than `OR`.
+//!
+//! Only metadata is consulted, so queries run without decrypting secrets.
+
+use super::items::{VaultItemType, VaultItemV1};
+use crate::timespec::{parse_date, parse_duration};
+use clap::ValueEnum;
+use thiserror::Error;
+
+/// Fields a `FIELD:VALUE` term can name.
+const FIELDS: &[&str] = &[
+    "type", "tag", "path", "name", "username", "url", "notes", "id", "created", "updated",
+];
+
+/// How deeply parentheses and `NOT`s may nest. The parser and matcher
+/// recurse once per level, so this keeps a hostile query from exhausting the
+/// stack.
+const MAX_DEPTH: usize = 32;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+#[error("invalid query at column {column}: {message}")]
+pub struct QueryError {
+    /// 1-based, in characters.
+    pub column: usize,
+    pub message: String,
+}
+
+/// A parsed query; see the module docs for the syntax.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ItemQuery {
+    expr: Expr,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Term(Term),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Term {
+    /// Bare word: substring of any text field. Lowercased.
+    Any(String),
+    Type(VaultItemType),
+    Text(TextField, Pattern),
+    Time {
+        field: TimeField,
+        bound: TimeBound,
+        /// `>` rather than `<`.
+        greater: bool,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TextField {
+    Tag,
+    Path,
+    Name,
+    Username,
+    Url,
+    Notes,
+    Id,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TimeField {
+    Created,
+    Updated,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TimeBound {
+    /// Seconds of age.
+    Age(u64),
+    /// Unix time.
+    At(u64),
+}
+
+/// Lowercased value of a text term.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Pattern {
+    /// `:`, exact unless it contains `*`.
+    Glob(String),
+    /// `~`.
+    Contains(String),
+}
+
+impl ItemQuery {
+    pub fn parse(input: &str) -> Result<Self, QueryError> {
+        let tokens = lex(input)?;
+        let mut parser = Parser {
+            input,
+            tokens,
+            next: 0,
+            depth: 0,
+        };
+        let expr = parser.parse_or()?;
+        match parser.peek() {
+            None => Ok(Self { expr }),
+            Some(token) if token.kind == TokenKind::RParen => {
+                Err(parser.error_at(token.offset, "unmatched ')'"))
+            }
+            Some(token) => Err(parser.error_at(token.offset, "expected a search term")),
+        }
+    }
+
+    /// Whether `item` matches; `now` (unix seconds) anchors `created` and
+    /// `updated` ages.
+    pub fn matches(&self, item: &VaultItemV1, now: u64) -> bool {
+        self.expr.matches(item, now)
+    }
+}
+
+impl Expr {
+    fn matches(&self, item: &VaultItemV1, now: u64) -> bool {
+        match self {
+            Expr::And(a, b) => a.matches(item, now) && b.matches(item, now),
+            Expr::Or(a, b) => a.matches(item, now) || b.matches(item, now),
+            Expr::Not(e) => !e.matches(item, now),
+            Expr::Term(term) => term.matches(item, now),
+        }
+    }
+}
+
+impl Term {
+    fn matches(&self, item: &VaultItemV1, now: u64) -> bool {
+        match self {
+            Term::Any(q) => matches_any_field(item, q),
+            Term::Type(t) => item.item_type == *t,
+            Term::Text(field, pattern) => {
+                let id;
+                let values: Vec<&str> = match field {
+                    TextField::Tag => item.tags.iter().map(String::as_str).collect(),
+                    TextField::Path => item.path.as_deref().into_iter().collect(),
+                    TextField::Name => vec![item.name.as_str()],
+                    TextField::Username => item.username.as_deref().into_iter().collect(),
+                    TextField::Url => item.urls.iter().map(String::as_str).collect(),
+                    TextField::Notes => item.notes.as_deref().into_iter().collect(),
+                    TextField::Id => {
+                        id = item.id.to_string();
+                        vec![id.as_str()]
+                    }
+                };
+                values
+                    .iter()
+                    .any(|value| pattern.matches(&value.to_lowercase(), *field == TextField::Path))
+            }
+            Term::Time {
+                field,
+                bound,
+                greater,
+            } => {
+                let at = match field {
+                    TimeField::Created => item.created_at,
+                    TimeField::Updated => item.updated_at,
+                };
+                match (bound, greater) {
+                    (TimeBound::Age(age), false) => now.saturating_sub(at) < *age,
+                    (TimeBound::Age(age), true) => now.saturating_sub(at) > *age,
+                    (TimeBound::At(bound), false) => at < *bound,
+                    (TimeBound::At(bound), true) => at > *bound,
+                }
+            }
+        }
+    }
+}
+
+impl Pattern {
+    /// `value` is lowercased. A plain `:` path also matches the subtree.
+    fn matches(&self, value: &str, subtree: bool) -> bool {
+        match self {
+            Pattern::Contains(needle) => value.contains(needle.as_str()),
+            Pattern::Glob(glob) if !glob.contains('*') => {
+                value == glob
+                    || (subtree
+                        && value
+                            .strip_prefix(glob.trim_end_matches('/'))
+                            .is_some_and(|rest| rest.starts_with('/')))
+            }
+            Pattern::Glob(glob) => glob_matches(glob, value),
+        }
+    }
+}
+
+/// `*` matches any run of characters, including `/`.
+fn glob_matches(glob: &str, value: &str) -> bool {
+    let mut parts = glob.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = value.strip_prefix(first) else {
+        return false;
+    };
+    let parts = parts.collect::<Vec<_>>();
+    let Some((last, middle)) = parts.split_last() else {
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(at) => rest = &rest[at + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+/// The bare-word match: a substring of any searchable field.
+fn matches_any_field(item: &VaultItemV1, q: &str) -> bool {
+    item.name.to_lowercase().contains(q)
+        || item
+            .path
+            .as_ref()
+            .is_some_and(|p| p.to_lowercase().contains(q))
+        || item.tags.iter().any(|t| t.to_lowercase().contains(q))
+        || item
+            .username
+            .as_ref()
+            .is_some_and(|u| u.to_lowercase().contains(q))
+        || item.urls.iter().any(|u| u.to_lowercase().contains(q))
+        || item
+            .notes
+            .as_ref()
+            .is_some_and(|n| n.to_lowercase().contains(q))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Token {
+    kind: TokenKind,
+    /// Byte offset into the query.
+    offset: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum TokenKind {
+    LParen,
+    RParen,
+    And,
+    Or,
+    Not,
+    /// A bare word or phrase.
+    Word(String),
+    /// `FIELD OP VALUE`; `value_offset` points at the value for errors.
+    Field {
+        field: String,
+        op: char,
+        value: String,
+        value_offset: usize,
+    },
+}
+
+fn lex(input: &str) -> Result<Vec<Token>, QueryError> {
+    let mut tokens = Vec::new();
+    let mut chars = input.char_indices().peekable();
+
+    while let Some(&(offset, c)) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' | ')' => {
+                chars.next();
+                let kind = if c == '(' {
+                    TokenKind::LParen
+                } else {
+                    TokenKind::RParen
+                };
+                tokens.push(Token { kind, offset });
+            }
+            '-' => {
+                chars.next();
+                match chars.peek() {
+                    Some(&(_, next)) if !next.is_whitespace() && next != ')' => {
+                        tokens.push(Token {
+                            kind: TokenKind::Not,
+                            offset,
+                        });
+                    }
+                    _ => tokens.push(Token {
+                        kind: TokenKind::Word("-".to_string()),
+                        offset,
+                    }),
+                }
+            }
+            '"' => {
+                let phrase = lex_quoted(input, &mut chars)?;
+                tokens.push(Token {
+                    kind: TokenKind::Word(phrase),
+                    offset,
+                });
+            }
+            _ => {
+                let head = take_while(input, &mut chars, |c| {
+                    !c.is_whitespace() && !"()\":~<>".contains(c)
+                });
+                let op = chars
+                    .peek()
+                    .map(|&(_, c)| c)
+                    .filter(|c| ":~<>".contains(*c));
+                let is_field = op.is_some()
+                    && !head.is_empty()
+                    && head.chars().all(|c| c.is_ascii_alphabetic())
+                    && !input[offset + head.len()..].starts_with("://");
+
+                let kind = match op {
+                    Some(op) if is_field => {
+                        chars.next();
+                        let value_offset = chars.peek().map_or(input.len(), |&(o, _)| o);
+                        let value = if chars.peek().is_some_and(|&(_, c)| c == '"') {
+                            lex_quoted(input, &mut chars)?
+                        } else {
+                            take_while(input, &mut chars, |c| {
+                                !c.is_whitespace() && !"()".contains(c)
+                            })
+                            .to_string()
+                        };
+                        TokenKind::Field {
+                            field: head.to_string(),
+                            op,
+                            value,
+                            value_offset,
+                        }
+                    }
+                    _ => {
+                        let rest = take_while(input, &mut chars, |c| {
+                            !c.is_whitespace() && !"()".contains(c)
+                        });
+                        let word = format!("{head}{rest}");
+                        match word.as_str() {
+                            "AND" => TokenKind::And,
+                            "OR" => TokenKind::Or,
+                            "NOT" => TokenKind::Not,
+                            _ => TokenKind::Word(word),
+                        }
+                    }
+                };
+                tokens.push(Token { kind, offset });
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+type Chars<'a> = std::iter::Peekable<std::str::CharIndices<'a>>;
+
+fn take_while<'a>(input: &'a str, chars: &mut Chars<'_>, keep: impl Fn(char) -> bool) -> &'a str {
+    let start = chars.peek().map_or(input.len(), |&(o, _)| o);
+    let mut end = start;
+    while let Some(&(offset, c)) = chars.peek() {
+        if !keep(c) {
+            break;
+        }
+        end = offset + c.len_utf8();
+        chars.next();
+    }
+    &input[start..end]
+}
+
+/// A `"..."` phrase, with `\"` and `\\` escapes; `chars` is at the opening quote.
+fn lex_quoted(input: &str, chars: &mut Chars<'_>) -> Result<String, QueryError> {
+    let (start, _) = chars.next().expect("at an opening quote");
+    let mut out = String::new();
+    while let Some((_, c)) = chars.next() {
+        match c {
+            '"' => return Ok(out),
+            '\\' => match chars.next() {
+                Some((_, escaped)) => out.push(escaped),
+                None => break,
+            },
+            c => out.push(c),
+        }
+    }
+    Err(QueryError {
+        column: column(input, start),
+        message: "unterminated quote".to_string(),
+    })
+}
+
+fn column(input: &str, offset: usize) -> usize {
+    input[..offset].chars().count() + 1
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    tokens: Vec<Token>,
+    next: usize,
+    /// Open parentheses and `NOT`s enclosing the current position.
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.next)
+    }
+
+    fn bump(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.next).cloned();
+        self.next += 1;
+        token
+    }
+
+    fn error_at(&self, offset: usize, message: impl Into<String>) -> QueryError {
+        QueryError {
+            column: column(self.input, offset),
+            message: message.into(),
+        }
+    }
+
+    /// Parse `parse` one nesting level down, failing past [`MAX_DEPTH`].
+    fn nested<T>(
+        &mut self,
+        offset: usize,
+        parse: impl FnOnce(&mut Self) -> Result<T, QueryError>,
+    ) -> Result<T, QueryError> {
+        if self.depth == MAX_DEPTH {
+            return Err(self.error_at(
+                offset,
+                format!("query nests more than {MAX_DEPTH} levels deep"),
+            ));
+        }
+        self.depth += 1;
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
+
+    fn parse_or(&mut self) -> Result<Expr, QueryError> {
+        let mut left = self.parse_and()?;
+        while self.peek().is_some_and(|t| t.kind == TokenKind::Or) {
+            self.bump();
+            let right = self.parse_and()?;
+            left = Expr::Or(Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, QueryError> {
+        let mut left = self.parse_unary()?;
+        loop {
+            match self.peek().map(|t| &t.kind) {
+                Some(TokenKind::And) => {
+                    self.bump();
+                }
+                Some(TokenKind::Or | TokenKind::RParen) | None => return Ok(left),
+                Some(_) => {}
+            }
+            let right = self.parse_unary()?;
+            left = Expr::And(Box::new(left), Box::new(right));
+        }
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, QueryError> {
+        if let Some(token) = self.peek().filter(|t| t.kind == TokenKind::Not) {
+            let offset = token.offset;
+            self.bump();
+            let inner = self.nested(offset, Self::parse_unary)?;
+            return Ok(Expr::Not(Box::new(inner)));
+        }
+        self.parse_primary()
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, QueryError> {
+        let Some(token) = self.bump() else {
+            return Err(self.error_at(self.input.len(), "expected a search term"));
+        };
+        match token.kind {
+            TokenKind::LParen => {
+                let inner = self.nested(token.offset, Self::parse_or)?;
+                match self.bump() {
+                    Some(Token {
+                        kind: TokenKind::RParen,
+                        ..
+                    }) => Ok(inner),
+                    _ => Err(self.error_at(token.offset, "'(' is never closed")),
+                }
+            }
+            TokenKind::Word(word) => Ok(Expr::Term(Term::Any(word.to_lowercase()))),
+            TokenKind::Field {
+                field,
+                op,
+                value,
+                value_offset,
+            } => self
+                .field_term(&field, op, value, token.offset, value_offset)
+                .map(Expr::Term),
+            TokenKind::RParen => Err(self.error_at(token.offset, "unmatched ')'")),
+            TokenKind::And | TokenKind::Or | TokenKind::Not => {
+                Err(self.error_at(token.offset, "expected a search term before this operator"))
+            }
+        }
+    }
+
+    fn field_term(
+        &self,
+        field: &str,
+        op: char,
+        value: String,
+        offset: usize,
+        value_offset: usize,
+    ) -> Result<Term, QueryError> {
+        let name = field.to_ascii_lowercase();
+        if !FIELDS.contains(&name.as_str()) {
+            return Err(self.error_at(
+                offset,
+                format!(
+                    "unknown field '{field}' (expected one of: {})",
+                    FIELDS.join(", ")
+                ),
+            ));
+        }
+        if value.is_empty() {
+            return Err(self.error_at(value_offset, format!("missing value after '{field}{op}'")));
+        }
+
+        let text_field = match name.as_str() {
+            "type" => {
+                if op != ':' {
+                    return Err(self.error_at(offset, format!("use type:TYPE, not type{op}")));
+                }
+                return VaultItemType::from_str(&value, true)
+                    .map(Term::Type)
+                    .map_err(|_| {
+                        let types = VaultItemType::value_variants()
+                            .iter()
+                            .filter_map(|t| t.to_possible_value())
+                            .map(|v| v.get_name().to_string())
+                            .collect::<Vec<_>>();
+                        self.error_at(
+                            value_offset,
+                            format!(
+                                "unknown item type '{value}' (expected one of: {})",
+                                types.join(", ")
+                            ),
+                        )
+                    });
+            }
+            "created" | "updated" => {
+                let field = if name == "created" {
+                    TimeField::Created
+                } else {
+                    TimeField::Updated
+                };
+                if op != '<' && op != '>' {
+                    return Err(self.error_at(
+                        offset,
+                        format!("compare {name} with < or >, e.g. {name}<30d"),
+                    ));
+                }
+                let bound = if value.contains('-') {
+                    parse_date(&value).map(TimeBound::At)
+                } else {
+                    parse_duration(&value).map(|d| TimeBound::Age(d.as_secs()))
+                }
+                .map_err(|message| self.error_at(value_offset, message))?;
+                return Ok(Term::Time {
+                    field,
+                    bound,
+                    greater: op == '>',
+                });
+            }
+            "tag" => TextField::Tag,
+            "path" => TextField::Path,
+            "name" => TextField::Name,
+            "username" => TextField::Username,
+            "url" => TextField::Url,
+            "notes" => TextField::Notes,
+            "id" => TextField::Id,
+            _ => unreachable!("checked against FIELDS"),
+        };
+
+        let value = value.to_lowercase();
+        let pattern = match op {
+            ':' => Pattern::Glob(value),
+            '~' => Pattern::Contains(value),
+            _ => {
+                return Err(self.error_at(
+                    offset,
+                    format!(
+                        "'{op}' only compares created and updated; use {name}:VALUE or {name}~VALUE"
+                    ),
+                ));
+            }
+        };
+        Ok(Term::Text(text_field, pattern))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: u64 = 24 * 60 * 60;
+    const NOW: u64 = 1_000 * DAY;
+
+    fn item(
+        name: &str,
+        path: Option<&str>,
+        tags: &[&str],
+        item_type: VaultItemType,
+    ) -> VaultItemV1 {
+        VaultItemV1 {
+            id: uuid::Uuid::new_v4(),
+            item_type,
+            name: name.into(),
+            path: path.map(Into::into),
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            username: None,
+            secret: String::new(),
+            urls: vec!["https://dashboard.stripe.com".into()],
+            notes: None,
+            fields: Vec::new(),
+            attachments: Vec::new(),
+            expires_at: None,
+            rotate_every: None,
+            secret_changed_at: None,
+            created_at: NOW - 100 * DAY,
+            updated_at: NOW - 10 * DAY,
+            history: Vec::new(),
+            deleted_at: None,
+        }
+    }
+
+    fn matches(query: &str, item: &VaultItemV1) -> bool {
+        ItemQuery::parse(query)
+            .expect("valid query")
+            .matches(item, NOW)
+    }
+
+    #[test]
+    fn field_terms_combine_with_implicit_and() {
+        let stripe = item(
+            "Stripe live key",
+            Some("services/payments"),
+            &["prod"],
+            VaultItemType::ApiToken,
+        );
+        let query =
+            r#"type:api-token tag:prod path:services/* name~"stripe" -tag:deprecated updated<30d"#;
+        assert!(matches(query, &stripe));
+
+        assert!(!matches("type:login", &stripe));
+        assert!(!matches("updated>30d", &stripe));
+        assert!(matches("created>30d", &stripe));
+        assert!(matches("path:services", &stripe));
+        assert!(!matches("path:serv", &stripe));
+        assert!(matches("name:stripe*key", &stripe));
+        assert!(!matches("name:stripe", &stripe));
+        assert!(matches("url:https://*.stripe.com", &stripe));
+        assert!(matches("created<1980-01-01", &stripe));
+        assert!(!matches("created<1972-01-01", &stripe));
+    }
+
+    #[test]
+    fn boolean_operators_group_and_negate() {
+        let prod = item("db", None, &["prod"], VaultItemType::Login);
+        let old = item("db", None, &["deprecated"], VaultItemType::Login);
+
+        assert!(matches("tag:staging OR tag:prod", &prod));
+        assert!(!matches("tag:staging OR tag:prod", &old));
+        assert!(matches("NOT tag:prod", &old));
+        assert!(matches("db -(tag:prod OR tag:staging)", &old));
+        assert!(!matches("db AND -(tag:prod OR tag:deprecated)", &old));
+        // AND binds tighter than OR.
+        assert!(matches("tag:prod OR tag:none AND tag:missing", &prod));
+        // Bare words keep the old substring behaviour, URLs included.
+        assert!(matches("DASHBOARD", &prod));
+        assert!(matches("https://dashboard", &prod));
+    }
+
+    #[test]
+    fn parse_errors_point_at_the_problem() {
+        let error = |query: &str| ItemQuery::parse(query).unwrap_err();
+
+        assert_eq!(error("nmae:x").column, 1);
+        assert!(error("nmae:x").message.starts_with("unknown field 'nmae'"));
+        assert_eq!(error("type:password").column, 6);
+        assert!(error("type:password").message.contains("api-token"));
+        assert_eq!(error("tag:a (tag:b").column, 7);
+        assert_eq!(error("tag:a )").message, "unmatched ')'");
+        assert_eq!(error(r#"name~"open"#).message, "unterminated quote");
+        assert!(error("updated:30d").message.contains("updated<30d"));
+        assert!(
+            error("name<3")
+                .message
+                .contains("only compares created and updated")
+        );
+        assert_eq!(error("tag:a OR").column, 9);
+        assert_eq!(error("OR tag:a").column, 1);
+        assert!(error("tag:").message.contains("missing value"));
+        assert!(error("updated<soon").message.contains("invalid duration"));
+
+        let nested = |depth: usize| format!("{}tag:a{}", "(".repeat(depth), ")".repeat(depth));
+        assert!(ItemQuery::parse(&nested(MAX_DEPTH)).is_ok());
+        assert_eq!(error(&nested(MAX_DEPTH + 1)).column, MAX_DEPTH + 1);
+        assert!(
+            error(&format!("{}tag:a", "-".repeat(MAX_DEPTH + 1)))
+                .message
+                .contains("nests more than")
+        );
+    }
+}
@@ -0,0 +1,248 @@
+//! Git plumbing for `vault sync`.
+//!
+//! The vault's directory doubles as a git repository that tracks only the
+//! vault file. Git never writes the vault file itself: incoming versions are
+//! read with `git show` and written by the vault layer (keeping `0600` and a
+//! backup), then the branch is moved with `update-ref`. Merges happen item by
+//! item in the vault layer because the ciphertext changes completely on
+//! every save.
+
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use thiserror::Error;
+
+/// `git config` key naming the vault file a repository syncs.
+const VAULT_CONFIG_KEY: &str = "passworder.vault";
+pub const REMOTE: &str = "origin";
+const DEFAULT_BRANCH: &str = "main";
+const FALLBACK_IDENTITY_NAME: &str = "passworder";
+const FALLBACK_IDENTITY_EMAIL: &str = "passworder@localhost";
+
+#[derive(Debug, Error)]
+pub enum SyncError {
+    #[error("failed to run git: {0}")]
+    Spawn(std::io::Error),
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    #[error("`git {command}` failed: {stderr}")]
+    Git { command: String, stderr: String },
+
+    #[error("vault sync is not set up for {0} (run `vault sync init`)")]
+    NotInitialized(String),
+
+    #[error("remote branch has no vault file {0}")]
+    MissingRemoteVault(String),
+
+    #[error("vault saved, but committing it for sync failed (run `vault sync` to retry): {0}")]
+    CommitAfterSave(Box<SyncError>),
+}
+
+/// A repository rooted at the vault's directory.
+pub struct GitRepo {
+    dir: PathBuf,
+    /// Vault file name relative to `dir`.
+    file: String,
+    /// Author/committer to use when git has no identity configured.
+    fallback_identity: bool,
+}
+
+impl GitRepo {
+    fn new(vault_path: &Path) -> Option<Self> {
+        let dir = vault_path.parent()?;
+        let dir = if dir.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            dir
+        };
+        let file = vault_path.file_name()?.to_str()?.to_string();
+        Some(Self {
+            dir: dir.to_path_buf(),
+            file,
+            fallback_identity: false,
+        })
+    }
+
+    /// The sync repository for `vault_path`, if `vault sync init` set one up.
+    pub fn open(vault_path: &Path) -> Result<Self, SyncError> {
+        let not_initialized = || SyncError::NotInitialized(vault_path.display().to_string());
+        let mut repo = Self::new(vault_path).ok_or_else(not_initialized)?;
+        if !repo.dir.join(".git").exists() {
+            return Err(not_initialized());
+        }
+        match repo.output(&["config", "--get", VAULT_CONFIG_KEY]) {
+            Ok(out) if out.status.success() && trimmed(&out.stdout) == repo.file => {}
+            _ => return Err(not_initialized()),
+        }
+        repo.fallback_identity = !repo.probe(&["config", "--get", "user.email"])?;
+        Ok(repo)
+    }
+
+    /// Create (or adopt) the repository and mark it as syncing `vault_path`.
+    pub fn init(vault_path: &Path, remote: Option<&str>) -> Result<Self, SyncError> {
+        let mut repo = Self::new(vault_path)
+            .ok_or_else(|| SyncError::NotInitialized(vault_path.display().to_string()))?;
+        std::fs::create_dir_all(&repo.dir)?;
+        if !repo.dir.join(".git").exists() {
+            repo.run(&["init", "-q", "-b", DEFAULT_BRANCH])?;
+        }
+        repo.fallback_identity = !repo.probe(&["config", "--get", "user.email"])?;
+        repo.run(&["config", VAULT_CONFIG_KEY, &repo.file.clone()])?;
+
+        // Track nothing but the vault file and this ignore list, so backups,
+        // lock files and temp files never reach the remote. An existing list
+        // belongs to whoever set the repository up.
+        let ignore_path = repo.dir.join(".gitignore");
+        if !ignore_path.exists() {
+            std::fs::write(ignore_path, format!("*\n!.gitignore\n!{}\n", repo.file))?;
+        }
+
+        if let Some(remote) = remote {
+            if repo.probe(&["remote", "get-url", REMOTE])? {
+                repo.run(&["remote", "set-url", REMOTE, remote])?;
+            } else {
+                repo.run(&["remote", "add", REMOTE, remote])?;
+            }
+        }
+        Ok(repo)
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn file(&self) -> &str {
+        &self.file
+    }
+
+    pub fn has_remote(&self) -> Result<bool, SyncError> {
+        self.probe(&["remote", "get-url", REMOTE])
+    }
+
+    pub fn remote_url(&self) -> Result<Option<String>, SyncError> {
+        let out = self.output(&["remote", "get-url", REMOTE])?;
+        Ok(out.status.success().then(|| trimmed(&out.stdout)))
+    }
+
+    pub fn branch(&self) -> Result<String, SyncError> {
+        Ok(trimmed(&self.run(&["symbolic-ref", "--short", "HEAD"])?))
+    }
+
+    pub fn remote_ref(&self) -> Result<String, SyncError> {
+        Ok(format!("refs/remotes/{REMOTE}/{}", self.branch()?))
+    }
+
+    /// Commit the vault file if it changed since the last commit. Returns
+    /// whether a commit was made.
+    pub fn commit_pending(&self, message: &str) -> Result<bool, SyncError> {
+        self.stage()?;
+        if self.probe(&["diff", "--cached", "--quiet"])? {
+            return Ok(false);
+        }
+        self.run(&["commit", "-q", "--no-verify", "-m", message])?;
+        Ok(true)
+    }
+
+    /// Fetch the remote branch; returns its commit if it exists.
+    pub fn fetch(&self) -> Result<Option<String>, SyncError> {
+        self.run(&["fetch", "-q", REMOTE])?;
+        self.rev_parse(&self.remote_ref()?)
+    }
+
+    pub fn rev_parse(&self, rev: &str) -> Result<Option<String>, SyncError> {
+        let out = self.output(&["rev-parse", "--verify", "-q", &format!("{rev}^{{commit}}")])?;
+        Ok(out.status.success().then(|| trimmed(&out.stdout)))
+    }
+
+    pub fn is_ancestor(&self, ancestor: &str, descendant: &str) -> Result<bool, SyncError> {
+        self.probe(&["merge-base", "--is-ancestor", ancestor, descendant])
+    }
+
+    pub fn merge_base(&self, a: &str, b: &str) -> Result<Option<String>, SyncError> {
+        let out = self.output(&["merge-base", a, b])?;
+        Ok(out.status.success().then(|| trimmed(&out.stdout)))
+    }
+
+    /// The vault file as of `commit`, if it existed then.
+    pub fn vault_at(&self, commit: &str) -> Result<Option<Vec<u8>>, SyncError> {
+        let out = self.output(&["show", &format!("{commit}:{}", self.file)])?;
+        Ok(out.status.success().then_some(out.stdout))
+    }
+
+    /// Point the current branch at `commit` and match the index to it,
+    /// leaving the working tree (the vault file) alone.
+    pub fn move_head(&self, commit: &str) -> Result<(), SyncError> {
+        self.run(&["update-ref", "HEAD", commit])?;
+        self.run(&["reset", "-q"])?;
+        Ok(())
+    }
+
+    /// Record the working vault file as a merge of HEAD and `other`.
+    pub fn commit_merge(&self, other: &str, message: &str) -> Result<String, SyncError> {
+        self.stage()?;
+        let tree = trimmed(&self.run(&["write-tree"])?);
+        let commit = trimmed(&self.run(&[
+            "commit-tree",
+            &tree,
+            "-p",
+            "HEAD",
+            "-p",
+            other,
+            "-m",
+            message,
+        ])?);
+        self.move_head(&commit)?;
+        Ok(commit)
+    }
+
+    pub fn push(&self) -> Result<(), SyncError> {
+        let branch = self.branch()?;
+        self.run(&["push", "-q", REMOTE, &format!("HEAD:refs/heads/{branch}")])?;
+        Ok(())
+    }
+
+    fn stage(&self) -> Result<(), SyncError> {
+        let mut add = vec!["add", "--"];
+        for path in [".gitignore", self.file.as_str()] {
+            if self.dir.join(path).exists() {
+                add.push(path);
+            }
+        }
+        self.run(&add)?;
+        Ok(())
+    }
+
+    fn run(&self, args: &[&str]) -> Result<Vec<u8>, SyncError> {
+        let out = self.output(args)?;
+        if !out.status.success() {
+            return Err(SyncError::Git {
+                command: args.first().copied().unwrap_or_default().to_string(),
+                stderr: String::from_utf8_lossy(&out.stderr).trim().to_string(),
+            });
+        }
+        Ok(out.stdout)
+    }
+
+    /// Run a git command whose exit status is the answer.
+    fn probe(&self, args: &[&str]) -> Result<bool, SyncError> {
+        Ok(self.output(args)?.status.success())
+    }
+
+    fn output(&self, args: &[&str]) -> Result<Output, SyncError> {
+        let mut command = Command::new("git");
+        command.arg("-C").arg(&self.dir).args(args);
+        if self.fallback_identity {
+            command
+                .env("GIT_AUTHOR_NAME", FALLBACK_IDENTITY_NAME)
+                .env("GIT_AUTHOR_EMAIL", FALLBACK_IDENTITY_EMAIL)
+                .env("GIT_COMMITTER_NAME", FALLBACK_IDENTITY_NAME)
+                .env("GIT_COMMITTER_EMAIL", FALLBACK_IDENTITY_EMAIL);
+        }
+        command.output().map_err(SyncError::Spawn)
+    }
+}
+
+fn trimmed(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).trim().to_string()
+}
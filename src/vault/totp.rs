@@ -0,0 +1,490 @@
+//! RFC 6238 time-based one-time passwords for `totp` items.
+//!
+//! A `totp` item's secret is stored as a canonical `otpauth://totp/` URI, so
+//! the algorithm, digit count and period are sealed together with the seed.
+//! `vault add` accepts such a URI (as exported by most sites' QR codes) or a
+//! bare base32 seed, which gets the usual SHA1 / 6 digits / 30 s defaults.
+
+use super::crypto;
+use sha2::{Digest, Sha256, Sha512};
+use std::fmt;
+use thiserror::Error;
+use zeroize::Zeroizing;
+
+const URI_PREFIX: &str = "otpauth://";
+const DEFAULT_DIGITS: u32 = 6;
+const DEFAULT_PERIOD: u64 = 30;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum TotpError {
+    #[error("invalid otpauth URI: {0}")]
+    InvalidUri(&'static str),
+
+    #[error("unsupported OTP type {0} (only totp is supported)")]
+    UnsupportedType(String),
+
+    #[error("TOTP secret is missing or not valid base32")]
+    InvalidSecret,
+
+    #[error("unsupported TOTP algorithm {0} (expected SHA1, SHA256 or SHA512)")]
+    UnsupportedAlgorithm(String),
+
+    #[error("unsupported TOTP digit count {0} (expected 6 or 8)")]
+    InvalidDigits(String),
+
+    #[error("invalid TOTP period {0} (expected a positive number of seconds)")]
+    InvalidPeriod(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TotpAlgorithm {
+    Sha1,
+    Sha256,
+    Sha512,
+}
+
+impl TotpAlgorithm {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TotpAlgorithm::Sha1 => "SHA1",
+            TotpAlgorithm::Sha256 => "SHA256",
+            TotpAlgorithm::Sha512 => "SHA512",
+        }
+    }
+
+    fn parse(value: &str) -> Result<Self, TotpError> {
+        match value.to_ascii_uppercase().replace('-', "").as_str() {
+            "SHA1" => Ok(TotpAlgorithm::Sha1),
+            "SHA256" => Ok(TotpAlgorithm::Sha256),
+            "SHA512" => Ok(TotpAlgorithm::Sha512),
+            _ => Err(TotpError::UnsupportedAlgorithm(value.to_string())),
+        }
+    }
+
+    fn block_len(self) -> usize {
+        match self {
+            TotpAlgorithm::Sha1 | TotpAlgorithm::Sha256 => 64,
+            TotpAlgorithm::Sha512 => 128,
+        }
+    }
+
+    fn digest(self, parts: &[&[u8]]) -> Vec<u8> {
+        match self {
+            TotpAlgorithm::Sha1 => sha1(&Zeroizing::new(parts.concat())).to_vec(),
+            TotpAlgorithm::Sha256 => {
+                let mut hasher = Sha256::new();
+                parts.iter().for_each(|p| hasher.update(p));
+                hasher.finalize().to_vec()
+            }
+            TotpAlgorithm::Sha512 => {
+                let mut hasher = Sha512::new();
+                parts.iter().for_each(|p| hasher.update(p));
+                hasher.finalize().to_vec()
+            }
+        }
+    }
+}
+
+/// A TOTP generator parsed from an item secret.
+pub struct Totp {
+    secret: Zeroizing<Vec<u8>>,
+    pub algorithm: TotpAlgorithm,
+    pub digits: u32,
+    pub period: u64,
+    pub issuer: Option<String>,
+    pub account: Option<String>,
+}
+
+/// The code for one time step.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TotpCode {
+    pub code: String,
+    /// Seconds until the next code.
+    pub remaining: u64,
+}
+
+impl fmt::Debug for Totp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Totp")
+            .field("secret", &"<redacted>")
+            .field("algorithm", &self.algorithm)
+            .field("digits", &self.digits)
+            .field("period", &self.period)
+            .field("issuer", &self.issuer)
+            .field("account", &self.account)
+            .finish()
+    }
+}
+
+impl Totp {
+    /// Parse an `otpauth://totp/` URI or a bare base32 seed.
+    pub fn parse(input: &str) -> Result<Self, TotpError> {
+        let input = input.trim();
+        match input.get(..URI_PREFIX.len()) {
+            Some(prefix) if prefix.eq_ignore_ascii_case(URI_PREFIX) => {
+                Self::parse_uri(&input[URI_PREFIX.len()..])
+            }
+            _ => Ok(Self::with_defaults(decode_secret(input)?)),
+        }
+    }
+
+    fn with_defaults(secret: Zeroizing<Vec<u8>>) -> Self {
+        Self {
+            secret,
+            algorithm: TotpAlgorithm::Sha1,
+            digits: DEFAULT_DIGITS,
+            period: DEFAULT_PERIOD,
+            issuer: None,
+            account: None,
+        }
+    }
+
+    fn parse_uri(rest: &str) -> Result<Self, TotpError> {
+        let (otp_type, rest) = rest
+            .split_once('/')
+            .ok_or(TotpError::InvalidUri("missing label"))?;
+        if !otp_type.eq_ignore_ascii_case("totp") {
+            return Err(TotpError::UnsupportedType(otp_type.to_string()));
+        }
+        let (label, query) = rest.split_once('?').unwrap_or((rest, ""));
+
+        // The label is `account` or `issuer:account`; an `issuer` parameter
+        // takes precedence over the prefix.
+        let label = percent_decode(label)?;
+        let (mut issuer, account) = match label.split_once(':') {
+            Some((issuer, account)) => (Some(issuer.trim().to_string()), account.trim()),
+            None => (None, label.trim()),
+        };
+
+        let mut totp = Self::with_defaults(Zeroizing::new(Vec::new()));
+        let mut secret = None;
+        for pair in query.split('&').filter(|p| !p.is_empty()) {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            let value = Zeroizing::new(percent_decode(value)?);
+            match name.to_ascii_lowercase().as_str() {
+                "secret" => secret = Some(decode_secret(&value)?),
+                "algorithm" => totp.algorithm = TotpAlgorithm::parse(&value)?,
+                "digits" => {
+                    totp.digits = match value.parse() {
+                        Ok(digits @ (6 | 8)) => digits,
+                        _ => return Err(TotpError::InvalidDigits(value.to_string())),
+                    }
+                }
+                "period" => {
+                    totp.period = match value.parse() {
+                        Ok(period) if period > 0 => period,
+                        _ => return Err(TotpError::InvalidPeriod(value.to_string())),
+                    }
+                }
+                "issuer" => issuer = Some(value.to_string()),
+                // Parameters such as `image` or `counter` do not affect codes.
+                _ => {}
+            }
+        }
+
+        totp.secret = secret.ok_or(TotpError::InvalidSecret)?;
+        totp.issuer = issuer.filter(|i| !i.is_empty());
+        totp.account = (!account.is_empty()).then(|| account.to_string());
+        Ok(totp)
+    }
+
+    /// The canonical URI stored as the item secret.
+    pub fn to_uri(&self) -> String {
+        let label = match (&self.issuer, &self.account) {
+            (Some(issuer), Some(account)) => {
+                format!("{}:{}", percent_encode(issuer), percent_encode(account))
+            }
+            (Some(issuer), None) => format!("{}:", percent_encode(issuer)),
+            (None, Some(account)) => percent_encode(account),
+            (None, None) => String::new(),
+        };
+        let mut uri = format!(
+            "{URI_PREFIX}totp/{label}?secret={}&algorithm={}&digits={}&period={}",
+            crypto::base32_encode(&self.secret),
+            self.algorithm.as_str(),
+            self.digits,
+            self.period
+        );
+        if let Some(issuer) = &self.issuer {
+            uri.push_str("&issuer=");
+            uri.push_str(&percent_encode(issuer));
+        }
+        uri
+    }
+
+    /// The code valid at `unix_seconds`.
+    pub fn at(&self, unix_seconds: u64) -> TotpCode {
+        TotpCode {
+            code: self.hotp(unix_seconds / self.period),
+            remaining: self.period - unix_seconds % self.period,
+        }
+    }
+
+    /// RFC 4226 HOTP with dynamic truncation.
+    fn hotp(&self, counter: u64) -> String {
+        let mac = hmac(self.algorithm, &self.secret, &counter.to_be_bytes());
+        let offset = usize::from(mac[mac.len() - 1] & 0x0f);
+        let binary = u32::from_be_bytes(
+            mac[offset..offset + 4]
+                .try_into()
+                .expect("offset leaves four bytes"),
+        ) & 0x7fff_ffff;
+        format!(
+            "{:0width$}",
+            binary % 10u32.pow(self.digits),
+            width = self.digits as usize
+        )
+    }
+}
+
+/// Base32 seeds are often shown lower case, grouped or padded.
+fn decode_secret(input: &str) -> Result<Zeroizing<Vec<u8>>, TotpError> {
+    let compact = Zeroizing::new(
+        input
+            .chars()
+            .filter(|c| !c.is_whitespace() && *c != '-' && *c != '=')
+            .map(|c| c.to_ascii_uppercase())
+            .collect::<String>(),
+    );
+    match crypto::base32_decode(&compact) {
+        Some(secret) if !secret.is_empty() => Ok(Zeroizing::new(secret)),
+        _ => Err(TotpError::InvalidSecret),
+    }
+}
+
+fn hmac(algorithm: TotpAlgorithm, key: &[u8], message: &[u8]) -> Vec<u8> {
+    let block_len = algorithm.block_len();
+    let mut block = Zeroizing::new(vec![0u8; block_len]);
+    if key.len() > block_len {
+        let digest = algorithm.digest(&[key]);
+        block[..digest.len()].copy_from_slice(&digest);
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+    let pad = |byte: u8| Zeroizing::new(block.iter().map(|b| b ^ byte).collect::<Vec<_>>());
+
+    let inner = algorithm.digest(&[&pad(0x36), message]);
+    algorithm.digest(&[&pad(0x5c), &inner])
+}
+
+/// SHA-1 (FIPS 180-4). It is only used inside HMAC-SHA1, still the default
+/// TOTP algorithm, where its collision weakness does not apply.
+fn sha1(message: &[u8]) -> [u8; 20] {
+    let mut state: [u32; 5] = [
+        0x6745_2301,
+        0xefcd_ab89,
+        0x98ba_dcfe,
+        0x1032_5476,
+        0xc3d2_e1f0,
+    ];
+
+    let mut padded = Zeroizing::new(message.to_vec());
+    padded.push(0x80);
+    while padded.len() % 64 != 56 {
+        padded.push(0);
+    }
+    padded.extend_from_slice(&(message.len() as u64 * 8).to_be_bytes());
+
+    for chunk in padded.chunks_exact(64) {
+        let mut w = [0u32; 80];
+        for (word, bytes) in w.iter_mut().zip(chunk.chunks_exact(4)) {
+            *word = u32::from_be_bytes(bytes.try_into().expect("four bytes"));
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e] = state;
+        for (i, word) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5a82_7999),
+                20..=39 => (b ^ c ^ d, 0x6ed9_eba1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8f1b_bcdc),
+                _ => (b ^ c ^ d, 0xca62_c1d6),
+            };
+            let temp = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(*word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+        for (s, value) in state.iter_mut().zip([a, b, c, d, e]) {
+            *s = s.wrapping_add(value);
+        }
+    }
+
+    let mut out = [0u8; 20];
+    for (bytes, word) in out.chunks_exact_mut(4).zip(state) {
+        bytes.copy_from_slice(&word.to_be_bytes());
+    }
+    out
+}
+
+fn percent_decode(input: &str) -> Result<String, TotpError> {
+    let bytes = input.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = bytes
+                .get(i + 1..i + 3)
+                .filter(|h| h.iter().all(u8::is_ascii_hexdigit))
+                .ok_or(TotpError::InvalidUri("malformed percent-escape"))?;
+            let hex = std::str::from_utf8(hex).expect("hex digits are ascii");
+            out.push(u8::from_str_radix(hex, 16).expect("validated hex digits"));
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(out).map_err(|_| TotpError::InvalidUri("escapes are not UTF-8"))
+}
+
+fn percent_encode(input: &str) -> String {
+    input
+        .bytes()
+        .map(|b| {
+            if b.is_ascii_alphanumeric() || b"-._~".contains(&b) {
+                char::from(b).to_string()
+            } else {
+                format!("%{b:02X}")
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn totp(seed: &[u8], algorithm: TotpAlgorithm, digits: u32) -> Totp {
+        Totp {
+            algorithm,
+            digits,
+            ..Totp::with_defaults(Zeroizing::new(seed.to_vec()))
+        }
+    }
+
+    #[test]
+    fn sha1_matches_fips_vectors() {
+        let hex = |bytes: [u8; 20]| bytes.iter().map(|b| format!("{b:02x}")).collect::<String>();
+        assert_eq!(
+            hex(sha1(b"abc")),
+            "a9993e364706816aba3e25717850c26c9cd0d89d"
+        );
+        assert_eq!(hex(sha1(b"")), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+        assert_eq!(
+            hex(sha1(
+                b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"
+            )),
+            "84983e441c3bd26ebaae4aa1f95129e5e54670f1"
+        );
+    }
+
+    #[test]
+    fn hotp_matches_rfc4226_vectors() {
+        let generator = totp(b"12345678901234567890", TotpAlgorithm::Sha1, 6);
+        let expected = [
+            "755224", "287082", "359152", "969429", "338314", "254676", "287922", "162583",
+            "399871", "520489",
+        ];
+        for (counter, code) in expected.iter().enumerate() {
+            assert_eq!(generator.hotp(counter as u64), *code);
+        }
+    }
+
+    #[test]
+    fn totp_matches_rfc6238_vectors() {
+        let sha1 = totp(b"12345678901234567890", TotpAlgorithm::Sha1, 8);
+        let sha256 = totp(
+            b"12345678901234567890123456789012",
+            TotpAlgorithm::Sha256,
+            8,
+        );
+        let sha512 = totp(
+            b"1234567890123456789012345678901234567890123456789012345678901234",
+            TotpAlgorithm::Sha512,
+            8,
+        );
+        let vectors = [
+            (59, "94287082", "46119246", "90693936"),
+            (1111111109, "07081804", "68084774", "25091201"),
+            (1111111111, "14050471", "67062674", "99943326"),
+            (1234567890, "89005924", "91819424", "93441116"),
+            (2000000000, "69279037", "90698825", "38618901"),
+            (20000000000, "65353130", "77737706", "47863826"),
+        ];
+        for (time, a, b, c) in vectors {
+            assert_eq!(sha1.at(time).code, a, "SHA1 at {time}");
+            assert_eq!(sha256.at(time).code, b, "SHA256 at {time}");
+            assert_eq!(sha512.at(time).code, c, "SHA512 at {time}");
+        }
+        assert_eq!(sha1.at(59).remaining, 1);
+        assert_eq!(sha1.at(60).remaining, 30);
+    }
+
+    #[test]
+    fn otpauth_uri_round_trips_through_canonical_form() {
+        let parsed = Totp::parse(
+            "otpauth://totp/ACME%20Co:john@example.com?secret=hxdm vjec jjws rb3h wizr 4ifu gftm xboz\
+             &issuer=ACME%20Co&algorithm=sha256&digits=8&period=60&image=x",
+        )
+        .unwrap();
+        assert_eq!(parsed.algorithm, TotpAlgorithm::Sha256);
+        assert_eq!((parsed.digits, parsed.period), (8, 60));
+        assert_eq!(parsed.issuer.as_deref(), Some("ACME Co"));
+        assert_eq!(parsed.account.as_deref(), Some("john@example.com"));
+
+        let uri = parsed.to_uri();
+        assert_eq!(
+            uri,
+            "otpauth://totp/ACME%20Co:john%40example.com?secret=HXDMVJECJJWSRB3HWIZR4IFUGFTMXBOZ\
+             &algorithm=SHA256&digits=8&period=60&issuer=ACME%20Co"
+        );
+        let reparsed = Totp::parse(&uri).unwrap();
+        assert_eq!(reparsed.to_uri(), uri);
+        assert_eq!(reparsed.at(1234).code, parsed.at(1234).code);
+    }
+
+    #[test]
+    fn bare_seed_uses_defaults() {
+        let parsed = Totp::parse("gezd gnbv gy3t qojq gezd gnbv gy3t qojq").unwrap();
+        assert_eq!(parsed.algorithm, TotpAlgorithm::Sha1);
+        assert_eq!((parsed.digits, parsed.period), (6, 30));
+        assert_eq!(parsed.at(59).code, "287082");
+    }
+
+    #[test]
+    fn rejects_unsupported_parameters() {
+        let err = |input: &str| Totp::parse(input).unwrap_err();
+        assert_eq!(
+            err("otpauth://hotp/x?secret=GEZDGNBV&counter=1"),
+            TotpError::UnsupportedType("hotp".into())
+        );
+        assert_eq!(
+            err("otpauth://totp/x?secret=GEZDGNBV&digits=7"),
+            TotpError::InvalidDigits("7".into())
+        );
+        assert_eq!(
+            err("otpauth://totp/x?secret=GEZDGNBV&period=0"),
+            TotpError::InvalidPeriod("0".into())
+        );
+        assert_eq!(
+            err("otpauth://totp/x?secret=GEZDGNBV&algorithm=MD5"),
+            TotpError::UnsupportedAlgorithm("MD5".into())
+        );
+        assert_eq!(err("otpauth://totp/x?issuer=y"), TotpError::InvalidSecret);
+        assert_eq!(err("not base32!"), TotpError::InvalidSecret);
+        assert_eq!(
+            err("otpauth://totp/%zz?secret=GEZDGNBV"),
+            TotpError::InvalidUri("malformed percent-escape")
+        );
+    }
+}
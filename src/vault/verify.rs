@@ -0,0 +1,528 @@
+use crate::vault::ops::{self, Credentials, VaultError};
+use crate::vault::{crypto, format_v1, format_v2, items};
+use std::collections::BTreeSet;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VerifySeverity {
+    /// The file is damaged or was modified; it should not be trusted.
+    Error,
+    /// The file is intact but holds data the current writer would not produce.
+    Warning,
+}
+
+impl VerifySeverity {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            VerifySeverity::Error => "error",
+            VerifySeverity::Warning => "warning",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct VerifyIssue {
+    pub severity: VerifySeverity,
+    /// Short stable name of the failed check (e.g. `header`, `payload-auth`).
+    pub check: &'static str,
+    pub location: String,
+    pub message: String,
+}
+
+/// How far the credentials got in opening the vault.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnlockOutcome {
+    /// No credentials were given; only the header was checked.
+    Skipped,
+    /// The header is too damaged to try the key slots.
+    NotAttempted,
+    /// A key slot opened and the payload authenticated.
+    Unlocked { slot_id: u8 },
+    /// A key slot opened but the payload did not authenticate, so the
+    /// credentials are right and the payload or header was modified.
+    PayloadTampered { slot_id: u8 },
+    /// No key slot opened: a wrong password or keyfile, which AEAD cannot
+    /// tell apart from a modified key slot.
+    WrongCredentials,
+    /// Every slot the password could open also needs a keyfile.
+    KeyfileRequired,
+}
+
+impl UnlockOutcome {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            UnlockOutcome::Skipped => "skipped",
+            UnlockOutcome::NotAttempted => "not-attempted",
+            UnlockOutcome::Unlocked { .. } => "unlocked",
+            UnlockOutcome::PayloadTampered { .. } => "payload-tampered",
+            UnlockOutcome::WrongCredentials => "wrong-credentials",
+            UnlockOutcome::KeyfileRequired => "keyfile-required",
+        }
+    }
+
+    pub fn slot_id(self) -> Option<u8> {
+        match self {
+            UnlockOutcome::Unlocked { slot_id } | UnlockOutcome::PayloadTampered { slot_id } => {
+                Some(slot_id)
+            }
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct VerifyReport {
+    pub file_len: usize,
+    pub key_slots: Option<usize>,
+    pub legacy_layout: Option<bool>,
+    pub unlock: UnlockOutcome,
+    pub payload_schema_version: Option<u32>,
+    pub items: Option<usize>,
+    pub issues: Vec<VerifyIssue>,
+}
+
+impl VerifyReport {
+    pub fn error_count(&self) -> usize {
+        self.issues
+            .iter()
+            .filter(|i| i.severity == VerifySeverity::Error)
+            .count()
+    }
+
+    pub fn warning_count(&self) -> usize {
+        self.issues.len() - self.error_count()
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.error_count() == 0
+    }
+
+    fn error(&mut self, check: &'static str, location: impl Into<String>, message: String) {
+        self.push(VerifySeverity::Error, check, location, message);
+    }
+
+    fn warning(&mut self, check: &'static str, location: impl Into<String>, message: String) {
+        self.push(VerifySeverity::Warning, check, location, message);
+    }
+
+    fn push(
+        &mut self,
+        severity: VerifySeverity,
+        check: &'static str,
+        location: impl Into<String>,
+        message: String,
+    ) {
+        self.issues.push(VerifyIssue {
+            severity,
+            check,
+            location: location.into(),
+            message,
+        });
+    }
+}
+
+/// Check a vault file end to end and collect every problem found.
+///
+/// Without credentials only the header is checked. Problems with the file
+/// itself end up in the report; `Err` is reserved for being unable to read it.
+pub fn vault_verify_v1(
+    vault_path: &Path,
+    credentials: Option<&Credentials>,
+) -> Result<VerifyReport, VaultError> {
+    let bytes = ops::read_existing_vault_bytes(vault_path)?;
+    Ok(verify_vault_bytes_v1(&bytes, credentials))
+}
+
+fn verify_vault_bytes_v1(bytes: &[u8], credentials: Option<&Credentials>) -> VerifyReport {
+    let mut report = VerifyReport {
+        file_len: bytes.len(),
+        key_slots: None,
+        legacy_layout: None,
+        unlock: UnlockOutcome::Skipped,
+        payload_schema_version: None,
+        items: None,
+        issues: Vec::new(),
+    };
+
+    let diagnose = match format_v1::parse_fixed_header(bytes) {
+        Ok(fixed) if fixed.version == format_v2::VERSION_V2 => format_v2::diagnose_vault_v2,
+        _ => format_v1::diagnose_vault_v1,
+    };
+    for issue in diagnose(bytes) {
+        report.error("header", issue.location, issue.error.to_string());
+    }
+
+    let parsed = match format_v2::parse_vault(bytes) {
+        Ok(parsed) => parsed,
+        Err(error) => {
+            if report.is_ok() {
+                report.error("header", "header", error.to_string());
+            }
+            if credentials.is_some() {
+                report.unlock = UnlockOutcome::NotAttempted;
+            }
+            return report;
+        }
+    };
+    report.key_slots = Some(parsed.key_slots().len());
+    report.legacy_layout = Some(parsed.legacy_layout());
+
+    let Some(credentials) = credentials else {
+        return report;
+    };
+
+    let (dek, slot_id) = match ops::unwrap_dek_with_credentials(&parsed, credentials) {
+        Ok((dek, _, slot)) => (dek, slot.id),
+        Err(VaultError::KeyfileRequired) => {
+            report.unlock = UnlockOutcome::KeyfileRequired;
+            report.error(
+                "unlock",
+                "key_slots",
+                "every matching key slot requires a keyfile".to_string(),
+            );
+            return report;
+        }
+        Err(VaultError::AuthFailed) => {
+            report.unlock = UnlockOutcome::WrongCredentials;
+            report.error(
+                "unlock",
+                "key_slots",
+                "no key slot accepted these credentials (wrong password or keyfile, \
+                 or the key slots were modified)"
+                    .to_string(),
+            );
+            return report;
+        }
+        Err(error) => {
+            report.unlock = UnlockOutcome::NotAttempted;
+            report.error("unlock", "key_slots", error.to_string());
+            return report;
+        }
+    };
+
+    let payload = match &parsed {
+        format_v2::ParsedVault::V1(parsed) => verify_payload_v1(parsed, &dek, slot_id, &mut report),
+        format_v2::ParsedVault::V2(parsed) => verify_records_v2(parsed, &dek, slot_id, &mut report),
+    };
+    let Some(mut payload) = payload else {
+        return report;
+    };
+    report.payload_schema_version = Some(payload.schema_version);
+    report.items = Some(payload.items.len());
+    if let Err(error) = ops::migrate_payload(&mut payload) {
+        report.error("payload-schema", "payload", error.to_string());
+    }
+
+    check_items(&payload.items, &mut report);
+    report
+}
+
+fn verify_payload_v1(
+    parsed: &format_v1::ParsedVaultV1<'_>,
+    dek: &crypto::SecretBytes,
+    slot_id: u8,
+    report: &mut VerifyReport,
+) -> Option<items::VaultPayloadV1> {
+    let aad = ops::aad_for_v1(&parsed.header);
+    let plaintext = match crypto::decrypt_payload(
+        dek,
+        &parsed.header.payload_nonce,
+        &aad,
+        parsed.payload_ciphertext,
+    ) {
+        Ok(plaintext) => plaintext,
+        Err(error) => {
+            report.unlock = UnlockOutcome::PayloadTampered { slot_id };
+            report.error("payload-auth", "payload", tamper_message(error, slot_id));
+            return None;
+        }
+    };
+    report.unlock = UnlockOutcome::Unlocked { slot_id };
+
+    let value: serde_json::Value = match serde_json::from_slice(&plaintext) {
+        Ok(value) => value,
+        Err(error) => {
+            report.error("payload-json", "payload", error.to_string());
+            return None;
+        }
+    };
+    match serde_json::from_value(value) {
+        Ok(payload) => Some(payload),
+        Err(error) => {
+            report.error("payload-schema", "payload", error.to_string());
+            None
+        }
+    }
+}
+
+/// Open the manifest and every item record, reporting each record that
+/// fails to authenticate, decode or match the manifest.
+fn verify_records_v2(
+    parsed: &format_v2::ParsedVaultV2,
+    dek: &crypto::SecretBytes,
+    slot_id: u8,
+    report: &mut VerifyReport,
+) -> Option<items::VaultPayloadV1> {
+    let opened = crypto::derive_manifest_key(dek).and_then(|key| {
+        crypto::decrypt_payload(
+            &key,
+            &parsed.manifest.nonce,
+            &format_v2::manifest_aad(),
+            &parsed.manifest.ciphertext,
+        )
+    });
+    let manifest_json = match opened {
+        Ok(plaintext) => plaintext,
+        Err(error) => {
+            report.unlock = UnlockOutcome::PayloadTampered { slot_id };
+            report.error("payload-auth", "manifest", tamper_message(error, slot_id));
+            return None;
+        }
+    };
+    let manifest: format_v2::ManifestV2 = match serde_json::from_slice(&manifest_json) {
+        Ok(manifest) => manifest,
+        Err(error) => {
+            report.unlock = UnlockOutcome::Unlocked { slot_id };
+            report.error("payload-schema", "manifest", error.to_string());
+            return None;
+        }
+    };
+
+    let mut listed = manifest
+        .items
+        .iter()
+        .map(|entry| (entry.id, entry))
+        .collect::<std::collections::HashMap<_, _>>();
+    let mut items = Vec::with_capacity(parsed.items.len());
+    let mut tampered = false;
+    for sealed in &parsed.items {
+        let location = format!("item {}", sealed.id);
+        match listed.remove(&sealed.id) {
+            None => {
+                tampered = true;
+                report.error(
+                    "manifest",
+                    &location,
+                    "item record is not listed in the manifest".into(),
+                );
+            }
+            Some(entry)
+                if entry.meta != ops::encode_digest(&sealed.meta.digest())
+                    || entry.secrets != ops::encode_digest(&sealed.secrets.digest()) =>
+            {
+                tampered = true;
+                report.error(
+                    "manifest",
+                    &location,
+                    "item record does not match its manifest digest".into(),
+                );
+            }
+            Some(_) => {}
+        }
+
+        let records = crypto::derive_item_key(dek, sealed.id.as_bytes()).and_then(|key| {
+            let meta = crypto::decrypt_payload(
+                &key,
+                &sealed.meta.nonce,
+                &format_v2::item_meta_aad(&sealed.id),
+                &sealed.meta.ciphertext,
+            )?;
+            let secrets = crypto::decrypt_payload(
+                &key,
+                &sealed.secrets.nonce,
+                &format_v2::item_secrets_aad(&sealed.id),
+                &sealed.secrets.ciphertext,
+            )?;
+            Ok((meta, secrets))
+        });
+        let (meta, secrets) = match records {
+            Ok(records) => records,
+            Err(error) => {
+                tampered = true;
+                report.error("payload-auth", &location, tamper_message(error, slot_id));
+                continue;
+            }
+        };
+        let item = serde_json::from_slice(&meta)
+            .and_then(|meta| Ok((meta, serde_json::from_slice(&secrets)?)))
+            .and_then(|(meta, secrets)| items::VaultItemV1::from_records_v2(meta, Some(secrets)));
+        match item {
+            Ok(item) if item.id == sealed.id => items.push(item),
+            Ok(_) => {
+                tampered = true;
+                report.error(
+                    "payload-auth",
+                    &location,
+                    "item record holds a different item id".into(),
+                );
+            }
+            Err(error) => report.error("payload-schema", &location, error.to_string()),
+        }
+    }
+    for id in listed.keys() {
+        tampered = true;
+        report.error(
+            "manifest",
+            format!("item {id}"),
+            "manifest lists an item record that is missing".into(),
+        );
+    }
+
+    report.unlock = if tampered {
+        UnlockOutcome::PayloadTampered { slot_id }
+    } else {
+        UnlockOutcome::Unlocked { slot_id }
+    };
+    Some(items::VaultPayloadV1 {
+        schema_version: manifest.schema_version,
+        items,
+        tombstones: manifest.tombstones,
+    })
+}
+
+fn tamper_message(error: crypto::CryptoError, slot_id: u8) -> String {
+    match error {
+        crypto::CryptoError::Aead => format!(
+            "payload failed authentication although key slot {slot_id} opened; \
+             the payload or header was modified"
+        ),
+        other => other.to_string(),
+    }
+}
+
+fn check_items(items: &[items::VaultItemV1], report: &mut VerifyReport) {
+    let mut ids = BTreeSet::new();
+    for item in items {
+        let location = format!("item {}", item.id);
+
+        if !ids.insert(item.id) {
+            report.error(
+                "duplicate-id",
+                &location,
+                "item id appears more than once".into(),
+            );
+        }
+        if item.updated_at < item.created_at {
+            report.warning(
+                "timestamps",
+                &location,
+                format!(
+                    "updated_at ({}) is before created_at ({})",
+                    item.updated_at, item.created_at
+                ),
+            );
+        }
+        if item.tags != ops::normalize_tags(item.tags.clone()) {
+            report.warning(
+                "tags",
+                &location,
+                "tags are not trimmed, lowercased, sorted and unique".into(),
+            );
+        }
+        if item.urls != ops::normalize_urls(item.urls.clone()) {
+            report.warning(
+                "urls",
+                &location,
+                "urls are not trimmed, sorted and unique".into(),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use secrecy::SecretString;
+
+    fn password(s: &str) -> Credentials {
+        Credentials {
+            master_password: SecretString::new(s.to_string().into_boxed_str()),
+            keyfile: None,
+        }
+    }
+
+    fn checks(report: &VerifyReport) -> Vec<&'static str> {
+        report.issues.iter().map(|i| i.check).collect()
+    }
+
+    fn item(id: uuid::Uuid, created_at: u64, updated_at: u64) -> items::VaultItemV1 {
+        items::VaultItemV1 {
+            id,
+            item_type: items::VaultItemType::Login,
+            name: "github".into(),
+            path: None,
+            tags: vec!["Prod ".into(), "dev".into()],
+            username: None,
+            secret: "s".into(),
+            urls: Vec::new(),
+            notes: None,
+            fields: Vec::new(),
+            attachments: Vec::new(),
+            expires_at: None,
+            rotate_every: None,
+            secret_changed_at: None,
+            created_at,
+            updated_at,
+            history: Vec::new(),
+            deleted_at: None,
+        }
+    }
+
+    #[test]
+    fn clean_vault_has_no_issues() {
+        let bytes = ops::test_vault_bytes(&password("pw"), &items::VaultPayloadV1::default());
+        let report = verify_vault_bytes_v1(&bytes, Some(&password("pw")));
+        assert!(report.issues.is_empty(), "{:?}", report.issues);
+        assert_eq!(report.unlock, UnlockOutcome::Unlocked { slot_id: 0 });
+        assert_eq!(report.items, Some(0));
+    }
+
+    #[test]
+    fn wrong_password_is_not_reported_as_tampering() {
+        let bytes = ops::test_vault_bytes(&password("pw"), &items::VaultPayloadV1::default());
+        let report = verify_vault_bytes_v1(&bytes, Some(&password("nope")));
+        assert_eq!(report.unlock, UnlockOutcome::WrongCredentials);
+        assert_eq!(checks(&report), vec!["unlock"]);
+    }
+
+    #[test]
+    fn flipped_payload_byte_is_reported_as_tampering() {
+        let mut bytes = ops::test_vault_bytes(&password("pw"), &items::VaultPayloadV1::default());
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0x01;
+
+        let report = verify_vault_bytes_v1(&bytes, Some(&password("pw")));
+        assert_eq!(report.unlock, UnlockOutcome::PayloadTampered { slot_id: 0 });
+        assert_eq!(checks(&report), vec!["payload-auth"]);
+
+        let header_only = verify_vault_bytes_v1(&bytes, None);
+        assert!(header_only.is_ok());
+        assert_eq!(header_only.unlock, UnlockOutcome::Skipped);
+    }
+
+    #[test]
+    fn every_item_problem_is_reported() {
+        let id = uuid::Uuid::new_v4();
+        let payload = items::VaultPayloadV1 {
+            items: vec![item(id, 10, 5), item(id, 1, 1)],
+            ..Default::default()
+        };
+        let bytes = ops::test_vault_bytes(&password("pw"), &payload);
+
+        let report = verify_vault_bytes_v1(&bytes, Some(&password("pw")));
+        assert_eq!(
+            checks(&report),
+            vec!["timestamps", "tags", "duplicate-id", "tags"]
+        );
+        assert_eq!(report.error_count(), 1);
+        assert_eq!(report.warning_count(), 3);
+    }
+
+    #[test]
+    fn truncated_file_reports_header_problems() {
+        let bytes = ops::test_vault_bytes(&password("pw"), &items::VaultPayloadV1::default());
+        let report = verify_vault_bytes_v1(&bytes[..10], Some(&password("pw")));
+        assert_eq!(checks(&report), vec!["header"]);
+        assert_eq!(report.unlock, UnlockOutcome::NotAttempted);
+    }
+}
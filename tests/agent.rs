@@ -0,0 +1,243 @@
+#![cfg(unix)]
+
+use assert_cmd::Command;
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+
+/// Stops the agent even when an assertion fails mid-test.
+struct AgentGuard {
+    socket: PathBuf,
+}
+
+impl Drop for AgentGuard {
+    fn drop(&mut self) {
+        let _ = Command::cargo_bin("passworder")
+            .expect("binary exists")
+            .args(["agent", "stop", "--socket"])
+            .arg(&self.socket)
+            .output();
+    }
+}
+
+fn passworder(socket: &Path) -> Command {
+    let mut cmd = Command::cargo_bin("passworder").expect("binary exists");
+    cmd.env("PASSWORDER_AGENT_SOCK", socket);
+    cmd
+}
+
+fn item_count(output: &std::process::Output) -> u64 {
+    let json: Value =
+        serde_json::from_str(&String::from_utf8_lossy(&output.stdout)).expect("valid json");
+    json["meta"]["count"].as_u64().expect("meta.count")
+}
+
+#[test]
+fn agent_caches_vault_key_until_locked() {
+    let dir = tempfile::tempdir().expect("temp dir");
+    let vault = dir.path().join("vault.pwder");
+    let vault = vault.to_str().unwrap();
+    let socket = dir.path().join("agent.sock");
+
+    let init = Command::cargo_bin("passworder")
+        .expect("binary exists")
+        .env("PASSWORDER_VAULT_TEST_KDF", "1")
+        .args(["vault", "init", "--path", vault])
+        .write_stdin("master\nmaster\n")
+        .output()
+        .expect("vault init output");
+    assert!(init.status.success());
+
+    let start = Command::cargo_bin("passworder")
+        .expect("binary exists")
+        .args(["agent", "--idle-timeout", "5m", "--socket"])
+        .arg(&socket)
+        .output()
+        .expect("agent start output");
+    assert!(start.status.success());
+    let _guard = AgentGuard {
+        socket: socket.clone(),
+    };
+    assert!(String::from_utf8_lossy(&start.stdout).contains("PASSWORDER_AGENT_SOCK="));
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = std::fs::metadata(&socket).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode, 0o600);
+    }
+
+    let add = passworder(&socket)
+        .args([
+            "vault",
+            "add",
+            "--path",
+            vault,
+            "--type",
+            "api-token",
+            "--name",
+            "API_KEY",
+            "--secret",
+            "abc123",
+            "--item-path",
+            "dev",
+        ])
+        .write_stdin("master\n")
+        .output()
+        .expect("vault add output");
+    assert!(add.status.success());
+
+    // No password on stdin: the cached key must be used.
+    let list = passworder(&socket)
+        .args(["vault", "list", "--path", vault, "--json"])
+        .write_stdin("")
+        .output()
+        .expect("vault list output");
+    assert!(list.status.success());
+    assert_eq!(item_count(&list), 1);
+
+    let env = passworder(&socket)
+        .args([
+            "env",
+            "--profile",
+            "dev",
+            "--format",
+            "bash",
+            "--unsafe",
+            "--path",
+            vault,
+        ])
+        .write_stdin("")
+        .output()
+        .expect("env output");
+    assert!(env.status.success());
+    assert!(String::from_utf8_lossy(&env.stdout).contains("API_KEY"));
+
+    let status = passworder(&socket)
+        .args(["agent", "status", "--json"])
+        .output()
+        .expect("agent status output");
+    assert!(status.status.success());
+    let json: Value =
+        serde_json::from_str(&String::from_utf8_lossy(&status.stdout)).expect("valid json");
+    assert_eq!(json["meta"]["vaults"].as_array().unwrap().len(), 1);
+
+    // Rekey without telling the agent: the cached key goes stale and the
+    // next command falls back to the password.
+    let rekey = Command::cargo_bin("passworder")
+        .expect("binary exists")
+        .env_remove("PASSWORDER_AGENT_SOCK")
+        .args(["vault", "rekey", "--path", vault])
+        .write_stdin("master\n")
+        .output()
+        .expect("vault rekey output");
+    assert!(rekey.status.success());
+
+    let stale = passworder(&socket)
+        .args(["vault", "list", "--path", vault, "--json"])
+        .write_stdin("master\n")
+        .output()
+        .expect("vault list output");
+    assert!(stale.status.success());
+    assert_eq!(item_count(&stale), 1);
+
+    let lock = passworder(&socket)
+        .args(["agent", "lock"])
+        .output()
+        .expect("agent lock output");
+    assert!(lock.status.success());
+
+    let locked = passworder(&socket)
+        .args(["vault", "list", "--path", vault])
+        .write_stdin("")
+        .output()
+        .expect("vault list output");
+    assert_eq!(
+        locked.status.code(),
+        Some(64),
+        "locked agent requires a password"
+    );
+}
+
+#[test]
+fn agent_commands_fail_without_running_agent() {
+    let dir = tempfile::tempdir().expect("temp dir");
+    let socket = dir.path().join("missing.sock");
+
+    let status = Command::cargo_bin("passworder")
+        .expect("binary exists")
+        .args(["agent", "status", "--socket"])
+        .arg(&socket)
+        .output()
+        .expect("agent status output");
+    assert_eq!(status.status.code(), Some(2));
+}
+
+#[test]
+fn ssh_agent_serves_ssh_key_items() {
+    use std::io::{Read, Write};
+    use std::os::unix::net::UnixStream;
+
+    let dir = tempfile::tempdir().expect("temp dir");
+    let vault = dir.path().join("vault.pwder");
+    let vault = vault.to_str().unwrap();
+    let socket = dir.path().join("ssh-agent.sock");
+
+    let run = |args: &[&str]| {
+        Command::cargo_bin("passworder")
+            .expect("binary exists")
+            .env("PASSWORDER_VAULT_TEST_KDF", "1")
+            .args(args)
+            .args(["--path", vault])
+            .write_stdin("pw\npw\n")
+            .output()
+            .expect("passworder output")
+    };
+    assert!(run(&["vault", "init"]).status.success());
+    let add = run(&[
+        "vault",
+        "add",
+        "--type",
+        "ssh-key",
+        "--name",
+        "deploy",
+        "--generate",
+    ]);
+    assert!(add.status.success());
+
+    let mut agent = std::process::Command::new(assert_cmd::cargo::cargo_bin("passworder"))
+        .env("PASSWORDER_VAULT_TEST_KDF", "1")
+        .args(["ssh-agent", "--path", vault, "--socket"])
+        .arg(&socket)
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .expect("ssh-agent spawn");
+    agent
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"pw\n")
+        .expect("write password");
+    let mut line = [0u8; 14];
+    agent
+        .stdout
+        .take()
+        .unwrap()
+        .read_exact(&mut line)
+        .expect("agent started");
+    assert_eq!(&line, b"SSH_AUTH_SOCK=");
+
+    // SSH_AGENTC_REQUEST_IDENTITIES -> SSH_AGENT_IDENTITIES_ANSWER with one key.
+    let mut stream = UnixStream::connect(&socket).expect("connect");
+    stream.write_all(&[0, 0, 0, 1, 11]).unwrap();
+    let mut len = [0u8; 4];
+    stream.read_exact(&mut len).unwrap();
+    let mut answer = vec![0u8; u32::from_be_bytes(len) as usize];
+    stream.read_exact(&mut answer).unwrap();
+    let _ = agent.kill();
+    let _ = agent.wait();
+
+    assert_eq!(answer[0], 12);
+    assert_eq!(&answer[1..5], &[0, 0, 0, 1]);
+    assert!(answer.ends_with(b"deploy"));
+}
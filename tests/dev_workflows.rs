@@ -181,3 +181,143 @@ fn run_injects_env_and_inject_substitutes_template() {
         assert_eq!(mode, 0o600);
     }
 }
+
+#[test]
+fn env_uses_new_master_password_after_passwd() {
+    let dir = tempfile::tempdir().expect("temp dir");
+    let vault = dir.path().join("vault.pwder");
+    let vault = vault.to_str().unwrap();
+
+    let init = Command::cargo_bin("passworder")
+        .expect("binary exists")
+        .env("PASSWORDER_VAULT_TEST_KDF", "1")
+        .args(["vault", "init", "--path", vault])
+        .write_stdin("pw\npw\n")
+        .output()
+        .expect("vault init");
+    assert!(init.status.success());
+
+    let add = Command::cargo_bin("passworder")
+        .expect("binary exists")
+        .args([
+            "vault",
+            "add",
+            "--path",
+            vault,
+            "--type",
+            "api-token",
+            "--name",
+            "API_KEY",
+            "--secret",
+            "abc123",
+            "--item-path",
+            "dev",
+        ])
+        .write_stdin("pw\n")
+        .output()
+        .expect("vault add");
+    assert!(add.status.success());
+
+    let passwd = Command::cargo_bin("passworder")
+        .expect("binary exists")
+        .args(["vault", "passwd", "--path", vault, "--rotate-dek"])
+        .write_stdin("pw\npw2\npw2\n")
+        .output()
+        .expect("vault passwd");
+    assert!(passwd.status.success());
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = fs::metadata(vault).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode, 0o600);
+    }
+
+    let json = Command::cargo_bin("passworder")
+        .expect("binary exists")
+        .args([
+            "env",
+            "--profile",
+            "dev",
+            "--format",
+            "json",
+            "--unsafe",
+            "--path",
+            vault,
+        ])
+        .write_stdin("pw2\n")
+        .output()
+        .expect("env json output");
+    assert!(json.status.success());
+    let stdout = String::from_utf8_lossy(&json.stdout);
+    let obj: Value = serde_json::from_str(&stdout).expect("valid json");
+    assert_eq!(obj.get("API_KEY").and_then(Value::as_str), Some("abc123"));
+}
+
+#[test]
+fn env_exports_custom_fields_of_one_item() {
+    let dir = tempfile::tempdir().expect("temp dir");
+    let vault = dir.path().join("vault.pwder");
+    let vault = vault.to_str().unwrap();
+
+    let init = Command::cargo_bin("passworder")
+        .expect("binary exists")
+        .env("PASSWORDER_VAULT_TEST_KDF", "1")
+        .args(["vault", "init", "--path", vault])
+        .write_stdin("pw\npw\n")
+        .output()
+        .expect("vault init");
+    assert!(init.status.success());
+
+    // DB_PASSWORD is concealed and prompted for after the item secret.
+    let add = Command::cargo_bin("passworder")
+        .expect("binary exists")
+        .args([
+            "vault",
+            "add",
+            "--path",
+            vault,
+            "--type",
+            "login",
+            "--name",
+            "database",
+            "--item-path",
+            "dev",
+            "--field",
+            "DB_HOST=db.internal",
+            "--field",
+            "DB_USER=app",
+            "--secret-field",
+            "DB_PASSWORD",
+        ])
+        .write_stdin("pw\nunused\ns3cret\n")
+        .output()
+        .expect("vault add");
+    assert!(add.status.success());
+
+    let json = Command::cargo_bin("passworder")
+        .expect("binary exists")
+        .args([
+            "env",
+            "--profile",
+            "dev",
+            "--format",
+            "json",
+            "--unsafe",
+            "--path",
+            vault,
+        ])
+        .write_stdin("pw\n")
+        .output()
+        .expect("env json output");
+    assert!(json.status.success());
+    let obj: Value = serde_json::from_str(&String::from_utf8_lossy(&json.stdout)).expect("json");
+    assert_eq!(
+        obj,
+        serde_json::json!({
+            "DB_HOST": "db.internal",
+            "DB_USER": "app",
+            "DB_PASSWORD": "s3cret",
+        })
+    );
+}
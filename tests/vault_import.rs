@@ -0,0 +1,240 @@
+use assert_cmd::Command;
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+
+fn fixture(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures/import")
+        .join(name)
+}
+
+fn dry_run(format: &str, file: &str) -> Value {
+    let dir = tempfile::tempdir().expect("temp dir");
+    let vault = dir.path().join("vault.pwder");
+
+    let output = Command::cargo_bin("passworder")
+        .expect("binary exists")
+        .args([
+            "vault",
+            "import",
+            "--json",
+            "--dry-run",
+            "--from",
+            format,
+            "--path",
+        ])
+        .arg(&vault)
+        .arg(fixture(file))
+        .output()
+        .expect("vault import output");
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(!vault.exists(), "dry run must not touch the vault");
+
+    let json: Value =
+        serde_json::from_str(&String::from_utf8_lossy(&output.stdout)).expect("valid json");
+    json["meta"].clone()
+}
+
+fn names(meta: &Value) -> Vec<(String, String, String)> {
+    meta["items"]
+        .as_array()
+        .expect("items array")
+        .iter()
+        .map(|i| {
+            (
+                i["type"].as_str().unwrap_or_default().to_string(),
+                i["path"].as_str().unwrap_or_default().to_string(),
+                i["name"].as_str().unwrap_or_default().to_string(),
+            )
+        })
+        .collect()
+}
+
+fn unmapped(meta: &Value) -> Vec<String> {
+    meta["unmapped"]
+        .as_array()
+        .expect("unmapped array")
+        .iter()
+        .map(|u| {
+            format!(
+                "{}: {}",
+                u["item"].as_str().unwrap(),
+                u["field"].as_str().unwrap()
+            )
+        })
+        .collect()
+}
+
+fn item(t: &str, p: &str, n: &str) -> (String, String, String) {
+    (t.to_string(), p.to_string(), n.to_string())
+}
+
+#[test]
+fn bitwarden_json_dry_run_maps_folders_and_reports_unmapped_fields() {
+    let meta = dry_run("bitwarden-json", "bitwarden.json");
+
+    assert_eq!(meta["kind"], "vault-import");
+    assert_eq!(meta["dry_run"], true);
+    assert_eq!(
+        names(&meta),
+        vec![
+            item("login", "Work/Dev", "GitHub"),
+            item("secure-note", "", "Wi-Fi"),
+        ]
+    );
+    assert_eq!(meta["items"][0]["username"], "octocat");
+    assert_eq!(meta["items"][0]["tags"], serde_json::json!(["favorite"]));
+    assert_eq!(meta["items"][0]["urls"].as_array().unwrap().len(), 2);
+    assert_eq!(
+        unmapped(&meta),
+        vec!["GitHub: login.totp", "GitHub: fields.PIN"]
+    );
+    assert_eq!(meta["skipped_records"][0]["name"], "Visa");
+}
+
+#[test]
+fn onepassword_1pux_dry_run_maps_categories() {
+    let meta = dry_run("1password-1pux", "1password.1pux");
+
+    assert_eq!(
+        names(&meta),
+        vec![
+            item("login", "Work", "VPN"),
+            item("api-token", "Work", "Stripe"),
+            item("secure-note", "Work", "Office"),
+        ]
+    );
+    assert_eq!(meta["items"][0]["tags"], serde_json::json!(["infra"]));
+    assert_eq!(meta["items"][0]["created_at"], 1614298956);
+    assert_eq!(meta["items"][1]["username"], "svc-deploy");
+    assert_eq!(
+        unmapped(&meta),
+        vec![
+            "VPN: loginFields.remember",
+            "VPN: sections.one-time password",
+            "Stripe: sections.hostname",
+        ]
+    );
+    assert_eq!(meta["skipped_records"].as_array().unwrap().len(), 2);
+}
+
+#[test]
+fn keepass_xml_dry_run_maps_groups_and_skips_recycle_bin() {
+    let meta = dry_run("keepass-xml", "keepass.xml");
+
+    assert_eq!(
+        names(&meta),
+        vec![
+            item("login", "", "Mail"),
+            item("login", "Servers/Prod", "db01")
+        ]
+    );
+    assert_eq!(
+        meta["items"][0]["tags"],
+        serde_json::json!(["mail", "personal"])
+    );
+    assert_eq!(unmapped(&meta), vec!["Mail: String.Security Question"]);
+}
+
+#[test]
+fn csv_dry_run_maps_lastpass_columns() {
+    let meta = dry_run("csv", "lastpass.csv");
+
+    assert_eq!(
+        names(&meta),
+        vec![
+            item("login", "Cloud/Prod", "AWS"),
+            item("login", "", "Example"),
+            item("secure-note", "Cloud", "Server notes"),
+            item("login", "", "2FA only"),
+        ]
+    );
+    assert_eq!(meta["by_type"]["login"], 3);
+    assert_eq!(unmapped(&meta), vec!["2FA only: totp"]);
+}
+
+#[test]
+fn foreign_import_writes_items_with_secrets() {
+    let dir = tempfile::tempdir().expect("temp dir");
+    let vault = dir.path().join("vault.pwder");
+    let vault = vault.to_str().unwrap();
+
+    let init = Command::cargo_bin("passworder")
+        .expect("binary exists")
+        .env("PASSWORDER_VAULT_TEST_KDF", "1")
+        .args(["vault", "init", "--path", vault])
+        .write_stdin("pw\npw\n")
+        .output()
+        .expect("vault init output");
+    assert!(init.status.success());
+
+    let import = Command::cargo_bin("passworder")
+        .expect("binary exists")
+        .args(["vault", "import", "--json", "--from", "bitwarden-json"])
+        .args(["--path", vault])
+        .arg(fixture("bitwarden.json"))
+        .write_stdin("pw\n")
+        .output()
+        .expect("vault import output");
+    assert!(import.status.success());
+    let json: Value =
+        serde_json::from_str(&String::from_utf8_lossy(&import.stdout)).expect("valid json");
+    assert_eq!(json["meta"]["added"], 2);
+    assert_eq!(json["meta"]["format"], "bitwarden-json");
+    assert_eq!(json["meta"]["unmapped"].as_array().unwrap().len(), 2);
+
+    let search = Command::cargo_bin("passworder")
+        .expect("binary exists")
+        .args(["vault", "search", "github", "--json", "--path", vault])
+        .write_stdin("pw\n")
+        .output()
+        .expect("vault search output");
+    let json: Value =
+        serde_json::from_str(&String::from_utf8_lossy(&search.stdout)).expect("valid json");
+    let id = json["meta"]["items"][0]["id"].as_str().unwrap().to_string();
+
+    let get = Command::cargo_bin("passworder")
+        .expect("binary exists")
+        .args(["vault", "get", &id, "--reveal", "--json", "--path", vault])
+        .write_stdin("pw\n")
+        .output()
+        .expect("vault get output");
+    let json: Value =
+        serde_json::from_str(&String::from_utf8_lossy(&get.stdout)).expect("valid json");
+    assert_eq!(
+        json["meta"]["item"]["secret"],
+        "correct horse battery staple"
+    );
+    assert_eq!(
+        json["meta"]["item"]["notes"],
+        "recovery codes are in the safe"
+    );
+}
+
+#[test]
+fn foreign_import_rejects_malformed_files() {
+    let dir = tempfile::tempdir().expect("temp dir");
+    let bogus = dir.path().join("bogus.1pux");
+    std::fs::write(&bogus, b"not a zip").unwrap();
+
+    let output = Command::cargo_bin("passworder")
+        .expect("binary exists")
+        .args([
+            "vault",
+            "import",
+            "--dry-run",
+            "--from",
+            "1password-1pux",
+            "--path",
+        ])
+        .arg(dir.path().join("vault.pwder"))
+        .arg(&bogus)
+        .output()
+        .expect("vault import output");
+    assert_eq!(output.status.code(), Some(64));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("invalid 1password-1pux export"));
+}
@@ -0,0 +1,219 @@
+use assert_cmd::Command;
+use serde_json::Value;
+use std::path::Path;
+
+fn passworder(vault: &Path, args: &[&str], stdin: &str) -> std::process::Output {
+    passworder_with_env(vault, &[], args, stdin)
+}
+
+fn passworder_with_env(
+    vault: &Path,
+    env: &[(&str, &str)],
+    args: &[&str],
+    stdin: &str,
+) -> std::process::Output {
+    Command::cargo_bin("passworder")
+        .expect("binary exists")
+        .env("PASSWORDER_VAULT_TEST_KDF", "1")
+        .env("GIT_CONFIG_GLOBAL", "/dev/null")
+        .env("GIT_CONFIG_NOSYSTEM", "1")
+        .env_remove("PASSWORDER_VAULT_PULL_ON_READ")
+        .envs(env.iter().copied())
+        .args(args)
+        .arg("--path")
+        .arg(vault)
+        .write_stdin(stdin)
+        .output()
+        .expect("passworder output")
+}
+
+fn json_ok(output: std::process::Output) -> Value {
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let json: Value =
+        serde_json::from_str(&String::from_utf8_lossy(&output.stdout)).expect("valid json");
+    json["meta"].clone()
+}
+
+fn git(dir: &Path, args: &[&str]) -> String {
+    let output = std::process::Command::new("git")
+        .env("GIT_CONFIG_GLOBAL", "/dev/null")
+        .env("GIT_CONFIG_NOSYSTEM", "1")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .output()
+        .expect("git output");
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8_lossy(&output.stdout).trim().to_string()
+}
+
+#[test]
+fn vault_sync_round_trips_through_a_bare_remote_and_merges_items() {
+    let dir = tempfile::tempdir().expect("temp dir");
+    let remote = dir.path().join("remote.git");
+    git(
+        dir.path(),
+        &["init", "-q", "--bare", remote.to_str().unwrap()],
+    );
+    let remote_url = format!("file://{}", remote.display());
+
+    // Device A creates the vault and publishes it.
+    let a = dir.path().join("a/vault.pwder");
+    assert!(
+        passworder(&a, &["vault", "init"], "pw\npw\n")
+            .status
+            .success()
+    );
+    let added = passworder(
+        &a,
+        &["vault", "add", "--type", "login", "--name", "GitHub"],
+        "pw\nhunter2\n",
+    );
+    assert!(added.status.success());
+    let github = String::from_utf8_lossy(&added.stdout)
+        .trim()
+        .strip_prefix("Added ")
+        .expect("added id")
+        .to_string();
+
+    let init = json_ok(passworder(
+        &a,
+        &["vault", "sync", "init", "--json", "--remote", &remote_url],
+        "",
+    ));
+    assert_eq!(init["kind"], "vault-sync-init");
+    assert_eq!(init["pulled"], false);
+    let sync = json_ok(passworder(&a, &["vault", "sync", "--json"], "pw\n"));
+    assert_eq!(sync["outcome"], "ahead");
+    assert_eq!(sync["pushed"], true);
+
+    // Only the vault file and the ignore list are tracked.
+    let tracked = git(a.parent().unwrap(), &["ls-files"]);
+    assert_eq!(tracked, ".gitignore\nvault.pwder");
+
+    // Device B joins from the remote.
+    let b = dir.path().join("b/vault.pwder");
+    let init = json_ok(passworder(
+        &b,
+        &["vault", "sync", "init", "--json", "--remote", &remote_url],
+        "",
+    ));
+    assert_eq!(init["pulled"], true);
+    let get = passworder(&b, &["vault", "get", &github, "--reveal", "--json"], "pw\n");
+    assert_eq!(json_ok(get)["item"]["secret"], "hunter2");
+
+    // Diverging edits: A renames the item, B adds another one. Each write is
+    // committed locally.
+    assert!(
+        passworder(
+            &a,
+            &["vault", "edit", &github, "--name", "GitHub (work)"],
+            "pw\n"
+        )
+        .status
+        .success()
+    );
+    assert!(
+        passworder(
+            &b,
+            &["vault", "add", "--type", "secure-note", "--name", "Wi-Fi"],
+            "pw\nsecret\n",
+        )
+        .status
+        .success()
+    );
+    assert_eq!(git(b.parent().unwrap(), &["status", "--porcelain"]), "");
+
+    let sync = json_ok(passworder(&a, &["vault", "sync", "--json"], "pw\n"));
+    assert_eq!(sync["outcome"], "ahead");
+    let sync = json_ok(passworder(&b, &["vault", "sync", "--json"], "pw\n"));
+    assert_eq!(sync["outcome"], "merged");
+    assert_eq!(sync["merge"]["updated"], 1);
+    assert_eq!(sync["pushed"], true);
+
+    // Reads only pull when asked to.
+    let names = |list: Value| {
+        let mut names = list["items"]
+            .as_array()
+            .expect("items")
+            .iter()
+            .map(|i| i["name"].as_str().unwrap().to_string())
+            .collect::<Vec<_>>();
+        names.sort();
+        names
+    };
+    let list = json_ok(passworder(&a, &["vault", "list", "--json"], "pw\n"));
+    assert_eq!(names(list), vec!["GitHub (work)"]);
+    let list = json_ok(passworder_with_env(
+        &a,
+        &[("PASSWORDER_VAULT_PULL_ON_READ", "1")],
+        &["vault", "list", "--json"],
+        "pw\n",
+    ));
+    assert_eq!(names(list), vec!["GitHub (work)", "Wi-Fi"]);
+    assert_eq!(
+        git(a.parent().unwrap(), &["rev-parse", "HEAD"]),
+        git(&remote, &["rev-parse", "main"])
+    );
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = std::fs::metadata(&a).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+}
+
+#[test]
+fn vault_sync_requires_init() {
+    let dir = tempfile::tempdir().expect("temp dir");
+    let vault = dir.path().join("vault.pwder");
+    assert!(
+        passworder(&vault, &["vault", "init"], "pw\npw\n")
+            .status
+            .success()
+    );
+
+    let output = passworder(&vault, &["vault", "sync"], "pw\n");
+    assert_eq!(output.status.code(), Some(64));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("vault sync init"));
+}
+
+#[test]
+fn vault_write_reports_a_failed_sync_commit() {
+    let dir = tempfile::tempdir().expect("temp dir");
+    let vault = dir.path().join("vault.pwder");
+    assert!(
+        passworder(&vault, &["vault", "init"], "pw\npw\n")
+            .status
+            .success()
+    );
+    json_ok(passworder(&vault, &["vault", "sync", "init", "--json"], ""));
+
+    // A stale index lock makes every commit fail.
+    let index_lock = dir.path().join(".git/index.lock");
+    std::fs::write(&index_lock, "").unwrap();
+    let add = passworder(
+        &vault,
+        &[
+            "vault", "add", "--type", "login", "--name", "GitHub", "--secret", "s",
+        ],
+        "pw\n",
+    );
+    assert_eq!(add.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&add.stderr).contains("committing it for sync failed"));
+    let list = json_ok(passworder(&vault, &["vault", "list", "--json"], "pw\n"));
+    assert_eq!(list["count"], 1, "the write itself went through");
+
+    std::fs::remove_file(&index_lock).unwrap();
+    let sync = json_ok(passworder(&vault, &["vault", "sync", "--json"], "pw\n"));
+    assert_eq!(sync["committed"], true);
+}